@@ -1,12 +1,11 @@
-extern "C" {
-    #[link_name = "llvm.arm.hint"]
-    fn hint(a: i32);
-}
+#[cfg(target_arch = "arm")]
+use core::arch::asm;
 
 /// NOP instruction
 #[inline(always)]
 pub fn nop() {
-    unsafe { hint(0) };
+    #[cfg(target_arch = "arm")]
+    unsafe { asm!("nop", options(nomem, nostack, preserves_flags)) };
 }
 
 /// WFI (Wait For Interrupt) makes the processor suspend
@@ -17,6 +16,6 @@ pub fn nop() {
 ///    A Debug Entry request made to the processor.
 #[inline(always)]
 pub unsafe fn wfi() {
-    hint(2);
+    #[cfg(target_arch = "arm")]
+    asm!("wfi");
 }
-
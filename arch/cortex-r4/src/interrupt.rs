@@ -0,0 +1,215 @@
+//! Interrupts masking and critical sections.
+//!
+//! IRQ and FIQ are masked through the CPSR I and F bits. A critical
+//! section saves the current I/F state, masks both and restores the
+//! saved state on exit, so critical sections can be nested safely.
+//!
+//! On non-ARM (host) builds the I/F bits are only emulated, so that code
+//! using critical sections can run against mocked peripherals.
+
+#[cfg(target_arch = "arm")]
+use core::arch::asm;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+#[cfg(not(target_arch = "arm"))]
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// CPSR: IRQ mask bit
+const CPSR_I: u32 = 0x1 << 7;
+/// CPSR: FIQ mask bit
+const CPSR_F: u32 = 0x1 << 6;
+
+/// Emulated CPSR I/F bits for host builds
+#[cfg(not(target_arch = "arm"))]
+static HOST_CPSR: AtomicU32 = AtomicU32::new(0);
+
+/// Disable IRQ and FIQ
+#[inline(always)]
+pub fn disable() {
+    #[cfg(target_arch = "arm")]
+    unsafe { asm!("cpsid if") };
+    #[cfg(not(target_arch = "arm"))]
+    HOST_CPSR.fetch_or(CPSR_I | CPSR_F, Ordering::SeqCst);
+}
+
+/// Enable IRQ and FIQ
+#[inline(always)]
+pub unsafe fn enable() {
+    #[cfg(target_arch = "arm")]
+    asm!("cpsie if");
+    #[cfg(not(target_arch = "arm"))]
+    HOST_CPSR.fetch_and(!(CPSR_I | CPSR_F), Ordering::SeqCst);
+}
+
+/// Disable IRQ only
+#[inline(always)]
+pub fn disable_irq() {
+    #[cfg(target_arch = "arm")]
+    unsafe { asm!("cpsid i") };
+    #[cfg(not(target_arch = "arm"))]
+    HOST_CPSR.fetch_or(CPSR_I, Ordering::SeqCst);
+}
+
+/// Enable IRQ only
+#[inline(always)]
+pub unsafe fn enable_irq() {
+    #[cfg(target_arch = "arm")]
+    asm!("cpsie i");
+    #[cfg(not(target_arch = "arm"))]
+    HOST_CPSR.fetch_and(!CPSR_I, Ordering::SeqCst);
+}
+
+/// Disable FIQ only
+#[inline(always)]
+pub fn disable_fiq() {
+    #[cfg(target_arch = "arm")]
+    unsafe { asm!("cpsid f") };
+    #[cfg(not(target_arch = "arm"))]
+    HOST_CPSR.fetch_or(CPSR_F, Ordering::SeqCst);
+}
+
+/// Enable FIQ only
+#[inline(always)]
+pub unsafe fn enable_fiq() {
+    #[cfg(target_arch = "arm")]
+    asm!("cpsie f");
+    #[cfg(not(target_arch = "arm"))]
+    HOST_CPSR.fetch_and(!CPSR_F, Ordering::SeqCst);
+}
+
+#[inline(always)]
+#[cfg(target_arch = "arm")]
+fn cpsr() -> u32 {
+    let r: u32;
+    unsafe { asm!("mrs {}, cpsr", out(reg) r) };
+    r
+}
+
+#[inline(always)]
+#[cfg(not(target_arch = "arm"))]
+fn cpsr() -> u32 {
+    HOST_CPSR.load(Ordering::SeqCst)
+}
+
+/// True if IRQs are currently masked
+pub fn irq_masked() -> bool {
+    cpsr() & CPSR_I != 0
+}
+
+/// True if FIQs are currently masked
+pub fn fiq_masked() -> bool {
+    cpsr() & CPSR_F != 0
+}
+
+/// Interrupts mask state saved when entering a critical section
+#[derive(Copy, Clone)]
+pub struct State(u32);
+
+impl State {
+    /// Build a state from a raw value returned by `State::bits()`
+    pub unsafe fn from_bits(bits: u32) -> State {
+        State(bits & (CPSR_I | CPSR_F))
+    }
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+/// Save the current IRQ/FIQ mask state and mask both.
+#[inline(always)]
+pub fn acquire() -> State {
+    let state = cpsr() & (CPSR_I | CPSR_F);
+    disable();
+    State(state)
+}
+
+/// Restore the IRQ/FIQ mask state saved by `acquire()`.
+/// Interrupts are re-enabled only if they were enabled on acquire.
+#[inline(always)]
+pub unsafe fn release(state: State) {
+    if state.0 & CPSR_F == 0 {
+        enable_fiq();
+    }
+    if state.0 & CPSR_I == 0 {
+        enable_irq();
+    }
+}
+
+/// Critical section token.
+/// Proves that interrupts are masked for the lifetime `'cs`.
+pub struct CriticalSection<'cs> {
+    _0: PhantomData<&'cs ()>,
+}
+
+impl<'cs> CriticalSection<'cs> {
+    /// Create a token. The caller must guarantee that interrupts are
+    /// masked for the whole lifetime of the token.
+    #[inline(always)]
+    pub unsafe fn new() -> Self {
+        CriticalSection { _0: PhantomData }
+    }
+}
+
+/// Execute a closure with IRQ and FIQ masked.
+/// The previous interrupts state is restored on exit, so calls can be
+/// nested.
+#[inline]
+pub fn free<F, R>(f: F) -> R
+where
+    F: FnOnce(&CriticalSection) -> R,
+{
+    let state = acquire();
+    let r = f(unsafe { &CriticalSection::new() });
+    unsafe { release(state) };
+    r
+}
+
+/// Mutex based on critical sections.
+/// Data can only be accessed while interrupts are masked, so it can be
+/// shared between `main` and interrupt handlers.
+pub struct Mutex<T> {
+    inner: UnsafeCell<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex {
+            inner: UnsafeCell::new(value),
+        }
+    }
+
+    /// Borrow the data for the duration of the critical section
+    pub fn borrow<'cs>(&'cs self, _cs: &'cs CriticalSection) -> &'cs T {
+        unsafe { &*self.inner.get() }
+    }
+
+    /// Mutable access, statically guaranteed by the unique borrow
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.inner.get() }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+unsafe impl<T> Sync for Mutex<T> where T: Send {}
+
+/// Implementation of the `critical-section` crate for single-core
+/// Cortex-R4 (lock-step cores count as a single core).
+#[cfg(feature = "critical-section-single-core")]
+mod single_core {
+    struct SingleCoreCriticalSection;
+    set_impl!(SingleCoreCriticalSection);
+
+    unsafe impl ::critical_section::Impl for SingleCoreCriticalSection {
+        unsafe fn acquire() -> ::critical_section::RawRestoreState {
+            super::acquire().bits()
+        }
+
+        unsafe fn release(state: ::critical_section::RawRestoreState) {
+            super::release(super::State::from_bits(state))
+        }
+    }
+}
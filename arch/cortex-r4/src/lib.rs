@@ -2,9 +2,16 @@
 
 #![crate_name = "cortexr4"]
 #![crate_type = "rlib"]
-#![feature(link_llvm_intrinsics)]
 #![no_std]
 
+#[cfg(feature = "critical-section-single-core")]
+#[macro_use]
+extern crate critical_section;
+
 pub mod asm;
+pub mod interrupt;
+#[cfg(target_arch = "arm")]
 pub mod mpu;
+#[cfg(target_arch = "arm")]
+pub mod pmu;
 
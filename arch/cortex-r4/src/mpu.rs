@@ -1,2 +1,391 @@
 //! Implementation of the ARM memory protection unit.
+//!
+//! The Cortex-R4 MPU splits the memory map in up to 12 regions (8 or 12
+//! depending on the silicon configuration). Each region is described by
+//! a base address, a power-of-two size, access permissions and memory
+//! attributes. Overlapping regions are allowed: the region with the
+//! highest number takes priority.
+//!
+//! Reference: Cortex-R4 and Cortex-R4F Technical Reference Manual (r1p4),
+//! Chapter 7 "Memory Protection Unit" and Chapter 4.3.20 "c6, MPU memory
+//! region programming registers".
 
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// SCTLR: MPU enable
+const SCTLR_M: u32 = 0x1;
+/// SCTLR: Background region enable
+const SCTLR_BR: u32 = 0x1 << 17;
+
+/// DRSR: Region enable
+const DRSR_EN: u32 = 0x1;
+/// DRACR: Execute Never
+const DRACR_XN: u32 = 0x1 << 12;
+
+/// FSR: Write not Read
+const FSR_WNR: u32 = 0x1 << 11;
+/// FSR fault status: background fault
+const FS_BACKGROUND: u32 = 0b00000;
+/// FSR fault status: permission fault
+const FS_PERMISSION: u32 = 0b01101;
+/// FSR value written by `clear_fault()`: fault status 0b11111 is not
+/// used by the Cortex-R4, so it can't be confused with a background
+/// fault (status 0b00000)
+const FSR_NONE: u32 = (0x1 << 10) | 0xF;
+
+/// Set once `FSR_NONE` has been written to the fault status registers:
+/// until then their reset value (0) is not a background fault
+static FSR_SEEDED: AtomicBool = AtomicBool::new(false);
+
+/// Maximum number of regions supported by the Cortex-R4 MPU
+pub const MAX_REGIONS: u32 = 12;
+
+/// Region size, encoded as in DRSR (size = 2^(N+1) bytes).
+#[derive(Copy, Clone, PartialEq, PartialOrd)]
+#[repr(u8)]
+pub enum RegionSize {
+    Size32B = 4,
+    Size64B = 5,
+    Size128B = 6,
+    Size256B = 7,
+    Size512B = 8,
+    Size1K = 9,
+    Size2K = 10,
+    Size4K = 11,
+    Size8K = 12,
+    Size16K = 13,
+    Size32K = 14,
+    Size64K = 15,
+    Size128K = 16,
+    Size256K = 17,
+    Size512K = 18,
+    Size1M = 19,
+    Size2M = 20,
+    Size4M = 21,
+    Size8M = 22,
+    Size16M = 23,
+    Size32M = 24,
+    Size64M = 25,
+    Size128M = 26,
+    Size256M = 27,
+    Size512M = 28,
+    Size1G = 29,
+    Size2G = 30,
+    Size4G = 31,
+}
+
+impl RegionSize {
+    /// Smallest region size able to hold `bytes` bytes.
+    /// Return `None` for a zero length.
+    pub fn from_bytes(bytes: u32) -> Option<RegionSize> {
+        if bytes == 0 {
+            return None;
+        }
+        // ceil(log2(bytes)) bounded to the 32 bytes minimum
+        let log2 = 32 - (bytes - 1).leading_zeros();
+        let n = if log2 < 5 { 4 } else { log2 - 1 };
+        // n is in 4..=31, all valid discriminants
+        Some(unsafe { ::core::mem::transmute::<u8, RegionSize>(n as u8) })
+    }
+
+    /// Region size in bytes. 4GB is reported as 0 (it wraps a u32).
+    pub fn bytes(self) -> u32 {
+        if self == RegionSize::Size4G {
+            0
+        } else {
+            0x1 << ((self as u32) + 1)
+        }
+    }
+
+    /// Mask of the address bits that must be zero in the region base
+    fn align_mask(self) -> u32 {
+        self.bytes().wrapping_sub(1)
+    }
+}
+
+/// Access permissions (DRACR AP field)
+#[derive(Copy, Clone, PartialEq)]
+pub enum Access {
+    /// No access in any mode
+    NoAccess = 0b000,
+    /// Privileged Read/Write, User no access
+    PrivRW = 0b001,
+    /// Privileged Read/Write, User Read-only
+    PrivRWUserRO = 0b010,
+    /// Read/Write in any mode
+    FullAccess = 0b011,
+    /// Privileged Read-only, User no access
+    PrivRO = 0b101,
+    /// Read-only in any mode
+    ReadOnly = 0b110,
+}
+
+/// Memory type and cache policy (DRACR TEX, C and B fields)
+#[derive(Copy, Clone, PartialEq)]
+pub enum MemoryType {
+    /// Strongly-ordered, always shareable
+    StronglyOrdered = 0b000_0_0_0,
+    /// Device, shareable
+    DeviceShared = 0b000_0_0_1,
+    /// Device, non-shareable
+    Device = 0b010_0_0_0,
+    /// Normal, outer and inner write-through, no write-allocate
+    NormalWriteThrough = 0b000_0_1_0,
+    /// Normal, outer and inner write-back, no write-allocate
+    NormalWriteBack = 0b000_0_1_1,
+    /// Normal, outer and inner non-cacheable
+    NormalNonCacheable = 0b001_0_0_0,
+    /// Normal, outer and inner write-back, write-allocate
+    NormalWriteBackAllocate = 0b001_0_1_1,
+}
+
+/// MPU region descriptor
+#[derive(Copy, Clone)]
+pub struct Region {
+    /// Region number (0..MAX_REGIONS). Higher numbers take priority.
+    pub number: u32,
+    /// Base address, must be aligned to the region size
+    pub base: u32,
+    pub size: RegionSize,
+    pub access: Access,
+    /// Execute Never
+    pub xn: bool,
+    pub mem_type: MemoryType,
+    /// Shareable attribute. Only meaningful for Normal memory.
+    pub shareable: bool,
+    /// Sub-region disable mask: bit n disables the n-th eighth of
+    /// the region. Only regions of 256 bytes or more have sub-regions.
+    pub subregion_disable: u8,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MpuError {
+    /// Region number not implemented in this MPU
+    InvalidRegion,
+    /// Base address is not aligned to the region size
+    Misaligned,
+    /// Sub-regions are not supported for regions smaller than 256 bytes
+    SubRegionNotSupported,
+}
+
+/// Source of an MPU fault
+#[derive(Copy, Clone, PartialEq)]
+pub enum FaultSource {
+    /// Data access (DFSR/DFAR)
+    Data,
+    /// Instruction fetch (IFSR/IFAR)
+    Instruction,
+}
+
+/// MPU fault as reported by the fault status registers
+#[derive(Copy, Clone)]
+pub struct MpuFault {
+    pub source: FaultSource,
+    /// Access was outside any enabled region (background fault)
+    pub background: bool,
+    /// Faulting access was a write (always false for instruction faults)
+    pub write: bool,
+    /// Faulting address
+    pub address: u32,
+}
+
+impl Region {
+    /// Region covering `size` bytes at `base` with the most restrictive
+    /// settings: privileged RW, execute never, strongly-ordered.
+    pub fn new(number: u32, base: u32, size: RegionSize) -> Region {
+        Region {
+            number,
+            base,
+            size,
+            access: Access::PrivRW,
+            xn: true,
+            mem_type: MemoryType::StronglyOrdered,
+            shareable: false,
+            subregion_disable: 0x0,
+        }
+    }
+
+    fn check(&self) -> Result<(), MpuError> {
+        if self.number >= regions() {
+            return Err(MpuError::InvalidRegion);
+        }
+        if self.base & self.size.align_mask() != 0 {
+            return Err(MpuError::Misaligned);
+        }
+        if self.subregion_disable != 0 && self.size < RegionSize::Size256B {
+            return Err(MpuError::SubRegionNotSupported);
+        }
+        Ok(())
+    }
+
+    fn drsr(&self) -> u32 {
+        (u32::from(self.subregion_disable) << 8) | ((self.size as u32) << 1) | DRSR_EN
+    }
+
+    fn dracr(&self) -> u32 {
+        let xn = if self.xn { DRACR_XN } else { 0x0 };
+        let s = (self.shareable as u32) << 2;
+        xn | ((self.access as u32) << 8) | s | (self.mem_type as u32)
+    }
+}
+
+#[inline(always)]
+fn read_mpuir() -> u32 {
+    let r: u32;
+    unsafe { asm!("mrc p15, #0, {}, c0, c0, #4", out(reg) r) };
+    r
+}
+
+#[inline(always)]
+fn read_sctlr() -> u32 {
+    let r: u32;
+    unsafe { asm!("mrc p15, #0, {}, c1, c0, #0", out(reg) r) };
+    r
+}
+
+#[inline(always)]
+unsafe fn write_sctlr(v: u32) {
+    asm!(
+        "dsb",
+        "mcr p15, #0, {}, c1, c0, #0",
+        "isb",
+        in(reg) v,
+    );
+}
+
+/// Select region `n`, then program base, size/enable and access control.
+/// The region is disabled while base and access are updated, so a
+/// partially configured region is never active.
+#[inline(always)]
+unsafe fn write_region(n: u32, base: u32, drsr: u32, dracr: u32) {
+    asm!(
+        "mcr p15, #0, {n}, c6, c2, #0",
+        "mov r12, #0",
+        "mcr p15, #0, r12, c6, c1, #2",
+        "mcr p15, #0, {base}, c6, c1, #0",
+        "mcr p15, #0, {dracr}, c6, c1, #4",
+        "mcr p15, #0, {drsr}, c6, c1, #2",
+        "dsb",
+        "isb",
+        n = in(reg) n,
+        base = in(reg) base,
+        drsr = in(reg) drsr,
+        dracr = in(reg) dracr,
+        out("r12") _,
+    );
+}
+
+/// Number of data regions implemented by the MPU
+pub fn regions() -> u32 {
+    (read_mpuir() >> 8) & 0xFF
+}
+
+/// Program a region. The MPU may be enabled while regions are updated,
+/// but the caller must be sure it is not changing the region that maps
+/// the code and stack in use.
+pub unsafe fn set_region(region: &Region) -> Result<(), MpuError> {
+    region.check()?;
+    write_region(region.number, region.base, region.drsr(), region.dracr());
+    Ok(())
+}
+
+/// Disable a region
+pub unsafe fn disable_region(n: u32) -> Result<(), MpuError> {
+    if n >= regions() {
+        return Err(MpuError::InvalidRegion);
+    }
+    write_region(n, 0x0, 0x0, 0x0);
+    Ok(())
+}
+
+/// Disable every implemented region
+pub unsafe fn disable_all_regions() {
+    for n in 0..regions() {
+        write_region(n, 0x0, 0x0, 0x0);
+    }
+}
+
+/// Enable the MPU, clearing the fault status (see `fault()`).
+/// When `background` is true the default memory map is used for
+/// privileged accesses that don't match any region.
+pub unsafe fn enable(background: bool) {
+    clear_fault();
+    let mut sctlr = read_sctlr() | SCTLR_M;
+    if background {
+        sctlr |= SCTLR_BR;
+    } else {
+        sctlr &= !SCTLR_BR;
+    }
+    write_sctlr(sctlr);
+}
+
+/// Disable the MPU
+pub unsafe fn disable() {
+    write_sctlr(read_sctlr() & !(SCTLR_M | SCTLR_BR));
+}
+
+pub fn is_enabled() -> bool {
+    read_sctlr() & SCTLR_M != 0
+}
+
+#[inline(always)]
+fn fault_status(fsr: u32) -> u32 {
+    ((fsr >> 6) & 0x10) | (fsr & 0xF)
+}
+
+/// Report the last MPU fault, if any.
+/// Data faults are checked first, then instruction faults.
+/// Other abort causes (ECC, external aborts) are not MPU faults
+/// and are not reported here.
+///
+/// A background read fault sets the FSR to 0: the fault status registers
+/// are set to a "no fault" value by `enable()` and `clear_fault()`, so
+/// that it can be told apart. Clear them again after handling a fault.
+/// No fault is reported before one of them has been called.
+pub fn fault() -> Option<MpuFault> {
+    if !FSR_SEEDED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let (dfsr, dfar, ifsr, ifar): (u32, u32, u32, u32);
+    unsafe {
+        asm!(
+            "mrc p15, #0, {}, c5, c0, #0",
+            "mrc p15, #0, {}, c6, c0, #0",
+            "mrc p15, #0, {}, c5, c0, #1",
+            "mrc p15, #0, {}, c6, c0, #2",
+            out(reg) dfsr,
+            out(reg) dfar,
+            out(reg) ifsr,
+            out(reg) ifar,
+        );
+    }
+    let decode = |source, fsr: u32, address| match fault_status(fsr) {
+        FS_BACKGROUND | FS_PERMISSION => Some(MpuFault {
+            source,
+            background: fault_status(fsr) == FS_BACKGROUND,
+            write: source == FaultSource::Data && fsr & FSR_WNR != 0,
+            address,
+        }),
+        _ => None,
+    };
+    if dfsr != FSR_NONE {
+        if let Some(f) = decode(FaultSource::Data, dfsr, dfar) {
+            return Some(f);
+        }
+    }
+    if ifsr != FSR_NONE {
+        return decode(FaultSource::Instruction, ifsr, ifar);
+    }
+    None
+}
+
+/// Clear data and instruction fault status registers
+pub unsafe fn clear_fault() {
+    asm!(
+        "mcr p15, #0, {0}, c5, c0, #0",
+        "mcr p15, #0, {0}, c5, c0, #1",
+        in(reg) FSR_NONE,
+    );
+    FSR_SEEDED.store(true, Ordering::Relaxed);
+}
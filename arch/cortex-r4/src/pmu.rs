@@ -0,0 +1,295 @@
+//! Implementation of the ARM performance monitor unit (PMU).
+//!
+//! The Cortex-R4 PMU provides a 32-bit cycle counter (PMCCNTR) and
+//! three 32-bit event counters, each one counting a selectable event.
+//!
+//! Reference: Cortex-R4 and Cortex-R4F Technical Reference Manual (r1p4),
+//! Chapter 6 "Events and Performance Monitor".
+
+use core::arch::asm;
+
+/// PMCR: Enable all counters
+const PMCR_E: u32 = 0x1;
+/// PMCR: Reset event counters
+const PMCR_P: u32 = 0x1 << 1;
+/// PMCR: Reset cycle counter
+const PMCR_C: u32 = 0x1 << 2;
+/// PMCR: Cycle counter divider (count every 64 cycles)
+const PMCR_D: u32 = 0x1 << 3;
+
+/// Cycle counter bit in PMCNTENSET/CLR, PMOVSR and PMINTENSET/CLR
+const CYCLE_COUNTER: u32 = 0x1 << 31;
+
+/// Number of event counters
+pub const EVENT_COUNTERS: usize = 3;
+
+/// Event counters
+#[derive(Copy, Clone, PartialEq)]
+pub enum Counter {
+    Zero = 0,
+    One = 1,
+    Two = 2,
+}
+
+/// Countable events (subset of the Cortex-R4 events list)
+#[derive(Copy, Clone, PartialEq)]
+pub enum Event {
+    /// Software increment (PMSWINC)
+    SoftwareIncrement = 0x00,
+    /// Instruction cache miss
+    ICacheMiss = 0x01,
+    /// Data cache miss
+    DCacheMiss = 0x03,
+    /// Data cache access
+    DCacheAccess = 0x04,
+    /// Data read executed
+    DataRead = 0x06,
+    /// Data write executed
+    DataWrite = 0x07,
+    /// Instruction executed
+    InstructionExecuted = 0x08,
+    /// Exception taken
+    ExceptionTaken = 0x0A,
+    /// Exception return executed
+    ExceptionReturn = 0x0B,
+    /// Software change of the PC
+    PcChange = 0x0D,
+    /// Branch mispredicted or not predicted
+    BranchMispredicted = 0x11,
+    /// Cycle count
+    Cycles = 0x12,
+    /// Predictable branches
+    BranchPredictable = 0x13,
+    /// Stall: instruction buffer can't deliver an instruction
+    StallInstructionBuffer = 0x40,
+    /// Stall: data dependency
+    StallDataDependency = 0x41,
+    /// External memory request
+    ExternalMemoryRequest = 0x43,
+    /// Cycles with FIQ disabled
+    FiqDisabledCycles = 0x46,
+    /// Cycles with IRQ disabled
+    IrqDisabledCycles = 0x47,
+    /// Instruction cache tag RAM parity/ECC error
+    ICacheTagError = 0x4A,
+    /// Instruction cache data RAM parity/ECC error
+    ICacheDataError = 0x4B,
+    /// Data cache tag RAM parity/ECC error
+    DCacheTagError = 0x4C,
+    /// Data cache data RAM parity/ECC error
+    DCacheDataError = 0x4D,
+    /// TCM fatal ECC error reported by the prefetch unit
+    TcmFatalEccPrefetch = 0x4E,
+    /// TCM fatal ECC error reported by the load/store unit
+    TcmFatalEccLoadStore = 0x4F,
+    /// Integer divide instruction executed
+    IntegerDivide = 0x53,
+    /// Double precision floating point instruction executed
+    DoublePrecisionFp = 0x5D,
+    /// Processor livelock because of hard errors or exception fetch
+    Livelock = 0x60,
+    /// ATCM multi-bit ECC error
+    AtcmMultiBitEcc = 0x61,
+    /// B0TCM multi-bit ECC error
+    B0tcmMultiBitEcc = 0x62,
+    /// B1TCM multi-bit ECC error
+    B1tcmMultiBitEcc = 0x63,
+    /// ATCM single-bit ECC error
+    AtcmSingleBitEcc = 0x64,
+    /// B0TCM single-bit ECC error
+    B0tcmSingleBitEcc = 0x65,
+    /// B1TCM single-bit ECC error
+    B1tcmSingleBitEcc = 0x66,
+    /// TCM correctable ECC error reported by the load/store unit
+    TcmCorrectableEccLoadStore = 0x67,
+    /// TCM correctable ECC error reported by the prefetch unit
+    TcmCorrectableEccPrefetch = 0x68,
+    /// TCM fatal ECC error reported by the AXI slave interface
+    TcmFatalEccAxiSlave = 0x69,
+    /// TCM correctable ECC error reported by the AXI slave interface
+    TcmCorrectableEccAxiSlave = 0x6A,
+}
+
+#[inline(always)]
+fn read_pmcr() -> u32 {
+    let r: u32;
+    unsafe { asm!("mrc p15, #0, {}, c9, c12, #0", out(reg) r) };
+    r
+}
+
+#[inline(always)]
+fn write_pmcr(v: u32) {
+    unsafe { asm!("mcr p15, #0, {}, c9, c12, #0", in(reg) v) };
+}
+
+#[inline(always)]
+fn write_cntenset(v: u32) {
+    unsafe { asm!("mcr p15, #0, {}, c9, c12, #1", in(reg) v) };
+}
+
+#[inline(always)]
+fn write_cntenclr(v: u32) {
+    unsafe { asm!("mcr p15, #0, {}, c9, c12, #2", in(reg) v) };
+}
+
+#[inline(always)]
+fn read_ovsr() -> u32 {
+    let r: u32;
+    unsafe { asm!("mrc p15, #0, {}, c9, c12, #3", out(reg) r) };
+    r
+}
+
+#[inline(always)]
+fn write_ovsr(v: u32) {
+    unsafe { asm!("mcr p15, #0, {}, c9, c12, #3", in(reg) v) };
+}
+
+#[inline(always)]
+fn select(counter: Counter) {
+    unsafe {
+        asm!(
+            "mcr p15, #0, {}, c9, c12, #5",
+            "isb",
+            in(reg) counter as u32,
+        )
+    };
+}
+
+/// Reset and enable the PMU. All counters are stopped and cleared.
+/// When `divider` is true the cycle counter increments every 64 cycles.
+pub fn init(divider: bool) {
+    write_cntenclr(CYCLE_COUNTER | 0x7);
+    write_ovsr(CYCLE_COUNTER | 0x7);
+    let d = if divider { PMCR_D } else { 0x0 };
+    write_pmcr((read_pmcr() & !PMCR_D) | PMCR_E | PMCR_P | PMCR_C | d);
+}
+
+/// Disable all counters
+pub fn disable() {
+    write_pmcr(read_pmcr() & !PMCR_E);
+}
+
+/// Number of event counters implemented
+pub fn counters() -> u32 {
+    (read_pmcr() >> 11) & 0x1F
+}
+
+/// Start/stop the cycle counter
+pub fn cycle_counter_enable(enable: bool) {
+    if enable {
+        write_cntenset(CYCLE_COUNTER);
+    } else {
+        write_cntenclr(CYCLE_COUNTER);
+    }
+}
+
+/// Set the cycle counter divider: count every 64 cycles when enabled
+pub fn cycle_counter_divider(enable: bool) {
+    if enable {
+        write_pmcr(read_pmcr() | PMCR_D);
+    } else {
+        write_pmcr(read_pmcr() & !PMCR_D);
+    }
+}
+
+/// Reset the cycle counter to zero
+pub fn cycle_counter_reset() {
+    write_pmcr(read_pmcr() | PMCR_C);
+}
+
+/// Current value of the cycle counter
+#[inline(always)]
+pub fn cycles() -> u32 {
+    let r: u32;
+    unsafe { asm!("mrc p15, #0, {}, c9, c13, #0", out(reg) r) };
+    r
+}
+
+/// Select the event counted by a given counter
+pub fn event_select(counter: Counter, event: Event) {
+    select(counter);
+    unsafe { asm!("mcr p15, #0, {}, c9, c13, #1", in(reg) event as u32) };
+}
+
+/// Start/stop an event counter
+pub fn counter_enable(counter: Counter, enable: bool) {
+    if enable {
+        write_cntenset(0x1 << (counter as u32));
+    } else {
+        write_cntenclr(0x1 << (counter as u32));
+    }
+}
+
+/// Reset all event counters to zero
+pub fn counters_reset() {
+    write_pmcr(read_pmcr() | PMCR_P);
+}
+
+/// Current value of an event counter
+pub fn count(counter: Counter) -> u32 {
+    let r: u32;
+    select(counter);
+    unsafe { asm!("mrc p15, #0, {}, c9, c13, #2", out(reg) r) };
+    r
+}
+
+/// Set the current value of an event counter
+pub fn set_count(counter: Counter, v: u32) {
+    select(counter);
+    unsafe { asm!("mcr p15, #0, {}, c9, c13, #2", in(reg) v) };
+}
+
+/// Increment the counters configured with `Event::SoftwareIncrement`
+pub fn software_increment(counter: Counter) {
+    unsafe {
+        asm!("mcr p15, #0, {}, c9, c12, #4", in(reg) 0x1 << (counter as u32))
+    };
+}
+
+/// True if the cycle counter has overflowed since last cleared
+pub fn cycle_overflow() -> bool {
+    read_ovsr() & CYCLE_COUNTER != 0
+}
+
+/// True if the event counter has overflowed since last cleared
+pub fn overflow(counter: Counter) -> bool {
+    read_ovsr() & (0x1 << (counter as u32)) != 0
+}
+
+/// Clear all overflow flags
+pub fn overflow_clear() {
+    write_ovsr(CYCLE_COUNTER | 0x7);
+}
+
+/// Enable/disable the overflow interrupt of the cycle counter
+/// and of all event counters
+pub fn overflow_interrupt(enable: bool) {
+    let mask = CYCLE_COUNTER | 0x7;
+    unsafe {
+        if enable {
+            asm!("mcr p15, #0, {}, c9, c14, #1", in(reg) mask);
+        } else {
+            asm!("mcr p15, #0, {}, c9, c14, #2", in(reg) mask);
+        }
+    }
+}
+
+/// Allow User mode to access the PMU registers
+pub unsafe fn user_access(enable: bool) {
+    asm!("mcr p15, #0, {}, c9, c14, #0", in(reg) enable as u32);
+}
+
+/// Busy-wait for at least `n` CPU cycles using the cycle counter.
+/// The cycle counter is enabled if needed and never reset, so it is
+/// safe to use while measuring something else. Wraps correctly as long
+/// as `n` is below 2^31.
+pub fn delay_cycles(n: u32) {
+    if read_pmcr() & PMCR_E == 0 {
+        init(false);
+    }
+    cycle_counter_enable(true);
+    let scale = if read_pmcr() & PMCR_D != 0 { 64 } else { 1 };
+    let ticks = n / scale + 1;
+    let start = cycles();
+    while cycles().wrapping_sub(start) < ticks {}
+}
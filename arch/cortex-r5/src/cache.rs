@@ -0,0 +1,102 @@
+//! Level one instruction and data caches.
+//!
+//! The Cortex-R5 caches are disabled out of reset and their content
+//! is undefined: they must be invalidated before being enabled.
+//! Cacheability of a region is then selected by the MPU memory type.
+//!
+//! Reference: Cortex-R5 Technical Reference Manual (r1p2),
+//! Chapter 8 "Level One Memory System" and Chapter 4.3 "System
+//! control coprocessor registers".
+
+use core::arch::asm;
+
+/// SCTLR: Data cache enable
+const SCTLR_C: u32 = 0x1 << 2;
+/// SCTLR: Instruction cache enable
+const SCTLR_I: u32 = 0x1 << 12;
+
+#[inline(always)]
+fn read_sctlr() -> u32 {
+    let r: u32;
+    unsafe { asm!("mrc p15, #0, {0}, c1, c0, #0", out(reg) r) };
+    r
+}
+
+#[inline(always)]
+unsafe fn write_sctlr(v: u32) {
+    asm!(
+        "dsb",
+        "mcr p15, #0, {0}, c1, c0, #0",
+        "isb",
+        in(reg) v,
+    );
+}
+
+/// Invalidate the whole instruction cache
+#[inline]
+pub unsafe fn icache_invalidate() {
+    asm!(
+        "mcr p15, #0, {0}, c7, c5, #0",
+        "isb",
+        in(reg) 0,
+    );
+}
+
+/// Invalidate the whole data cache. Dirty lines are lost.
+#[inline]
+pub unsafe fn dcache_invalidate() {
+    asm!(
+        "dsb",
+        "mcr p15, #0, {0}, c15, c5, #0",
+        "dsb",
+        in(reg) 0,
+    );
+}
+
+/// Clean and invalidate the whole data cache, set by set and way by way
+pub unsafe fn dcache_clean_invalidate() {
+    let ccsidr: u32;
+    // CSSELR: level 1 data cache, then read its geometry from CCSIDR
+    asm!(
+        "mcr p15, #2, {sel}, c0, c0, #0",
+        "isb",
+        "mrc p15, #1, {id}, c0, c0, #0",
+        sel = in(reg) 0,
+        id = out(reg) ccsidr,
+    );
+    let line_shift = (ccsidr & 0x7) + 4;
+    let ways = ((ccsidr >> 3) & 0x3FF) + 1;
+    let sets = ((ccsidr >> 13) & 0x7FFF) + 1;
+    let way_shift = (ways - 1).leading_zeros();
+
+    asm!("dsb");
+    for way in 0..ways {
+        for set in 0..sets {
+            let sw = (way << way_shift) | (set << line_shift);
+            asm!("mcr p15, #0, {0}, c7, c14, #2", in(reg) sw);
+        }
+    }
+    asm!("dsb");
+}
+
+/// Invalidate and enable the instruction and data caches
+pub unsafe fn enable() {
+    dcache_invalidate();
+    icache_invalidate();
+    write_sctlr(read_sctlr() | SCTLR_I | SCTLR_C);
+}
+
+/// Clean the data cache and disable both caches
+pub unsafe fn disable() {
+    write_sctlr(read_sctlr() & !(SCTLR_I | SCTLR_C));
+    dcache_clean_invalidate();
+    icache_invalidate();
+}
+
+pub fn icache_enabled() -> bool {
+    read_sctlr() & SCTLR_I == SCTLR_I
+}
+
+pub fn dcache_enabled() -> bool {
+    read_sctlr() & SCTLR_C == SCTLR_C
+}
@@ -0,0 +1,21 @@
+//! Shared implementations for ARM Cortex-R5 MCUs.
+//!
+//! The Cortex-R5 keeps the Cortex-R4 programmer's model: the common
+//! routines are re-exported from `cortexr4`, only the R5 additions
+//! (caches, larger MPU) are implemented here.
+
+#![crate_name = "cortexr5"]
+#![crate_type = "rlib"]
+#![no_std]
+
+extern crate cortexr4;
+
+pub use cortexr4::asm;
+pub use cortexr4::interrupt;
+#[cfg(target_arch = "arm")]
+pub use cortexr4::pmu;
+
+#[cfg(target_arch = "arm")]
+pub mod cache;
+#[cfg(target_arch = "arm")]
+pub mod mpu;
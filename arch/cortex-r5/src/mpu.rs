@@ -0,0 +1,14 @@
+//! Implementation of the ARM memory protection unit.
+//!
+//! The Cortex-R5 MPU has the same programming model of the Cortex-R4
+//! one, with up to 16 regions (12 or 16 depending on the silicon
+//! configuration). The number of implemented regions is read from
+//! MPUIR, see `regions()`.
+//!
+//! Reference: Cortex-R5 Technical Reference Manual (r1p2),
+//! Chapter 7 "Memory Protection Unit".
+
+pub use cortexr4::mpu::*;
+
+/// Maximum number of regions supported by the Cortex-R5 MPU
+pub const MAX_REGIONS: u32 = 16;
@@ -1,15 +1,111 @@
 extern crate cc;
 use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Supported devices: cargo feature and linker script
+const DEVICES: [(&str, &str); 9] = [
+    ("tms570ls0432", "tms570ls0432.ld"),
+    ("tms570ls0714", "tms570ls0714.ld"),
+    ("tms570ls1227", "tms570ls1227.ld"),
+    ("tms570ls2125", "tms570ls2125.ld"),
+    ("tms570ls3137", "tms570ls3137.ld"),
+    ("rm46", "rm46l852.ld"),
+    ("rm48", "rm48l952.ld"),
+    ("tms570lc4357", "tms570lc4357.ld"),
+    ("rm57", "rm57l843.ld"),
+];
+
+/// Cortex-R5F devices, all the others are Cortex-R4F
+const CORTEX_R5: [&str; 2] = ["tms570lc4357", "rm57"];
+
+/// Little-endian devices (RM parts), all the others are big-endian
+const LITTLE_ENDIAN: [&str; 3] = ["rm46", "rm48", "rm57"];
+
+/// Feature and linker script of the device selected by the cargo
+/// features. Exactly one device must be selected.
+fn device() -> (&'static str, &'static str) {
+    let selected: Vec<_> = DEVICES
+        .iter()
+        .filter(|(feature, _)| {
+            let var = format!("CARGO_FEATURE_{}", feature.to_uppercase());
+            env::var_os(var).is_some()
+        })
+        .collect();
+
+    match selected.as_slice() {
+        [device] => **device,
+        [] => panic!(
+            "no device selected: enable one of the features {:?}",
+            DEVICES.iter().map(|d| d.0).collect::<Vec<_>>()
+        ),
+        _ => panic!(
+            "more than one device selected: {:?}",
+            selected.iter().map(|d| d.0).collect::<Vec<_>>()
+        ),
+    }
+}
+
+/// Cortex-R4 errata workarounds that can be forced on or off
+const ERRATA: [&str; 2] = ["errata57", "errata66"];
 
 fn main() {
     let target = env::var("TARGET").unwrap();
+    let (device, script) = device();
+
+    for erratum in ERRATA.iter() {
+        let on = format!("CARGO_FEATURE_{}", erratum.to_uppercase());
+        let off = format!("CARGO_FEATURE_NO_{}", erratum.to_uppercase());
+        if env::var_os(on).is_some() && env::var_os(off).is_some() {
+            panic!("{} forced both on and off: enable {} or no-{}", erratum, erratum, erratum);
+        }
+    }
+
+    println!("cargo:rustc-check-cfg=cfg(vfp)");
+    println!("cargo:rustc-check-cfg=cfg(cortex_r5)");
 
-    cc::Build::new()
+    if CORTEX_R5.contains(&device) {
+        println!("cargo:rustc-cfg=cortex_r5");
+    }
+
+    // Host builds (mock register backend) have no entry code to assemble
+    if env::var("CARGO_CFG_TARGET_ARCH").unwrap() != "arm" {
+        return;
+    }
+
+    let endian = env::var("CARGO_CFG_TARGET_ENDIAN").unwrap();
+    let expected = if LITTLE_ENDIAN.contains(&device) { "little" } else { "big" };
+    if endian != expected {
+        panic!(
+            "{} is a {}-endian device, {} is {}-endian",
+            device, expected, target, endian
+        );
+    }
+
+    // The selected device script is available to the final link as
+    // `tms570.ld` (it includes bsp.ld, copied as well)
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::copy(script, out.join("tms570.ld")).unwrap();
+    fs::copy("bsp.ld", out.join("bsp.ld")).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+
+    let mut build = cc::Build::new();
+    build
+        .file("src/startup.s")
         .file("src/dabort.s")
         .file("src/cpustack.s")
-        .compile("libtms570");
+        .file("src/fault.s")
+        .file("src/svc.s")
+        .file("src/vfp.s");
+
+    if CORTEX_R5.contains(&device) {
+        build.flag("-mcpu=cortex-r5");
+    }
 
     if target.contains("eabihf") {
+        build.flag("-Wa,--defsym,VFP=1");
         println!("cargo:rustc-cfg=vfp");
     }
+
+    build.compile("libtms570");
 }
@@ -1,20 +1,37 @@
 set -euxo pipefail
 
+# Big-endian and little-endian devices
+BE_DEVICES="tms570ls0432 tms570ls0714 tms570ls1227 tms570ls2125 tms570ls3137 rm46 rm48 tms570lc4357"
+LE_DEVICES="rm57"
+
 main() {
 
     arm-none-eabi-gcc --version
 
     case $TARGET in
-        arm*v7r-none-eabi*)
-            if [ $TRAVIS_RUST_VERSION = nightly ]; then
-                cargo build --target $TARGET
-                cargo build --target $TARGET --release
-            fi
+        armebv7r-none-eabi*)
+            DEVICES=$BE_DEVICES
             ;;
-
-        x86_64-unknown-linux-gnu)
+        armv7r-none-eabi*)
+            DEVICES=$LE_DEVICES
+            ;;
+        *)
+            DEVICES="$BE_DEVICES $LE_DEVICES"
             ;;
     esac
+
+    for device in $DEVICES; do
+        case $TARGET in
+            arm*v7r-none-eabi*)
+                cargo build --target $TARGET --features $device
+                cargo build --target $TARGET --features $device --release
+                ;;
+
+            x86_64-unknown-linux-gnu)
+                cargo test --target $TARGET --features $device
+                ;;
+        esac
+    done
 }
 
 main
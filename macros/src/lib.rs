@@ -0,0 +1,294 @@
+//! Attribute macros for the TMS570 Board Support Package.
+//!
+//! - `#[interrupt(VimChannel::Name)]` registers a VIM interrupt handler.
+//! - `#[exception(Kind)]` registers an undefined instruction, prefetch
+//!   abort or data abort handler.
+//!
+//! Handlers are registered at link time, so there is no runtime
+//! registration and no function pointer casting. Registering twice the
+//! same channel (or exception) is a compile error: both registrations
+//! export the same symbol.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, AttributeArgs, FnArg, Ident, ItemFn, Lit, Meta, NestedMeta, Path,
+    ReturnType, Type,
+};
+
+/// Exceptions that can be handled with `#[exception]`
+const EXCEPTIONS: [&str; 3] = ["Undef", "PrefetchAbort", "DataAbort"];
+
+fn error(span: Span, msg: &str) -> TokenStream {
+    syn::Error::new(span, msg).to_compile_error().into()
+}
+
+/// Common signature checks: a plain, non generic, safe Rust function
+fn check_plain_fn(f: &ItemFn) -> Result<(), TokenStream> {
+    let sig = &f.sig;
+    if sig.constness.is_some()
+        || sig.asyncness.is_some()
+        || sig.unsafety.is_some()
+        || sig.abi.is_some()
+        || sig.variadic.is_some()
+        || !sig.generics.params.is_empty()
+        || sig.generics.where_clause.is_some()
+    {
+        return Err(error(
+            sig.span(),
+            "handler must be a plain `fn`: no const, async, unsafe, extern or generics",
+        ));
+    }
+    let output_ok = match &sig.output {
+        ReturnType::Default => true,
+        ReturnType::Type(_, ty) => match **ty {
+            Type::Tuple(ref t) => t.elems.is_empty(),
+            Type::Never(_) => true,
+            _ => false,
+        },
+    };
+    if !output_ok {
+        return Err(error(sig.output.span(), "handler must return `()` or `!`"));
+    }
+    Ok(())
+}
+
+/// Single path argument, e.g. `VimChannel::Rti0` or `Undef`
+fn single_path(args: AttributeArgs, usage: &str) -> Result<Path, TokenStream> {
+    let mut iter = args.into_iter();
+    match (iter.next(), iter.next()) {
+        (Some(NestedMeta::Meta(Meta::Path(p))), None) => Ok(p),
+        (Some(other), _) => Err(error(other.span(), usage)),
+        (None, _) => Err(error(Span::call_site(), usage)),
+    }
+}
+
+/// VFP context handling of an interrupt handler
+enum Fpu {
+    /// Handler doesn't use the VFP: nothing saved
+    None,
+    /// FPSCR and d0-d15 always saved and restored
+    Eager,
+    /// Context saved on first VFP instruction (FPEXC.EN trap)
+    Lazy,
+}
+
+/// `#[interrupt]` arguments: channel path and optional `fpu = "..."`
+fn interrupt_args(args: AttributeArgs) -> Result<(Path, Fpu), TokenStream> {
+    let usage = "expected `#[interrupt(VimChannel::Name)]` or \
+                 `#[interrupt(VimChannel::Name, fpu = \"eager\" | \"lazy\")]`";
+    let mut iter = args.into_iter();
+    let channel = match iter.next() {
+        Some(NestedMeta::Meta(Meta::Path(p))) => p,
+        Some(other) => return Err(error(other.span(), usage)),
+        None => return Err(error(Span::call_site(), usage)),
+    };
+    let fpu = match iter.next() {
+        None => Fpu::None,
+        Some(NestedMeta::Meta(Meta::NameValue(nv))) if nv.path.is_ident("fpu") => match nv.lit {
+            Lit::Str(ref s) if s.value() == "eager" => Fpu::Eager,
+            Lit::Str(ref s) if s.value() == "lazy" => Fpu::Lazy,
+            ref lit => return Err(error(lit.span(), "`fpu` must be \"eager\" or \"lazy\"")),
+        },
+        Some(other) => return Err(error(other.span(), usage)),
+    };
+    if let Some(other) = iter.next() {
+        return Err(error(other.span(), usage));
+    }
+    Ok((channel, fpu))
+}
+
+/// Register a function as the handler of a VIM channel.
+///
+/// ```ignore
+/// use tms570::vim::VimChannel;
+///
+/// #[interrupt(VimChannel::Rti0)]
+/// fn tick() {
+///     // ...
+/// }
+/// ```
+///
+/// The handler must be `fn()` (or `fn() -> !`). An IRQ entry trampoline
+/// is generated for each handler, so the address stored in VIM RAM is a
+/// real exception entry point (hardware vectored mode). The same
+/// trampoline works for channels mapped to FIQ.
+///
+/// Handlers using floating point must preserve the VFP context of the
+/// interrupted code (requires the `vfp` cfg, i.e. an `eabihf` target):
+///
+/// - `#[interrupt(VimChannel::Rti0, fpu = "eager")]` always saves and
+///   restores FPSCR and d0-d15.
+/// - `#[interrupt(VimChannel::Rti0, fpu = "lazy")]` disables the VFP
+///   (FPEXC.EN) while the handler runs and saves the context only if a
+///   VFP instruction traps. Relies on the default `undef_entry`.
+#[proc_macro_attribute]
+pub fn interrupt(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let f = parse_macro_input!(input as ItemFn);
+
+    let (channel, fpu) = match interrupt_args(args) {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if let Err(e) = check_plain_fn(&f) {
+        return e;
+    }
+    if !f.sig.inputs.is_empty() {
+        return error(f.sig.inputs.span(), "interrupt handlers take no arguments");
+    }
+
+    let variant = &channel.segments.last().unwrap().ident;
+    let ident = &f.sig.ident;
+    let isr = format_ident!("__tms570_isr_{}", variant);
+    let irq = format_ident!("__tms570_irq_{}", variant);
+    let entry = format_ident!("__TMS570_VIM_ENTRY_{}", variant);
+    let entry_symbol = format!("__tms570_vim_channel_{}", variant);
+    let trampoline = match fpu {
+        Fpu::None => irq_trampoline(&irq, &isr),
+        Fpu::Eager => irq_vfp_trampoline(&irq, &isr, "tms570_vfp_eager"),
+        Fpu::Lazy => irq_vfp_trampoline(&irq, &isr, "tms570_vfp_lazy"),
+    };
+
+    quote!(
+        #f
+
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        #[no_mangle]
+        pub unsafe extern "C" fn #isr() {
+            #ident()
+        }
+
+        ::core::arch::global_asm!(#trampoline);
+
+        #[doc(hidden)]
+        #[allow(non_upper_case_globals)]
+        #[used]
+        #[link_section = ".vim_isrs"]
+        #[export_name = #entry_symbol]
+        static #entry: tms570::vim::VimEntry = {
+            #[allow(non_snake_case)]
+            extern "C" {
+                fn #irq();
+            }
+            tms570::vim::VimEntry {
+                channel: #channel,
+                isr: #irq,
+            }
+        };
+    )
+    .into()
+}
+
+/// IRQ/FIQ entry: save the caller-saved registers, call the handler and
+/// return from the exception restoring CPSR.
+/// Braces are doubled: the string is a `global_asm!` template.
+fn irq_trampoline(irq: &Ident, isr: &Ident) -> String {
+    format!(
+        r#"
+    .section .text.{irq},"ax",%progbits
+    .arm
+    .align 2
+    .global {irq}
+    .type {irq}, %function
+{irq}:
+    stmfd sp!, {{{{r0 - r3, r12, lr}}}}
+    bl    {isr}
+    ldmfd sp!, {{{{r0 - r3, r12, lr}}}}
+    subs  pc, lr, #4
+"#,
+        irq = irq,
+        isr = isr
+    )
+}
+
+/// Same as `irq_trampoline`, calling the handler through one of the VFP
+/// context wrappers (see vfp.s) with the handler address in r0.
+fn irq_vfp_trampoline(irq: &Ident, isr: &Ident, wrapper: &str) -> String {
+    format!(
+        r#"
+    .section .text.{irq},"ax",%progbits
+    .arm
+    .align 2
+    .global {irq}
+    .type {irq}, %function
+{irq}:
+    stmfd sp!, {{{{r0 - r3, r12, lr}}}}
+    ldr   r0, ={isr}
+    bl    {wrapper}
+    ldmfd sp!, {{{{r0 - r3, r12, lr}}}}
+    subs  pc, lr, #4
+    .ltorg
+"#,
+        irq = irq,
+        isr = isr,
+        wrapper = wrapper
+    )
+}
+
+/// Register a function as handler of a CPU exception.
+///
+/// ```ignore
+/// use tms570::fault::FaultReport;
+///
+/// #[exception(DataAbort)]
+/// fn on_data_abort(report: &FaultReport) {
+///     // ...
+/// }
+/// ```
+///
+/// Supported exceptions are `Undef`, `PrefetchAbort` and `DataAbort`.
+/// The handler receives the decoded fault report; the CPU stops when
+/// the handler returns.
+#[proc_macro_attribute]
+pub fn exception(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let f = parse_macro_input!(input as ItemFn);
+
+    let usage = "expected `#[exception(Undef | PrefetchAbort | DataAbort)]`";
+    let kind = match single_path(args, usage) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+    let kind = match kind.get_ident() {
+        Some(i) if EXCEPTIONS.iter().any(|e| i == e) => i.clone(),
+        _ => return error(kind.span(), usage),
+    };
+    if let Err(e) = check_plain_fn(&f) {
+        return e;
+    }
+    let one_ref_arg = f.sig.inputs.len() == 1
+        && match f.sig.inputs.first() {
+            Some(FnArg::Typed(arg)) => match *arg.ty {
+                Type::Reference(ref r) => r.mutability.is_none(),
+                _ => false,
+            },
+            _ => false,
+        };
+    if !one_ref_arg {
+        return error(
+            f.sig.inputs.span(),
+            "exception handlers take a single `&FaultReport` argument",
+        );
+    }
+
+    let ident = &f.sig.ident;
+    let handler = format_ident!("__tms570_exception_{}", kind);
+
+    quote!(
+        #f
+
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        #[no_mangle]
+        pub fn #handler(report: &tms570::fault::FaultReport) {
+            #ident(report)
+        }
+    )
+    .into()
+}
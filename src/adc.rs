@@ -1,122 +1,223 @@
-///!
-///! Analog To Digital Converter (ADC) Module
-///!
-///! The microcontroller includes two 12-bit ADC modules.
-///! The main features of each of the ADC modules are:
-///! - Selectable 10-bit or 12-bit resolution
-///! - Successive-approximation-register architecture
-///! - Three conversion groups – Group1, Group2 and Event Group
-///! - All three conversion groups can be configured to be
-///!   hardware-triggered; group1 and group2 can also be triggered by software
-///! - Conversion results are stored in a 64-word memory (SRAM)
-///!   These 64 words are divided between the three conversion groups and are
-///!   configurable by software
-///!   Accesses to the conversion result RAM are protected by parity
-///! - Flexible options for generating DMA requests for transferring conversion
-///!   results
-///! - Multichannel conversions performed in ascending order, one channel at a time
-///! - Single or continuous conversion modes
-///! - Embedded self-test logic for input channel failure mode (open / short)
-///!   detection
-///! - Embedded calibration logic for offset error correction
-///! - Enhanced Power-down mode
-///! - External event pin (ADEVT) to trigger conversions
-///!   ADEVT is also programmable as general-purpose I/O
-///! - Eight hardware events to trigger conversions
+//!
+//! Analog To Digital Converter (ADC) Module
+//!
+//! The microcontroller includes two 12-bit ADC modules.
+//! The main features of each of the ADC modules are:
+//! - Selectable 10-bit or 12-bit resolution
+//! - Successive-approximation-register architecture
+//! - Three conversion groups – Group1, Group2 and Event Group
+//! - All three conversion groups can be configured to be
+//!   hardware-triggered; group1 and group2 can also be triggered by software
+//! - Conversion results are stored in a 64-word memory (SRAM)
+//!   These 64 words are divided between the three conversion groups and are
+//!   configurable by software
+//!   Accesses to the conversion result RAM are protected by parity
+//! - Flexible options for generating DMA requests for transferring conversion
+//!   results
+//! - Multichannel conversions performed in ascending order, one channel at a time
+//! - Single or continuous conversion modes
+//! - Embedded self-test logic for input channel failure mode (open / short)
+//!   detection
+//! - Embedded calibration logic for offset error correction
+//! - Enhanced Power-down mode
+//! - External event pin (ADEVT) to trigger conversions
+//!   ADEVT is also programmable as general-purpose I/O
+//! - Eight hardware events to trigger conversions
 use core::cmp::min;
-use vcell::VolatileCell;
+use crate::clock::Clocks;
+use crate::peripherals::{sealed::Sealed, ADC1};
+#[cfg(not(feature = "tms570ls0432"))]
+use crate::peripherals::ADC2;
+use crate::regs::{map, Reg};
+
+register_bitfields! {
+    /// Reset Control
+    RSTCR [
+        /// Module held in reset
+        RESET OFFSET(0) NUMBITS(1) []
+    ],
+    /// Operating Mode Control
+    OPMODECR [
+        /// ADC core resolution
+        RESOLUTION OFFSET(31) NUMBITS(1) [
+            Bit10 = 0,
+            Bit12 = 1
+        ],
+        /// Continue conversion in suspend mode
+        COS OFFSET(24) NUMBITS(1) [],
+        /// ADC enable
+        ADC_EN OFFSET(0) NUMBITS(1) []
+    ],
+    /// Clock Control
+    CLOCKCR [
+        /// ADCLK prescaler (PS + 1)
+        PS OFFSET(0) NUMBITS(5) []
+    ],
+    /// Calibration Control
+    CALCR [
+        /// Self-test mode
+        SELF_TEST OFFSET(24) NUMBITS(1) [],
+        /// Start calibration conversion
+        CAL_ST OFFSET(16) NUMBITS(1) [],
+        /// Calibration bridge enable
+        BRIDGE_EN OFFSET(9) NUMBITS(1) [],
+        /// Calibration reference high (1) or low (0)
+        HILO OFFSET(8) NUMBITS(1) [],
+        /// Calibration enable
+        CAL_EN OFFSET(0) NUMBITS(1) []
+    ],
+    /// Group Mode Control
+    GxMODECR [
+        /// Conversion result format
+        DATA_FMT OFFSET(8) NUMBITS(2) [
+            Bit12 = 0,
+            Bit10 = 1,
+            Bit8 = 2
+        ],
+        /// Channel id in conversion results
+        CHID OFFSET(5) NUMBITS(1) [],
+        /// Continuous conversion
+        MODE OFFSET(1) NUMBITS(1) []
+    ],
+    /// Group Interrupt Flag
+    GxINTFLG [
+        /// Group conversion end
+        END OFFSET(3) NUMBITS(1) [],
+        /// Group memory empty
+        MEM_EMPTY OFFSET(2) NUMBITS(1) [],
+        /// Group memory overrun
+        MEM_OVERRUN OFFSET(1) NUMBITS(1) [],
+        /// Group threshold counter reached zero
+        THR_INT_FLG OFFSET(0) NUMBITS(1) []
+    ],
+    /// Buffer Boundary Control
+    BNDCR [
+        /// Event group buffer end (in pairs of words)
+        BNDA OFFSET(16) NUMBITS(9) [],
+        /// Group 1 buffer end (in pairs of words)
+        BNDB OFFSET(0) NUMBITS(9) []
+    ],
+    /// Buffer Boundary End
+    BNDEND [
+        /// Result memory initialization in progress
+        BUF_INIT_ACTIVE OFFSET(16) NUMBITS(1) [],
+        /// Result memory size
+        BNDEND OFFSET(0) NUMBITS(3) [
+            Words16 = 0,
+            Words32 = 1,
+            Words64 = 2
+        ]
+    ],
+    /// Group FIFO Reset Control
+    GxFIFORESETCR [
+        FIFO_RESET OFFSET(0) NUMBITS(1) []
+    ],
+    /// Parity Control
+    PARCR [
+        /// Parity test mode
+        TEST OFFSET(8) NUMBITS(1) [],
+        /// Parity enable key
+        PARITY_ENA OFFSET(0) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ]
+    ]
+}
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct GxBUF {
-    BUF0: VolatileCell<u32>,
-    BUF1: VolatileCell<u32>,
-    BUF2: VolatileCell<u32>,
-    BUF3: VolatileCell<u32>,
-    BUF4: VolatileCell<u32>,
-    BUF5: VolatileCell<u32>,
-    BUF6: VolatileCell<u32>,
-    BUF7: VolatileCell<u32>,
+    BUF0: Reg,
+    BUF1: Reg,
+    BUF2: Reg,
+    BUF3: Reg,
+    BUF4: Reg,
+    BUF5: Reg,
+    BUF6: Reg,
+    BUF7: Reg,
 }
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct AdcRegisters {
-    RSTCR: VolatileCell<u32>,              // 0x0000: Reset control
-    OPMODECR: VolatileCell<u32>,           // 0x0004: Operating mode control
-    CLOCKCR: VolatileCell<u32>,            // 0x0008: Clock control
-    CALCR: VolatileCell<u32>,              // 0x000C: Calibration control
-    GxMODECR: [VolatileCell<u32>; 3],      // 0x0010,0x0014,0x0018: Group 0-2 mode control
-    EVSRC: VolatileCell<u32>,              // 0x001C: Group 0 trigger source control
-    G1SRC: VolatileCell<u32>,              // 0x0020: Group 1 trigger source control
-    G2SRC: VolatileCell<u32>,              // 0x0024: Group 2 trigger source control
-    GxINTENA: [VolatileCell<u32>; 3],      // 0x0028,0x002C,0x0030: Group 0-2 interrupt enable
-    GxINTFLG: [VolatileCell<u32>; 3],      // 0x0034,0x0038,0x003C: Group 0-2 interrupt flag
-    GxINTCR: [VolatileCell<u32>; 3],       // 0x0040-0x0048: Group 0-2 interrupt threshold
-    EVDMACR: VolatileCell<u32>,            // 0x004C: Group 0 DMA control
-    G1DMACR: VolatileCell<u32>,            // 0x0050: Group 1 DMA control
-    G2DMACR: VolatileCell<u32>,            // 0x0054: Group 2 DMA control
-    BNDCR: VolatileCell<u32>,              // 0x0058: Buffer boundary control
-    BNDEND: VolatileCell<u32>,             // 0x005C: Buffer boundary end
-    EVSAMP: VolatileCell<u32>,             // 0x0060: Group 0 sample window
-    G1SAMP: VolatileCell<u32>,             // 0x0064: Group 1 sample window
-    G2SAMP: VolatileCell<u32>,             // 0x0068: Group 2 sample window
-    EVSR: VolatileCell<u32>,               // 0x006C: Group 0 status
-    G1SR: VolatileCell<u32>,               // 0x0070: Group 1 status
-    G2SR: VolatileCell<u32>,               // 0x0074: Group 2 status
-    GxSEL: [VolatileCell<u32>; 3],         // 0x0078-0x007C: Group 0-2 channel select
-    CALR: VolatileCell<u32>,               // 0x0084: Calibration
-    SMSTATE: VolatileCell<u32>,            // 0x0088: State machine state
-    LASTCONV: VolatileCell<u32>,           // 0x008C: Last conversion
-    buff: [GxBUF; 3],                      // 0x0090 ..0x00EC: Group x-y result buffer
-    EVEMUBUFFER: VolatileCell<u32>,        // 0x00F0: Group 0 emulation result buffer
-    G1EMUBUFFER: VolatileCell<u32>,        // 0x00F4: Group 1 emulation result buffer
-    G2EMUBUFFER: VolatileCell<u32>,        // 0x00F8: Group 2 emulation result buffer
-    EVTDIR: VolatileCell<u32>,             // 0x00FC: Event pin direction
-    EVTOUT: VolatileCell<u32>,             // 0x0100: Event pin digital output
-    EVTIN: VolatileCell<u32>,              // 0x0104: Event pin digital input
-    EVTSET: VolatileCell<u32>,             // 0x0108: Event pin set
-    EVTCLR: VolatileCell<u32>,             // 0x010C: Event pin clear
-    EVTPDR: VolatileCell<u32>,             // 0x0110: Event pin open drain
-    EVTDIS: VolatileCell<u32>,             // 0x0114: Event pin pull disable
-    EVTPSEL: VolatileCell<u32>,            // 0x0118: Event pin pull select
-    EVSAMPDISEN: VolatileCell<u32>,        // 0x011C: Group 0 sample discharge
-    G1SAMPDISEN: VolatileCell<u32>,        // 0x0120: Group 1 sample discharge
-    G2SAMPDISEN: VolatileCell<u32>,        // 0x0124: Group 2 sample discharge
-    MAGINTCR1: VolatileCell<u32>,          // 0x0128: Magnitude interrupt control 1
-    MAGINT1MASK: VolatileCell<u32>,        // 0x012C: Magnitude interrupt mask 1
-    MAGINTCR2: VolatileCell<u32>,          // 0x0130: Magnitude interrupt control 2
-    MAGINT2MASK: VolatileCell<u32>,        // 0x0134: Magnitude interrupt mask 2
-    MAGINTCR3: VolatileCell<u32>,          // 0x0138: Magnitude interrupt control 3
-    MAGINT3MASK: VolatileCell<u32>,        // 0x013C: Magnitude interrupt mask 3
-    _reserved1: VolatileCell<u32>,         // 0x0140: Reserved
-    _reserved2: VolatileCell<u32>,         // 0x0144: Reserved
-    _reserved3: VolatileCell<u32>,         // 0x0148: Reserved
-    _reserved4: VolatileCell<u32>,         // 0x014C: Reserved
-    _reserved5: VolatileCell<u32>,         // 0x0150: Reserved
-    _reserved6: VolatileCell<u32>,         // 0x0154: Reserved
-    MAGTHRINTENASET: VolatileCell<u32>,    // 0x0158: Magnitude interrupt set
-    MAGTHRINTENACLR: VolatileCell<u32>,    // 0x015C: Magnitude interrupt clear
-    MAGTHRINTFLG: VolatileCell<u32>,       // 0x0160: Magnitude interrupt flag
-    MAGTHRINTOFFSET: VolatileCell<u32>,    // 0x0164: Magnitude interrupt offset
-    GxFIFORESETCR: [VolatileCell<u32>; 3], // 0x0168,0x016C,0x0170: Group 0-2 fifo reset
-    EVRAMADDR: VolatileCell<u32>,          // 0x0174: Group 0 RAM pointer
-    G1RAMADDR: VolatileCell<u32>,          // 0x0178: Group 1 RAM pointer
-    G2RAMADDR: VolatileCell<u32>,          // 0x017C: Group 2 RAM pointer
-    PARCR: VolatileCell<u32>,              // 0x0180: Parity control
-    PARADDR: VolatileCell<u32>,            // 0x0184: Parity error address
-    PWRUPDLYCTRL: VolatileCell<u32>,       // 0x0188: Power-Up delay control
-    _reserved7: VolatileCell<u32>,         // 0x018C: Reserved
-    ADEVCHNSELMODECTRL: VolatileCell<u32>, // 0x0190: Event Group Channel Selection Mode Control
-    ADG1CHNSELMODECTRL: VolatileCell<u32>, // 0x0194: Group1 Channel Selection Mode Control
-    ADG2CHNSELMODECTRL: VolatileCell<u32>, // 0x0198: Group2 Channel Selection Mode Control
-    ADEVCURRCOUNT: VolatileCell<u32>,      // 0x019C: Event Group Current Count
-    ADEVMAXCOUNT: VolatileCell<u32>,       // 0x01A0: Event Group Max Count
-    ADG1CURRCOUNT: VolatileCell<u32>,      // 0x01A4: Group1 Current Count
-    ADG1MAXCOUNT: VolatileCell<u32>,       // 0x01A8: Group1 Max Count
-    ADG2CURRCOUNT: VolatileCell<u32>,      // 0x01AC: Group2 Current Count
-    ADG2MAXCOUNT: VolatileCell<u32>,       // 0x01B0: Group2 Max Count
+    RSTCR: Reg<u32, RSTCR::Register>,                      // 0x0000: Reset control
+    OPMODECR: Reg<u32, OPMODECR::Register>,                // 0x0004: Operating mode control
+    CLOCKCR: Reg<u32, CLOCKCR::Register>,                  // 0x0008: Clock control
+    CALCR: Reg<u32, CALCR::Register>,                      // 0x000C: Calibration control
+    GxMODECR: [Reg<u32, GxMODECR::Register>; 3],           // 0x0010,0x0014,0x0018: Group 0-2 mode control
+    EVSRC: Reg,                                            // 0x001C: Group 0 trigger source control
+    G1SRC: Reg,                                            // 0x0020: Group 1 trigger source control
+    G2SRC: Reg,                                            // 0x0024: Group 2 trigger source control
+    GxINTENA: [Reg; 3],                                    // 0x0028,0x002C,0x0030: Group 0-2 interrupt enable
+    GxINTFLG: [Reg<u32, GxINTFLG::Register>; 3],           // 0x0034,0x0038,0x003C: Group 0-2 interrupt flag
+    GxINTCR: [Reg; 3],                                     // 0x0040-0x0048: Group 0-2 interrupt threshold
+    EVDMACR: Reg,                                          // 0x004C: Group 0 DMA control
+    G1DMACR: Reg,                                          // 0x0050: Group 1 DMA control
+    G2DMACR: Reg,                                          // 0x0054: Group 2 DMA control
+    BNDCR: Reg<u32, BNDCR::Register>,                      // 0x0058: Buffer boundary control
+    BNDEND: Reg<u32, BNDEND::Register>,                    // 0x005C: Buffer boundary end
+    EVSAMP: Reg,                                           // 0x0060: Group 0 sample window
+    G1SAMP: Reg,                                           // 0x0064: Group 1 sample window
+    G2SAMP: Reg,                                           // 0x0068: Group 2 sample window
+    EVSR: Reg,                                             // 0x006C: Group 0 status
+    G1SR: Reg,                                             // 0x0070: Group 1 status
+    G2SR: Reg,                                             // 0x0074: Group 2 status
+    GxSEL: [Reg; 3],                                       // 0x0078-0x007C: Group 0-2 channel select
+    CALR: Reg,                                             // 0x0084: Calibration
+    SMSTATE: Reg,                                          // 0x0088: State machine state
+    LASTCONV: Reg,                                         // 0x008C: Last conversion
+    buff: [GxBUF; 3],                                      // 0x0090 ..0x00EC: Group x-y result buffer
+    EVEMUBUFFER: Reg,                                      // 0x00F0: Group 0 emulation result buffer
+    G1EMUBUFFER: Reg,                                      // 0x00F4: Group 1 emulation result buffer
+    G2EMUBUFFER: Reg,                                      // 0x00F8: Group 2 emulation result buffer
+    EVTDIR: Reg,                                           // 0x00FC: Event pin direction
+    EVTOUT: Reg,                                           // 0x0100: Event pin digital output
+    EVTIN: Reg,                                            // 0x0104: Event pin digital input
+    EVTSET: Reg,                                           // 0x0108: Event pin set
+    EVTCLR: Reg,                                           // 0x010C: Event pin clear
+    EVTPDR: Reg,                                           // 0x0110: Event pin open drain
+    EVTDIS: Reg,                                           // 0x0114: Event pin pull disable
+    EVTPSEL: Reg,                                          // 0x0118: Event pin pull select
+    EVSAMPDISEN: Reg,                                      // 0x011C: Group 0 sample discharge
+    G1SAMPDISEN: Reg,                                      // 0x0120: Group 1 sample discharge
+    G2SAMPDISEN: Reg,                                      // 0x0124: Group 2 sample discharge
+    MAGINTCR1: Reg,                                        // 0x0128: Magnitude interrupt control 1
+    MAGINT1MASK: Reg,                                      // 0x012C: Magnitude interrupt mask 1
+    MAGINTCR2: Reg,                                        // 0x0130: Magnitude interrupt control 2
+    MAGINT2MASK: Reg,                                      // 0x0134: Magnitude interrupt mask 2
+    MAGINTCR3: Reg,                                        // 0x0138: Magnitude interrupt control 3
+    MAGINT3MASK: Reg,                                      // 0x013C: Magnitude interrupt mask 3
+    _reserved1: Reg,                                       // 0x0140: Reserved
+    _reserved2: Reg,                                       // 0x0144: Reserved
+    _reserved3: Reg,                                       // 0x0148: Reserved
+    _reserved4: Reg,                                       // 0x014C: Reserved
+    _reserved5: Reg,                                       // 0x0150: Reserved
+    _reserved6: Reg,                                       // 0x0154: Reserved
+    MAGTHRINTENASET: Reg,                                  // 0x0158: Magnitude interrupt set
+    MAGTHRINTENACLR: Reg,                                  // 0x015C: Magnitude interrupt clear
+    MAGTHRINTFLG: Reg,                                     // 0x0160: Magnitude interrupt flag
+    MAGTHRINTOFFSET: Reg,                                  // 0x0164: Magnitude interrupt offset
+    GxFIFORESETCR: [Reg<u32, GxFIFORESETCR::Register>; 3], // 0x0168,0x016C,0x0170: Group 0-2 fifo reset
+    EVRAMADDR: Reg,                                        // 0x0174: Group 0 RAM pointer
+    G1RAMADDR: Reg,                                        // 0x0178: Group 1 RAM pointer
+    G2RAMADDR: Reg,                                        // 0x017C: Group 2 RAM pointer
+    PARCR: Reg<u32, PARCR::Register>,                      // 0x0180: Parity control
+    PARADDR: Reg,                                          // 0x0184: Parity error address
+    PWRUPDLYCTRL: Reg,                                     // 0x0188: Power-Up delay control
+    _reserved7: Reg,                                       // 0x018C: Reserved
+    ADEVCHNSELMODECTRL: Reg,                               // 0x0190: Event Group Channel Selection Mode Control
+    ADG1CHNSELMODECTRL: Reg,                               // 0x0194: Group1 Channel Selection Mode Control
+    ADG2CHNSELMODECTRL: Reg,                               // 0x0198: Group2 Channel Selection Mode Control
+    ADEVCURRCOUNT: Reg,                                    // 0x019C: Event Group Current Count
+    ADEVMAXCOUNT: Reg,                                     // 0x01A0: Event Group Max Count
+    ADG1CURRCOUNT: Reg,                                    // 0x01A4: Group1 Current Count
+    ADG1MAXCOUNT: Reg,                                     // 0x01A8: Group1 Max Count
+    ADG2CURRCOUNT: Reg,                                    // 0x01AC: Group2 Current Count
+    ADG2MAXCOUNT: Reg,                                     // 0x01B0: Group2 Max Count
 }
+/// Maximum ADCLK (VCLK / (PS + 1)) set by `Adc::new()` [Hz]
+const ADCLK_MAX: u32 = MHz!(10);
+
 const ADC1_BASE_ADDR: *const AdcRegisters = 0xFFF7_C000 as *const AdcRegisters;
 const ADC2_BASE_ADDR: *const AdcRegisters = 0xFFF7_C200 as *const AdcRegisters;
 
@@ -153,6 +254,20 @@ pub enum AdcID {
     Two = 1,
 }
 
+/// ADC module instance: `ADC1` or `ADC2`
+pub trait Instance: Sealed {
+    const ID: AdcID;
+}
+
+impl Instance for ADC1 {
+    const ID: AdcID = AdcID::One;
+}
+
+#[cfg(not(feature = "tms570ls0432"))]
+impl Instance for ADC2 {
+    const ID: AdcID = AdcID::Two;
+}
+
 #[derive(Copy, Clone)]
 pub enum AdcGroup {
     /// ADC event group
@@ -198,33 +313,34 @@ pub struct Adc {
 }
 
 impl Adc {
-    pub fn new(
-        id: AdcID,
+    pub fn new<I: Instance>(
+        _adc: I,
+        clocks: &Clocks,
         fifo_size: u8,
         event_buff_size: u8,
         group_buff_size: u8,
         res: AdcCoreResolution,
     ) -> Adc {
         assert!(event_buff_size <= group_buff_size);
+        let id = I::ID;
         let adc = Adc {
             id,
             fifo_size,
             format: ReadDataFormat::Bit12,
-            regs: unsafe { &*ADC_BASE_ADDR[id as usize] },
+            regs: unsafe { map(ADC_BASE_ADDR[id as usize]) },
             ram: unsafe { &*ADC_RAM_ADDR[id as usize] },
             pram: unsafe { &*ADC_PRAM_ADDR[id as usize] },
             lut: unsafe { &*ADC_LUT_ADDR[id as usize] },
         };
 
         // Reset ADC
-        adc.regs.RSTCR.set(0x1);
-        adc.regs.RSTCR.set(0x0);
-        let cres = (res as u32) << 31;
-        adc.regs.OPMODECR.set(adc.regs.OPMODECR.get() | cres);
-        adc.regs.CLOCKCR.set(0x7);
-        let bndcr = (u32::from(event_buff_size) << 16) | u32::from(group_buff_size);
-        adc.regs.BNDCR.set(bndcr);
-        adc.regs.BNDEND.set(adc.regs.BNDEND.get() & 0xFFFF0002);
+        adc.regs.RSTCR.write(RSTCR::RESET::SET);
+        adc.regs.RSTCR.write(RSTCR::RESET::CLEAR);
+        adc.regs.OPMODECR.modify(OPMODECR::RESOLUTION.val(res as u32));
+        adc.regs.CLOCKCR.write(CLOCKCR::PS.val(clocks.vclk1().div_ceil(ADCLK_MAX).max(1) - 1));
+        adc.regs.BNDCR.write(BNDCR::BNDA.val(u32::from(event_buff_size))
+                             + BNDCR::BNDB.val(u32::from(group_buff_size)));
+        adc.regs.BNDEND.modify(BNDEND::BNDEND::Words64);
 
         //TODO: move outside
         adc.regs.G1SAMP.set(1);
@@ -239,36 +355,41 @@ impl Adc {
         // Note: 0101b disable parity. It is recommended to write 1010b to enable parity, to
         // guard against soft error from flipping this field to a disabled state.
         if enable {
-            self.regs.PARCR.set(0b1010);
+            self.regs.PARCR.write(PARCR::PARITY_ENA::Enable);
         } else {
-            self.regs.PARCR.set(0b0101);
+            self.regs.PARCR.write(PARCR::PARITY_ENA::Disable);
         }
     }
 
     pub fn group_resolution(&mut self, grp: AdcGroup, dformat: ReadDataFormat) {
         self.format = dformat;
         // Always add channel id in conversion result
-        self.regs.GxMODECR[grp as usize].set((dformat as u32) | 0x20);
+        let fmt = match dformat {
+            ReadDataFormat::Bit12 => GxMODECR::DATA_FMT::Bit12,
+            ReadDataFormat::Bit10 => GxMODECR::DATA_FMT::Bit10,
+            ReadDataFormat::Bit8 => GxMODECR::DATA_FMT::Bit8,
+        };
+        self.regs.GxMODECR[grp as usize].write(fmt + GxMODECR::CHID::SET);
     }
 
     pub fn activate(&self) {
-        self.regs.OPMODECR.set(self.regs.OPMODECR.get() | 0x0000_0001);
+        self.regs.OPMODECR.modify(OPMODECR::ADC_EN::SET);
         // Wait for buffer initialization complete
-        wait_until_not_zero!(self.regs.BNDEND.get(), 0xFFFF_0000);
+        wait_until_true!(self.regs.BNDEND.is_set(BNDEND::BUF_INIT_ACTIVE));
     }
 
     pub fn done(&self, group: AdcGroup) -> bool {
-        self.regs.GxINTFLG[group as usize].get() & ConvEvent::ConversionEnd as u32 != 0
+        self.regs.GxINTFLG[group as usize].is_set(GxINTFLG::END)
     }
 
     pub fn fifo_full(&self, group: AdcGroup) -> bool {
-        let is_full = ConvEvent::Threshold as u32 | ConvEvent::ConversionOverrun as u32;
-        self.regs.GxINTFLG[group as usize].get() & is_full != 0
+        let flags = self.regs.GxINTFLG[group as usize].extract();
+        flags.is_set(GxINTFLG::THR_INT_FLG) || flags.is_set(GxINTFLG::MEM_OVERRUN)
     }
 
     /// Resets ADC FiFo
     pub fn fifo_clean(&self, group: AdcGroup) {
-        self.regs.GxFIFORESETCR[group as usize].set(0x1)
+        self.regs.GxFIFORESETCR[group as usize].write(GxFIFORESETCR::FIFO_RESET::SET)
     }
 
     /// A discharge period for the sampling capacitor is
@@ -321,7 +442,7 @@ impl Adc {
             self.unpack(raw, s);
         }
         // clear "G1 END" and "THR INT FLG"
-        self.regs.GxINTFLG[group as usize].set(0x9);
+        self.regs.GxINTFLG[group as usize].write(GxINTFLG::END::SET + GxINTFLG::THR_INT_FLG::SET);
         size
     }
 
@@ -343,7 +464,7 @@ impl Adc {
 
     /// Resets the FiFo read and write pointers
     pub fn reset_fifo(&self, group: AdcGroup) {
-        self.regs.GxFIFORESETCR[group as usize].set(0x0);
+        self.regs.GxFIFORESETCR[group as usize].write(GxFIFORESETCR::FIFO_RESET::CLEAR);
     }
 
     /// Stops a conversion of the ADC hardware group
@@ -365,24 +486,24 @@ impl Adc {
     pub fn calibrate(&self) -> i16 {
         let mut sum = 0;
         let old_mode = self.regs.OPMODECR.get();
-        self.regs.OPMODECR.set(AdcCoreResolution::Bit12 as u32);
+        self.regs.OPMODECR.modify(OPMODECR::RESOLUTION::Bit12);
 
         self.stop_all();
 
         for test in 0..3 {
             // Disable Calibration
-            self.regs.CALCR.set(0x0);
+            self.regs.CALCR.write(CALCR::CAL_EN::CLEAR);
             // Select test (change Bride-En, HiLo at each iteration)
-            self.regs.CALCR.set((test as u32) << 8);
+            self.regs.CALCR.write(CALCR::BRIDGE_EN.val(test >> 1) + CALCR::HILO.val(test));
             // Enable Calibration
-            self.regs.CALCR.set(self.regs.CALCR.get() | 0x1);
+            self.regs.CALCR.modify(CALCR::CAL_EN::SET);
             // Start calibration conversion
-            self.regs.CALCR.set(self.regs.CALCR.get() | 0x0001_0000);
-            wait_until_reset!(self.regs.CALCR.get(), 0x0001_0000);
+            self.regs.CALCR.modify(CALCR::CAL_ST::SET);
+            wait_until_true!(self.regs.CALCR.is_set(CALCR::CAL_ST));
 
             sum += self.regs.CALR.get();
         }
-        self.regs.CALCR.set(0x0);
+        self.regs.CALCR.write(CALCR::CAL_EN::CLEAR);
 
         // calculate error and write it back to CALR register as
         // a two's complement value
@@ -432,3 +553,75 @@ impl Adc {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    const RSTCR: u32 = 0xFFF7_C000;
+    const OPMODECR: u32 = 0xFFF7_C004;
+    const CLOCKCR: u32 = 0xFFF7_C008;
+    const G1INTFLG: u32 = 0xFFF7_C038;
+    const G1INTCR: u32 = 0xFFF7_C044;
+    const BNDCR: u32 = 0xFFF7_C058;
+    const BNDEND: u32 = 0xFFF7_C05C;
+    const G1SEL: u32 = 0xFFF7_C07C;
+    const G1BUF0: u32 = 0xFFF7_C0B0;
+
+    fn adc1() -> Adc {
+        let clocks = Clocks {
+            gclk: 160_000_000,
+            hclk: 160_000_000,
+            vclk: [80_000_000; 4],
+            avclk1: 80_000_000,
+            rticlk1: 80_000_000,
+            modulation: 0,
+        };
+        let adc1 = unsafe { ADC1::steal() };
+        Adc::new(adc1, &clocks, 16, 8, 16, AdcCoreResolution::Bit12)
+    }
+
+    #[test]
+    fn setup() {
+        mock::reset();
+        let adc = adc1();
+        assert_eq!(mock::writes_to(RSTCR), [0x1, 0x0]);
+        assert_eq!(mock::writes_to(OPMODECR), [0x8000_0000]);
+        // ADCLK = 80 MHz / 8
+        assert_eq!(mock::writes_to(CLOCKCR), [7]);
+        assert_eq!(mock::writes_to(BNDCR), [(8 << 16) | 16]);
+        assert_eq!(mock::writes_to(BNDEND), [0x2]);
+
+        // buffer initialization still active on the first 3 polls
+        mock::poke(BNDEND, mock::peek(BNDEND) | 0x1 << 16);
+        mock::clear_after_reads(BNDEND, 0x1 << 16, 3);
+        adc.activate();
+        assert_eq!(mock::writes_to(OPMODECR), [0x8000_0000, 0x8000_0001]);
+        assert_eq!(mock::peek(BNDEND), 0x2);
+    }
+
+    #[test]
+    fn conversion() {
+        mock::reset();
+        let adc = adc1();
+        adc.start(AdcGroup::One, 3);
+        assert_eq!(mock::writes_to(G1INTCR), [16]);
+        assert_eq!(mock::writes_to(G1SEL), [0x8]);
+
+        // 14 free FIFO words: 2 results, the second one not valid
+        mock::poke(G1INTCR, 14);
+        mock::read_sequence(G1BUF0, &[(3 << 16) | 0x123, 0x8000_0000 | (4 << 16) | 0xABC]);
+        let mut samples = [AdcSample::default(); 4];
+        assert_eq!(adc.get(AdcGroup::One, &mut samples), 2);
+        assert!(samples[0].valid);
+        assert_eq!((samples[0].ch, samples[0].value), (3, 0x123));
+        assert!(!samples[1].valid);
+        assert_eq!((samples[1].ch, samples[1].value), (4, 0xABC));
+        // END and THR_INT_FLG cleared
+        assert_eq!(mock::writes_to(G1INTFLG), [0x9]);
+
+        adc.stop(AdcGroup::One);
+        assert_eq!(mock::writes_to(G1SEL), [0x8, 0x0]);
+    }
+}
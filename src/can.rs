@@ -1,94 +1,222 @@
-///! DCAN Controller Area Network module
-///!
-///! The Controller Area Network is a high integrity serial communications
-///! protocol for distributed real-time applications.
-///! The DCAN module supports bit rates up to 1 Mbit/s and is compliant
-///! to the CAN 2.0B protocol specification.
+//! DCAN Controller Area Network module
+//!
+//! The Controller Area Network is a high integrity serial communications
+//! protocol for distributed real-time applications.
+//! The DCAN module supports bit rates up to 1 Mbit/s and is compliant
+//! to the CAN 2.0B protocol specification.
 
-use vcell::VolatileCell;
+use crate::clock::{ClockError, Clocks};
+use crate::peripherals::{sealed::Sealed, DCAN1, DCAN2};
+#[cfg(not(feature = "tms570ls0432"))]
+use crate::peripherals::DCAN3;
+use crate::regs::{map, Reg};
+
+register_bitfields! {
+    /// Control
+    CTL [
+        /// Wake up on bus activity
+        WUBA OFFSET(25) NUMBITS(1) [],
+        /// Local power down mode request
+        PDR OFFSET(24) NUMBITS(1) [],
+        /// Software reset
+        SWR OFFSET(15) NUMBITS(1) [],
+        /// Parity/SECDED enable key
+        PMD OFFSET(10) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ],
+        /// Auto bus on
+        ABO OFFSET(9) NUMBITS(1) [],
+        /// Test mode enable
+        TEST OFFSET(7) NUMBITS(1) [],
+        /// Configuration change enable
+        CCE OFFSET(6) NUMBITS(1) [],
+        /// Disable automatic retransmission
+        DAR OFFSET(5) NUMBITS(1) [],
+        /// Error interrupt enable
+        EIE OFFSET(3) NUMBITS(1) [],
+        /// Status change interrupt enable
+        SIE OFFSET(2) NUMBITS(1) [],
+        /// Interrupt line 0 enable
+        IE0 OFFSET(1) NUMBITS(1) [],
+        /// Initialization mode
+        INIT OFFSET(0) NUMBITS(1) []
+    ],
+    /// Error and Status
+    ES [
+        /// Parity error detected
+        PER OFFSET(8) NUMBITS(1) [],
+        /// Bus-off state
+        BOFF OFFSET(7) NUMBITS(1) [],
+        /// Warning state
+        EWARN OFFSET(6) NUMBITS(1) [],
+        /// Error passive state
+        EPASS OFFSET(5) NUMBITS(1) [],
+        /// Received a message successfully
+        RXOK OFFSET(4) NUMBITS(1) [],
+        /// Transmitted a message successfully
+        TXOK OFFSET(3) NUMBITS(1) [],
+        /// Last error code
+        LEC OFFSET(0) NUMBITS(3) [
+            NoError = 0,
+            Stuff = 1,
+            Form = 2,
+            Ack = 3,
+            Bit1 = 4,
+            Bit0 = 5,
+            Crc = 6,
+            NoEvent = 7
+        ]
+    ],
+    /// Bit Timing
+    BTR [
+        /// Baud rate prescaler extension
+        BRPE OFFSET(16) NUMBITS(4) [],
+        /// Time segment after the sample point (TSEG2 - 1)
+        TSEG2 OFFSET(12) NUMBITS(3) [],
+        /// Time segment before the sample point (TSEG1 - 1)
+        TSEG1 OFFSET(8) NUMBITS(4) [],
+        /// Synchronization jump width (SJW - 1)
+        SJW OFFSET(6) NUMBITS(2) [],
+        /// Baud rate prescaler (BRP - 1)
+        BRP OFFSET(0) NUMBITS(6) []
+    ],
+    /// IF1/IF2 Command
+    IFCMD [
+        /// Transfer direction
+        WR_RD OFFSET(7) NUMBITS(1) [
+            /// Message object to IFx registers
+            Read = 0,
+            /// IFx registers to message object
+            Write = 1
+        ],
+        /// Access mask bits
+        MASK OFFSET(6) NUMBITS(1) [],
+        /// Access arbitration bits
+        ARB OFFSET(5) NUMBITS(1) [],
+        /// Access control bits
+        CONTROL OFFSET(4) NUMBITS(1) [],
+        /// Clear interrupt pending bit
+        CLRINTPND OFFSET(3) NUMBITS(1) [],
+        /// Set TxRqst (write) or clear NewDat (read)
+        TXRQST_NEWDAT OFFSET(2) NUMBITS(1) [],
+        /// Access data bytes 0-3
+        DATA_A OFFSET(1) NUMBITS(1) [],
+        /// Access data bytes 4-7
+        DATA_B OFFSET(0) NUMBITS(1) []
+    ],
+    /// IF1/IF2 Status
+    IFSTAT [
+        /// Transfer between IFx and message RAM in progress
+        BUSY OFFSET(7) NUMBITS(1) []
+    ],
+    /// IF1/IF2/IF3 Message Control
+    IFMCTL [
+        /// New data
+        NEWDAT OFFSET(15) NUMBITS(1) [],
+        /// Message lost
+        MSGLST OFFSET(14) NUMBITS(1) [],
+        /// Interrupt pending
+        INTPND OFFSET(13) NUMBITS(1) [],
+        /// Use acceptance mask
+        UMASK OFFSET(12) NUMBITS(1) [],
+        /// Transmit interrupt enable
+        TXIE OFFSET(11) NUMBITS(1) [],
+        /// Receive interrupt enable
+        RXIE OFFSET(10) NUMBITS(1) [],
+        /// Remote enable
+        RMTEN OFFSET(9) NUMBITS(1) [],
+        /// Transmit request
+        TXRQST OFFSET(8) NUMBITS(1) [],
+        /// End of block
+        EOB OFFSET(7) NUMBITS(1) [],
+        /// Data length code
+        DLC OFFSET(0) NUMBITS(4) []
+    ]
+}
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct CanRegisters {
-    CTL: VolatileCell<u32>,                 // 0x0000: Control
-    ES: VolatileCell<u32>,                  // 0x0004: Error and Status
-    EERC: VolatileCell<u32>,                // 0x0008: Error Counter
-    BTR: VolatileCell<u32>,                 // 0x000C: Bit Timing
-    INT: VolatileCell<u32>,                 // 0x0010: Interrupt
-    TEST: VolatileCell<u32>,                // 0x0014: Test
-    _reserved1: VolatileCell<u32>,          // 0x0018: Reserved
-    PERR: VolatileCell<u32>,                // 0x001C: Parity/SECDED Error Code
-    _reserved2: [VolatileCell<u32>; 24],    // 0x002C - 0x7C: Reserved
-    ABOTR: VolatileCell<u32>,               // 0x0080: Auto Bus On Time
-    TXRQX: VolatileCell<u32>,               // 0x0084: Transmission Request X
-    TXRQx: [VolatileCell<u32>; 4],          // 0x0088-0x0094: Transmission Request
-    NWDATX: VolatileCell<u32>,              // 0x0098: New Data X
-    NWDATx: [VolatileCell<u32>; 4],         // 0x009C-0x00A8: New Data
-    INTPNDX: VolatileCell<u32>,             // 0x00AC: Interrupt Pending X
-    INTPNDx: [VolatileCell<u32>; 4],        // 0x00B0-0x00BC: Interrupt Pending
-    MSGVALX: VolatileCell<u32>,             // 0x00C0: Message Valid X
-    MSGVALx: [VolatileCell<u32>; 4],        // 0x00C4-0x00D0: Message Valid
-    _reserved3: VolatileCell<u32>,          // 0x00D4: Reserved
-    INTMUXx: [VolatileCell<u32>; 4],        // 0x00D8-0x00E4: Interrupt Multiplexer
-    _reserved4: [VolatileCell<u32>; 6],     // 0x00E8: Reserved
+    CTL: Reg<u32, CTL::Register>,        // 0x0000: Control
+    ES: Reg<u32, ES::Register>,          // 0x0004: Error and Status
+    EERC: Reg,                           // 0x0008: Error Counter
+    BTR: Reg<u32, BTR::Register>,        // 0x000C: Bit Timing
+    INT: Reg,                            // 0x0010: Interrupt
+    TEST: Reg,                           // 0x0014: Test
+    _reserved1: Reg,                     // 0x0018: Reserved
+    PERR: Reg,                           // 0x001C: Parity/SECDED Error Code
+    _reserved2: [Reg; 24],               // 0x002C - 0x7C: Reserved
+    ABOTR: Reg,                          // 0x0080: Auto Bus On Time
+    TXRQX: Reg,                          // 0x0084: Transmission Request X
+    TXRQx: [Reg; 4],                     // 0x0088-0x0094: Transmission Request
+    NWDATX: Reg,                         // 0x0098: New Data X
+    NWDATx: [Reg; 4],                    // 0x009C-0x00A8: New Data
+    INTPNDX: Reg,                        // 0x00AC: Interrupt Pending X
+    INTPNDx: [Reg; 4],                   // 0x00B0-0x00BC: Interrupt Pending
+    MSGVALX: Reg,                        // 0x00C0: Message Valid X
+    MSGVALx: [Reg; 4],                   // 0x00C4-0x00D0: Message Valid
+    _reserved3: Reg,                     // 0x00D4: Reserved
+    INTMUXx: [Reg; 4],                   // 0x00D8-0x00E4: Interrupt Multiplexer
+    _reserved4: [Reg; 6],                // 0x00E8: Reserved
 
 #[cfg(target_endian = "little")]
-    IF1NO: VolatileCell<u8>,                // 0x0100: IF1 Msg Number
+    IF1NO: Reg<u8>,                      // 0x0100: IF1 Msg Number
 #[cfg(target_endian = "little")]
-    IF1STAT: VolatileCell<u8>,              // 0x0100: IF1 Status
+    IF1STAT: Reg<u8, IFSTAT::Register>,  // 0x0100: IF1 Status
 #[cfg(target_endian = "little")]
-    IF1CMD: VolatileCell<u8>,               // 0x0100: IF1 Command
+    IF1CMD: Reg<u8, IFCMD::Register>,    // 0x0100: IF1 Command
 #[cfg(target_endian = "little")]
-    _reserved9: VolatileCell<u8>,           // 0x0100: IF1 Reserved
+    _reserved9: Reg<u8>,                 // 0x0100: IF1 Reserved
 
 #[cfg(not(target_endian = "little"))]
-    _reserved9: VolatileCell<u8>,           // 0x0100: IF1 Reserved
+    _reserved9: Reg<u8>,                 // 0x0100: IF1 Reserved
 #[cfg(not(target_endian = "little"))]
-    IF1CMD: VolatileCell<u8>,               // 0x0100: IF1 Command
+    IF1CMD: Reg<u8, IFCMD::Register>,    // 0x0100: IF1 Command
 #[cfg(not(target_endian = "little"))]
-    IF1STAT: VolatileCell<u8>,              // 0x0100: IF1 Status
+    IF1STAT: Reg<u8, IFSTAT::Register>,  // 0x0100: IF1 Status
 #[cfg(not(target_endian = "little"))]
-    IF1NO: VolatileCell<u8>,                // 0x0100: IF1 Msg Number
+    IF1NO: Reg<u8>,                      // 0x0100: IF1 Msg Number
 
-    IF1MSK: VolatileCell<u32>,              // 0x0104: IF1 Mask
-    IF1ARB: VolatileCell<u32>,              // 0x0108: IF1 Arbitration
-    IF1MCTL: VolatileCell<u32>,             // 0x010C: IF1 Message Control
-    IF1DATx: [VolatileCell<u8>; 8],         // 0x0110-0x0114: IF1 Data A and B
-    _reserved5: [VolatileCell<u32>; 2],     // 0x0118: Reserved
+    IF1MSK: Reg,                         // 0x0104: IF1 Mask
+    IF1ARB: Reg,                         // 0x0108: IF1 Arbitration
+    IF1MCTL: Reg<u32, IFMCTL::Register>, // 0x010C: IF1 Message Control
+    IF1DATx: [Reg<u8>; 8],               // 0x0110-0x0114: IF1 Data A and B
+    _reserved5: [Reg; 2],                // 0x0118: Reserved
 
 #[cfg(target_endian = "little")]
-    IF2NO: VolatileCell<u8>,                // 0x0120: IF2 Msg No
+    IF2NO: Reg<u8>,                      // 0x0120: IF2 Msg No
 #[cfg(target_endian = "little")]
-    IF2STAT: VolatileCell<u8>,              // 0x0120: IF2 Status
+    IF2STAT: Reg<u8, IFSTAT::Register>,  // 0x0120: IF2 Status
 #[cfg(target_endian = "little")]
-    IF2CMD: VolatileCell<u8>,               // 0x0120: IF2 Command
+    IF2CMD: Reg<u8, IFCMD::Register>,    // 0x0120: IF2 Command
 #[cfg(target_endian = "little")]
-    _reserved10: VolatileCell<u8>,          // 0x0120: IF2 Reserved
+    _reserved10: Reg<u8>,                // 0x0120: IF2 Reserved
 
 #[cfg(not(target_endian = "little"))]
-    _reserved10: VolatileCell<u8>,          // 0x0120: IF2 Reserved
+    _reserved10: Reg<u8>,                // 0x0120: IF2 Reserved
 #[cfg(not(target_endian = "little"))]
-    IF2CMD: VolatileCell<u8>,               // 0x0120: IF2 Command
+    IF2CMD: Reg<u8, IFCMD::Register>,    // 0x0120: IF2 Command
 #[cfg(not(target_endian = "little"))]
-    IF2STAT: VolatileCell<u8>,              // 0x0120: IF2 Status
+    IF2STAT: Reg<u8, IFSTAT::Register>,  // 0x0120: IF2 Status
 #[cfg(not(target_endian = "little"))]
-    IF2NO: VolatileCell<u8>,                // 0x0120: IF2 Msg Number
-
-    IF2MSK: VolatileCell<u32>,              // 0x0124: IF2 Mask
-    IF2ARB: VolatileCell<u32>,              // 0x0128: IF2 Arbitration
-    IF2MCTL: VolatileCell<u32>,             // 0x012C: IF2 Message Control
-    IF2DATx: [VolatileCell<u8>; 8],         // 0x0130-0x0134: IF2 Data A and B
-    _reserved6: [VolatileCell<u32>; 2],     // 0x0138: Reserved
-    IF3OBS: VolatileCell<u32>,              // 0x0140: IF3 Observation
-    IF3MSK: VolatileCell<u32>,              // 0x0144: IF3 Mask
-    IF3ARB: VolatileCell<u32>,              // 0x0148: IF3 Arbitration
-    IF3MCTL: VolatileCell<u32>,             // 0x014C: IF3 Message Control
-    IF3DATx: [VolatileCell<u8>; 8],         // 0x0150-0x0154: IF3 Data A and B
-    _reserved7: [VolatileCell<u32>; 2],     // 0x0158: Reserved
-    IF3UEy: [VolatileCell<u32>; 4],         // 0x0160-0x016C: IF3 Update Enable
-    _reserved8: [VolatileCell<u32>; 28],    // 0x0170: Reserved
-    TIOC: VolatileCell<u32>,                // 0x01E0: TX IO Control
-    RIOC: VolatileCell<u32>,                // 0x01E4: RX IO Control
+    IF2NO: Reg<u8>,                      // 0x0120: IF2 Msg Number
+
+    IF2MSK: Reg,                         // 0x0124: IF2 Mask
+    IF2ARB: Reg,                         // 0x0128: IF2 Arbitration
+    IF2MCTL: Reg<u32, IFMCTL::Register>, // 0x012C: IF2 Message Control
+    IF2DATx: [Reg<u8>; 8],               // 0x0130-0x0134: IF2 Data A and B
+    _reserved6: [Reg; 2],                // 0x0138: Reserved
+    IF3OBS: Reg,                         // 0x0140: IF3 Observation
+    IF3MSK: Reg,                         // 0x0144: IF3 Mask
+    IF3ARB: Reg,                         // 0x0148: IF3 Arbitration
+    IF3MCTL: Reg<u32, IFMCTL::Register>, // 0x014C: IF3 Message Control
+    IF3DATx: [Reg<u8>; 8],               // 0x0150-0x0154: IF3 Data A and B
+    _reserved7: [Reg; 2],                // 0x0158: Reserved
+    IF3UEy: [Reg; 4],                    // 0x0160-0x016C: IF3 Update Enable
+    _reserved8: [Reg; 28],               // 0x0170: Reserved
+    TIOC: Reg,                           // 0x01E0: TX IO Control
+    RIOC: Reg,                           // 0x01E4: RX IO Control
 }
 
 const CAN1_BASE_ADDR: *const CanRegisters = 0xFFF7_DC00 as *const CanRegisters;
@@ -114,15 +242,6 @@ const CAN_PARRAM_ADDR: [*const u32; 3] = [
 ];
 
 
-const DIR_WRITE:u8 = 0x1 << 7;
-const DIR_READ:u8  = 0x0 << 7;
-const NEWDAT:u8    = 0x1;
-const TXREQ:u8     = 0x0;
-const BUSY:u8      = 0x1 << 7;
-const MSG_LOST:u32 = 0x1 << 14; /* IFxMCTL: message lost */
-const DATA_A:u8    = 0x1;
-const DATA_B:u8    = 0x1 << 1;
-
 const CAN_FRAME_SIZE:usize = 8;
 
 #[cfg(target_endian = "big")]
@@ -135,6 +254,24 @@ pub enum CanID {
     Three   = 2,
 }
 
+/// DCAN module instance: `DCAN1`, `DCAN2` or `DCAN3`
+pub trait Instance: Sealed {
+    const ID: CanID;
+}
+
+impl Instance for DCAN1 {
+    const ID: CanID = CanID::One;
+}
+
+impl Instance for DCAN2 {
+    const ID: CanID = CanID::Two;
+}
+
+#[cfg(not(feature = "tms570ls0432"))]
+impl Instance for DCAN3 {
+    const ID: CanID = CanID::Three;
+}
+
 pub enum CanReturn {
     Success { ret: usize },
     DataLost,
@@ -144,7 +281,7 @@ pub enum CanReturn {
 }
 
 macro_rules! valid_mbox {
-    ($mbox: expr) => { $mbox >= 0x1 && $mbox <= 0x80 }
+    ($mbox: expr) => { (0x1..=0x80).contains(&$mbox) }
 }
 
 #[allow(dead_code)]
@@ -153,13 +290,16 @@ pub struct DCan {
     regs: &'static CanRegisters,
     ram: *const u32,
     pram: *const u32,
+    clocks: Clocks,
 }
 
 impl DCan  {
-    pub fn new(id: CanID) -> DCan {
+    pub fn new<I: Instance>(_can: I, clocks: &Clocks) -> DCan {
+        let id = I::ID;
         DCan {
             id,
-            regs: unsafe { &*CAN_BASE_ADDR[id as usize] },
+            clocks: *clocks,
+            regs: unsafe { map(CAN_BASE_ADDR[id as usize]) },
             ram: unsafe { &*CAN_RAM_ADDR[id as usize] },
             pram: unsafe { &*CAN_PARRAM_ADDR[id as usize] },
         }
@@ -169,6 +309,55 @@ impl DCan  {
         self.regs.ES.get()
     }
 
+    /// Local power down mode. With `wakeup`, CAN bus activity leaves it
+    /// and raises the level 0 interrupt (VIM `CanxLevel0`), that can wake
+    /// the device up from a low-power mode.
+    pub fn low_power(&self, enable: bool, wakeup: bool) {
+        if enable {
+            self.regs.CTL.modify(CTL::WUBA.val(wakeup as u32) + CTL::PDR::SET);
+        } else {
+            self.regs.CTL.modify(CTL::WUBA::CLEAR + CTL::PDR::CLEAR);
+        }
+    }
+
+    /// Bit rate [bit/s]: VCLKA1 / (BRP x (1 + TSEG1 + TSEG2)), with 8 to
+    /// 25 time quanta per bit and the sample point at about 80%
+    pub fn set_bitrate(&self, bitrate: u32) -> Result<(), ClockError> {
+        if bitrate == 0 {
+            return Err(ClockError::Rate);
+        }
+        let clk = self.clocks.avclk1();
+        // (error, BRP, time quanta) closest to the bit rate
+        let mut best: Option<(u32, u32, u32)> = None;
+        for tq in (8..=25).rev() {
+            let brp = udiv_round_closest!(clk, bitrate * tq);
+            if !(1..=1024).contains(&brp) {
+                continue;
+            }
+            let actual = clk / (brp * tq);
+            let err = actual.abs_diff(bitrate);
+            if best.is_none_or(|(e, _, _)| err < e) {
+                best = Some((err, brp, tq));
+            }
+        }
+        let (_, brp, tq) = best.ok_or(ClockError::Rate)?;
+        self.clocks.check_rate(bitrate, clk / (brp * tq))?;
+        let tseg2 = (tq / 5).clamp(1, 8);
+        let tseg1 = tq - 1 - tseg2;
+        let sjw = tseg2.min(4);
+
+        // BTR is writable in initialization mode, with CCE set
+        let init = self.regs.CTL.read(CTL::INIT);
+        self.regs.CTL.modify(CTL::INIT::SET + CTL::CCE::SET);
+        self.regs.BTR.write(BTR::BRPE.val((brp - 1) >> 6)
+                            + BTR::TSEG2.val(tseg2 - 1)
+                            + BTR::TSEG1.val(tseg1 - 1)
+                            + BTR::SJW.val(sjw - 1)
+                            + BTR::BRP.val((brp - 1) & 0x3F));
+        self.regs.CTL.modify(CTL::CCE::CLEAR + CTL::INIT.val(init));
+        Ok(())
+    }
+
     #[inline(always)]
     #[cfg(target_endian = "big")]
     fn raw_set(&self, i: usize, b: u8) {
@@ -203,14 +392,17 @@ impl DCan  {
 
         let regid = (mbox >> 5) as usize;
         if self.regs.TXRQx[regid].get() & (0x1 << mbox) == 0 {
-            wait_until_set!(self.regs.IF1STAT.get(), BUSY);
+            wait_until_true!(self.regs.IF1STAT.is_set(IFSTAT::BUSY));
             if !data.is_empty() {
-                self.regs.IF1CMD.set(DIR_WRITE | TXREQ | DATA_A | DATA_B);
-                for i in 0..data.len() {
-                    self.raw_set(i, data[i]);
+                self.regs.IF1CMD.write(IFCMD::WR_RD::Write
+                                       + IFCMD::TXRQST_NEWDAT::SET
+                                       + IFCMD::DATA_A::SET
+                                       + IFCMD::DATA_B::SET);
+                for (i, &byte) in data.iter().enumerate() {
+                    self.raw_set(i, byte);
                 }
             } else { // Remote Frame
-                self.regs.IF1CMD.set(DIR_WRITE | TXREQ);
+                self.regs.IF1CMD.write(IFCMD::WR_RD::Write + IFCMD::TXRQST_NEWDAT::SET);
             }
             self.regs.IF1NO.set(mbox);
             return CanReturn::Success{ret: data.len()};
@@ -225,28 +417,93 @@ impl DCan  {
             return CanReturn::InvalidMsgBox;
         }
         // check if data have been lost
-        if self.regs.IF2MCTL.get() & MSG_LOST != 0 {
+        if self.regs.IF2MCTL.is_set(IFMCTL::MSGLST) {
             return CanReturn::DataLost;
         }
 
         let regid = (mbox >> 5) as usize;
         if self.regs.NWDATx[regid].get() & (0x1 << mbox) == 0 {
-            wait_until_set!(self.regs.IF2STAT.get(), BUSY);
-            self.regs.IF1CMD.set(DIR_READ | NEWDAT | DATA_A | DATA_B);
+            wait_until_true!(self.regs.IF2STAT.is_set(IFSTAT::BUSY));
+            self.regs.IF2CMD.write(IFCMD::WR_RD::Read
+                                   + IFCMD::TXRQST_NEWDAT::SET
+                                   + IFCMD::DATA_A::SET
+                                   + IFCMD::DATA_B::SET);
             self.regs.IF2NO.set(mbox);
-            wait_until_set!(self.regs.IF2STAT.get(), BUSY);
+            wait_until_true!(self.regs.IF2STAT.is_set(IFSTAT::BUSY));
             // Get real frame size
-            size = (self.regs.IF2MCTL.get() & 0xF) as usize;
+            size = self.regs.IF2MCTL.read(IFMCTL::DLC) as usize;
             if size > CAN_FRAME_SIZE {
                 size = CAN_FRAME_SIZE;
             }
             if size > data.len() {
                 return CanReturn::WrongBufferSize;
             }
-            for i in 0..size {
-                data[i] = self.raw_get(i);
+            for (i, byte) in data[..size].iter_mut().enumerate() {
+                *byte = self.raw_get(i);
             }
         }
         CanReturn::Success{ret: size}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    const CTL: u32 = 0xFFF7_DC00;
+    const BTR: u32 = 0xFFF7_DC0C;
+    const IF1NO: u32 = 0xFFF7_DD00;
+
+    fn dcan1() -> DCan {
+        let clocks = Clocks {
+            gclk: 160_000_000,
+            hclk: 160_000_000,
+            vclk: [80_000_000; 4],
+            avclk1: 80_000_000,
+            rticlk1: 80_000_000,
+            modulation: 0,
+        };
+        DCan::new(unsafe { DCAN1::steal() }, &clocks)
+    }
+
+    #[test]
+    fn bitrate() {
+        mock::reset();
+        let can = dcan1();
+        // 80 MHz / (8 x 20 tq): TSEG1 15, TSEG2 4, SJW 4
+        assert!(can.set_bitrate(500_000).is_ok());
+        assert_eq!(mock::writes_to(BTR), [0x3EC7]);
+        // INIT and CCE around the BTR write, back to normal operation
+        assert_eq!(mock::writes_to(CTL), [0x41, 0x0]);
+
+        // already in initialization mode: stays there
+        mock::clear_log();
+        mock::poke(CTL, 0x1);
+        assert!(can.set_bitrate(1_000_000).is_ok());
+        assert_eq!(mock::writes_to(CTL), [0x41, 0x1]);
+
+        mock::clear_log();
+        assert_eq!(can.set_bitrate(0), Err(ClockError::Rate));
+        assert_eq!(can.set_bitrate(50_000_000), Err(ClockError::Rate));
+        assert!(mock::writes_to(BTR).is_empty());
+    }
+
+    #[test]
+    fn send() {
+        mock::reset();
+        let can = dcan1();
+        // IF1 busy on the first 2 polls (IF1STAT is the byte after IF1NO)
+        mock::poke(IF1NO, 0x80 << 8);
+        mock::clear_after_reads(IF1NO + 1, 0x80, 2);
+        let ret = can.send(3, &[0x11, 0x22, 0x33]);
+        assert!(matches!(ret, CanReturn::Success { ret: 3 }));
+        let writes = mock::writes();
+        // data first, the message number write starts the transfer
+        assert_eq!(writes.iter().filter(|w| w.width == 1).count(), 5);
+        assert_eq!(writes.last().map(|w| (w.addr, w.value)), Some((IF1NO, 3)));
+
+        assert!(matches!(can.send(0, &[]), CanReturn::InvalidMsgBox));
+        assert!(matches!(can.send(1, &[0; 9]), CanReturn::WrongBufferSize));
+    }
+}
@@ -1,6 +1,8 @@
 use crate::esm::Esm;
 use crate::esm_ch::{EsmError, EsmGroup};
 use crate::vim::Vim;
+use crate::peripherals::{CCM, ESM, VIM};
+use crate::regs::map;
 use vcell::VolatileCell;
 
 #[repr(C)]
@@ -30,6 +32,18 @@ pub enum SelfTestError {
     CompareMismatch,
 }
 
+/// CCM self-test error channel
+#[cfg(not(cortex_r5))]
+const ESM_SELF_TEST: EsmError = EsmError::CCMR4SelfTest;
+#[cfg(cortex_r5)]
+const ESM_SELF_TEST: EsmError = EsmError::CCMR5SelfTest;
+
+/// CPU compare (lock-step) error channel
+#[cfg(not(cortex_r5))]
+const ESM_LOCK_STEP: EsmError = EsmError::CCMR4LockStep;
+#[cfg(cortex_r5)]
+const ESM_LOCK_STEP: EsmError = EsmError::CCMR5LockStep;
+
 /// CCMR4F Modes
 pub enum CcmMode {
     Lockstep = 0x0,
@@ -39,8 +53,8 @@ pub enum CcmMode {
 }
 
 impl Ccm {
-    pub unsafe fn new() -> &'static Ccm {
-        &*CCMR4F_BASE_ADDR
+    pub fn new(_ccm: CCM) -> &'static Ccm {
+        unsafe { map(CCMR4F_BASE_ADDR) }
     }
 
     pub fn set_mode(&self, mode: CcmMode) {
@@ -74,6 +88,14 @@ impl Ccm {
         self.ccmsr.set(CMPE)
     }
 
+    /// CCM-R4 self-test and error forcing test, `true` if both passed.
+    ///
+    /// # Safety
+    ///
+    /// The error forcing test raises a lock-step compare error: the ESM
+    /// error pin and interrupt are triggered unless masked. The ESM and the
+    /// VIM are accessed without their peripheral token, the ESM CCM flags
+    /// are cleared.
     pub unsafe fn self_test(&self) -> bool {
         self.set_mode(CcmMode::SelfTest);
         // Wait for CCM self-test to complete
@@ -81,39 +103,39 @@ impl Ccm {
         if self.self_test_error() {
             return false;
         }
-        let esm = Esm::new();
+        let esm = Esm::new(unsafe { ESM::steal() });
         // Check CCM-R4 self-test error flag by itself (without compare error)
-        if esm.error_is_set(EsmError::CCMR4SelfTest) {
+        if esm.error_is_set(ESM_SELF_TEST) {
             return false;
         }
         self.set_mode(CcmMode::ErrorForcing);
         wait_until_neq!(self.ccmkeyr.get(), 0);
 
         // check if compare error flag is set
-        if esm.error_is_set(EsmError::CCMR4LockStep) {
+        if esm.error_is_set(ESM_LOCK_STEP) {
             return false;
         }
 
         // Check FIQIVEC to ESM High Interrupt flag is set
-        if Vim::new().fiq_id() == 1 {
+        if Vim::new(unsafe { VIM::steal() }).fiq_id() == 1 {
             return false;
         }
 
         // clear ESM dual-CPU lock-step error
-        esm.clear_error(EsmError::CCMR4LockStep);
+        esm.clear_error(ESM_LOCK_STEP);
         // clear ESM group2 shadow status flag
         esm.shadow_stat_clear(EsmGroup::Two);
         // clear ESM CCM-R4 self-test error
-        esm.clear_error(EsmError::CCMR4SelfTest);
+        esm.clear_error(ESM_SELF_TEST);
         // nERROR pin will become inactive once the LTC counter expires
         esm.error_reset();
 
         self.set_mode(CcmMode::SelfTestErrorForcing);
         wait_until_neq!(self.ccmkeyr.get(), 0);
-        if esm.error_is_set(EsmError::CCMR4SelfTest) {
+        if esm.error_is_set(ESM_SELF_TEST) {
             return false;
         }
-        esm.clear_error(EsmError::CCMR4SelfTest);
+        esm.clear_error(ESM_SELF_TEST);
         true
     }
 }
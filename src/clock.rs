@@ -0,0 +1,702 @@
+//!
+//! Clock tree configuration
+//!
+//! `ClockConfig` describes the wanted clock tree, from the oscillator
+//! (OSCIN) frequency to the GCLK, HCLK, VCLK1/2/3 and AVCLK frequencies.
+//! `solve()` computes the PLL settings (NR, NF, OD, R) and the clock
+//! dividers, that `Sys::setup_pll()` and `Sys::setup_clocks()` program:
+//!
+//! ```ignore
+//! let setup = ClockConfig::new(MHz!(16), MHz!(180))
+//!     .vclk1(MHz!(90))
+//!     .vclk2(MHz!(90))
+//!     .solve()
+//!     .unwrap();
+//! sys.setup_pll(&setup);
+//! sys.wait_pll_lock();
+//! sys.setup_clocks(&setup);
+//! ```
+//!
+//! PLL output: OSCIN / NR * NF / OD / R, with
+//! - 1 <= NR <= 64, 1 <= NF <= 256, 1 <= OD <= 8, 1 <= R <= 32
+//! - OSCIN / NR (INTCLK) from 1 to 20 MHz
+//! - OSCIN / NR * NF (VCO) from 150 to 550 MHz
+//!
+//! PLL1 frequency modulation (spread spectrum) is enabled with
+//! `spread_spectrum()`: GCLK, and all the clocks derived from it, swing
+//! by +/- NV / 2048 at INTCLK / (2 x NS). The worst-case GCLK must stay
+//! within the device maximum, and the depth within `RATE_TOLERANCE`,
+//! the timing budget of the peripherals' rates:
+//!
+//! ```ignore
+//! // +/- 1%
+//! let setup = ClockConfig::new(MHz!(16), MHz!(160))
+//!     .spread_spectrum(Modulation::new(40, 20))
+//!     .solve()?;
+//! ```
+//!
+//! Only exact frequencies are accepted, and the peripheral clocks
+//! (VCLK1/2/3, AVCLK) must stay within `Device::max_vclk`. The solver
+//! doesn't touch any register and runs on the host as well.
+//!
+//! `Clocks` reports the frequencies actually running, as decoded from the
+//! SYS registers by `Sys::clocks()` (also returned by `Sys::setup_clocks()`).
+//! Drivers computing a rate take it in their constructors, and reject a
+//! rate that can't be hit within `RATE_TOLERANCE`:
+//!
+//! ```ignore
+//! let clocks = Sys::new(p.SYS).clocks();
+//! let mut sci = SciChipset::new(p.SCI, &clocks, DataBits::Eight, StopBits::One, Parity::None);
+//! sci.set_baudrate(115_200)?;
+//! let spi = MibSpi::new(p.MIBSPI3, &clocks, true);
+//! ```
+//!
+//! Reference:
+//! TMS570LS Series Technical Reference Manual (TRM), code SPNU489B
+//! (Clocks, PLL)
+
+use crate::device::DEVICE;
+
+/// Oscillator (OSCIN) range [Hz]
+const OSC_MIN: u32 = MHz!(5);
+const OSC_MAX: u32 = MHz!(20);
+/// PLL reference clock (OSCIN / NR) range [Hz]
+const INTCLK_MIN: u32 = MHz!(1);
+const INTCLK_MAX: u32 = MHz!(20);
+/// PLL VCO (OSCIN / NR * NF) range [Hz]
+const VCO_MIN: u32 = MHz!(150);
+const VCO_MAX: u32 = MHz!(550);
+
+const NR_MAX: u32 = 64;
+const NF_MAX: u32 = 256;
+const OD_MAX: u32 = 8;
+const R_MAX: u32 = 32;
+
+/// HCLK divider from GCLK (HCLKCNTL, Cortex-R5F parts only): maximum
+/// and default
+#[cfg(cortex_r5)]
+const HCLK_DIV_MAX: u32 = 4;
+#[cfg(cortex_r5)]
+const HCLK_DIV_DEFAULT: u32 = 2;
+#[cfg(not(cortex_r5))]
+const HCLK_DIV_MAX: u32 = 1;
+#[cfg(not(cortex_r5))]
+const HCLK_DIV_DEFAULT: u32 = 1;
+/// Maximum VCLKx divider from HCLK
+const VCLK_DIV_MAX: u32 = 16;
+
+/// Maximum deviation of the rates (baud rates, bit rates) set by the
+/// drivers from the requested ones [%]
+pub const RATE_TOLERANCE: u32 = 2;
+
+/// Clock that can't be set up as requested
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ClockError {
+    /// Oscillator out of the 5-20 MHz range
+    Oscillator,
+    /// Above the device maximum, or no PLL1 setting within the limits
+    Gclk,
+    /// Not GCLK / 1..4 (Cortex-R5F) or not GCLK (Cortex-R4F), or above
+    /// the device maximum
+    Hclk,
+    /// Not HCLK / 1..16, or above the device maximum
+    Vclk1,
+    /// Not HCLK / 1..16, not a multiple of VCLK1, or above the device
+    /// maximum
+    Vclk2,
+    /// Not HCLK / 1..16, or above the device maximum
+    Vclk3,
+    /// Neither VCLK1 nor a PLL2 output within the limits, or above the
+    /// device maximum
+    Avclk,
+    /// Rate out of `RATE_TOLERANCE`, or out of the divider range, with
+    /// the running clocks
+    Rate,
+    /// Frequency modulation out of range, worst-case GCLK above the
+    /// device maximum or depth above `RATE_TOLERANCE`
+    Modulation,
+}
+
+
+/// PLL setting: OSCIN / NR * NF / OD / R
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Pll {
+    /// Reference clock divider
+    pub nr: u32,
+    /// Multiplication factor
+    pub nf: u32,
+    /// Internal output divider
+    pub od: u32,
+    /// Output clock divider
+    pub r: u32,
+}
+
+impl Pll {
+    /// PLL setting giving exactly `fout` from `osc`, `None` if there
+    /// is none within the limits. The highest INTCLK (lowest NR) and
+    /// then the lowest dividers are preferred.
+    pub fn solve(osc: u32, fout: u32) -> Option<Pll> {
+        if fout == 0 {
+            return None;
+        }
+        let (osc64, fout64) = (u64::from(osc), u64::from(fout));
+        for nr in 1..=NR_MAX {
+            let intclk = osc / nr;
+            if intclk < INTCLK_MIN {
+                break;
+            }
+            if intclk > INTCLK_MAX {
+                continue;
+            }
+            for od in 1..=OD_MAX {
+                for r in 1..=R_MAX {
+                    // fout * NR * OD * R = OSCIN * NF
+                    let n = fout64 * u64::from(nr * od * r);
+                    if n % osc64 != 0 {
+                        continue;
+                    }
+                    let nf = n / osc64;
+                    let vco = fout64 * u64::from(od * r);
+                    if nf >= 1 && nf <= u64::from(NF_MAX)
+                        && vco >= u64::from(VCO_MIN) && vco <= u64::from(VCO_MAX) {
+                        return Some(Pll { nr, nf: nf as u32, od, r });
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Output frequency [Hz] from `osc`
+    pub fn output(&self, osc: u32) -> u32 {
+        (u64::from(osc) * u64::from(self.nf)
+            / u64::from(self.nr * self.od * self.r)) as u32
+    }
+}
+
+/// Modulation rate and amount range (NS, NV)
+const NS_MAX: u32 = 512;
+const NV_MAX: u32 = 512;
+/// Bandwidth adjustment (MULMOD) maximum and default
+const MULMOD_MAX: u32 = 511;
+const MULMOD_DEFAULT: u32 = 7;
+
+/// PLL frequency modulation: +/- NV / 2048 of the PLL output, at
+/// INTCLK / (2 x NS)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Modulation {
+    /// Spreading rate (NS)
+    pub ns: u32,
+    /// Spreading amount (NV)
+    pub nv: u32,
+    /// Bandwidth adjustment (MULMOD)
+    pub mulmod: u32,
+}
+
+impl Modulation {
+    /// Spreading rate `ns` (1-512) and amount `nv` (1-512)
+    pub const fn new(ns: u32, nv: u32) -> Modulation {
+        Modulation {
+            ns,
+            nv,
+            mulmod: MULMOD_DEFAULT,
+        }
+    }
+
+    /// Bandwidth adjustment (0-511, 7 by default)
+    pub const fn bandwidth(mut self, mulmod: u32) -> Modulation {
+        self.mulmod = mulmod;
+        self
+    }
+
+    /// Modulation depth [1/10000]
+    pub fn depth(&self) -> u32 {
+        self.nv * 10_000 / 2048
+    }
+
+    /// Modulation frequency [Hz], from the PLL INTCLK (OSCIN / NR)
+    pub fn frequency(&self, intclk: u32) -> u32 {
+        intclk / (2 * self.ns)
+    }
+
+    /// Highest frequency [Hz] of a clock running at `f`
+    pub fn worst_case(&self, f: u32) -> u32 {
+        (u64::from(f) * u64::from(2048 + self.nv) / 2048) as u32
+    }
+
+    fn check(&self, gclk: u32) -> Result<(), ClockError> {
+        if self.ns < 1 || self.ns > NS_MAX || self.nv < 1 || self.nv > NV_MAX
+            || self.mulmod > MULMOD_MAX
+            || self.worst_case(gclk) > DEVICE.max_gclk
+            || self.depth() > RATE_TOLERANCE * 100 {
+            return Err(ClockError::Modulation);
+        }
+        Ok(())
+    }
+}
+
+/// Wanted clock tree. Unset clocks default to:
+/// HCLK = GCLK (GCLK / 2 on Cortex-R5F parts), VCLK1 = the fastest
+/// HCLK / n within the device maximum, VCLK2 = VCLK3 = VCLK1,
+/// AVCLK = VCLK1.
+#[derive(Copy, Clone)]
+pub struct ClockConfig {
+    osc: u32,
+    gclk: u32,
+    hclk: Option<u32>,
+    vclk1: Option<u32>,
+    vclk2: Option<u32>,
+    vclk3: Option<u32>,
+    avclk: Option<u32>,
+    modulation: Option<Modulation>,
+}
+
+impl ClockConfig {
+    /// Oscillator (OSCIN) and CPU clock (GCLK, from PLL1) [Hz]
+    pub const fn new(osc: u32, gclk: u32) -> ClockConfig {
+        ClockConfig {
+            osc,
+            gclk,
+            hclk: None,
+            vclk1: None,
+            vclk2: None,
+            vclk3: None,
+            avclk: None,
+            modulation: None,
+        }
+    }
+
+    /// System bus clock [Hz]
+    pub const fn hclk(mut self, hz: u32) -> ClockConfig {
+        self.hclk = Some(hz);
+        self
+    }
+
+    /// Peripheral clock (VCLK) [Hz]
+    pub const fn vclk1(mut self, hz: u32) -> ClockConfig {
+        self.vclk1 = Some(hz);
+        self
+    }
+
+    /// HET/HTU clock [Hz]
+    pub const fn vclk2(mut self, hz: u32) -> ClockConfig {
+        self.vclk2 = Some(hz);
+        self
+    }
+
+    /// VCLK3 (and VCLK4) clock [Hz]
+    pub const fn vclk3(mut self, hz: u32) -> ClockConfig {
+        self.vclk3 = Some(hz);
+        self
+    }
+
+    /// Asynchronous peripheral clocks (VCLKA1..4) [Hz]: VCLK1, or PLL2
+    /// for any other frequency
+    pub const fn avclk(mut self, hz: u32) -> ClockConfig {
+        self.avclk = Some(hz);
+        self
+    }
+
+    /// PLL1 frequency modulation
+    pub const fn spread_spectrum(mut self, modulation: Modulation) -> ClockConfig {
+        self.modulation = Some(modulation);
+        self
+    }
+
+    /// PLL settings and clock dividers
+    pub fn solve(&self) -> Result<ClockSetup, ClockError> {
+        if self.osc < OSC_MIN || self.osc > OSC_MAX {
+            return Err(ClockError::Oscillator);
+        }
+        if self.gclk > DEVICE.max_gclk {
+            return Err(ClockError::Gclk);
+        }
+        let pll1 = Pll::solve(self.osc, self.gclk).ok_or(ClockError::Gclk)?;
+
+        let hclk = self.hclk.unwrap_or(self.gclk / HCLK_DIV_DEFAULT);
+        let hclk_div = divider(self.gclk, hclk, HCLK_DIV_MAX).ok_or(ClockError::Hclk)?;
+        if cfg!(cortex_r5) && hclk > DEVICE.max_gclk / 2 {
+            return Err(ClockError::Hclk);
+        }
+
+        // VCLKx dividers, within the device maximum
+        let vclk_divider = |vclk: Option<u32>, default: u32, error: ClockError| match vclk {
+            Some(vclk) => divider(hclk, vclk, VCLK_DIV_MAX)
+                .filter(|_| vclk <= DEVICE.max_vclk)
+                .ok_or(error),
+            None => Ok(default),
+        };
+        let vclk1_div = vclk_divider(self.vclk1, hclk.div_ceil(DEVICE.max_vclk),
+                                     ClockError::Vclk1)?;
+        let vclk2_div = vclk_divider(self.vclk2, vclk1_div, ClockError::Vclk2)?;
+        // VCLK2 must be an integer multiple of VCLK1
+        if vclk1_div % vclk2_div != 0 {
+            return Err(ClockError::Vclk2);
+        }
+        let vclk3_div = vclk_divider(self.vclk3, vclk1_div, ClockError::Vclk3)?;
+
+        // PLL2 runs as PLL1 if not used by the asynchronous clocks
+        let vclk1 = hclk / vclk1_div;
+        let avclk = self.avclk.unwrap_or(vclk1);
+        let pll2 = if avclk == vclk1 {
+            None
+        } else if avclk > DEVICE.max_vclk {
+            return Err(ClockError::Avclk);
+        } else {
+            Some(Pll::solve(self.osc, avclk).ok_or(ClockError::Avclk)?)
+        };
+
+        if let Some(m) = self.modulation {
+            m.check(self.gclk)?;
+        }
+
+        Ok(ClockSetup {
+            osc: self.osc,
+            pll1,
+            pll2,
+            hclk_div,
+            vclk1_div,
+            vclk2_div,
+            vclk3_div,
+            modulation: self.modulation,
+        })
+    }
+}
+
+/// `from / to`, if it is an integer divider within 1..=max
+fn divider(from: u32, to: u32, max: u32) -> Option<u32> {
+    if from.checked_rem(to) != Some(0) {
+        return None;
+    }
+    let div = from / to;
+    if div >= 1 && div <= max {
+        Some(div)
+    } else {
+        None
+    }
+}
+
+/// Validated clock tree, computed by `ClockConfig::solve()`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ClockSetup {
+    osc: u32,
+    pll1: Pll,
+    /// PLL2 setting, if it drives the asynchronous clocks
+    pll2: Option<Pll>,
+    hclk_div: u32,
+    vclk1_div: u32,
+    vclk2_div: u32,
+    vclk3_div: u32,
+    modulation: Option<Modulation>,
+}
+
+impl ClockSetup {
+    /// PLL1 frequency modulation, if enabled
+    pub fn modulation(&self) -> Option<Modulation> {
+        self.modulation
+    }
+
+    /// Frequency modulation rate [Hz], if enabled
+    pub fn modulation_frequency(&self) -> Option<u32> {
+        self.modulation.map(|m| m.frequency(self.osc / self.pll1.nr))
+    }
+
+    /// Highest GCLK [Hz], with the frequency modulation
+    pub fn gclk_max(&self) -> u32 {
+        match self.modulation {
+            Some(m) => m.worst_case(self.gclk()),
+            None => self.gclk(),
+        }
+    }
+
+    /// PLL1 setting (GCLK)
+    pub fn pll1(&self) -> Pll {
+        self.pll1
+    }
+
+    /// PLL2 setting: the AVCLK one, or the PLL1 one if AVCLK is VCLK1
+    pub fn pll2(&self) -> Pll {
+        self.pll2.unwrap_or(self.pll1)
+    }
+
+    /// Asynchronous clocks from PLL2 (`true`) or VCLK1 (`false`)
+    pub fn avclk_from_pll2(&self) -> bool {
+        self.pll2.is_some()
+    }
+
+    /// HCLK divider from GCLK
+    pub fn hclk_divider(&self) -> u32 {
+        self.hclk_div
+    }
+
+    /// VCLK1, VCLK2 and VCLK3 dividers from HCLK
+    pub fn vclk_dividers(&self) -> (u32, u32, u32) {
+        (self.vclk1_div, self.vclk2_div, self.vclk3_div)
+    }
+
+    /// Oscillator frequency [Hz]
+    pub fn osc(&self) -> u32 {
+        self.osc
+    }
+
+    /// CPU clock frequency [Hz]
+    pub fn gclk(&self) -> u32 {
+        self.pll1.output(self.osc)
+    }
+
+    /// System bus clock frequency [Hz]
+    pub fn hclk(&self) -> u32 {
+        self.gclk() / self.hclk_div
+    }
+
+    /// Peripheral clock frequency [Hz]
+    pub fn vclk1(&self) -> u32 {
+        self.hclk() / self.vclk1_div
+    }
+
+    pub fn vclk2(&self) -> u32 {
+        self.hclk() / self.vclk2_div
+    }
+
+    pub fn vclk3(&self) -> u32 {
+        self.hclk() / self.vclk3_div
+    }
+
+    /// Asynchronous peripheral clocks frequency [Hz]
+    pub fn avclk(&self) -> u32 {
+        match self.pll2 {
+            Some(pll2) => pll2.output(self.osc),
+            None => self.vclk1(),
+        }
+    }
+}
+
+/// Frozen clock frequencies [Hz], as running
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Clocks {
+    pub(crate) gclk: u32,
+    pub(crate) hclk: u32,
+    pub(crate) vclk: [u32; 4],
+    pub(crate) avclk1: u32,
+    pub(crate) rticlk1: u32,
+    /// Frequency modulation depth [1/10000], 0 if disabled
+    pub(crate) modulation: u32,
+}
+
+impl Clocks {
+    /// CPU clock
+    pub fn gclk(&self) -> u32 {
+        self.gclk
+    }
+
+    /// System bus clock
+    pub fn hclk(&self) -> u32 {
+        self.hclk
+    }
+
+    /// Peripheral clock (SCI, LIN, I2C, MibSPI, ADC)
+    pub fn vclk1(&self) -> u32 {
+        self.vclk[0]
+    }
+
+    /// HET/HTU clock
+    pub fn vclk2(&self) -> u32 {
+        self.vclk[1]
+    }
+
+    pub fn vclk3(&self) -> u32 {
+        self.vclk[2]
+    }
+
+    pub fn vclk4(&self) -> u32 {
+        self.vclk[3]
+    }
+
+    /// Asynchronous peripheral clock 1 (DCAN)
+    pub fn avclk1(&self) -> u32 {
+        self.avclk1
+    }
+
+    /// RTI1 counters and digital watchdog clock
+    pub fn rticlk1(&self) -> u32 {
+        self.rticlk1
+    }
+
+    /// Frequency modulation depth of the clocks [1/10000]
+    pub fn modulation_depth(&self) -> u32 {
+        self.modulation
+    }
+
+    /// `Ok` if `actual` is within `RATE_TOLERANCE` of `wanted`, frequency
+    /// modulation depth included
+    pub(crate) fn check_rate(&self, wanted: u32, actual: u32) -> Result<(), ClockError> {
+        let (wanted, actual) = (u64::from(wanted), u64::from(actual));
+        let deviation = actual.abs_diff(wanted) * 10_000 + wanted * u64::from(self.modulation);
+        if wanted > 0 && deviation <= wanted * u64::from(RATE_TOLERANCE) * 100 {
+            Ok(())
+        } else {
+            Err(ClockError::Rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    /// Solver output within the PLL limits, and exact
+    fn check_limits(osc: u32, fout: u32, pll: &Pll) {
+        assert!((1..=NR_MAX).contains(&pll.nr), "{:?}", pll);
+        assert!((1..=NF_MAX).contains(&pll.nf), "{:?}", pll);
+        assert!((1..=OD_MAX).contains(&pll.od), "{:?}", pll);
+        assert!((1..=R_MAX).contains(&pll.r), "{:?}", pll);
+        assert!((INTCLK_MIN..=INTCLK_MAX).contains(&(osc / pll.nr)), "{:?}", pll);
+        let vco = u64::from(osc) * u64::from(pll.nf) / u64::from(pll.nr);
+        assert!((u64::from(VCO_MIN)..=u64::from(VCO_MAX)).contains(&vco), "{:?}", pll);
+        assert_eq!(pll.output(osc), fout);
+    }
+
+    #[test]
+    fn default_clocks() {
+        let setup = config::CLOCKS.solve().unwrap();
+        #[cfg(feature = "tms570ls0432")]
+        let (pll1, dividers) = (Pll { nr: 1, nf: 10, od: 1, r: 2 }, (1, 1, 1, 1));
+        #[cfg(not(any(feature = "tms570ls0432", cortex_r5)))]
+        let (pll1, dividers) = (Pll { nr: 1, nf: 10, od: 1, r: 1 }, (1, 2, 2, 2));
+        #[cfg(cortex_r5)]
+        let (pll1, dividers) = (Pll { nr: 4, nf: 75, od: 1, r: 1 }, (2, 2, 2, 2));
+        assert_eq!(setup.pll1(), pll1);
+        let (vclk1, vclk2, vclk3) = setup.vclk_dividers();
+        assert_eq!((setup.hclk_divider(), vclk1, vclk2, vclk3), dividers);
+        check_limits(config::OSCIN, config::GCLK, &setup.pll1());
+        assert_eq!(setup.gclk(), config::GCLK);
+        assert_eq!(setup.hclk(), config::HCLK);
+        assert_eq!(setup.vclk1(), config::VCLK);
+        assert_eq!(setup.vclk2(), config::VCLK);
+        assert_eq!(setup.vclk3(), config::VCLK);
+        // asynchronous clocks from VCLK1, PLL2 as PLL1
+        assert!(!setup.avclk_from_pll2());
+        assert_eq!(setup.pll2(), setup.pll1());
+        assert_eq!(setup.avclk(), config::VCLK);
+        assert_eq!(setup.modulation(), None);
+        assert_eq!(setup.gclk_max(), config::GCLK);
+    }
+
+    #[test]
+    fn pll_ranges() {
+        for osc in [MHz!(5), MHz!(10), MHz!(16), MHz!(20)] {
+            for fout in (1..=600).map(|f| f * MHz!(1) / 2) {
+                if let Some(pll) = Pll::solve(osc, fout) {
+                    check_limits(osc, fout, &pll);
+                }
+            }
+        }
+
+        // VCO above 550 MHz
+        assert_eq!(Pll::solve(MHz!(10), MHz!(550)), Some(Pll { nr: 1, nf: 55, od: 1, r: 1 }));
+        assert_eq!(Pll::solve(MHz!(10), MHz!(551)), None);
+        // VCO below 150 MHz: the output dividers bring it in range
+        assert_eq!(Pll::solve(MHz!(16), MHz!(80)), Some(Pll { nr: 1, nf: 10, od: 1, r: 2 }));
+        // INTCLK above 20 MHz
+        assert_eq!(Pll::solve(MHz!(40), MHz!(200)), Some(Pll { nr: 2, nf: 10, od: 1, r: 1 }));
+        // INTCLK below 1 MHz
+        assert_eq!(Pll::solve(MHz!(1), MHz!(200)), Some(Pll { nr: 1, nf: 200, od: 1, r: 1 }));
+        assert_eq!(Pll::solve(MHz!(1) - 1, MHz!(200)), None);
+        assert_eq!(Pll::solve(MHz!(16), 0), None);
+    }
+
+    #[test]
+    fn pll_factor_limits() {
+        // NF up to 256
+        assert_eq!(Pll::solve(MHz!(1), MHz!(256)), Some(Pll { nr: 1, nf: 256, od: 1, r: 1 }));
+        assert_eq!(Pll::solve(MHz!(1), MHz!(257)), None);
+        // OD x R up to 8 x 32
+        assert_eq!(Pll::solve(MHz!(16), 600_000), Some(Pll { nr: 5, nf: 48, od: 8, r: 32 }));
+        assert_eq!(Pll::solve(MHz!(16), 300_000), None);
+        // lowest NR giving NF, OD and R within their limits
+        assert_eq!(Pll::solve(MHz!(20), 7_550_000), Some(Pll { nr: 8, nf: 151, od: 2, r: 25 }));
+    }
+
+    #[test]
+    fn oscillator_range() {
+        assert_eq!(ClockConfig::new(MHz!(5) - 1, config::GCLK).solve(), Err(ClockError::Oscillator));
+        assert_eq!(ClockConfig::new(MHz!(20) + 1, config::GCLK).solve(), Err(ClockError::Oscillator));
+        // GCLK above the device maximum
+        let gclk = DEVICE.max_gclk + MHz!(1);
+        assert_eq!(ClockConfig::new(config::OSCIN, gclk).solve().err(), Some(ClockError::Gclk));
+    }
+
+    #[test]
+    fn vclk2_multiple_of_vclk1() {
+        let base = ClockConfig::new(config::OSCIN, config::GCLK).hclk(config::HCLK);
+        let hclk = config::HCLK;
+
+        let setup = base.vclk1(hclk / 10).vclk2(hclk / 5).solve().unwrap();
+        assert_eq!(setup.vclk2(), 2 * setup.vclk1());
+        assert!(base.vclk1(hclk / 4).vclk2(hclk / 4).solve().is_ok());
+        // VCLK2 slower than VCLK1, or not a multiple
+        assert_eq!(base.vclk1(hclk / 2).vclk2(hclk / 4).solve().err(), Some(ClockError::Vclk2));
+        assert_eq!(base.vclk1(hclk / 2).vclk2(hclk / 5).solve().err(), Some(ClockError::Vclk2));
+        // dividers from HCLK up to 16
+        assert_eq!(base.vclk1(hclk / 32).solve().err(), Some(ClockError::Vclk1));
+        assert_eq!(base.vclk1(hclk / 4).vclk3(hclk / 32).solve().err(), Some(ClockError::Vclk3));
+    }
+
+    #[test]
+    fn vclk_limits() {
+        let hclk = config::HCLK;
+        let base = ClockConfig::new(config::OSCIN, config::GCLK).hclk(hclk);
+
+        // unset VCLKs default to the fastest within the device maximum
+        let setup = base.solve().unwrap();
+        assert!(setup.vclk1() <= DEVICE.max_vclk);
+        let (vclk1_div, _, _) = setup.vclk_dividers();
+        assert!(vclk1_div == 1 || hclk / (vclk1_div - 1) > DEVICE.max_vclk);
+        assert_eq!(setup.vclk2(), setup.vclk1());
+        assert_eq!(setup.vclk3(), setup.vclk1());
+        assert_eq!(setup.avclk(), setup.vclk1());
+
+        // VCLKx above the device maximum
+        if hclk > DEVICE.max_vclk {
+            assert_eq!(base.vclk1(hclk).solve().err(), Some(ClockError::Vclk1));
+            let base = base.vclk1(config::VCLK);
+            assert_eq!(base.vclk2(hclk).solve().err(), Some(ClockError::Vclk2));
+            assert_eq!(base.vclk3(hclk).solve().err(), Some(ClockError::Vclk3));
+        }
+        let avclk = DEVICE.max_vclk + MHz!(2);
+        assert_eq!(base.avclk(avclk).solve().err(), Some(ClockError::Avclk));
+    }
+
+    #[test]
+    fn hclk() {
+        let base = ClockConfig::new(config::OSCIN, config::GCLK);
+        // HCLK = GCLK on Cortex-R4F parts, GCLK / 1..4 and at most half
+        // the device maximum on Cortex-R5F parts
+        #[cfg(not(cortex_r5))]
+        assert_eq!(base.hclk(config::GCLK / 2).solve().err(), Some(ClockError::Hclk));
+        #[cfg(cortex_r5)]
+        {
+            assert_eq!(base.hclk(config::GCLK).solve().err(), Some(ClockError::Hclk));
+            assert!(base.hclk(config::GCLK / 4).solve().is_ok());
+        }
+    }
+
+    #[test]
+    fn avclk_from_pll2() {
+        let base = ClockConfig::new(config::OSCIN, config::GCLK).vclk1(config::VCLK);
+
+        let setup = base.avclk(config::VCLK).solve().unwrap();
+        assert!(!setup.avclk_from_pll2());
+
+        let setup = base.avclk(MHz!(40)).solve().unwrap();
+        assert!(setup.avclk_from_pll2());
+        assert_eq!(setup.avclk(), MHz!(40));
+        assert_eq!(setup.vclk1(), config::VCLK);
+        check_limits(config::OSCIN, MHz!(40), &setup.pll2());
+
+        // no PLL2 setting
+        assert_eq!(base.avclk(MHz!(551)).solve().err(), Some(ClockError::Avclk));
+        assert_eq!(base.avclk(1).solve().err(), Some(ClockError::Avclk));
+    }
+}
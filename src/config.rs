@@ -1,7 +1,38 @@
-/// VCLK frequency [MHz]
-pub const VCLK: u32 = MHz!(80);
+use crate::clock::ClockConfig;
 
-/// LPO value to use if not available in OTP memory (Low-frequency trim value)
+/// Oscillator (OSCIN) frequency [Hz]
+pub const OSCIN: u32 = MHz!(16);
+
+/// GCLK frequency [Hz]
+#[cfg(feature = "tms570ls0432")]
+pub const GCLK: u32 = MHz!(80);
+#[cfg(not(any(feature = "tms570ls0432", cortex_r5)))]
+pub const GCLK: u32 = MHz!(160);
+#[cfg(cortex_r5)]
+pub const GCLK: u32 = MHz!(300);
+
+/// HCLK frequency [Hz]: GCLK on Cortex-R4F parts
+#[cfg(not(cortex_r5))]
+pub const HCLK: u32 = GCLK;
+#[cfg(cortex_r5)]
+pub const HCLK: u32 = GCLK / 2;
+
+/// VCLK frequency [Hz]
+#[cfg(feature = "tms570ls0432")]
+pub const VCLK: u32 = HCLK;
+#[cfg(not(feature = "tms570ls0432"))]
+pub const VCLK: u32 = HCLK / 2;
+
+/// Clock tree set up by the startup code (see `clock`)
+pub const CLOCKS: ClockConfig = ClockConfig::new(OSCIN, GCLK)
+    .hclk(HCLK)
+    .vclk1(VCLK)
+    .vclk2(VCLK)
+    .vclk3(VCLK);
+
+/// LPO value to use if not available in OTP memory (Low-frequency trim value).
+/// Boards without OTP trim can measure their own once with
+/// `Sys::calibrate_lpo()`, store it and set it with `Sys::set_lpo_trim()`.
 ///
 /// Admitted values (forced to 1Fh if out of range):
 ///
@@ -14,9 +45,6 @@ pub const VCLK: u32 = MHz!(80);
 /// | 1Eh 165.90 | 1Fh 170.42 |
 pub const LPO: u16 = 0x10;
 
-/// RTI1 Clock Frequency [MHz]
-pub const RTICLK1: u32 = MHz!(80);
-
 /// LPC preload as used by the ESM driver
 pub const LPC_PRELOAD: u32 = 16384;
 
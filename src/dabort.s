@@ -60,6 +60,8 @@ noRAMerror:
     tst     r0, #0x80           @ check if bit 7 is set, this indicates uncorrectable ECC error on ATCM
     bne     flashErrorFound
 
+    sub     r0, lr, #8          @ faulting instruction address
+    mrs     r1, spsr
     bl      custom_dabort       @ custom data abort handler required
                                 @ If this custom handler is written in assembly, all registers used in the routine
                                 @ and the link register must be saved on to the stack upon entry, and restored before
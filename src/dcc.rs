@@ -1,120 +1,445 @@
-///
-/// Dual-Clock Comparator (DCC) Module
-///
-use vcell;
+//!
+//! Dual-Clock Comparator (DCC) Module
+//!
+//! The DCC checks the frequency of a clock (clock 1) against a reference
+//! clock (clock 0). Counter 0 counts `count0` then `valid0` cycles of
+//! clock 0, counter 1 counts down `count1` cycles of clock 1: the
+//! measurement passes when counter 1 expires inside the `valid0` window.
+//! Otherwise the error flag is set and signaled to the ESM (`DCC1`,
+//! `DCC2Error` channels).
+//!
+//! `Seeds::new()` computes the seeds for a nominal frequency ratio and a
+//! tolerance. The check can be run once (`Dcc::check()`) or continuously
+//! (`Dcc::monitor()`), the latter restarting a new measurement as soon as
+//! one completes and reporting errors via the ESM.
+//!
+//! ```ignore
+//! let seeds = Seeds::new(config::OSCIN, clocks.gclk(), 1).unwrap();
+//! let dcc = Dcc::new(p.DCC1);
+//! dcc.monitor(Clock0Source::Oscin, Clock1Source::Pll1, &seeds, esm);
+//! ```
+//!
+//! `Dcc::frequency()` measures a clock against the reference one, e.g.
+//! to trim the LPO (`Sys::calibrate_lpo()`).
+//!
+//! Clock sources are the DCC1 ones. DCC2 only compares OSCIN or TCK
+//! (clock 0) against VCLK or N2HET2[0] (clock 1).
+
+use crate::esm::Esm;
+use crate::esm_ch::EsmError;
+use crate::peripherals::{sealed::Sealed, DCC1};
+#[cfg(not(feature = "tms570ls0432"))]
+use crate::peripherals::DCC2;
+use crate::regs::{map, Reg};
+
+register_bitfields! {
+    /// Global Control
+    GCTRL [
+        /// Done interrupt enable key (single-shot mode only)
+        DONEINTENA OFFSET(12) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ],
+        /// Single-shot mode key
+        SINGLESHOT OFFSET(8) NUMBITS(4) [
+            SingleShot = 0xA,
+            Continuous = 0x5
+        ],
+        /// Error signal enable key
+        ERRENA OFFSET(4) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ],
+        /// DCC enable key. Counters are reloaded with the seeds
+        /// when enabled.
+        DCCENA OFFSET(0) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ]
+    ],
+    /// Status. Flags are cleared writing 1.
+    STAT [
+        /// Single-shot measurement completed without error
+        DONE OFFSET(1) NUMBITS(1) [],
+        /// Error
+        ERR OFFSET(0) NUMBITS(1) []
+    ],
+    /// Counter 1 Clock Source Selection
+    CNT1CLKSRC [
+        /// Source selection key: any value other than `Enable` selects
+        /// N2HET1[31] (DCC1) or N2HET2[0] (DCC2)
+        KEY OFFSET(12) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ],
+        /// Clock source
+        CNT1_SRC OFFSET(0) NUMBITS(4) []
+    ],
+    /// Counter 0 Clock Source Selection
+    CNT0CLKSRC [
+        /// Clock source
+        CNT0_SRC OFFSET(0) NUMBITS(4) []
+    ]
+}
 
 #[repr(C)]
-pub struct Dcc {
+#[allow(non_snake_case)]
+pub struct DccRegisters {
     /// Control
-    gctrl: VolatileCell<u32>,
+    GCTRL: Reg<u32, GCTRL::Register>,
     /// Revision Id
-    rev: VolatileCell<u32>,
+    REV: Reg,
     /// Counter0 Seed
-    cnt0seed: VolatileCell<u32>,
+    CNT0SEED: Reg,
     /// Valid0 Seed
-    valid0seed: VolatileCell<u32>,
+    VALID0SEED: Reg,
     /// Counter1 Seed
-    cnt1seed: VolatileCell<u32>,
+    CNT1SEED: Reg,
     /// Status
-    stat: VolatileCell<u32>,
+    STAT: Reg<u32, STAT::Register>,
     /// Counter0 Value
-    cnt0: VolatileCell<u32>,
+    CNT0: Reg,
     /// Valid0 Value
-    valid0: VolatileCell<u32>,
+    VALID0: Reg,
     /// Counter1 Value
-    cnt1: VolatileCell<u32>,
+    CNT1: Reg,
     /// Counter1 Clock Source Selection
-    cnt1clrsrc: VolatileCell<u32>,
+    CNT1CLKSRC: Reg<u32, CNT1CLKSRC::Register>,
     /// Counter0 Clock Source Selection
-    cnt0clrsrc: VolatileCell<u32>,
+    CNT0CLKSRC: Reg<u32, CNT0CLKSRC::Register>,
 }
-const DCC1_BASE_ADDR: *const Dcc = 0xFFFF_EC00 as *const Dcc;
-const DCC2_BASE_ADDR: *const Dcc = 0xFFFF_F400 as *const Dcc;
+const DCC1_BASE_ADDR: *const DccRegisters = 0xFFFF_EC00 as *const DccRegisters;
+const DCC2_BASE_ADDR: *const DccRegisters = 0xFFFF_F400 as *const DccRegisters;
+
+const DCC_BASE_ADDR: [*const DccRegisters; 2] = [DCC1_BASE_ADDR, DCC2_BASE_ADDR];
+
+/// Counter 0 and counter 1 seeds are 20 bits wide
+pub const COUNT_MAX: u32 = 0xF_FFFF;
+/// Valid 0 seed is 16 bits wide
+pub const VALID_MAX: u32 = 0xFFFF;
+/// Smallest valid window: it covers the DCC measurement uncertainty
+pub const VALID_MIN: u32 = 4;
 
 #[derive(Copy, Clone)]
-enum DccId {
+pub enum DccId {
     One = 0,
     Two = 1,
 }
 
+/// DCC module instance: `DCC1` or `DCC2`
+pub trait Instance: Sealed {
+    const ID: DccId;
+    /// ESM group 1 error channel
+    const ESM: EsmError;
+}
+
+impl Instance for DCC1 {
+    const ID: DccId = DccId::One;
+    const ESM: EsmError = EsmError::DCC1;
+}
+
+#[cfg(not(feature = "tms570ls0432"))]
+impl Instance for DCC2 {
+    const ID: DccId = DccId::Two;
+    const ESM: EsmError = EsmError::DCC2Error;
+}
+
+/// Counter 0 (reference) clock source
 #[derive(Copy, Clone)]
-enum DccClockSource {
-    CNT0_HF_LPO = 0x5,
-    CNT0_TCK = 0xA,
-    CNT0_OSCIN = 0xF,
-    CNT1_PLL1 = 0x0,
-    CNT1_PLL2 = 0x1,
-    CNT1_LF_LPO = 0x2,
-    CNT1_HF_LPO = 0x3,
-    CNT1_EXTCLKIN1 = 0x5,
-    CNT1_EXTCLKIN2 = 0x6,
-    CNT1_VCLK = 0x8,
-    CNT1_N2HET1_31 = 0xA,
+pub enum Clock0Source {
+    Oscin = 0xF,
+    HfLpo = 0x5,
+    Tck = 0xA,
+}
+
+/// Counter 1 (measured) clock source
+#[derive(Copy, Clone)]
+pub enum Clock1Source {
+    Pll1 = 0x0,
+    Pll2 = 0x1,
+    LfLpo = 0x2,
+    HfLpo = 0x3,
+    ExtClkIn1 = 0x5,
+    ExtClkIn2 = 0x6,
+    Vclk = 0x8,
+    /// N2HET1[31] (DCC1) or N2HET2[0] (DCC2), selected with a key other
+    /// than `Enable`
+    Het = 0xA,
+}
+
+/// Measurement mode
+#[derive(Copy, Clone, PartialEq)]
+pub enum DccMode {
+    /// Stop after one measurement, setting the done or the error flag
+    SingleShot,
+    /// Restart a new measurement after each one, stop on error
+    Continuous,
+}
+
+/// Counter seeds of a measurement
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Seeds {
+    /// Clock 0 cycles before the valid window
+    pub count0: u32,
+    /// Valid window [clock 0 cycles]
+    pub valid0: u32,
+    /// Clock 1 cycles
+    pub count1: u32,
+}
+
+impl Seeds {
+    /// Seeds to check that clock 1 runs at `clk1` Hz within `tolerance` %
+    /// of the reference clock 0, running at `clk0` Hz.
+    /// The measurement window is the longest one fitting the counters, to
+    /// keep the quantization error as low as possible.
+    /// `None` when the tolerance is smaller than the DCC resolution.
+    pub fn new(clk0: u32, clk1: u32, tolerance: u32) -> Option<Seeds> {
+        if clk0 == 0 || clk1 == 0 || tolerance == 0 || tolerance >= 100 {
+            return None;
+        }
+        let (f0, f1, tol) = (u64::from(clk0), u64::from(clk1), u64::from(tolerance));
+        // Window [clock 0 cycles] is count0 + valid0 / 2
+        let window = (u64::from(COUNT_MAX) * 100 / (100 - tol))
+            .min(u64::from(VALID_MAX) * 100 / (2 * tol))
+            .min(u64::from(COUNT_MAX) * f0 / f1);
+        let count1 = window * f1 / f0;
+        let window = count1 * f0 / f1;
+        let valid0 = window * 2 * tol / 100;
+        let count0 = window - valid0 / 2;
+        if count1 == 0 || count0 == 0 || valid0 < u64::from(VALID_MIN) {
+            return None;
+        }
+        Some(Seeds {
+            count0: count0 as u32,
+            valid0: valid0 as u32,
+            count1: count1 as u32,
+        })
+    }
+}
+
+pub struct Dcc {
+    regs: &'static DccRegisters,
+    esm: EsmError,
 }
 
 impl Dcc {
-    pub fn new(id: DccId) -> &'static Dcc {
-        match id {
-            DccId::One => unsafe { &*DCC1_BASE_ADDR },
-            DccId::Two => unsafe { &*DCC2_BASE_ADDR },
+    pub fn new<I: Instance>(_dcc: I) -> Dcc {
+        Dcc {
+            regs: unsafe { map(DCC_BASE_ADDR[I::ID as usize]) },
+            esm: I::ESM,
         }
     }
 
-    /// Enable/Disable DCC module. Module will automatically start/stop counting.
+    pub fn revision(&self) -> u32 {
+        self.regs.REV.get()
+    }
+
+    /// ESM group 1 channel the errors are signaled on
+    pub fn esm_error(&self) -> EsmError {
+        self.esm
+    }
+
+    /// Enable/Disable DCC module. Module will automatically start/stop
+    /// counting, reloading the seeds when started.
     pub fn enable(&self, enable: bool) {
         if enable {
-            self.gctrl.set(self.gctrl.get() & 0xFFFF_FFFA);
+            self.regs.GCTRL.modify(GCTRL::DCCENA::Enable);
         } else {
-            self.gctrl.set(self.gctrl.get() & 0xFFFF_FFF5);
+            self.regs.GCTRL.modify(GCTRL::DCCENA::Disable);
         }
     }
 
-    /// Set clock source 0. Must be enabled using clock1_source_enable()
-    pub fn clock0_source(&self, clk_src: DccClockSource) {
-        self.cnt0clrsrc.set(clk_src as u32)
+    /// Set both clock sources. DCC shall be disabled.
+    pub fn select_source(&self, clk0: Clock0Source, clk1: Clock1Source) {
+        self.regs.CNT0CLKSRC.write(CNT0CLKSRC::CNT0_SRC.val(clk0 as u32));
+        // The N2HET signal is selected by the key, not by CNT1_SRC
+        let key = match clk1 {
+            Clock1Source::Het => CNT1CLKSRC::KEY::Disable,
+            _ => CNT1CLKSRC::KEY::Enable,
+        };
+        self.regs
+            .CNT1CLKSRC
+            .write(key + CNT1CLKSRC::CNT1_SRC.val(clk1 as u32));
     }
 
-    /// Set clock source 1 but not enable it.
-    /// Must be enabled in the next using clock1_source_enable();
-    pub fn clock1_source(&self, clk_src: DccClockSource) {
-        self.cnt1clrsrc.set(clk_src as u32)
+    /// Set the counter seeds. DCC shall be disabled.
+    pub fn set_seeds(&self, seeds: &Seeds) {
+        self.regs.CNT0SEED.set(seeds.count0);
+        self.regs.VALID0SEED.set(seeds.valid0);
+        self.regs.CNT1SEED.set(seeds.count1);
     }
 
-    /// Set and enable both clock sources. Automatically enable source clock 1.
-    pub fn select_source(&self, clk0_src: DccClockSource, clk1_src: DccClockSource) {
-        self.cnt0clrsrc.set(clk0_src as u32);
-        self.cnt1clrsrc.set(clk1_src as u32);
-        clock1_source_enable(true)
+    pub fn set_mode(&self, mode: DccMode) {
+        match mode {
+            DccMode::SingleShot => self.regs.GCTRL.modify(GCTRL::SINGLESHOT::SingleShot),
+            DccMode::Continuous => self.regs.GCTRL.modify(GCTRL::SINGLESHOT::Continuous),
+        }
+    }
+
+    /// Enable/Disable the error signal to the ESM
+    pub fn error_enable(&self, enable: bool) {
+        if enable {
+            self.regs.GCTRL.modify(GCTRL::ERRENA::Enable);
+        } else {
+            self.regs.GCTRL.modify(GCTRL::ERRENA::Disable);
+        }
     }
 
-    /// Disable/disable clock 1 source. Clock source 0 is always enabled and can't be disabled
-    pub fn clock1_source_enable(&self, enable: bool) {
-        const ENABLE_KEY: u32 = 0xA;
+    /// Enable/Disable the done interrupt (single-shot mode only)
+    pub fn done_interrupt(&self, enable: bool) {
         if enable {
-            self.cnt1clrsrc
-                .set(self.cnt0clrsrc.get() | (ENABLE_KEY << 12))
+            self.regs.GCTRL.modify(GCTRL::DONEINTENA::Enable);
         } else {
-            self.cnt1clrsrc
-                .set(self.cnt0clrsrc.get() | (!ENABLE_KEY << 12))
+            self.regs.GCTRL.modify(GCTRL::DONEINTENA::Disable);
         }
     }
 
-    /// Set DCC Clock source 0 counter seed value
-    pub fn counter0_seed(&self, cnt0: u32) {
-        self.cnt0seed.set(cnt0);
+    pub fn error(&self) -> bool {
+        self.regs.STAT.is_set(STAT::ERR)
     }
 
-    /// Set DCC Clock Source 0 Counter tolerance value
-    pub fn tollerance(&self, valid0: u32) {
-        self.valid0.set(valid0);
+    pub fn done(&self) -> bool {
+        self.regs.STAT.is_set(STAT::DONE)
     }
 
-    /// Set DCC Clock Source 1 Counter tolerance value
-    pub fn counter1_seed(&self, cnt1: u32) {
-        self.cnt1seed.set(cnt1);
+    /// Clear error and done flags
+    pub fn clear_flags(&self) {
+        self.regs.STAT.write(STAT::ERR::SET + STAT::DONE::SET);
     }
 
-    pub fn error(&self) -> bool {
-        self.stat.get() & 0x1 != 0
+    /// Counter 0, valid 0 and counter 1 current values
+    pub fn counters(&self) -> (u32, u32, u32) {
+        (
+            self.regs.CNT0.get(),
+            self.regs.VALID0.get(),
+            self.regs.CNT1.get(),
+        )
+    }
+
+    /// Stop counting and program a new measurement
+    fn setup(&self, clk0: Clock0Source, clk1: Clock1Source, seeds: &Seeds, mode: DccMode) {
+        self.enable(false);
+        self.clear_flags();
+        self.select_source(clk0, clk1);
+        self.set_seeds(seeds);
+        self.set_mode(mode);
+    }
+
+    /// Single measurement, polling for its end.
+    /// 'true' if clock 1 is within the seeds tolerance.
+    pub fn check(&self, clk0: Clock0Source, clk1: Clock1Source, seeds: &Seeds) -> bool {
+        self.setup(clk0, clk1, seeds, DccMode::SingleShot);
+        self.enable(true);
+        wait_until_false!(self.done() || self.error());
+        let passed = !self.error();
+        self.enable(false);
+        self.clear_flags();
+        passed
+    }
+
+    /// Frequency of clock 1 [Hz], counted over `window` cycles of the
+    /// reference clock 0 running at `clk0_hz`.
+    /// `None` if clock 1 is too fast to be counted over the window.
+    /// The error signal is off during the measurement, and then restored.
+    pub fn frequency(&self, clk0: Clock0Source, clk0_hz: u32, clk1: Clock1Source,
+                     window: u32) -> Option<u32> {
+        let seeds = Seeds {
+            count0: window,
+            valid0: VALID_MIN,
+            count1: COUNT_MAX,
+        };
+        let errena = self.regs.GCTRL.read(GCTRL::ERRENA);
+        self.error_enable(false);
+        self.setup(clk0, clk1, &seeds, DccMode::SingleShot);
+        self.enable(true);
+        // Counter 1 can't expire in the valid window: the measurement
+        // ends with an error once counter 0 and valid 0 are elapsed
+        wait_until_false!(self.done() || self.error());
+        let (_, _, count1) = self.counters();
+        self.enable(false);
+        self.clear_flags();
+        self.regs.GCTRL.modify(GCTRL::ERRENA.val(errena));
+        if count1 == 0 {
+            return None;
+        }
+        let cycles = u64::from(COUNT_MAX - count1) * u64::from(clk0_hz);
+        Some((cycles / u64::from(window + VALID_MIN)) as u32)
+    }
+
+    /// Continuous monitoring of clock 1. Errors stop the DCC and are
+    /// signaled to the ESM, with an ESM group 1 interrupt.
+    pub fn monitor(&self, clk0: Clock0Source, clk1: Clock1Source, seeds: &Seeds, esm: &Esm) {
+        self.setup(clk0, clk1, seeds, DccMode::Continuous);
+        esm.clear_error(self.esm);
+        esm.enable_interrupt(self.esm);
+        self.error_enable(true);
+        self.enable(true);
+    }
+
+    /// Restart the monitoring after an error, clearing the ESM flag
+    pub fn restart(&self, esm: &Esm) {
+        self.enable(false);
+        self.clear_flags();
+        esm.clear_error(self.esm);
+        self.enable(true);
+    }
+
+    /// Stop the monitoring
+    pub fn stop(&self, esm: &Esm) {
+        self.enable(false);
+        self.error_enable(false);
+        esm.disable_interrupt(self.esm);
+        self.clear_flags();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    const GCTRL: u32 = 0xFFFF_EC00;
+    const STAT: u32 = 0xFFFF_EC14;
+    const CNT1: u32 = 0xFFFF_EC20;
+    const CNT1CLKSRC: u32 = 0xFFFF_EC24;
+    const CNT0CLKSRC: u32 = 0xFFFF_EC28;
+
+    #[test]
+    fn select_source() {
+        mock::reset();
+        let dcc = Dcc::new(unsafe { DCC1::steal() });
+        dcc.select_source(Clock0Source::Oscin, Clock1Source::Pll1);
+        assert_eq!(mock::peek(CNT0CLKSRC), 0xF);
+        assert_eq!(mock::peek(CNT1CLKSRC), 0xA << 12);
+        dcc.select_source(Clock0Source::HfLpo, Clock1Source::Vclk);
+        assert_eq!(mock::peek(CNT0CLKSRC), 0x5);
+        assert_eq!(mock::peek(CNT1CLKSRC), 0xA << 12 | 0x8);
+        // N2HET selected by a key other than 0xA
+        dcc.select_source(Clock0Source::Oscin, Clock1Source::Het);
+        assert_ne!(mock::peek(CNT1CLKSRC) >> 12, 0xA);
+    }
+
+    #[test]
+    fn frequency_without_error_signal() {
+        mock::reset();
+        let dcc = Dcc::new(unsafe { DCC1::steal() });
+        dcc.error_enable(true);
+        mock::poke(STAT, 0x1);
+        mock::poke(CNT1, COUNT_MAX - 1000);
+        let errena = |v: u32| (v >> 4) & 0xF;
+
+        mock::clear_log();
+        let hz = dcc.frequency(Clock0Source::Oscin, 16_000_000, Clock1Source::LfLpo, 996);
+        assert_eq!(hz, Some(16_000_000));
+        // error signal off before the DCC is enabled, restored afterwards
+        let gctrl = mock::writes_to(GCTRL);
+        let enabled = gctrl.iter().position(|v| v & 0xF == 0xA).unwrap();
+        assert!(gctrl[..=enabled].iter().all(|&v| errena(v) == 0x5));
+        assert_eq!(errena(mock::peek(GCTRL)), 0xA);
+
+        // left off when it was off
+        dcc.error_enable(false);
+        dcc.frequency(Clock0Source::Oscin, 16_000_000, Clock1Source::LfLpo, 996);
+        assert_eq!(errena(mock::peek(GCTRL)), 0x5);
     }
 }
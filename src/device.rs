@@ -0,0 +1,387 @@
+//!
+//! Device selection
+//!
+//! The target part is selected with one cargo feature:
+//!
+//! | Feature        | Device        | Flash   | RAM    | Data Flash | CPU     |
+//! |----------------|---------------|---------|--------|------------|---------|
+//! | `tms570ls0432` | TMS570LS0432  | 384 KB  | 32 KB  | 16 KB      | 80 MHz  |
+//! | `tms570ls0714` | TMS570LS0714  | 768 KB  | 128 KB | 64 KB      | 160 MHz |
+//! | `tms570ls1227` | TMS570LS1227  | 1280 KB | 192 KB | 64 KB      | 180 MHz |
+//! | `tms570ls2125` | TMS570LS2125  | 2048 KB | 192 KB | 64 KB      | 180 MHz |
+//! | `tms570ls3137` | TMS570LS3137  | 3072 KB | 256 KB | 64 KB      | 180 MHz |
+//! | `rm46`         | RM46L852      | 1280 KB | 192 KB | 64 KB      | 220 MHz |
+//! | `rm48`         | RM48L952      | 3072 KB | 256 KB | 64 KB      | 220 MHz |
+//! | `tms570lc4357` | TMS570LC4357  | 4096 KB | 512 KB | 128 KB     | 300 MHz |
+//! | `rm57`         | RM57L843      | 4096 KB | 512 KB | 128 KB     | 330 MHz |
+//!
+//! The TMS570LC4357 and the RM57L843 are Cortex-R5F parts (`cortex_r5`
+//! cfg, set by build.rs): three PCRs, L2FMC flash controller, 96 ESM
+//! group 1 channels, EPC and SCM modules, no TCRAM wrappers. The RM46,
+//! RM48 and RM57 parts are little-endian (`armv7r-none-eabihf` target),
+//! the TMS570 ones are big-endian (`armebv7r-none-eabihf`).
+//!
+//! The feature selects:
+//! - the linker script, available to the final link as `tms570.ld`
+//!   (see build.rs)
+//! - the peripheral instances handed out by `Peripherals::take()`:
+//!   using a module the part doesn't have is a compile error
+//! - the ESM channel assignment (`esm_ch`)
+//! - the named pin multiplexing table (`pinmux::PinMux`, TMS570LS0714
+//!   only; `pinmux::PinSelect` works on every device)
+//!
+//! Selecting no device, more than one device or a target with the wrong
+//! endianness fails the build.
+//!
+//! `DeviceInfo` is the identification of the running part, decoded from
+//! the DEV and die ID registers and from the OTP (`Sys::device_info()`).
+//!
+//! The Cortex-R4 errata workarounds are selected at startup from the
+//! silicon revision (`Errata::for_revision()`), as listed in the device
+//! errata sheet: erratum 57 is fixed from revision B (revision C on the
+//! TMS570LS31x/21x and RM48, revision A on the TMS570LS0714). The
+//! `errata57`/`errata66` features force a workaround on, the
+//! `no-errata57`/`no-errata66` ones force it off.
+
+/// Device information
+pub struct Device {
+    pub name: &'static str,
+    /// Program flash size [bytes]
+    pub flash_size: u32,
+    /// Data RAM size [bytes]
+    pub ram_size: u32,
+    /// Data flash (bank 7, EEPROM emulation) size [bytes]
+    pub data_flash_size: u32,
+    /// Maximum CPU clock (GCLK) [Hz]. It is also the maximum HCLK on the
+    /// Cortex-R4F parts, Cortex-R5F parts run HCLK at most at GCLK / 2.
+    pub max_gclk: u32,
+    /// Maximum peripheral clocks (VCLK1, VCLK2, VCLK3 and AVCLK) [Hz]
+    pub max_vclk: u32,
+}
+
+#[cfg(feature = "tms570ls0432")]
+pub const DEVICE: Device = Device {
+    name: "TMS570LS0432",
+    flash_size: 384 * 1024,
+    ram_size: 32 * 1024,
+    data_flash_size: 16 * 1024,
+    max_gclk: MHz!(80),
+    max_vclk: MHz!(80),
+};
+
+#[cfg(feature = "tms570ls0714")]
+pub const DEVICE: Device = Device {
+    name: "TMS570LS0714",
+    flash_size: 768 * 1024,
+    ram_size: 128 * 1024,
+    data_flash_size: 64 * 1024,
+    max_gclk: MHz!(160),
+    max_vclk: MHz!(80),
+};
+
+#[cfg(feature = "tms570ls1227")]
+pub const DEVICE: Device = Device {
+    name: "TMS570LS1227",
+    flash_size: 1280 * 1024,
+    ram_size: 192 * 1024,
+    data_flash_size: 64 * 1024,
+    max_gclk: MHz!(180),
+    max_vclk: MHz!(100),
+};
+
+#[cfg(feature = "tms570ls2125")]
+pub const DEVICE: Device = Device {
+    name: "TMS570LS2125",
+    flash_size: 2048 * 1024,
+    ram_size: 192 * 1024,
+    data_flash_size: 64 * 1024,
+    max_gclk: MHz!(180),
+    max_vclk: MHz!(100),
+};
+
+#[cfg(feature = "tms570ls3137")]
+pub const DEVICE: Device = Device {
+    name: "TMS570LS3137",
+    flash_size: 3072 * 1024,
+    ram_size: 256 * 1024,
+    data_flash_size: 64 * 1024,
+    max_gclk: MHz!(180),
+    max_vclk: MHz!(100),
+};
+
+#[cfg(feature = "rm46")]
+pub const DEVICE: Device = Device {
+    name: "RM46L852",
+    flash_size: 1280 * 1024,
+    ram_size: 192 * 1024,
+    data_flash_size: 64 * 1024,
+    max_gclk: MHz!(220),
+    max_vclk: MHz!(100),
+};
+
+#[cfg(feature = "rm48")]
+pub const DEVICE: Device = Device {
+    name: "RM48L952",
+    flash_size: 3072 * 1024,
+    ram_size: 256 * 1024,
+    data_flash_size: 64 * 1024,
+    max_gclk: MHz!(220),
+    max_vclk: MHz!(100),
+};
+
+#[cfg(feature = "tms570lc4357")]
+pub const DEVICE: Device = Device {
+    name: "TMS570LC4357",
+    flash_size: 4096 * 1024,
+    ram_size: 512 * 1024,
+    data_flash_size: 128 * 1024,
+    max_gclk: MHz!(300),
+    max_vclk: MHz!(75),
+};
+
+#[cfg(feature = "rm57")]
+pub const DEVICE: Device = Device {
+    name: "RM57L843",
+    flash_size: 4096 * 1024,
+    ram_size: 512 * 1024,
+    data_flash_size: 128 * 1024,
+    max_gclk: MHz!(330),
+    max_vclk: 82_500_000,
+};
+
+/// CPU errata workarounds
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Errata {
+    /// Cortex-R4 erratum 57: out-of-order single-precision floating
+    /// point multiply-accumulate disabled
+    pub erratum57: bool,
+    /// Cortex-R4 erratum 66: out-of-order completion of divide
+    /// instructions disabled
+    pub erratum66: bool,
+}
+
+/// Revisions with both Cortex-R4 errata
+#[cfg(not(any(cortex_r5, feature = "tms570ls0714")))]
+const ERRATA_57_66: Errata = Errata { erratum57: true, erratum66: true };
+/// Revisions with erratum 57 fixed
+#[cfg(not(cortex_r5))]
+const ERRATA_66: Errata = Errata { erratum57: false, erratum66: true };
+
+/// Errata of each silicon revision (from revision A) in the device
+/// errata sheet. Later revisions get the last one.
+#[cfg(any(feature = "tms570ls2125", feature = "tms570ls3137"))]
+const REVISION_ERRATA: &[Errata] = &[ERRATA_57_66, ERRATA_57_66, ERRATA_66];
+#[cfg(feature = "tms570ls1227")]
+const REVISION_ERRATA: &[Errata] = &[ERRATA_57_66, ERRATA_66];
+#[cfg(feature = "tms570ls0714")]
+const REVISION_ERRATA: &[Errata] = &[ERRATA_66];
+#[cfg(feature = "tms570ls0432")]
+const REVISION_ERRATA: &[Errata] = &[ERRATA_57_66, ERRATA_66];
+#[cfg(feature = "rm48")]
+const REVISION_ERRATA: &[Errata] = &[ERRATA_57_66, ERRATA_57_66, ERRATA_66];
+#[cfg(feature = "rm46")]
+const REVISION_ERRATA: &[Errata] = &[ERRATA_57_66, ERRATA_66];
+#[cfg(cortex_r5)]
+const REVISION_ERRATA: &[Errata] = &[];
+
+/// Workaround forced on (`Some(true)`), off (`Some(false)`) by the
+/// `errataNN`/`no-errataNN` features, or selected by revision (`None`)
+const fn forced(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+const FORCE57: Option<bool> = forced(cfg!(feature = "errata57"), cfg!(feature = "no-errata57"));
+const FORCE66: Option<bool> = forced(cfg!(feature = "errata66"), cfg!(feature = "no-errata66"));
+
+impl Errata {
+    /// Workarounds needed by silicon `revision` (0 is revision A), unless
+    /// forced on or off by the `errata57`/`no-errata57` and
+    /// `errata66`/`no-errata66` features. None is needed, nor forced, on
+    /// the Cortex-R5F parts.
+    pub fn for_revision(revision: u8) -> Errata {
+        if cfg!(cortex_r5) {
+            return Errata::default();
+        }
+        let errata = REVISION_ERRATA
+            .get(usize::from(revision))
+            .or(REVISION_ERRATA.last())
+            .copied()
+            .unwrap_or_default();
+        Errata {
+            erratum57: FORCE57.unwrap_or(errata.erratum57),
+            erratum66: FORCE66.unwrap_or(errata.erratum66),
+        }
+    }
+}
+
+/// CPU family
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Family {
+    CortexR4F,
+    CortexR5F,
+}
+
+/// Program flash memory protection
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FlashProtection {
+    None = 0,
+    Parity = 1,
+    Ecc = 2,
+    Reserved = 3,
+}
+
+/// Memories on the CPU Tightly Coupled Memory interfaces
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TcmType {
+    /// Flash on ATCM, RAM on B0TCM/B1TCM through the TCRAM wrappers
+    /// (Cortex-R4F parts)
+    Tcram,
+    /// TCMs unused, flash and RAM are on the L2 memory subsystem
+    /// (Cortex-R5F parts)
+    L2,
+}
+
+/// Die identification
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DieId {
+    pub lot: u32,
+    pub wafer: u8,
+    /// Die X coordinate on the wafer
+    pub x: u8,
+    /// Die Y coordinate on the wafer
+    pub y: u8,
+    /// Test temperature code
+    pub temperature: u8,
+}
+
+impl DieId {
+    /// Decode the DIEIDL_REG0 and DIEIDH_REG1 registers
+    pub fn decode(low: u32, high: u32) -> DieId {
+        DieId {
+            lot: (low >> 22) | ((high & 0x3FFF) << 10),
+            wafer: ((low >> 16) & 0x3F) as u8,
+            y: (low >> 8) as u8,
+            x: low as u8,
+            temperature: ((high >> 14) & 0xF) as u8,
+        }
+    }
+}
+
+/// Identification of the running part
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceInfo {
+    /// Part selected at build time (`DEVICE`)
+    pub name: &'static str,
+    pub family: Family,
+    /// Silicon revision: 0 is revision A
+    pub revision: u8,
+    pub flash: FlashProtection,
+    pub ram_ecc: bool,
+    pub peripheral_parity: bool,
+    pub tcm: TcmType,
+    /// Always 0b101 on Hercules parts
+    pub platform_id: u8,
+    /// Device identifier, unique for each part number
+    pub unique_id: u16,
+    /// Process technology (0b0101 is F021)
+    pub technology: u8,
+    pub die_id: DieId,
+    part_number: [u8; 32],
+}
+
+impl DeviceInfo {
+    /// Decode the DEV register, the die ID and the part number read from
+    /// the OTP (see `flash::dev_part_number_symb()`)
+    pub fn decode(dev: u32, die_id: DieId, part_number: [u8; 32]) -> DeviceInfo {
+        let flash = match (dev >> 9) & 0x3 {
+            0 => FlashProtection::None,
+            1 => FlashProtection::Parity,
+            2 => FlashProtection::Ecc,
+            _ => FlashProtection::Reserved,
+        };
+        DeviceInfo {
+            name: DEVICE.name,
+            #[cfg(not(cortex_r5))]
+            family: Family::CortexR4F,
+            #[cfg(cortex_r5)]
+            family: Family::CortexR5F,
+            revision: ((dev >> 3) & 0x1F) as u8,
+            flash,
+            ram_ecc: dev & (0x1 << 8) != 0,
+            peripheral_parity: dev & (0x1 << 11) != 0,
+            #[cfg(not(cortex_r5))]
+            tcm: TcmType::Tcram,
+            #[cfg(cortex_r5)]
+            tcm: TcmType::L2,
+            platform_id: (dev & 0x7) as u8,
+            unique_id: ((dev >> 17) & 0x3FFF) as u16,
+            technology: ((dev >> 13) & 0xF) as u8,
+            die_id,
+            part_number,
+        }
+    }
+
+    /// CPU errata workarounds needed by this part
+    pub fn errata(&self) -> Errata {
+        Errata::for_revision(self.revision)
+    }
+
+    /// Silicon revision letter
+    pub fn revision_letter(&self) -> char {
+        char::from(b'A' + self.revision)
+    }
+
+    /// Part number programmed in OTP, empty if not programmed
+    pub fn part_number(&self) -> &str {
+        let len = self.part_number.iter()
+            .position(|&c| c == 0 || c == 0xFF)
+            .unwrap_or(self.part_number.len());
+        core::str::from_utf8(&self.part_number[..len]).unwrap_or("")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// DEV register of revision A, B, C and D, and their errata
+    #[cfg(not(any(feature = "errata57", feature = "errata66",
+                  feature = "no-errata57", feature = "no-errata66")))]
+    #[test]
+    fn errata_for_revision() {
+        let both = Errata { erratum57: true, erratum66: true };
+        let only66 = Errata { erratum57: false, erratum66: true };
+        #[cfg(any(feature = "tms570ls2125", feature = "tms570ls3137", feature = "rm48"))]
+        let expected = [(0x802A_AD05, both), (0x802A_AD0D, both),
+                        (0x802A_AD15, only66), (0x802A_AD1D, only66)];
+        #[cfg(any(feature = "tms570ls1227", feature = "rm46"))]
+        let expected = [(0x8044_AD05, both), (0x8044_AD0D, only66),
+                        (0x8044_AD15, only66), (0x8044_AD1D, only66)];
+        #[cfg(feature = "tms570ls0714")]
+        let expected = [(0x8046_AD05, only66), (0x8046_AD0D, only66),
+                        (0x8046_AD15, only66), (0x8046_AD1D, only66)];
+        #[cfg(feature = "tms570ls0432")]
+        let expected = [(0x8040_2D05, both), (0x8040_2D0D, only66),
+                        (0x8040_2D15, only66), (0x8040_2D1D, only66)];
+        #[cfg(cortex_r5)]
+        let expected = [(0x8A9A_AD05, Errata::default()), (0x8A9A_AD0D, Errata::default()),
+                        (0x8A9A_AD15, Errata::default()), (0x8A9A_AD1D, Errata::default())];
+        let _ = (both, only66);
+
+        for (revision, &(dev, errata)) in expected.iter().enumerate() {
+            let info = DeviceInfo::decode(dev, DieId::decode(0, 0), [0; 32]);
+            assert_eq!(usize::from(info.revision), revision);
+            assert_eq!(info.errata(), errata, "revision {}", info.revision_letter());
+        }
+    }
+
+    #[test]
+    fn errata_forced() {
+        assert_eq!(forced(true, false), Some(true));
+        assert_eq!(forced(false, true), Some(false));
+        assert_eq!(forced(false, false), None);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::dma_ctrl::DmaControlPacket;
+use cortexr4::interrupt;
 ///
 /// Direct Memory Access Controller (DMA) Module
 ///
@@ -19,7 +20,8 @@ use crate::dma_ctrl::DmaControlPacket;
 /// - Power-management mode
 /// - Memory Protection for the adress range DMA can access with four configurable
 ///   memory regions
-use vcell::VolatileCell;
+use crate::peripherals::DMA;
+use crate::regs::{map, Reg};
 
 /// Number of supported DMA Regions
 const DMA_REGION_NUM: usize = 4;
@@ -27,191 +29,191 @@ const DMA_REGION_NUM: usize = 4;
 #[repr(C)]
 struct DmaMpr {
     /// Protection Region Start Address
-    start_add: VolatileCell<u32>,
+    start_add: Reg,
     /// Protection Region End Address
-    end_add: VolatileCell<u32>,
+    end_add: Reg,
 }
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct DmaRegisters {
     /// Global Control
-    GCTRL: VolatileCell<u32>,
+    GCTRL: Reg,
     /// Channel Pending
-    PEND: VolatileCell<u32>,
+    PEND: Reg,
     /// Fall Back
-    FBREG: VolatileCell<u32>,
+    FBREG: Reg,
     /// Status
-    DMASTAT: VolatileCell<u32>,
+    DMASTAT: Reg,
     /// Reserved
-    _reserved1: VolatileCell<u32>,
+    _reserved1: Reg,
     /// HW Channel Enable Set
-    HWCHENAS: VolatileCell<u32>,
+    HWCHENAS: Reg,
     /// Reserved
-    _reserved2: VolatileCell<u32>,
+    _reserved2: Reg,
     /// HW Channel Enable Reset
-    HWCHENAR: VolatileCell<u32>,
+    HWCHENAR: Reg,
     /// Reserved
-    _reserved3: VolatileCell<u32>,
+    _reserved3: Reg,
     /// SW Channel Enable Set
-    SWCHENAS: VolatileCell<u32>,
+    SWCHENAS: Reg,
     /// Reserved
-    _reserved4: VolatileCell<u32>,
+    _reserved4: Reg,
     /// SW Channel Enable Reset
-    SWCHENAR: VolatileCell<u32>,
+    SWCHENAR: Reg,
     /// Reserved
-    _reserved5: VolatileCell<u32>,
+    _reserved5: Reg,
     /// Channel Priority Set
-    CHPRIOS: VolatileCell<u32>,
+    CHPRIOS: Reg,
     /// Reserved
-    _reserved6: VolatileCell<u32>,
+    _reserved6: Reg,
     /// Channel Priority Reset
-    CHPRIOR: VolatileCell<u32>,
+    CHPRIOR: Reg,
     /// Reserved
-    _reserved7: VolatileCell<u32>,
+    _reserved7: Reg,
     /// Global Channel Interrupt Enable Set
-    GCHIENAS: VolatileCell<u32>,
+    GCHIENAS: Reg,
     /// Reserved
-    _reserved8: VolatileCell<u32>,
+    _reserved8: Reg,
     /// Global Channel Interrupt Enable Reset
-    GCHIENAR: VolatileCell<u32>,
+    GCHIENAR: Reg,
     /// Reserved
-    _reserved9: VolatileCell<u32>,
+    _reserved9: Reg,
     /// DMA Request Assignment
-    DREQASI: [VolatileCell<u32>; 8],
+    DREQASI: [Reg; 8],
     /// Reserved
-    _reserved10: [VolatileCell<u32>; 8],
+    _reserved10: [Reg; 8],
     /// Port Assignment
-    PAR: [VolatileCell<u32>; 4],
+    PAR: [Reg; 4],
     /// Reserved
-    _reserved11: [VolatileCell<u32>; 4],
+    _reserved11: [Reg; 4],
     /// FTC Interrupt Mapping
-    FTCMAP: VolatileCell<u32>,
+    FTCMAP: Reg,
     /// Reserved
-    _reserved12: VolatileCell<u32>,
+    _reserved12: Reg,
     /// LFS Interrupt Mapping
-    LFSMAP: VolatileCell<u32>,
+    LFSMAP: Reg,
     /// Reserved
-    _reserved13: VolatileCell<u32>,
+    _reserved13: Reg,
     /// HBC Interrupt Mapping
-    HBCMAP: VolatileCell<u32>,
+    HBCMAP: Reg,
     /// Reserved
-    _reserved14: VolatileCell<u32>,
+    _reserved14: Reg,
     /// BTC Interrupt Mapping
-    BTCMAP: VolatileCell<u32>,
+    BTCMAP: Reg,
     /// Reserved
-    _reserved15: VolatileCell<u32>,
+    _reserved15: Reg,
     /// BER Interrupt Mapping
-    BERMAP: VolatileCell<u32>,
+    BERMAP: Reg,
     /// Reserved
-    _reserved16: VolatileCell<u32>,
+    _reserved16: Reg,
     /// FTC Interrupt Enable Set
-    FTCINTENAS: VolatileCell<u32>,
+    FTCINTENAS: Reg,
     /// Reserved
-    _reserved17: VolatileCell<u32>,
+    _reserved17: Reg,
     /// FTC Interrupt Enable Reset
-    FTCINTENAR: VolatileCell<u32>,
+    FTCINTENAR: Reg,
     /// Reserved
-    _reserved18: VolatileCell<u32>,
+    _reserved18: Reg,
     /// LFS Interrupt Enable Set
-    LFSINTENAS: VolatileCell<u32>,
+    LFSINTENAS: Reg,
     /// Reserved
-    _reserved19: VolatileCell<u32>,
+    _reserved19: Reg,
     /// LFS Interrupt Enable Reset
-    LFSINTENAR: VolatileCell<u32>,
+    LFSINTENAR: Reg,
     /// Reserved
-    _reserved20: VolatileCell<u32>,
+    _reserved20: Reg,
     /// HBC Interrupt Enable Set
-    HBCINTENAS: VolatileCell<u32>,
+    HBCINTENAS: Reg,
     /// Reserved
-    _reserved21: VolatileCell<u32>,
+    _reserved21: Reg,
     /// HBC Interrupt Enable Reset
-    HBCINTENAR: VolatileCell<u32>,
+    HBCINTENAR: Reg,
     /// Reserved
-    _reserved22: VolatileCell<u32>,
+    _reserved22: Reg,
     /// BTC Interrupt Enable Set
-    BTCINTENAS: VolatileCell<u32>,
+    BTCINTENAS: Reg,
     /// Reserved
-    _reserved23: VolatileCell<u32>,
+    _reserved23: Reg,
     /// BTC Interrupt Enable Reset
-    BTCINTENAR: VolatileCell<u32>,
+    BTCINTENAR: Reg,
     /// Reserved
-    _reserved24: VolatileCell<u32>,
+    _reserved24: Reg,
     /// Global Interrupt Flag
-    GINTFLAG: VolatileCell<u32>,
+    GINTFLAG: Reg,
     /// Reserved
-    _reserved25: VolatileCell<u32>,
+    _reserved25: Reg,
     /// FTC Interrupt Flag
-    FTCFLAG: VolatileCell<u32>,
+    FTCFLAG: Reg,
     /// Reserved
-    _reserved26: VolatileCell<u32>,
+    _reserved26: Reg,
     /// LFS Interrupt Flag
-    LFSFLAG: VolatileCell<u32>,
+    LFSFLAG: Reg,
     /// Reserved
-    _reserved27: VolatileCell<u32>,
+    _reserved27: Reg,
     /// HBC Interrupt Flag
-    HBCFLAG: VolatileCell<u32>,
+    HBCFLAG: Reg,
     /// Reserved
-    _reserved28: VolatileCell<u32>,
+    _reserved28: Reg,
     /// BTC Interrupt Flag
-    BTCFLAG: VolatileCell<u32>,
+    BTCFLAG: Reg,
     /// Reserved
-    _reserved29: VolatileCell<u32>,
+    _reserved29: Reg,
     /// BER Interrupt Flag
-    BERFLAG: VolatileCell<u32>,
+    BERFLAG: Reg,
     /// Reserved
-    _reserved30: VolatileCell<u32>,
+    _reserved30: Reg,
     /// FTCA Interrupt Channel Offset
-    FTCAOFFSET: VolatileCell<u32>,
+    FTCAOFFSET: Reg,
     /// LFSA Interrupt Channel Offset
-    LFSAOFFSET: VolatileCell<u32>,
+    LFSAOFFSET: Reg,
     /// HBCA Interrupt Channel Offset
-    HBCAOFFSET: VolatileCell<u32>,
+    HBCAOFFSET: Reg,
     /// BTCA Interrupt Channel Offset
-    BTCAOFFSET: VolatileCell<u32>,
+    BTCAOFFSET: Reg,
     /// BERA Interrupt Channel Offset
-    BERAOFFSET: VolatileCell<u32>,
+    BERAOFFSET: Reg,
     /// FTCB Interrupt Channel Offset
-    FTCBOFFSET: VolatileCell<u32>,
+    FTCBOFFSET: Reg,
     /// LFSB Interrupt Channel Offset
-    LFSBOFFSET: VolatileCell<u32>,
+    LFSBOFFSET: Reg,
     //  HBCB Interrupt Channel Offset
-    HBCBOFFSET: VolatileCell<u32>,
+    HBCBOFFSET: Reg,
     // 0x016C: BTCB Interrupt Channel Offset
-    BTCBOFFSET: VolatileCell<u32>,
+    BTCBOFFSET: Reg,
     // 0x0170: BERB Interrupt Channel Offset
-    BERBOFFSET: VolatileCell<u32>,
+    BERBOFFSET: Reg,
     /// Reserved
-    _reserved31: VolatileCell<u32>,
+    _reserved31: Reg,
     /// Port Control
-    PTCRL: VolatileCell<u32>,
+    PTCRL: Reg,
     /// RAM Test Control
-    RTCTRL: VolatileCell<u32>,
+    RTCTRL: Reg,
     ///  Debug Control
-    DCTRL: VolatileCell<u32>,
+    DCTRL: Reg,
     /// Watch Point
-    WPR: VolatileCell<u32>,
+    WPR: Reg,
     /// Watch Mask
-    WMR: VolatileCell<u32>,
-    PAACSADDR: VolatileCell<u32>,
-    PAACDADDR: VolatileCell<u32>,
-    PAACTC: VolatileCell<u32>,
+    WMR: Reg,
+    PAACSADDR: Reg,
+    PAACDADDR: Reg,
+    PAACTC: Reg,
     /// Port B Active Channel Source Address
-    PBACSADDR: VolatileCell<u32>,
+    PBACSADDR: Reg,
     /// Port B Active Channel Destination Address
-    PBACDADDR: VolatileCell<u32>,
+    PBACDADDR: Reg,
     /// Port B Active Channel Transfer Count
-    PBACTC: VolatileCell<u32>,
+    PBACTC: Reg,
     /// Reserved
-    _reserved32: VolatileCell<u32>,
+    _reserved32: Reg,
     /// Parity Control
-    DMAPCR: VolatileCell<u32>,
+    DMAPCR: Reg,
     /// DMA Parity Error Address
-    DMAPAR: VolatileCell<u32>,
+    DMAPAR: Reg,
     /// DMA Memory Protection Control
-    DMAMPCTRL: VolatileCell<u32>,
+    DMAMPCTRL: Reg,
     /// DMA Memory Protection Status
-    DMAMPST: VolatileCell<u32>,
+    DMAMPST: Reg,
     /// DMA Memory Protection Address range
     DMAMPR: [DmaMpr; DMA_REGION_NUM],
 }
@@ -220,30 +222,30 @@ const DMA_BASE_ADDR: *const DmaRegisters = 0xFFFF_F000 as *const DmaRegisters;
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Pcp {
-    ISADDR: VolatileCell<u32>,
-    IDADDR: VolatileCell<u32>,
-    ITCOUNT: VolatileCell<u32>,
-    _reserved1: VolatileCell<u32>,
-    CHCTRL: VolatileCell<u32>,
-    EIOFF: VolatileCell<u32>,
-    FIOFF: VolatileCell<u32>,
-    _reserved2: VolatileCell<u32>,
+    ISADDR: Reg,
+    IDADDR: Reg,
+    ITCOUNT: Reg,
+    _reserved1: Reg,
+    CHCTRL: Reg,
+    EIOFF: Reg,
+    FIOFF: Reg,
+    _reserved2: Reg,
 }
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Wcp {
-    CSADDR: VolatileCell<u32>,
-    CDADDR: VolatileCell<u32>,
-    CTCOUNT: VolatileCell<u32>,
-    _reserved: VolatileCell<u32>,
+    CSADDR: Reg,
+    CDADDR: Reg,
+    CTCOUNT: Reg,
+    _reserved: Reg,
 }
 
 const DMA_CH_MAX: usize = 32;
 #[repr(C)]
 struct DmaRam {
     pcp: [Pcp; DMA_CH_MAX],
-    _reserved: [VolatileCell<u32>; 256],
+    _reserved: [Reg; 256],
     _wcp: [Wcp; DMA_CH_MAX],
 }
 const DMA_RAM_BASE_ADDR: *const DmaRam = 0xFFF8_0000 as *const DmaRam;
@@ -307,10 +309,10 @@ pub struct Dma {
 }
 
 impl Dma {
-    pub fn new() -> Dma {
+    pub fn new(_dma: DMA) -> Dma {
         Dma {
-            regs: unsafe { &*DMA_BASE_ADDR },
-            ram: unsafe { &*DMA_RAM_BASE_ADDR },
+            regs: unsafe { map(DMA_BASE_ADDR) },
+            ram: unsafe { map(DMA_RAM_BASE_ADDR) },
         }
     }
 
@@ -326,7 +328,7 @@ impl Dma {
                 .set(self.regs.GCTRL.get() | DmaDebug::ImmediateStop as u32);
         } else {
             // be sure to complete tranfer
-            wait_until_set!(self.regs.GCTRL.get(), DMA_BUSY);
+            wait_until_reset!(self.regs.GCTRL.get(), DMA_BUSY);
             self.regs.GCTRL.set(0x0);
         }
     }
@@ -336,8 +338,10 @@ impl Dma {
         let offset = (0x3 - (ch - (reg_id << 2))) << 3;
         // link 'channel' to 'line'
         let dreqasi_reg = &self.regs.DREQASI[reg_id as usize];
-        dreqasi_reg.set(dreqasi_reg.get() & !(0xff << offset));
-        dreqasi_reg.set(dreqasi_reg.get() | line);
+        interrupt::free(|_| {
+            dreqasi_reg.set(dreqasi_reg.get() & !(0xff << offset));
+            dreqasi_reg.set(dreqasi_reg.get() | (line << offset));
+        })
     }
 
     /// Assigns Low/High priority to a given channel
@@ -423,28 +427,107 @@ impl Dma {
         self.ram.pcp[ch].IDADDR.set(ctrl_pkt.destination);
         self.ram.pcp[ch]
             .ITCOUNT
-            .set((ctrl_pkt.frame_count << 16) | ctrl_pkt.frame_count);
+            .set((ctrl_pkt.frame_count << 16) | ctrl_pkt.element_count);
 
         let chctrl = ((ctrl_pkt.read_size as u32) << 14)
             | ((ctrl_pkt.write_size as u32) << 12)
             | ((ctrl_pkt.trigger_type as u32) << 8)
             | ((ctrl_pkt.addressing_mode_src as u32) << 3)
-            | ((ctrl_pkt.addressing_mode_dst as u32) << 2)
+            | ((ctrl_pkt.addressing_mode_dst as u32) << 1)
             | (ctrl_pkt.auto_init as u32);
 
-        self.ram.pcp[ch].CHCTRL.set((chctrl << 16) | chctrl);
+        // no chained channel
+        self.ram.pcp[ch].CHCTRL.set(chctrl);
         self.ram.pcp[ch].EIOFF.set(
             ((ctrl_pkt.element_dest_offset as u32) << 16) | (ctrl_pkt.element_source_offset as u32),
         );
 
-        self.ram.pcp[ch].EIOFF.set(
+        self.ram.pcp[ch].FIOFF.set(
             ((ctrl_pkt.frame_dest_offset as u32) << 16) | (ctrl_pkt.frame_source_offset as u32),
         );
 
         let reg_id = ch >> 3;
         let bit = (0x7 - (ch - (reg_id << 3))) << 2;
 
-        self.regs.PAR[reg_id].set(self.regs.PAR[reg_id].get() & !(0xff << bit));
+        self.regs.PAR[reg_id].set(self.regs.PAR[reg_id].get() & !(0xf << bit));
         self.regs.PAR[reg_id].set(self.regs.PAR[reg_id].get() | ((ctrl_pkt.port as u32) << bit));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dma_ctrl::{AccessWidth, DmaTrigger};
+    use crate::mock;
+
+    const GCTRL: u32 = 0xFFFF_F000;
+    const DREQASI0: u32 = 0xFFFF_F054;
+    const DREQASI1: u32 = 0xFFFF_F058;
+    const PAR0: u32 = 0xFFFF_F094;
+    /// Control packet of channel 2
+    const PCP2: u32 = 0xFFF8_0040;
+
+    fn dma() -> Dma {
+        Dma::new(unsafe { DMA::steal() })
+    }
+
+    #[test]
+    fn enable() {
+        mock::reset();
+        let dma = dma();
+        dma.enable(true);
+        assert_eq!(mock::writes_to(GCTRL), [0x0001_0000, 0x0001_0300]);
+
+        // transfer still running on the first 2 polls
+        mock::clear_log();
+        mock::poke(GCTRL, DMA_EN | DMA_BUSY);
+        mock::clear_after_reads(GCTRL, DMA_BUSY, 2);
+        dma.enable(false);
+        assert_eq!(mock::writes_to(GCTRL), [0x0]);
+    }
+
+    #[test]
+    fn assign() {
+        mock::reset();
+        let dma = dma();
+        mock::poke(DREQASI0, 0xFFFF_FFFF);
+        dma.assign(0, 5);
+        assert_eq!(mock::writes_to(DREQASI0), [0x00FF_FFFF, 0x05FF_FFFF]);
+        dma.assign(5, 12);
+        assert_eq!(mock::writes_to(DREQASI1), [0x0, 0x000C_0000]);
+    }
+
+    #[test]
+    fn control_packet() {
+        mock::reset();
+        let dma = dma();
+        mock::poke(PAR0, 0xFFFF_FFFF);
+        dma.control_packet(2, DmaControlPacket {
+            source: 0x0800_0000,
+            destination: 0xFFF7_E438,
+            frame_count: 4,
+            element_count: 16,
+            element_dest_offset: 0,
+            element_source_offset: 1,
+            frame_dest_offset: 0,
+            frame_source_offset: 16,
+            port: 4,
+            read_size: AccessWidth::Bits8,
+            write_size: AccessWidth::Bits32,
+            trigger_type: DmaTrigger::Sw,
+            addressing_mode_src: AccessWidth::Bits16,
+            addressing_mode_dst: AccessWidth::Bits8,
+            auto_init: true,
+        });
+        assert_eq!(mock::writes_to(PCP2), [0x0800_0000]);
+        assert_eq!(mock::writes_to(PCP2 + 0x04), [0xFFF7_E438]);
+        // frame count, element count
+        assert_eq!(mock::writes_to(PCP2 + 0x08), [0x0004_0010]);
+        // WES 32 bit, block transfer, read post-increment, auto-init
+        assert_eq!(mock::writes_to(PCP2 + 0x10), [0x2109]);
+        assert_eq!(mock::writes_to(PCP2 + 0x14), [0x1]);
+        assert_eq!(mock::writes_to(PCP2 + 0x18), [0x10]);
+        // channel 2 port: PAR0 bits 22-20
+        assert_eq!(mock::writes_to(PAR0), [0xFF0F_FFFF, 0xFF4F_FFFF]);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::clock::{ClockError, Clocks};
+use crate::peripherals::DWWD;
 
 #[derive(Copy,Clone,PartialEq)]
 pub enum WdViolation {
@@ -9,14 +11,14 @@ pub enum WdViolation {
 }
 
 pub trait DWD {
-    fn new() -> Self;
-    fn start(&self, expire:u32);
+    fn new(dwwd: DWWD, clocks: &Clocks) -> Self;
+    fn start(&self, expire:u32) -> Result<(), ClockError>;
     fn reset(&self);
     fn sys_reset(&self);
     fn status(&self) -> WdViolation;
     fn time_violation(&self) -> bool;
     fn status_clear(&self);
-    fn expire(&self, expire:u32) -> Result<(), u32>;
+    fn expire(&self, expire:u32) -> Result<(), ClockError>;
     fn counter_enable(&self);
     fn count_down(&self) -> u32;
 }
@@ -1,3 +1,5 @@
+use crate::peripherals::{EFC, ESM};
+use crate::regs::map;
 use vcell::VolatileCell;
 use crate::esm;
 use crate::esm_ch::EsmError;
@@ -71,8 +73,8 @@ pub enum EfcError {
 }
 
 impl Efc {
-    pub unsafe fn new() -> &'static Efc {
-        &*EFC_BASE_ADDR
+    pub fn new(_efc: EFC) -> &'static Efc {
+        unsafe { map(EFC_BASE_ADDR) }
     }
 
     pub fn self_test(&self) {
@@ -109,7 +111,7 @@ impl Efc {
                    EFC_AUTOLOAD_ERROR_EN   | EFC_SELF_TEST_ERROR_EN;
         self.boundary.set(OUTPUT_ENABLE | test);
 
-        let err = unsafe { esm::Esm::new() };
+        let err = esm::Esm::new(unsafe { ESM::steal() });
         // If not at least one of the above errors is stuck at 0.
         let pin = PIN_AUTOLOAD | PIN_INST_ERR | PIN_SIGLE_BIT_ERR | PIN_SELF_TEST_ERR;
         if self.pins.get() & pin == pin {
@@ -126,6 +128,13 @@ impl Efc {
         result
     }
 
+    /// Wait for the end of the self-test, `true` if it passed.
+    ///
+    /// # Safety
+    ///
+    /// A self-test must be running (`self_test()`), otherwise this never
+    /// returns. The ESM is accessed without its peripheral token: nothing
+    /// else may be using it, as at startup.
     pub unsafe fn self_test_completed(&self) -> bool {
         wait_until_zero!(self.pins.get(), EFC_SELF_TEST_DONE);
 
@@ -135,7 +144,7 @@ impl Efc {
         if self.pins.get() & EFC_SELF_TEST_ERROR == 0 &&
            self.error.get() & (efc_self_test | EFC_ERROR_INSTRUC_DONE) == 0 {
             // At least one EFC self-test error occurred
-            let esm = esm::Esm::new();
+            let esm = esm::Esm::new(unsafe { ESM::steal() });
             let efuse_err = esm.error_is_set(EsmError::EFuseError);
             let efuse_self_err = esm.error_is_set(EsmError::EFuseSelfTestError);
             if !efuse_err && !efuse_self_err {
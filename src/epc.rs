@@ -0,0 +1,135 @@
+//!
+//! Error Profiling Controller (EPC), Cortex-R5F parts only
+//!
+//! The Cortex-R5F reports correctable ECC errors of its interconnect
+//! initiators (CPU caches, DMA, ...) to the EPC instead of the ESM: the
+//! faulting addresses are stored in a CAM (content addressable memory),
+//! so that repeated errors at the same address are reported only once,
+//! and the ESM is signaled when a new address is stored. Uncorrectable
+//! errors are latched per initiator.
+//!
+//! Reference:
+//! TMS570LC43x Technical Reference Manual (TRM), code SPNU563
+
+use crate::peripherals::EPC;
+use crate::regs::{map, Reg};
+
+register_bitfields! {
+    /// EPC Control
+    EPCCNTRL [
+        /// Single bit (correctable) error event to the ESM, key
+        SERRENA OFFSET(0) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ]
+    ],
+    /// EPC Error Status
+    EPCERRSTAT [
+        /// Uncorrectable error on the interconnect
+        BUSERR OFFSET(0) NUMBITS(1) [],
+        /// A correctable error was stored in the CAM
+        CORRERR OFFSET(1) NUMBITS(1) [],
+        /// Correctable error lost: CAM and FIFO full
+        CAMOVERFLOW OFFSET(2) NUMBITS(1) []
+    ]
+}
+
+/// Number of CAM entries
+pub const CAM_ENTRIES: usize = 32;
+/// Number of initiators with an uncorrectable error address register
+pub const INITIATORS: usize = 5;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct Epc {
+    /// Revision ID
+    EPCREVID: Reg,
+    /// Control
+    EPCCNTRL: Reg<u32, EPCCNTRL::Register>,
+    /// Uncorrectable error status, one bit per initiator
+    UERRSTAT: Reg,
+    /// Error status
+    EPCERRSTAT: Reg<u32, EPCERRSTAT::Register>,
+    /// FIFO full status, one bit per initiator
+    FIFOFULLSTAT: Reg,
+    /// FIFO overflow status, one bit per initiator
+    OVRFLWSTAT: Reg,
+    /// Number of free CAM entries
+    CAMAVAILSTAT: Reg,
+    _reserved1: Reg,
+    /// Uncorrectable error address, per initiator
+    UERRADDR: [Reg; INITIATORS],
+    _reserved2: [Reg; 19],
+    /// CAM content: address of the correctable errors
+    CAM_CONTENT: [Reg; CAM_ENTRIES],
+    _reserved3: [Reg; 32],
+    /// CAM index: one byte per CAM entry (5: available, 0xA: in use)
+    CAM_INDEX: [Reg; CAM_ENTRIES / 4],
+}
+const EPC_BASE_ADDR: *const Epc = 0xFFFF_0C00 as *const Epc;
+
+/// CAM entry is free
+const CAM_AVAILABLE: u32 = 0x5;
+
+impl Epc {
+    pub fn new(_epc: EPC) -> &'static Epc {
+        unsafe { map(EPC_BASE_ADDR) }
+    }
+
+    /// Signal (or not) correctable errors to the ESM
+    pub fn correctable_error_event(&self, enable: bool) {
+        if enable {
+            self.EPCCNTRL.write(EPCCNTRL::SERRENA::Enable)
+        } else {
+            self.EPCCNTRL.write(EPCCNTRL::SERRENA::Disable)
+        }
+    }
+
+    /// Address of the last uncorrectable error of an initiator, if any.
+    /// The error is cleared.
+    pub fn uncorrectable_error(&self, initiator: usize) -> Option<u32> {
+        let mask = 0x1 << initiator;
+        if self.UERRSTAT.get() & mask == 0 {
+            return None;
+        }
+        let addr = self.UERRADDR[initiator].get();
+        self.UERRSTAT.set(mask);
+        Some(addr)
+    }
+
+    /// Address stored in a CAM entry, if in use
+    pub fn cam_entry(&self, entry: usize) -> Option<u32> {
+        let shift = (entry % 4) * 8;
+        let index = (self.CAM_INDEX[entry / 4].get() >> shift) & 0xFF;
+        if index == CAM_AVAILABLE {
+            None
+        } else {
+            Some(self.CAM_CONTENT[entry].get())
+        }
+    }
+
+    /// Release a CAM entry: a new error at the same address will be
+    /// reported again
+    pub fn cam_release(&self, entry: usize) {
+        let shift = (entry % 4) * 8;
+        let index = &self.CAM_INDEX[entry / 4];
+        index.set((index.get() & !(0xFF << shift)) | (CAM_AVAILABLE << shift));
+    }
+
+    /// Number of free CAM entries
+    pub fn cam_available(&self) -> u32 {
+        self.CAMAVAILSTAT.get()
+    }
+
+    /// Correctable errors were lost (CAM full)
+    pub fn overflow(&self) -> bool {
+        self.EPCERRSTAT.is_set(EPCERRSTAT::CAMOVERFLOW)
+    }
+
+    /// Clear the error status flags
+    pub fn clear_status(&self) {
+        self.EPCERRSTAT.write(EPCERRSTAT::BUSERR::SET
+                              + EPCERRSTAT::CORRERR::SET
+                              + EPCERRSTAT::CAMOVERFLOW::SET);
+    }
+}
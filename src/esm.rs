@@ -1,45 +1,114 @@
 use crate::esm_ch::{EsmError, EsmGroup};
-use vcell::VolatileCell;
+use crate::peripherals::ESM;
+use crate::regs::{map, Reg};
+
+register_bitfields! {
+    /// Error Pin Status
+    EPSR [
+        /// ERROR pin state (0: error active)
+        EPSF OFFSET(0) NUMBITS(1) []
+    ],
+    /// Interrupt Offset High
+    IOFFHR [
+        /// Offset of the pending high level interrupt (channel + 1)
+        INTOFFH OFFSET(0) NUMBITS(8) []
+    ],
+    /// Interrupt Offset Low
+    IOFFLR [
+        /// Offset of the pending low level interrupt (channel + 1)
+        INTOFFL OFFSET(0) NUMBITS(8) []
+    ],
+    /// Low-Time Counter Preload
+    LTCPR [
+        LTCPR OFFSET(0) NUMBITS(16) []
+    ],
+    /// Error Key
+    EKR [
+        EKEY OFFSET(0) NUMBITS(4) [
+            Normal = 0x0,
+            /// Reset the ERROR pin
+            ErrorReset = 0x5,
+            /// Force the ERROR pin low (error forcing mode)
+            ErrorForce = 0xA
+        ]
+    ]
+}
+
+/// Group 1 channel bank: 32 channels with configurable interrupt and
+/// ERROR pin behavior. `sr` holds the status of the group 1 channels
+/// of the bank (in the first bank, also group 2 and group 3 status).
+#[repr(C)]
+struct ChannelBank {
+    /// Enable ERROR pin action/response (EEPAPR1, IEPSRx)
+    iepsr: Reg,
+    /// Disable ERROR pin action/response (DEPAPR1, IEPCRx)
+    iepcr: Reg,
+    /// Interrupt enable set
+    iesr: Reg,
+    /// Interrupt enable clear
+    iecr: Reg,
+    /// Interrupt level set
+    ilsr: Reg,
+    /// Interrupt level clear
+    ilcr: Reg,
+    /// Status
+    sr: [Reg; 3],
+}
 
 #[repr(C)]
 pub struct Esm {
-    eepapr1: VolatileCell<u32>,
-    depapr1: VolatileCell<u32>,
-    iesr1: VolatileCell<u32>,
-    iecr1: VolatileCell<u32>,
-    ilsr1: VolatileCell<u32>,
-    ilcr1: VolatileCell<u32>,
-    sr1: [VolatileCell<u32>; 3],
-    epsr: VolatileCell<u32>,
-    ioffhr: VolatileCell<u32>,
-    iofflr: VolatileCell<u32>,
-    ltcr: VolatileCell<u32>,
-    ltcpr: VolatileCell<u32>,
-    ekr: VolatileCell<u32>,
-    ssr2: VolatileCell<u32>,
-    iepsr4: VolatileCell<u32>,
-    iepcr4: VolatileCell<u32>,
-    iesr4: VolatileCell<u32>,
-    iecr4: VolatileCell<u32>,
-    ilsr4: VolatileCell<u32>,
-    ilcr4: VolatileCell<u32>,
-    sr4: [VolatileCell<u32>; 3],
+    /// Group 1 channels 0-31
+    bank1: ChannelBank,
+    epsr: Reg<u32, EPSR::Register>,
+    ioffhr: Reg<u32, IOFFHR::Register>,
+    iofflr: Reg<u32, IOFFLR::Register>,
+    ltcr: Reg,
+    ltcpr: Reg<u32, LTCPR::Register>,
+    ekr: Reg<u32, EKR::Register>,
+    ssr2: Reg,
+    /// Group 1 channels 32-63
+    bank4: ChannelBank,
+    #[cfg(cortex_r5)]
+    _reserved1: [Reg; 7],
+    /// Group 1 channels 64-95
+    #[cfg(cortex_r5)]
+    bank7: ChannelBank,
 }
 
 const ESM_BASE_ADDR: *const Esm = 0xFFFF_F500 as *const Esm;
 
 impl Esm {
-    pub unsafe fn new() -> &'static Esm {
-        &*ESM_BASE_ADDR
+    pub fn new(_esm: ESM) -> &'static Esm {
+        unsafe { map(ESM_BASE_ADDR) }
+    }
+
+    /// Group 1 banks: 2 on Cortex-R4F parts (64 channels),
+    /// 3 on Cortex-R5F parts (96 channels)
+    fn banks(&self) -> impl Iterator<Item = &ChannelBank> {
+        let banks = [&self.bank1, &self.bank4];
+        #[cfg(cortex_r5)]
+        let banks = [banks[0], banks[1], &self.bank7];
+        IntoIterator::into_iter(banks)
+    }
+
+    /// Bank of a group 1 channel
+    fn bank(&self, ch: usize) -> &ChannelBank {
+        match ch / 32 {
+            0 => &self.bank1,
+            1 => &self.bank4,
+            #[cfg(cortex_r5)]
+            2 => &self.bank7,
+            _ => unreachable!(),
+        }
     }
 
     /// Init and reset the ESM driver.
     pub fn reset(&self, preload: u16) {
         // disable error pin channels and interrupts
-        self.depapr1.set(0xFFFF_FFFF);
-        self.iepcr4.set(0xFFFF_FFFF);
-        self.iecr1.set(0xFFFF_FFFF);
-        self.iecr4.set(0xFFFF_FFFF);
+        for bank in self.banks() {
+            bank.iepcr.set(0xFFFF_FFFF);
+            bank.iecr.set(0xFFFF_FFFF);
+        }
 
         self.clear_all_errors();
         if self.error_pin_active() {
@@ -51,32 +120,33 @@ impl Esm {
     }
 
     pub fn set_preload(&self, preload: u16) {
-        self.ltcpr.set(u32::from(preload - 1))
+        self.ltcpr.write(LTCPR::LTCPR.val(u32::from(preload - 1)))
     }
 
     pub fn error_reset(&self) {
-        self.ekr.set(0x5)
+        self.ekr.write(EKR::EKEY::ErrorReset)
     }
 
     pub fn normal_operation(&self) {
-        self.ekr.set(0x0)
+        self.ekr.write(EKR::EKEY::Normal)
     }
 
     pub fn error_pin_active(&self) -> bool {
-        self.epsr.get() == 0x0
+        !self.epsr.is_set(EPSR::EPSF)
     }
 
     pub fn high_level_interrupt(&self) -> u32 {
-        self.ioffhr.get() - 1
+        self.ioffhr.read(IOFFHR::INTOFFH) - 1
     }
 
     pub fn low_level_interrupt(&self) -> u32 {
-        self.iofflr.get() - 1
+        self.iofflr.read(IOFFLR::INTOFFL) - 1
     }
 
-    /// Up to 128 error channels are supported, divided into 3 different groups:
+    /// Error channels are divided into 3 different groups:
     /// – 64 Group1 (low severity) channels with configurable interrupt
     ///   generation and configurable ERROR pin behavior
+    ///   (96 channels on Cortex-R5F parts)
     /// – 32 Group2 (high severity) channels with predefined interrupt
     ///   generation and predefined ERROR pin behavior
     /// – 32 Group3 (high severity) channels with no interrupt generation
@@ -84,54 +154,151 @@ impl Esm {
     ///   response as they are reserved for CPU based diagnostics which generate
     ///   aborts directly to the CPU.
     pub fn error_is_set(&self, err: EsmError) -> bool {
-        let ch = err.ch();
-        let group = err.group();
-        if ch < 31 {
-            (self.sr1[group].get() >> ch) & 0x1 == 0x1
-        } else {
-            (self.sr4[group].get() >> (ch - 32)) & 0x1 == 0x1
-        }
+        let (sr, bit) = self.status(err);
+        sr.get() & bit == bit
     }
 
     pub fn clear_error(&self, err: EsmError) {
+        let (sr, bit) = self.status(err);
+        sr.set(bit);
+    }
+
+    /// Status register and bit of an error
+    fn status(&self, err: EsmError) -> (&Reg, u32) {
         let ch = err.ch();
-        let group = err.group();
-        if ch < 31 {
-            self.sr1[group].set(0x1 << ch);
+        let bit = 0x1 << (ch % 32);
+        if err.group() == EsmGroup::One as usize {
+            (&self.bank(ch).sr[0], bit)
         } else {
-            self.sr4[group].set(0x1 << (ch - 32));
+            (&self.bank1.sr[err.group()], bit)
         }
     }
 
     pub fn clear_all_errors(&self) {
-        self.sr1[0].set(0xFFFF_FFFF);
-        self.sr1[1].set(0xFFFF_FFFF);
-        self.sr1[2].set(0xFFFF_FFFF);
+        self.bank1.sr[1].set(0xFFFF_FFFF);
+        self.bank1.sr[2].set(0xFFFF_FFFF);
         self.ssr2.set(0xFFFF_FFFF);
-        self.sr4[0].set(0xFFFF_FFFF)
+        for bank in self.banks() {
+            bank.sr[0].set(0xFFFF_FFFF);
+        }
     }
 
     pub fn shadow_stat_clear(&self, group: EsmGroup) {
         self.ssr2.set(0x1 << (group as u32));
     }
 
+    /// Interrupt and ERROR pin behavior can be configured for group 1
+    /// channels only
     pub fn disable_interrupt(&self, err: EsmError) {
-        self.iecr4.set(0x1 << (err.ch() - 31));
-        self.iecr1.set(0x1 << err.ch());
+        let ch = err.ch();
+        self.bank(ch).iecr.set(0x1 << (ch % 32));
     }
 
     pub fn enable_interrupt(&self, err: EsmError) {
-        self.iesr4.set(0x1 << (err.ch() - 31));
-        self.iesr1.set(0x1 << err.ch());
+        let ch = err.ch();
+        self.bank(ch).iesr.set(0x1 << (ch % 32));
     }
 
     pub fn disable_error(&self, err: EsmError) {
-        self.iepcr4.set(0x1 << (err.ch() - 31));
-        self.depapr1.set(0x1 << err.ch());
+        let ch = err.ch();
+        self.bank(ch).iepcr.set(0x1 << (ch % 32));
     }
 
     pub fn enable_error(&self, err: EsmError) {
-        self.iepsr4.set(0x1 << (err.ch() - 31));
-        self.eepapr1.set(0x1 << err.ch());
+        let ch = err.ch();
+        self.bank(ch).iepsr.set(0x1 << (ch % 32));
+    }
+
+    /// Group 1 channel interrupt on the high (true) or low level line
+    pub fn interrupt_level(&self, err: EsmError, high: bool) {
+        let ch = err.ch();
+        let bank = self.bank(ch);
+        if high {
+            bank.ilsr.set(0x1 << (ch % 32));
+        } else {
+            bank.ilcr.set(0x1 << (ch % 32));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    const IEPSR1: u32 = 0xFFFF_F500;
+    const IEPCR1: u32 = 0xFFFF_F504;
+    const IESR1: u32 = 0xFFFF_F508;
+    const IECR1: u32 = 0xFFFF_F50C;
+    const SR1: u32 = 0xFFFF_F518;
+    const SR2: u32 = 0xFFFF_F51C;
+    const SR3: u32 = 0xFFFF_F520;
+    const EPSR: u32 = 0xFFFF_F524;
+    const IOFFHR: u32 = 0xFFFF_F528;
+    const LTCPR: u32 = 0xFFFF_F534;
+    const EKR: u32 = 0xFFFF_F538;
+    const SSR2: u32 = 0xFFFF_F53C;
+    const IEPCR4: u32 = 0xFFFF_F544;
+    const IESR4: u32 = 0xFFFF_F548;
+    const IECR4: u32 = 0xFFFF_F54C;
+    const SR4: u32 = 0xFFFF_F558;
+
+    fn esm() -> &'static Esm {
+        Esm::new(unsafe { ESM::steal() })
+    }
+
+    #[test]
+    fn reset() {
+        mock::reset();
+        let esm = esm();
+        // ERROR pin active (EPSF = 0): it gets reset
+        esm.reset(0x4000);
+        for reg in [IEPCR1, IECR1, IEPCR4, IECR4, SR1, SR2, SR3, SSR2, SR4] {
+            assert_eq!(mock::writes_to(reg), [0xFFFF_FFFF], "{:#x}", reg);
+        }
+        assert_eq!(mock::writes_to(EKR), [0x5]);
+        assert_eq!(mock::writes_to(LTCPR), [0x3FFF]);
+
+        mock::clear_log();
+        mock::poke(EPSR, 0x1);
+        esm.reset(0x4000);
+        assert_eq!(mock::writes_to(EKR), [0x0]);
+    }
+
+    #[test]
+    fn channels() {
+        mock::reset();
+        let esm = esm();
+        // group 1, channel 30
+        esm.enable_error(EsmError::DCC1);
+        esm.enable_interrupt(EsmError::DCC1);
+        assert_eq!(mock::writes_to(IEPSR1), [0x1 << 30]);
+        assert_eq!(mock::writes_to(IESR1), [0x1 << 30]);
+        // group 1, channel 37: second bank
+        esm.enable_interrupt(EsmError::IOMMAccess);
+        esm.disable_error(EsmError::IOMMAccess);
+        assert_eq!(mock::writes_to(IESR4), [0x1 << 5]);
+        assert_eq!(mock::writes_to(IEPCR4), [0x1 << 5]);
+
+        mock::poke(SR4, 0x1 << 5);
+        assert!(esm.error_is_set(EsmError::IOMMAccess));
+        assert!(!esm.error_is_set(EsmError::DCC1));
+        esm.clear_error(EsmError::IOMMAccess);
+        assert_eq!(mock::writes_to(SR4), [0x1 << 5]);
+
+        // group 2, channel 2
+        let lock_step = EsmError::from(32 + 2);
+        assert_eq!((lock_step.group(), lock_step.ch()), (EsmGroup::Two as usize, 2));
+        esm.clear_error(lock_step);
+        assert_eq!(mock::writes_to(SR2), [0x1 << 2]);
+    }
+
+    #[test]
+    fn pending_interrupt() {
+        mock::reset();
+        let esm = esm();
+        mock::poke(IOFFHR, 31);
+        assert_eq!(esm.high_level_interrupt(), 30);
+        assert!(matches!(EsmError::from(30), EsmError::DCC1));
     }
 }
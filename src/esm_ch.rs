@@ -1,13 +1,25 @@
-///! ESM Channel Assignments for TMS5700714
-///!
-///! Texas Instruments TMS5700714APGEQQ1 datasheet
-///! SPNS226C – JUNE 2013 – REVISED NOVEMBER 2014
-///!
-///! Table 6-31. ESM Channel Assignments
+//! ESM Channel Assignments
+//!
+//! Texas Instruments TMS5700714APGEQQ1 datasheet
+//! SPNS226C – JUNE 2013 – REVISED NOVEMBER 2014
+//!
+//! Table 6-31. ESM Channel Assignments
+//!
+//! The ESM channel tables of the other Cortex-R4F datasheets
+//! (TMS570LS31x/21x SPNS162, TMS570LS12x SPNS185, RM48 SPNS177,
+//! RM46 SPNS184, TMS570LS04x SPNS186) use the same numbering, except
+//! for the MibSPI5 RAM parity error: channel 24 on those parts, 25 on
+//! the TMS570LS0714. Channels of modules the selected device doesn't
+//! have (e.g. DMA, DCAN3 or MibADC2 on the TMS570LS0432) are not defined.
+//!
+//! Cortex-R5F devices (TMS570LC4357, RM57L843) have their own assignment:
+//! Texas Instruments TMS570LC4357 datasheet (SPNS195), "ESM Channel
+//! Assignments" table.
+//! Channels with the same meaning keep the same name on both families.
 
 macro_rules! map_ch_group {
     ($ch: expr, $group: expr) => {
-        (($group as isize) << 24) | ($ch as isize)
+        (($group as i32) << 24) | ($ch as i32)
     };
 }
 
@@ -18,15 +30,21 @@ pub enum EsmGroup {
     Four = 3,
 }
 
+#[cfg(not(cortex_r5))]
 #[derive(Copy, Clone)]
+#[repr(i32)]
 pub enum EsmError {
     /// MibADC2 - RAM parity error
+    #[cfg(not(feature = "tms570ls0432"))]
     MibADC2Ram = map_ch_group!(EsmGroup::One, 1),
     /// DMA - MPU configuration violation
+    #[cfg(not(feature = "tms570ls0432"))]
     DmaMpu = map_ch_group!(EsmGroup::One, 2),
     /// DMA - control packet RAM parity error
+    #[cfg(not(feature = "tms570ls0432"))]
     DmaParity = map_ch_group!(EsmGroup::One, 3),
     /// DMA - error on DMA read access, imprecise error
+    #[cfg(not(feature = "tms570ls0432"))]
     DmaErrorImprecise = map_ch_group!(EsmGroup::One, 5),
     /// FMC - correctable ECC error: bus1 and bus2 interfaces
     /// (does not include accesses to Bank 7)
@@ -34,31 +52,40 @@ pub enum EsmError {
     /// N2HET1 - RAM parity error
     N2HET1Parity = map_ch_group!(EsmGroup::One, 7),
     /// HET TU1/HET TU2 - dual-control packet RAM parity error
+    #[cfg(not(feature = "tms570ls0432"))]
     HETTUxParity = map_ch_group!(EsmGroup::One, 8),
     /// HET TU1/HET TU2 - MPU configuration violation
+    #[cfg(not(feature = "tms570ls0432"))]
     HETTUxMpuErr = map_ch_group!(EsmGroup::One, 9),
     /// PLL1 - Slip
     Pll1Splip = map_ch_group!(EsmGroup::One, 10),
     /// Clock Monitor - oscillator fail
     OscFail = map_ch_group!(EsmGroup::One, 11),
     /// DMA - error on DMA write access, imprecise error
+    #[cfg(not(feature = "tms570ls0432"))]
     DmaErrorPrecise = map_ch_group!(EsmGroup::One, 13),
     /// VIM RAM - parity error
     VimParity = map_ch_group!(EsmGroup::One, 15),
     /// MibSPI1 - RAM parity error
     MibSPI1Parity = map_ch_group!(EsmGroup::One, 17),
     /// MibSPI3 - RAM parity error
+    #[cfg(not(feature = "tms570ls0432"))]
     MibSPI3Parity = map_ch_group!(EsmGroup::One, 18),
     /// MibADC1 - RAM parity error
     MibADC1Parity = map_ch_group!(EsmGroup::One, 19),
     /// DCAN1 - RAM parity error
     DCAN1Parity = map_ch_group!(EsmGroup::One, 21),
     /// DCAN3 - RAM parity error
+    #[cfg(not(feature = "tms570ls0432"))]
     DCAN3Parity = map_ch_group!(EsmGroup::One, 22),
     /// DCAN2 - RAM parity error
     DCAN2Parity = map_ch_group!(EsmGroup::One, 23),
     /// MibSPI5 - RAM parity error
+    #[cfg(feature = "tms570ls0714")]
     MibSPI5Parity = map_ch_group!(EsmGroup::One, 25),
+    /// MibSPI5 - RAM parity error
+    #[cfg(not(any(feature = "tms570ls0432", feature = "tms570ls0714")))]
+    MibSPI5Parity = map_ch_group!(EsmGroup::One, 24),
     /// RAM even bank (B0TCM) - correctable ECC error
     RamEvenCorrectableECC = map_ch_group!(EsmGroup::One, 26),
     /// CPU - self-test failed
@@ -70,6 +97,7 @@ pub enum EsmError {
     /// CCM-R4 - self-test failed
     CCMR4SelfTest = map_ch_group!(EsmGroup::One, 31),
     /// N2HET2 - RAM parity error
+    #[cfg(not(feature = "tms570ls0432"))]
     N2HET2Parity = map_ch_group!(EsmGroup::One, 34),
     /// FMC - correctable ECC error (Bank 7 access)
     FMCCorrectableEcc = map_ch_group!(EsmGroup::One, 35),
@@ -93,6 +121,7 @@ pub enum EsmError {
     /// will also be set.
     EFuseSelfTestError = map_ch_group!(EsmGroup::One, 41),
     /// DCC2 - error
+    #[cfg(not(feature = "tms570ls0432"))]
     DCC2Error = map_ch_group!(EsmGroup::One, 62),
     /// CCMR4 - dual-CPU lock-step error
     CCMR4LockStep = map_ch_group!(EsmGroup::Two, 2),
@@ -121,6 +150,81 @@ pub enum EsmError {
     FMCUncorrectableECC = map_ch_group!(EsmGroup::Three, 7),
 }
 
+#[cfg(cortex_r5)]
+#[derive(Copy, Clone)]
+#[repr(i32)]
+pub enum EsmError {
+    /// MibADC2 - RAM parity error
+    MibADC2Ram = map_ch_group!(EsmGroup::One, 1),
+    /// DMA - MPU configuration violation
+    DmaMpu = map_ch_group!(EsmGroup::One, 2),
+    /// DMA - ECC uncorrectable error
+    DmaEcc = map_ch_group!(EsmGroup::One, 3),
+    /// EPC - correctable error
+    EpcCorrectable = map_ch_group!(EsmGroup::One, 4),
+    /// L2FMC - correctable ECC error
+    FmcError = map_ch_group!(EsmGroup::One, 6),
+    /// N2HET1 - RAM parity error
+    N2HET1Parity = map_ch_group!(EsmGroup::One, 7),
+    /// HET TU1/HET TU2 - dual-control packet RAM parity error
+    HETTUxParity = map_ch_group!(EsmGroup::One, 8),
+    /// HET TU1/HET TU2 - MPU configuration violation
+    HETTUxMpuErr = map_ch_group!(EsmGroup::One, 9),
+    /// PLL1 - Slip
+    Pll1Splip = map_ch_group!(EsmGroup::One, 10),
+    /// Clock Monitor - oscillator fail
+    OscFail = map_ch_group!(EsmGroup::One, 11),
+    /// VIM RAM - ECC uncorrectable error
+    VimParity = map_ch_group!(EsmGroup::One, 15),
+    /// MibSPI1 - ECC uncorrectable error
+    MibSPI1Ecc = map_ch_group!(EsmGroup::One, 17),
+    /// MibSPI3 - ECC uncorrectable error
+    MibSPI3Ecc = map_ch_group!(EsmGroup::One, 18),
+    /// MibADC1 - RAM parity error
+    MibADC1Parity = map_ch_group!(EsmGroup::One, 19),
+    /// DCAN1 - ECC uncorrectable error
+    DCAN1Ecc = map_ch_group!(EsmGroup::One, 21),
+    /// DCAN3 - ECC uncorrectable error
+    DCAN3Ecc = map_ch_group!(EsmGroup::One, 22),
+    /// DCAN2 - ECC uncorrectable error
+    DCAN2Ecc = map_ch_group!(EsmGroup::One, 23),
+    /// MibSPI5 - ECC uncorrectable error
+    MibSPI5Ecc = map_ch_group!(EsmGroup::One, 24),
+    /// L2RAMW - correctable ECC error
+    L2RamCorrectableECC = map_ch_group!(EsmGroup::One, 26),
+    /// CPU - self-test (LBIST) failed
+    CPUSelfTest = map_ch_group!(EsmGroup::One, 27),
+    /// DCC1 - error
+    DCC1 = map_ch_group!(EsmGroup::One, 30),
+    /// CCM-R5F - self-test failed
+    CCMR5SelfTest = map_ch_group!(EsmGroup::One, 31),
+    /// N2HET2 - RAM parity error
+    N2HET2Parity = map_ch_group!(EsmGroup::One, 34),
+    /// IOMM - Access to unimplemented location in IOMM frame, or
+    /// write access detected in unprivileged mode
+    IOMMAccess = map_ch_group!(EsmGroup::One, 37),
+    /// Power domain controller compare error
+    PowerDomainCompare = map_ch_group!(EsmGroup::One, 38),
+    /// Power domain controller self-test error
+    PowerDomainSelfTest = map_ch_group!(EsmGroup::One, 39),
+    /// eFuse Controller Error
+    EFuseError = map_ch_group!(EsmGroup::One, 40),
+    /// eFuse Controller - Self Test Error
+    EFuseSelfTestError = map_ch_group!(EsmGroup::One, 41),
+    /// PLL2 - Slip
+    Pll2Slip = map_ch_group!(EsmGroup::One, 42),
+    /// DCC2 - error
+    DCC2Error = map_ch_group!(EsmGroup::One, 62),
+    /// CCM-R5F - dual-CPU lock-step error
+    CCMR5LockStep = map_ch_group!(EsmGroup::Two, 2),
+    /// Windowed Watchdog (WWD) violation
+    WWD = map_ch_group!(EsmGroup::Two, 24),
+    /// eFuse Farm - autoload error
+    EfuseAutoload = map_ch_group!(EsmGroup::Three, 1),
+    /// L2FMC - uncorrectable ECC error
+    FMCUncorrectableECC = map_ch_group!(EsmGroup::Three, 7),
+}
+
 impl EsmError {
     pub fn ch(self) -> usize {
         (self as usize) >> 24
@@ -131,13 +235,15 @@ impl EsmError {
     }
 }
 
+/// Error from a pending interrupt offset (`INTOFFH`/`INTOFFL` - 1):
+/// group 1 channels 0-31, group 2 channels 0-31, then the group 1
+/// channels 32-63 and (Cortex-R5F) 64-95.
 impl From<u8> for EsmError {
     fn from(v: u8) -> Self {
         let e = match v {
             0  ..= 31 => map_ch_group!(EsmGroup::One, v),
             32 ..= 63 => map_ch_group!(EsmGroup::Two, v - 32),
-            64 ..= 95 => map_ch_group!(EsmGroup::Three, v - 64),
-            96 ..= 127 => map_ch_group!(EsmGroup::Four, v - 96),
+            64 ..= 127 => map_ch_group!(EsmGroup::One, v - 32),
             _ => unreachable!(),
         };
         unsafe { core::mem::transmute(e) }
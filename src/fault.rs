@@ -0,0 +1,229 @@
+//!
+//! Undefined instruction, prefetch and data abort reports
+//!
+//! Default exception handlers (`undef_entry`, `prefetch_abort` and
+//! `custom_dabort`, see fault.s) collect the CP15 fault registers,
+//! decode them into a `FaultReport` and pass it to a user hook.
+//! Handlers are weak symbols, so an application can still provide its
+//! own ones.
+//!
+//! Note: handlers run on the Abort/Undef mode stacks (ABORT_SP, UNDEF_SP
+//! in the linker script), so the hook must be small.
+//!
+//! Reference: Cortex-R4 and Cortex-R4F Technical Reference Manual (r1p4),
+//! Chapter 4.3.15 "c5, Fault Status Registers" and 4.3.16 "c6, Fault
+//! Address Registers".
+
+use core::arch::asm;
+
+/// FSR: Write not Read (DFSR only)
+const FSR_WNR: u32 = 0x1 << 11;
+/// FSR: AXI Slave error (SLVERR) instead of decode error (DECERR)
+const FSR_SD: u32 = 0x1 << 12;
+/// AFSR: Recoverable error
+const AFSR_RECOVERABLE: u32 = 0x1 << 21;
+/// SPSR: Thumb state
+const SPSR_T: u32 = 0x1 << 5;
+
+/// Exception that generated the report
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FaultKind {
+    Undefined = 0,
+    PrefetchAbort = 1,
+    DataAbort = 2,
+}
+
+/// Decoded fault status (FS field of DFSR/IFSR)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FaultStatus {
+    /// Access outside any MPU region
+    Background,
+    /// Unaligned access
+    Alignment,
+    /// MPU permission fault
+    Permission,
+    /// AXI bus error. `slave` is true for SLVERR, false for DECERR
+    External { slave: bool },
+    /// Parity or ECC error on a memory access
+    Ecc,
+    /// Debug event
+    Debug,
+    /// Fault status not decoded (raw FS value)
+    Unknown(u32),
+}
+
+/// Memory reporting an ECC/parity error (AxFSR SIDE field)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EccSource {
+    Cache,
+    Atcm,
+    Btcm,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FaultReport {
+    pub kind: FaultKind,
+    /// Fault status. Not meaningful for undefined instructions.
+    pub status: FaultStatus,
+    /// Precise abort: `pc` is the instruction that caused the fault
+    pub precise: bool,
+    /// Faulting access was a write (data aborts only)
+    pub write: bool,
+    /// Memory that reported the ECC error, if any
+    pub ecc_source: Option<EccSource>,
+    /// The ECC error is correctable (AxFSR recoverable bit)
+    pub ecc_recoverable: bool,
+    /// Faulting address (DFAR/IFAR), when valid
+    pub address: Option<u32>,
+    /// Faulting instruction address, from the banked LR
+    pub pc: u32,
+    /// CPSR at the time of the exception
+    pub spsr: u32,
+    /// Raw DFSR/IFSR
+    pub fsr: u32,
+    /// Raw ADFSR/AIFSR
+    pub afsr: u32,
+}
+
+#[allow(non_snake_case)]
+extern "Rust" {
+    // Handlers registered with #[exception], or
+    // tms570_default_fault_handler (see bsp.ld)
+    fn __tms570_exception_Undef(report: &FaultReport);
+    fn __tms570_exception_PrefetchAbort(report: &FaultReport);
+    fn __tms570_exception_DataAbort(report: &FaultReport);
+}
+
+/// User hook called with the fault report, when no handler is
+/// registered with `#[exception]`.
+/// The CPU stops (WFI loop) when the hook returns.
+static mut FAULT_HOOK: fn(&FaultReport) = default_hook;
+
+fn default_hook(_report: &FaultReport) {}
+
+/// Install the fault hook
+pub unsafe fn set_hook(hook: fn(&FaultReport)) {
+    FAULT_HOOK = hook;
+}
+
+#[inline(always)]
+fn read_data_fault() -> (u32, u32, u32) {
+    let (fsr, far, afsr): (u32, u32, u32);
+    unsafe {
+        asm!(
+            "mrc p15, #0, {}, c5, c0, #0",
+            "mrc p15, #0, {}, c6, c0, #0",
+            "mrc p15, #0, {}, c5, c1, #0",
+            out(reg) fsr,
+            out(reg) far,
+            out(reg) afsr,
+        );
+    }
+    (fsr, far, afsr)
+}
+
+#[inline(always)]
+fn read_instruction_fault() -> (u32, u32, u32) {
+    let (fsr, far, afsr): (u32, u32, u32);
+    unsafe {
+        asm!(
+            "mrc p15, #0, {}, c5, c0, #1",
+            "mrc p15, #0, {}, c6, c0, #2",
+            "mrc p15, #0, {}, c5, c1, #1",
+            out(reg) fsr,
+            out(reg) far,
+            out(reg) afsr,
+        );
+    }
+    (fsr, far, afsr)
+}
+
+fn decode_status(fsr: u32) -> (FaultStatus, bool) {
+    let fs = ((fsr >> 6) & 0x10) | (fsr & 0xF);
+    match fs {
+        0b00000 => (FaultStatus::Background, true),
+        0b00001 => (FaultStatus::Alignment, true),
+        0b00010 => (FaultStatus::Debug, true),
+        0b01101 => (FaultStatus::Permission, true),
+        0b01000 => (FaultStatus::External { slave: fsr & FSR_SD != 0 }, true),
+        0b10110 => (FaultStatus::External { slave: fsr & FSR_SD != 0 }, false),
+        0b11001 => (FaultStatus::Ecc, true),
+        0b11000 => (FaultStatus::Ecc, false),
+        _ => (FaultStatus::Unknown(fs), true),
+    }
+}
+
+fn decode_ecc_source(afsr: u32) -> Option<EccSource> {
+    match (afsr >> 22) & 0x3 {
+        0x0 => Some(EccSource::Cache),
+        0x1 => Some(EccSource::Atcm),
+        0x2 => Some(EccSource::Btcm),
+        _ => None,
+    }
+}
+
+impl FaultReport {
+    /// Build a report reading the CP15 fault registers
+    pub fn collect(kind: FaultKind, pc: u32, spsr: u32) -> FaultReport {
+        let (fsr, far, afsr) = match kind {
+            FaultKind::Undefined => (0x0, 0x0, 0x0),
+            FaultKind::PrefetchAbort => read_instruction_fault(),
+            FaultKind::DataAbort => read_data_fault(),
+        };
+        let (status, precise) = decode_status(fsr);
+        let ecc = kind != FaultKind::Undefined && status == FaultStatus::Ecc;
+        // FAR is not updated on imprecise aborts
+        let address = if kind != FaultKind::Undefined && precise {
+            Some(far)
+        } else {
+            None
+        };
+
+        FaultReport {
+            kind,
+            status,
+            precise,
+            write: kind == FaultKind::DataAbort && fsr & FSR_WNR != 0,
+            ecc_source: if ecc { decode_ecc_source(afsr) } else { None },
+            ecc_recoverable: ecc && afsr & AFSR_RECOVERABLE != 0,
+            address,
+            pc,
+            spsr,
+            fsr,
+            afsr,
+        }
+    }
+
+    /// Faulting instruction was executed in Thumb state
+    pub fn thumb(&self) -> bool {
+        self.spsr & SPSR_T != 0
+    }
+}
+
+/// Used when no handler is registered with `#[exception]`:
+/// forward the report to the fault hook.
+#[no_mangle]
+pub fn tms570_default_fault_handler(report: &FaultReport) {
+    unsafe { FAULT_HOOK(report) }
+}
+
+/// Common entry point of the default handlers (see fault.s).
+/// `pc` is the faulting instruction address, already adjusted from the
+/// banked LR by the assembly entry code.
+#[no_mangle]
+pub unsafe extern "C" fn tms570_fault_report(kind: u32, pc: u32, spsr: u32) -> ! {
+    let kind = match kind {
+        0 => FaultKind::Undefined,
+        1 => FaultKind::PrefetchAbort,
+        _ => FaultKind::DataAbort,
+    };
+    let report = FaultReport::collect(kind, pc, spsr);
+    match kind {
+        FaultKind::Undefined => __tms570_exception_Undef(&report),
+        FaultKind::PrefetchAbort => __tms570_exception_PrefetchAbort(&report),
+        FaultKind::DataAbort => __tms570_exception_DataAbort(&report),
+    }
+    loop {
+        cortexr4::asm::wfi();
+    }
+}
@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+    .section .text
+    .syntax unified
+    .cpu cortex-r4
+    .arm
+
+@ Default undefined instruction, prefetch abort and data abort handlers.
+@ All of them end up in tms570_fault_report(kind, pc, spsr) that never
+@ returns. Registers are pushed on the mode stack so that they can be
+@ inspected with a debugger.
+
+    .extern tms570_fault_report
+    .extern tms570_vfp_lazy_trap
+
+    .weak undef_entry
+    .type undef_entry, %function
+
+undef_entry:
+    push    {r0, r1, r12, lr}
+    bl      tms570_vfp_lazy_trap    @ lazy VFP save (see vfp.s)?
+    pop     {r0, r1, r12, lr}
+    subseq  pc, lr, #4              @ yes: retry the VFP instruction
+    stmfd   sp!, {r0 - r12, lr}
+    mrs     r2, spsr
+    tst     r2, #0x20           @ Thumb state: undefined instruction is 2 bytes back
+    subeq   r1, lr, #4
+    subne   r1, lr, #2
+    mov     r0, #0              @ FaultKind::Undefined
+    b       tms570_fault_report
+
+    .weak prefetch_abort
+    .type prefetch_abort, %function
+
+prefetch_abort:
+    stmfd   sp!, {r0 - r12, lr}
+    sub     r1, lr, #4
+    mrs     r2, spsr
+    mov     r0, #1              @ FaultKind::PrefetchAbort
+    b       tms570_fault_report
+
+@ Called by _dabort (dabort.s) with r0 = faulting pc, r1 = spsr
+@ when the abort is not an expected ECC self-test error.
+
+    .weak custom_dabort
+    .type custom_dabort, %function
+
+custom_dabort:
+    mov     r2, r1
+    mov     r1, r0
+    mov     r0, #2              @ FaultKind::DataAbort
+    b       tms570_fault_report
@@ -1,4 +1,6 @@
 use crate::config;
+use crate::peripherals::FLASH;
+use crate::regs::{map, Reg};
 use vcell::VolatileCell;
 
 /// Read LPO TRIM value from OTP memory
@@ -15,17 +17,17 @@ pub fn lpo_trim_available() -> bool {
 
 /// Retrive device part number as ASCII digit
 pub fn dev_part_number_symb(pn: &mut [u8; 32]) {
-    const LPO_PN_SYMBOLIZATION: *const u8 = 0xF008_01E0 as *const u8;
-    let mut reg = LPO_PN_SYMBOLIZATION;
+    const LPO_PN_SYMBOLIZATION: *const [Reg; 8] = 0xF008_01E0 as *const [Reg; 8];
+    let otp = unsafe { map(LPO_PN_SYMBOLIZATION) };
 
-    for p in pn.iter_mut() {
-        unsafe {
-            *p = ::core::ptr::read_volatile(reg);
-            reg = reg.offset(1);
-        }
+    for (p, word) in pn.chunks_mut(4).zip(otp.iter()) {
+        // Native byte order keeps the characters in memory order
+        p.copy_from_slice(&word.get().to_ne_bytes());
     }
 }
 
+/// F021 Flash Module Controller (Cortex-R4F parts)
+#[cfg(not(cortex_r5))]
 #[repr(C)]
 #[allow(non_snake_case)]
 pub struct Flash {
@@ -80,8 +82,64 @@ pub struct Flash {
     EESTATUS: VolatileCell<u32>,
     EEUNCERRADD: VolatileCell<u32>,
 }
+
+/// Level 2 Flash Module Controller (L2FMC, Cortex-R5F parts).
+/// Same base address, FSM and bank power registers of the F021 FMC, but
+/// a different read control and ECC register set.
+#[cfg(cortex_r5)]
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct Flash {
+    FRDCNTL: VolatileCell<u32>,
+    _reserved1: VolatileCell<u32>,
+    EEFEDACCTRL1: VolatileCell<u32>,
+    _reserved2: [VolatileCell<u32>; 2],
+    FEDACPASTATUS: VolatileCell<u32>,
+    FEDACPBSTATUS: VolatileCell<u32>,
+    FEDACGBLSTATUS: VolatileCell<u32>,
+    _reserved3: VolatileCell<u32>,
+    FEDACSDIS: VolatileCell<u32>,
+    FPRIMADDTAG: VolatileCell<u32>,
+    FDUPADDTAG: VolatileCell<u32>,
+    FBPROT: VolatileCell<u32>,
+    FBSE: VolatileCell<u32>,
+    FBBUSY: VolatileCell<u32>,
+    FBAC: VolatileCell<u32>,
+    FBPWRMODE: VolatileCell<u32>,
+    FBPRDY: VolatileCell<u32>,
+    FPAC1: VolatileCell<u32>,
+    _reserved4: VolatileCell<u32>,
+    FMAC: VolatileCell<u32>,
+    FMSTAT: VolatileCell<u32>,
+    FEMUDMSW: VolatileCell<u32>,
+    FEMUDLSW: VolatileCell<u32>,
+    FEMUECC: VolatileCell<u32>,
+    FLOCK: VolatileCell<u32>,
+    _reserved5: VolatileCell<u32>,
+    FDIAGCTRL: VolatileCell<u32>,
+    _reserved6: VolatileCell<u32>,
+    FRAWADDR: VolatileCell<u32>,
+    _reserved7: VolatileCell<u32>,
+    FPAROVR: VolatileCell<u32>,
+    _reserved8: [VolatileCell<u32>; 12],
+    RCRVALID: VolatileCell<u32>,
+    ACCTHRESHOLD: VolatileCell<u32>,
+    _reserved9: VolatileCell<u32>,
+    FEDACSDIS2: VolatileCell<u32>,
+    _reserved10: [VolatileCell<u32>; 4],
+    RCRVALUE0: VolatileCell<u32>,
+    RCRVALUE1: VolatileCell<u32>,
+    _reserved11: [VolatileCell<u32>; 108],
+    FSMWRENA: VolatileCell<u32>,
+    _reserved12: [VolatileCell<u32>; 11],
+    EEPROMCONFIG: VolatileCell<u32>,
+    _reserved13: VolatileCell<u32>,
+    FSMSECTOR1: VolatileCell<u32>,
+    FSMSECTOR2: VolatileCell<u32>,
+}
 const FLASH_BASE_ADDR: *const Flash = 0xFFF8_7000 as *const Flash;
 
+#[derive(Clone, Copy)]
 pub enum FlashWPowerModes {
     /// flash bank power mode sleep
     Sleep = 0x0,
@@ -108,8 +166,8 @@ pub enum DiagModes {
 }
 
 impl Flash {
-    pub unsafe fn new() -> &'static Flash {
-        &*FLASH_BASE_ADDR
+    pub fn new(_flash: FLASH) -> &'static Flash {
+        unsafe { map(FLASH_BASE_ADDR) }
     }
 
     /// Unlock FSM registers for writing
@@ -132,17 +190,30 @@ impl Flash {
     /// # Arguments
     ///
     /// * `ws` The random read wait state bits indicate how many wait states
-    /// are added to a flash read access. In Pipeline mode there is always one wait.
+    ///   are added to a flash read access. In Pipeline mode there is always one wait.
     ///
     /// * `address_ws` Address Setup Wait State is enabled. Address is latched one
-    /// cycle before decoding to determine pipeline hit or miss. Address Setup Wait State
-    /// is only available in pipeline mode
+    ///   cycle before decoding to determine pipeline hit or miss. Address Setup Wait State
+    ///   is only available in pipeline mode. Not available on the L2FMC (ignored).
+    ///
+    /// * `pipeline` Pipeline mode. On the L2FMC it enables the prefetch
+    ///   of both ports (PFUENA and PFUENB).
     pub fn setup(&self, power: FlashWPowerModes, ws: u8, address_ws: bool, pipeline: bool) {
-        let aswsten = (address_ws as u32) << 4;
         let rwait = u32::from(ws & 0xF) << 8;
-        let enpipe = pipeline as u32;
 
-        self.FRDCNTL.set(rwait | aswsten | enpipe);
+        #[cfg(not(cortex_r5))]
+        {
+            let aswsten = (address_ws as u32) << 4;
+            let enpipe = pipeline as u32;
+            self.FRDCNTL.set(rwait | aswsten | enpipe);
+        }
+
+        #[cfg(cortex_r5)]
+        {
+            let _ = address_ws;
+            let prefetch = if pipeline { 0x3 } else { 0x0 };
+            self.FRDCNTL.set(rwait | prefetch);
+        }
 
         // Setup flash access wait states for bank 7
         self.unlock_fsm();
@@ -153,9 +224,18 @@ impl Flash {
         );
         self.lock_fsm();
 
-        // Setup flash bank power modes
+        self.set_power_mode(power);
+    }
+
+    /// Fallback power mode of the banks (0, 1 and 7): the mode entered
+    /// when not accessed. An access wakes a bank up.
+    pub fn set_power_mode(&self, power: FlashWPowerModes) {
         let mode = power as u32;
-        self.FBFALLBACK.set(
+        #[cfg(not(cortex_r5))]
+        let fallback = &self.FBFALLBACK;
+        #[cfg(cortex_r5)]
+        let fallback = &self.FBPWRMODE;
+        fallback.set(
             (mode << 14) | // BANK 7
                             (mode << 2)  | // BANK 1
                             mode,
@@ -1,226 +1,828 @@
-///! The general-purpose input/output (GIO) module provides
-///! the TMS570 familyof devices with input/output (I/O) capability.
-///! The I/O pins are bidirectional and bit-programmable.
-///! The GIO module also supports external interrupt capability.
-use vcell::VolatileCell;
-
-#[repr(C)]
-struct GioRegisters {
-    /// Global Control
-    gcr0: VolatileCell<u32>,
-    /// Reserved
-    _reserved: VolatileCell<u32>,
-    /// Interrupt Detect
-    intdet: VolatileCell<u32>,
-    /// Interrupt Polarity
-    pol: VolatileCell<u32>,
-    /// Interrupt Enable Set
-    enaset: VolatileCell<u32>,
-    /// Interrupt Enable Clear
-    enaclr: VolatileCell<u32>,
-    /// Interrupt Priority Set
-    lvlset: VolatileCell<u32>,
-    /// Interrupt Priority Clear
-    lvlclr: VolatileCell<u32>,
-    /// Interrupt Flag
-    flg: VolatileCell<u32>,
-    /// Interrupt Offset A
-    off1: VolatileCell<u32>,
-    /// Interrupt Offset B
-    off2: VolatileCell<u32>,
-    /// Emulation 1
-    emu1: VolatileCell<u32>,
-    /// Emulation 2
-    emu2: VolatileCell<u32>,
-}
-const GIO_BASE_ADDR: *const GioRegisters = 0xFFF7_BC00 as *const GioRegisters;
-
-#[repr(C)]
-struct GioPortsRegisters {
-    /// Data Direction
-    dir: VolatileCell<u32>,
-    /// Data Input
-    din: VolatileCell<u32>,
-    /// Data Output
-    dout: VolatileCell<u32>,
-    /// Data Output Set
-    dset: VolatileCell<u32>,
-    /// Data Output Clear
-    dclr: VolatileCell<u32>,
-    /// Open Drain
-    pdr: VolatileCell<u32>,
-    /// Pullup Disable
-    puldis: VolatileCell<u32>,
-    /// Pull Up/Down Selection
-    psl: VolatileCell<u32>,
-}
-const GIO_PORTA_ADDR: *const GioPortsRegisters = 0xFFF7_BC34 as *const GioPortsRegisters;
-const GIO_PORTB_ADDR: *const GioPortsRegisters = 0xFFF7_BC54 as *const GioPortsRegisters;
-const MIBSPI_PORT1_ADDR: *const GioPortsRegisters = 0xFFF7_F418 as *const GioPortsRegisters;
-const MIBSPI_PORT3_ADDR: *const GioPortsRegisters = 0xFFF7_F818 as *const GioPortsRegisters;
-const MIBSPI_PORT5_ADDR: *const GioPortsRegisters = 0xFFF7_FC18 as *const GioPortsRegisters;
-const LIN_PORT_ADDR: *const GioPortsRegisters = 0xFFF7_E440 as *const GioPortsRegisters;
-const SCI_PORT_ADDR: *const GioPortsRegisters = 0xFFF7_E540 as *const GioPortsRegisters;
-const HET_PORT1_ADDR: *const GioPortsRegisters = 0xFFF7_B84C as *const GioPortsRegisters;
-const HET_PORT2_ADDR: *const GioPortsRegisters = 0xFFF7_B94C as *const GioPortsRegisters;
-const I2C_PORT_ADDR: *const GioPortsRegisters = 0xFFF7_D44C as *const GioPortsRegisters;
-
-pub struct Gio {
-    regs: &'static GioRegisters,
-    ports: [&'static GioPortsRegisters; 10],
-}
-
-#[derive(Clone, Copy, PartialEq)]
-pub enum GioDirection {
-    Output = 0x0,
-    Input = 0x1,
-}
-
-#[derive(Clone, Copy, PartialEq, PartialOrd)]
-pub enum GioPorts {
-    /// Gio Port A
-    A = 0,
-    /// Gio Port B
-    B = 1,
-    /// Gio Port 1 from MIBSPI
-    MibSpiPort1 = 2,
-    /// Gio Port 3 from MIBSPI
-    MibSpiPort3 = 3,
-    /// Gio Port 5 from MIBSPI
-    MibSpiPort5 = 4,
-    /// Gio Port from LIN
-    LinPort = 5,
-    /// Gio Port from SCI
-    SciPort = 6,
-    /// Gio Port 1 from HET
-    HetPort1 = 7,
-    /// Gio Port 2 from HET
-    HetPort2 = 8,
-    /// Gio Port 1 from I2C
-    I2cPort1 = 9,
-}
-
-#[derive(Clone, Copy)]
-pub enum Edge {
-    /// Falling edge (high to low)
-    Falling,
-    /// Rising edge (low to high)
-    Rising,
-}
-
-#[derive(Clone, Copy)]
-pub enum Pull {
-    /// Pull DOWN
-    Down = 0x0,
-    /// Pull UP
-    Up = 0x1,
-}
-
-impl Gio {
-    pub fn new() -> Gio {
-        let gio = Gio {
-            regs: unsafe { &*GIO_BASE_ADDR },
-            ports: unsafe {
-                [
-                    &*GIO_PORTA_ADDR,
-                    &*GIO_PORTB_ADDR,
-                    &*MIBSPI_PORT1_ADDR,
-                    &*MIBSPI_PORT3_ADDR,
-                    &*MIBSPI_PORT5_ADDR,
-                    &*LIN_PORT_ADDR,
-                    &*SCI_PORT_ADDR,
-                    &*HET_PORT1_ADDR,
-                    &*HET_PORT2_ADDR,
-                    &*I2C_PORT_ADDR,
-                ]
-            },
-        };
-        // Reset it (if not already out of reset)
-        if gio.regs.gcr0.get() == 0x0 {
-            gio.reset();
-        }
-        gio
-    }
-
-    /// Force GIO module reset.
-    /// Struct constructor put GIO module out of reset, so usually
-    /// there is no need to explicit reset the controller.
-    pub fn reset(&self) {
-        self.regs.gcr0.set(0x1);
-        self.regs.enaclr.set(0xFF);
-        self.regs.lvlclr.set(0xFF);
-    }
-
-    /// Configure GIO direction.
-    pub fn direction(&self, port: GioPorts, n: usize, dir: GioDirection) {
-        let d = self.ports[port as usize].dir.get();
-        match dir {
-            GioDirection::Input => self.ports[port as usize].dir.set(d & !(0x1 << n)),
-            GioDirection::Output => self.ports[port as usize].dir.set(d | 0x1 << n),
-        }
-    }
-
-    pub fn set(&self, port: GioPorts, n: usize, on: bool) {
-        if on {
-            self.ports[port as usize].dset.set(0x1 << n);
-        } else {
-            self.ports[port as usize].dclr.set(0x1 << n);
-        }
-    }
-
-    pub fn toogle(&self, port: GioPorts, n: usize) {
-        self.set(port, n, !self.get(port, n))
-    }
-
-    pub fn set_all(&self, port: GioPorts, v: u32) {
-        self.ports[port as usize].dout.set(v)
-    }
-
-    pub fn get(&self, port: GioPorts, n: usize) -> bool {
-        (self.ports[port as usize].din.get() >> n) & 0x1 == 0x1
-    }
-
-    pub fn get_all(&self, port: GioPorts) -> u32 {
-        self.ports[port as usize].din.get()
-    }
-
-    /// Enable/Disable pull up/down functionality.
-    /// Function has effect only when GIO pin is an input pin
-    pub fn pull_enable(&self, port: GioPorts, n: usize, enable: bool) {
-        self.ports[port as usize].puldis.set((enable as u32) << n);
-    }
-
-    /// Configure pin a in pull up or pull down functionality.
-    /// No need to explicity invoke pull_enable(true).
-    pub fn pull(&self, port: GioPorts, n: usize, p: Pull) {
-        self.ports[port as usize].psl.set((p as u32) << n);
-        self.pull_enable(port, n, true);
-    }
-
-    pub fn open_drain(&self, port: GioPorts, n: usize, od: bool) {
-        let pdr = self.ports[port as usize].pdr.get();
-        self.ports[port as usize].pdr.set(pdr | (od as u32) << n);
-    }
-
-    /// Controls the polarity — rising edge (low to high)
-    /// or falling edge (high to low)
-    /// To ensure recognition of the signal as an edge, the signal must
-    /// maintain the new level for at least one VCLK cycle
-    pub fn edge(&self, port: GioPorts, n: usize, e: Edge) {
-        let mask = ((port as u32) * 8) + (n as u32);
-        match e {
-            Edge::Falling => self.regs.pol.set(self.regs.pol.get() & !mask),
-            Edge::Rising => self.regs.pol.set(self.regs.pol.get() | mask),
-        }
-    }
-
-    pub fn interrupt(&self, port: GioPorts, pin: usize, enable: bool) {
-        let mask = (enable as u32) << (((port as u32) * 8) + (pin as u32));
-        if enable {
-            self.regs.enaset.set(mask);
-        } else {
-            self.regs.flg.set(mask); // clear pending, if any
-            self.regs.enaclr.set(mask);
-        }
-    }
-}
+//! The general-purpose input/output (GIO) module provides
+//! the TMS570 familyof devices with input/output (I/O) capability.
+//! The I/O pins are bidirectional and bit-programmable.
+//! The GIO module also supports external interrupt capability.
+//!
+//! Single pins, of the GIO ports or of the GIO-capable peripheral
+//! ports (MibSPI, LIN/SCI, N2HET, I2C), implement the embedded-hal
+//! digital traits:
+//!
+//! ```ignore
+//! let gio = Gio::new(p.GIO);
+//! let led = gio.pin(GioPorts::A, 2);
+//! led.set_direction(GioDirection::Output);
+//! led.set_high();
+//! ```
+//!
+//! Port A and B pins are also available as type-state pins, where the
+//! pin mode is part of the type and invalid operations (e.g. driving an
+//! input) don't compile:
+//!
+//! ```ignore
+//! let pins = Gio::new(p.GIO).split();
+//! let mut led = pins.pa2.into_push_pull_output();
+//! let button = pins.pb0.into_pull_up_input();
+//! let encoder = pins.pb1.into_rising_edge_interrupt();
+//! encoder.enable_interrupt();
+//! led.set_high();
+//! ```
+//!
+//! Port A and B pins can generate interrupts on the GIO high or low
+//! level VIM channels. `handle_high_level_interrupt()` and
+//! `handle_low_level_interrupt()` dispatch them to the callbacks
+//! registered per pin:
+//!
+//! ```ignore
+//! encoder.set_level(InterruptLevel::High);
+//! encoder.on_interrupt(encoder_step);
+//! encoder.enable_interrupt();
+//!
+//! #[interrupt(VimChannel::GioHigh)]
+//! fn gio_high() {
+//!     gio::handle_high_level_interrupt();
+//! }
+//! ```
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use cortexr4::interrupt;
+use crate::peripherals::GIO;
+use crate::regs::map;
+use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin, ToggleableOutputPin};
+use vcell::VolatileCell;
+
+#[repr(C)]
+struct GioRegisters {
+    /// Global Control
+    gcr0: VolatileCell<u32>,
+    /// Reserved
+    _reserved: VolatileCell<u32>,
+    /// Interrupt Detect
+    intdet: VolatileCell<u32>,
+    /// Interrupt Polarity
+    pol: VolatileCell<u32>,
+    /// Interrupt Enable Set
+    enaset: VolatileCell<u32>,
+    /// Interrupt Enable Clear
+    enaclr: VolatileCell<u32>,
+    /// Interrupt Priority Set
+    lvlset: VolatileCell<u32>,
+    /// Interrupt Priority Clear
+    lvlclr: VolatileCell<u32>,
+    /// Interrupt Flag
+    flg: VolatileCell<u32>,
+    /// Interrupt Offset A
+    off1: VolatileCell<u32>,
+    /// Interrupt Offset B
+    off2: VolatileCell<u32>,
+    /// Emulation 1
+    emu1: VolatileCell<u32>,
+    /// Emulation 2
+    emu2: VolatileCell<u32>,
+}
+const GIO_BASE_ADDR: *const GioRegisters = 0xFFF7_BC00 as *const GioRegisters;
+
+#[repr(C)]
+struct GioPortsRegisters {
+    /// Data Direction
+    dir: VolatileCell<u32>,
+    /// Data Input
+    din: VolatileCell<u32>,
+    /// Data Output
+    dout: VolatileCell<u32>,
+    /// Data Output Set
+    dset: VolatileCell<u32>,
+    /// Data Output Clear
+    dclr: VolatileCell<u32>,
+    /// Open Drain
+    pdr: VolatileCell<u32>,
+    /// Pullup Disable
+    puldis: VolatileCell<u32>,
+    /// Pull Up/Down Selection
+    psl: VolatileCell<u32>,
+}
+const GIO_PORTA_ADDR: *const GioPortsRegisters = 0xFFF7_BC34 as *const GioPortsRegisters;
+const GIO_PORTB_ADDR: *const GioPortsRegisters = 0xFFF7_BC54 as *const GioPortsRegisters;
+const MIBSPI_PORT1_ADDR: *const GioPortsRegisters = 0xFFF7_F418 as *const GioPortsRegisters;
+const MIBSPI_PORT3_ADDR: *const GioPortsRegisters = 0xFFF7_F818 as *const GioPortsRegisters;
+const MIBSPI_PORT5_ADDR: *const GioPortsRegisters = 0xFFF7_FC18 as *const GioPortsRegisters;
+const LIN_PORT_ADDR: *const GioPortsRegisters = 0xFFF7_E440 as *const GioPortsRegisters;
+const SCI_PORT_ADDR: *const GioPortsRegisters = 0xFFF7_E540 as *const GioPortsRegisters;
+const HET_PORT1_ADDR: *const GioPortsRegisters = 0xFFF7_B84C as *const GioPortsRegisters;
+const HET_PORT2_ADDR: *const GioPortsRegisters = 0xFFF7_B94C as *const GioPortsRegisters;
+const I2C_PORT_ADDR: *const GioPortsRegisters = 0xFFF7_D44C as *const GioPortsRegisters;
+
+/// Pin function registers of the peripheral ports (MibSPI SPIPC0,
+/// LIN/SCI SCIPIO0, I2C I2CPFNC), just before the port registers.
+/// N2HET pins are GIO when not driven by the timer program.
+const MIBSPI_FUN1_ADDR: *const VolatileCell<u32> = 0xFFF7_F414 as *const VolatileCell<u32>;
+const MIBSPI_FUN3_ADDR: *const VolatileCell<u32> = 0xFFF7_F814 as *const VolatileCell<u32>;
+const MIBSPI_FUN5_ADDR: *const VolatileCell<u32> = 0xFFF7_FC14 as *const VolatileCell<u32>;
+const LIN_FUN_ADDR: *const VolatileCell<u32> = 0xFFF7_E43C as *const VolatileCell<u32>;
+const SCI_FUN_ADDR: *const VolatileCell<u32> = 0xFFF7_E53C as *const VolatileCell<u32>;
+const I2C_FUN_ADDR: *const VolatileCell<u32> = 0xFFF7_D448 as *const VolatileCell<u32>;
+
+pub struct Gio {
+    regs: &'static GioRegisters,
+    ports: [&'static GioPortsRegisters; 10],
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GioDirection {
+    Output = 0x0,
+    Input = 0x1,
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum GioPorts {
+    /// Gio Port A
+    A = 0,
+    /// Gio Port B
+    B = 1,
+    /// Gio Port 1 from MIBSPI
+    MibSpiPort1 = 2,
+    /// Gio Port 3 from MIBSPI
+    MibSpiPort3 = 3,
+    /// Gio Port 5 from MIBSPI
+    MibSpiPort5 = 4,
+    /// Gio Port from LIN
+    LinPort = 5,
+    /// Gio Port from SCI
+    SciPort = 6,
+    /// Gio Port 1 from HET
+    HetPort1 = 7,
+    /// Gio Port 2 from HET
+    HetPort2 = 8,
+    /// Gio Port 1 from I2C
+    I2cPort1 = 9,
+}
+
+#[derive(Clone, Copy)]
+pub enum Edge {
+    /// Falling edge (high to low)
+    Falling,
+    /// Rising edge (low to high)
+    Rising,
+}
+
+/// GIO interrupt line (VIM channel `GioHigh` or `GioLow`)
+#[derive(Clone, Copy, PartialEq)]
+pub enum InterruptLevel {
+    Low,
+    High,
+}
+
+/// Interrupt capable pins: port A and port B
+const IRQ_PINS: usize = 16;
+
+/// Callbacks of the port A and B pins, by interrupt flag bit
+static mut HANDLERS: [Option<fn()>; IRQ_PINS] = [None; IRQ_PINS];
+
+/// Dispatch the pending interrupts of the high level line
+/// (`VimChannel::GioHigh`) to the callbacks registered per pin
+pub fn handle_high_level_interrupt() {
+    let gio: &GioRegisters = unsafe { map(GIO_BASE_ADDR) };
+    dispatch(gio, &gio.off1)
+}
+
+/// Dispatch the pending interrupts of the low level line
+/// (`VimChannel::GioLow`) to the callbacks registered per pin
+pub fn handle_low_level_interrupt() {
+    let gio: &GioRegisters = unsafe { map(GIO_BASE_ADDR) };
+    dispatch(gio, &gio.off2)
+}
+
+/// GIOOFFx is the highest priority pending pin + 1 (0: none). Reading it
+/// clears the flag, but not in emulation mode: the flag is cleared
+/// explicitly as well.
+fn dispatch(gio: &GioRegisters, off: &VolatileCell<u32>) {
+    loop {
+        let pending = off.get() as usize;
+        if pending == 0 || pending > IRQ_PINS {
+            break;
+        }
+        let bit = pending - 1;
+        gio.flg.set(0x1 << bit);
+        if let Some(handler) = unsafe { HANDLERS[bit] } {
+            handler();
+        }
+    }
+}
+
+/// Register the callback of a port A or B pin interrupt
+fn set_handler(bit: usize, handler: Option<fn()>) {
+    interrupt::free(|_| unsafe { HANDLERS[bit] = handler })
+}
+
+#[derive(Clone, Copy)]
+pub enum Pull {
+    /// Pull DOWN
+    Down = 0x0,
+    /// Pull UP
+    Up = 0x1,
+}
+
+impl Gio {
+    pub fn new(_gio: GIO) -> Gio {
+        let gio = Gio {
+            regs: unsafe { map(GIO_BASE_ADDR) },
+            ports: unsafe {
+                [
+                    map(GIO_PORTA_ADDR),
+                    map(GIO_PORTB_ADDR),
+                    map(MIBSPI_PORT1_ADDR),
+                    map(MIBSPI_PORT3_ADDR),
+                    map(MIBSPI_PORT5_ADDR),
+                    map(LIN_PORT_ADDR),
+                    map(SCI_PORT_ADDR),
+                    map(HET_PORT1_ADDR),
+                    map(HET_PORT2_ADDR),
+                    map(I2C_PORT_ADDR),
+                ]
+            },
+        };
+        // Reset it (if not already out of reset)
+        if gio.regs.gcr0.get() == 0x0 {
+            gio.reset();
+        }
+        gio
+    }
+
+    /// Force GIO module reset.
+    /// Struct constructor put GIO module out of reset, so usually
+    /// there is no need to explicit reset the controller.
+    pub fn reset(&self) {
+        self.regs.gcr0.set(0x1);
+        self.regs.enaclr.set(0xFF);
+        self.regs.lvlclr.set(0xFF);
+    }
+
+    /// Single pin of a port, switched to GIO function if it belongs to a
+    /// peripheral port. Direction and pull are left unchanged.
+    pub fn pin(&self, port: GioPorts, n: usize) -> GioPin {
+        let function = match port {
+            GioPorts::A | GioPorts::B | GioPorts::HetPort1 | GioPorts::HetPort2 => None,
+            GioPorts::MibSpiPort1 => Some((MIBSPI_FUN1_ADDR, false)),
+            GioPorts::MibSpiPort3 => Some((MIBSPI_FUN3_ADDR, false)),
+            GioPorts::MibSpiPort5 => Some((MIBSPI_FUN5_ADDR, false)),
+            GioPorts::LinPort => Some((LIN_FUN_ADDR, false)),
+            GioPorts::SciPort => Some((SCI_FUN_ADDR, false)),
+            // I2CPFNC: 1 selects GIO for both SDA and SCL
+            GioPorts::I2cPort1 => Some((I2C_FUN_ADDR, true)),
+        };
+        if let Some((addr, gio)) = function {
+            let fun = unsafe { map(addr) };
+            interrupt::free(|_| {
+                if gio {
+                    fun.set(0x1);
+                } else {
+                    fun.set(fun.get() & !(0x1 << n));
+                }
+            })
+        }
+        GioPin {
+            port,
+            n,
+            regs: self.ports[port as usize],
+        }
+    }
+
+    /// Configure GIO direction.
+    pub fn direction(&self, port: GioPorts, n: usize, dir: GioDirection) {
+        interrupt::free(|_| {
+            let d = self.ports[port as usize].dir.get();
+            match dir {
+                GioDirection::Input => self.ports[port as usize].dir.set(d & !(0x1 << n)),
+                GioDirection::Output => self.ports[port as usize].dir.set(d | 0x1 << n),
+            }
+        })
+    }
+
+    pub fn set(&self, port: GioPorts, n: usize, on: bool) {
+        if on {
+            self.ports[port as usize].dset.set(0x1 << n);
+        } else {
+            self.ports[port as usize].dclr.set(0x1 << n);
+        }
+    }
+
+    pub fn toogle(&self, port: GioPorts, n: usize) {
+        self.set(port, n, !self.get(port, n))
+    }
+
+    pub fn set_all(&self, port: GioPorts, v: u32) {
+        self.ports[port as usize].dout.set(v)
+    }
+
+    pub fn get(&self, port: GioPorts, n: usize) -> bool {
+        (self.ports[port as usize].din.get() >> n) & 0x1 == 0x1
+    }
+
+    pub fn get_all(&self, port: GioPorts) -> u32 {
+        self.ports[port as usize].din.get()
+    }
+
+    /// Enable/Disable pull up/down functionality.
+    /// Function has effect only when GIO pin is an input pin
+    pub fn pull_enable(&self, port: GioPorts, n: usize, enable: bool) {
+        PinId::modify(&self.ports[port as usize].puldis, 0x1 << n, !enable);
+    }
+
+    /// Configure pin a in pull up or pull down functionality.
+    /// No need to explicity invoke pull_enable(true).
+    pub fn pull(&self, port: GioPorts, n: usize, p: Pull) {
+        PinId::modify(&self.ports[port as usize].psl, 0x1 << n, matches!(p, Pull::Up));
+        self.pull_enable(port, n, true);
+    }
+
+    pub fn open_drain(&self, port: GioPorts, n: usize, od: bool) {
+        PinId::modify(&self.ports[port as usize].pdr, 0x1 << n, od);
+    }
+
+    /// Controls the polarity — rising edge (low to high)
+    /// or falling edge (high to low)
+    /// To ensure recognition of the signal as an edge, the signal must
+    /// maintain the new level for at least one VCLK cycle
+    pub fn edge(&self, port: GioPorts, n: usize, e: Edge) {
+        let mask = 0x1 << (((port as u32) * 8) + (n as u32));
+        interrupt::free(|_| match e {
+            Edge::Falling => self.regs.pol.set(self.regs.pol.get() & !mask),
+            Edge::Rising => self.regs.pol.set(self.regs.pol.get() | mask),
+        })
+    }
+
+    /// Interrupt line of a port A or B pin
+    pub fn interrupt_level(&self, port: GioPorts, pin: usize, level: InterruptLevel) {
+        let mask = 0x1 << (((port as u32) * 8) + (pin as u32));
+        match level {
+            InterruptLevel::High => self.regs.lvlset.set(mask),
+            InterruptLevel::Low => self.regs.lvlclr.set(mask),
+        }
+    }
+
+    /// Callback invoked by `handle_high_level_interrupt()` or
+    /// `handle_low_level_interrupt()` on a port A or B pin interrupt
+    pub fn on_interrupt(&self, port: GioPorts, pin: usize, handler: fn()) {
+        set_handler((port as usize) * 8 + pin, Some(handler))
+    }
+
+    pub fn interrupt(&self, port: GioPorts, pin: usize, enable: bool) {
+        let mask = 0x1 << (((port as u32) * 8) + (pin as u32));
+        if enable {
+            self.regs.enaset.set(mask);
+        } else {
+            self.regs.flg.set(mask); // clear pending, if any
+            self.regs.enaclr.set(mask);
+        }
+    }
+}
+
+/// Single GIO pin (embedded-hal digital traits)
+pub struct GioPin {
+    port: GioPorts,
+    n: usize,
+    regs: &'static GioPortsRegisters,
+}
+
+impl GioPin {
+    pub fn port(&self) -> GioPorts {
+        self.port
+    }
+
+    pub fn number(&self) -> usize {
+        self.n
+    }
+
+    pub fn set_direction(&self, dir: GioDirection) {
+        interrupt::free(|_| {
+            let d = self.regs.dir.get();
+            match dir {
+                GioDirection::Input => self.regs.dir.set(d & !(0x1 << self.n)),
+                GioDirection::Output => self.regs.dir.set(d | 0x1 << self.n),
+            }
+        })
+    }
+
+    /// Output driven by the pin (data output register)
+    fn output(&self) -> bool {
+        (self.regs.dout.get() >> self.n) & 0x1 == 0x1
+    }
+}
+
+impl OutputPin for GioPin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.regs.dclr.set(0x1 << self.n);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.regs.dset.set(0x1 << self.n);
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for GioPin {
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.output())
+    }
+
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.output())
+    }
+}
+
+impl ToggleableOutputPin for GioPin {
+    type Error = Infallible;
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        if self.output() {
+            self.set_low()
+        } else {
+            self.set_high()
+        }
+    }
+}
+
+impl InputPin for GioPin {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok((self.regs.din.get() >> self.n) & 0x1 == 0x1)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        self.is_high().map(|h| !h)
+    }
+}
+
+/// Input mode (type state)
+pub struct Input<MODE> {
+    _mode: PhantomData<MODE>,
+}
+
+/// Floating input (pull disabled)
+pub struct Floating;
+/// Pulled up input
+pub struct PullUp;
+/// Pulled down input (GIO reset state)
+pub struct PullDown;
+
+/// Output mode (type state)
+pub struct Output<MODE> {
+    _mode: PhantomData<MODE>,
+}
+
+/// Push pull output
+pub struct PushPull;
+/// Open drain output
+pub struct OpenDrain;
+
+/// Input with interrupt on edge (type state). The pull is left unchanged.
+pub struct Interrupt<EDGE> {
+    _edge: PhantomData<EDGE>,
+}
+
+/// Interrupt on rising edge
+pub struct Rising;
+/// Interrupt on falling edge
+pub struct Falling;
+/// Interrupt on both edges
+pub struct BothEdges;
+
+/// Port A or B pin, configured through the GIO registers
+#[derive(Clone, Copy)]
+struct PinId {
+    port: GioPorts,
+    n: usize,
+}
+
+impl PinId {
+    fn regs(self) -> &'static GioPortsRegisters {
+        match self.port {
+            GioPorts::A => unsafe { map(GIO_PORTA_ADDR) },
+            _ => unsafe { map(GIO_PORTB_ADDR) },
+        }
+    }
+
+    fn gio(self) -> &'static GioRegisters {
+        unsafe { map(GIO_BASE_ADDR) }
+    }
+
+    fn mask(self) -> u32 {
+        0x1 << self.n
+    }
+
+    /// Bit of the pin in the interrupt registers
+    fn irq_bit(self) -> usize {
+        (self.port as usize) * 8 + self.n
+    }
+
+    fn irq_mask(self) -> u32 {
+        0x1 << self.irq_bit()
+    }
+
+    fn modify(reg: &VolatileCell<u32>, mask: u32, set: bool) {
+        interrupt::free(|_| {
+            if set {
+                reg.set(reg.get() | mask);
+            } else {
+                reg.set(reg.get() & !mask);
+            }
+        })
+    }
+
+    /// Input with the given pull (`None`: pull disabled)
+    fn input(self, pull: Option<Pull>) {
+        let regs = self.regs();
+        PinId::modify(&regs.dir, self.mask(), false);
+        PinId::modify(&regs.pdr, self.mask(), false);
+        if let Some(p) = pull {
+            PinId::modify(&regs.psl, self.mask(), matches!(p, Pull::Up));
+        }
+        PinId::modify(&regs.puldis, self.mask(), pull.is_none());
+    }
+
+    fn output(self, open_drain: bool) {
+        let regs = self.regs();
+        PinId::modify(&regs.pdr, self.mask(), open_drain);
+        PinId::modify(&regs.dir, self.mask(), true);
+    }
+
+    /// Input pin interrupt. It is enabled by `Interrupt::enable_interrupt()`
+    fn interrupt(self, edge: Option<Edge>) {
+        let gio = self.gio();
+        PinId::modify(&self.regs().dir, self.mask(), false);
+        gio.enaclr.set(self.irq_mask());
+        gio.flg.set(self.irq_mask());
+        PinId::modify(&gio.intdet, self.irq_mask(), edge.is_none());
+        if let Some(e) = edge {
+            PinId::modify(&gio.pol, self.irq_mask(), matches!(e, Edge::Rising));
+        }
+    }
+
+    fn set(self, high: bool) {
+        if high {
+            self.regs().dset.set(self.mask());
+        } else {
+            self.regs().dclr.set(self.mask());
+        }
+    }
+
+    fn is_set(self) -> bool {
+        self.regs().dout.get() & self.mask() != 0
+    }
+
+    fn is_high(self) -> bool {
+        self.regs().din.get() & self.mask() != 0
+    }
+}
+
+macro_rules! gio_pins {
+    ($($PXi:ident: ($pxi:ident, $port:expr, $i:expr),)+) => {
+        /// GIO port A and B pins, as input with pull-down (reset state)
+        pub struct Parts {
+            $(pub $pxi: $PXi<Input<PullDown>>,)+
+        }
+
+        impl Gio {
+            /// Split the GIO module in independent type-state pins
+            pub fn split(self) -> Parts {
+                Parts {
+                    $($pxi: $PXi { _mode: PhantomData },)+
+                }
+            }
+        }
+
+        $(
+            pub struct $PXi<MODE> {
+                _mode: PhantomData<MODE>,
+            }
+
+            impl<MODE> $PXi<MODE> {
+                const PIN: PinId = PinId { port: $port, n: $i };
+
+                pub fn into_floating_input(self) -> $PXi<Input<Floating>> {
+                    Self::PIN.input(None);
+                    $PXi { _mode: PhantomData }
+                }
+
+                pub fn into_pull_up_input(self) -> $PXi<Input<PullUp>> {
+                    Self::PIN.input(Some(Pull::Up));
+                    $PXi { _mode: PhantomData }
+                }
+
+                pub fn into_pull_down_input(self) -> $PXi<Input<PullDown>> {
+                    Self::PIN.input(Some(Pull::Down));
+                    $PXi { _mode: PhantomData }
+                }
+
+                pub fn into_push_pull_output(self) -> $PXi<Output<PushPull>> {
+                    Self::PIN.output(false);
+                    $PXi { _mode: PhantomData }
+                }
+
+                pub fn into_open_drain_output(self) -> $PXi<Output<OpenDrain>> {
+                    Self::PIN.output(true);
+                    $PXi { _mode: PhantomData }
+                }
+
+                pub fn into_rising_edge_interrupt(self) -> $PXi<Interrupt<Rising>> {
+                    Self::PIN.interrupt(Some(Edge::Rising));
+                    $PXi { _mode: PhantomData }
+                }
+
+                pub fn into_falling_edge_interrupt(self) -> $PXi<Interrupt<Falling>> {
+                    Self::PIN.interrupt(Some(Edge::Falling));
+                    $PXi { _mode: PhantomData }
+                }
+
+                pub fn into_both_edges_interrupt(self) -> $PXi<Interrupt<BothEdges>> {
+                    Self::PIN.interrupt(None);
+                    $PXi { _mode: PhantomData }
+                }
+            }
+
+            impl<EDGE> $PXi<Interrupt<EDGE>> {
+                pub fn enable_interrupt(&self) {
+                    Self::PIN.gio().enaset.set(Self::PIN.irq_mask());
+                }
+
+                pub fn disable_interrupt(&self) {
+                    Self::PIN.gio().enaclr.set(Self::PIN.irq_mask());
+                }
+
+                pub fn is_interrupt_pending(&self) -> bool {
+                    Self::PIN.gio().flg.get() & Self::PIN.irq_mask() != 0
+                }
+
+                pub fn clear_interrupt(&self) {
+                    Self::PIN.gio().flg.set(Self::PIN.irq_mask());
+                }
+
+                /// Interrupt line (VIM channel `GioHigh` or `GioLow`)
+                pub fn set_level(&self, level: InterruptLevel) {
+                    match level {
+                        InterruptLevel::High => Self::PIN.gio().lvlset.set(Self::PIN.irq_mask()),
+                        InterruptLevel::Low => Self::PIN.gio().lvlclr.set(Self::PIN.irq_mask()),
+                    }
+                }
+
+                /// Callback invoked by the interrupt handlers of the GIO
+                pub fn on_interrupt(&self, handler: fn()) {
+                    set_handler(Self::PIN.irq_bit(), Some(handler))
+                }
+
+                /// Remove the callback (the interrupt is still serviced)
+                pub fn remove_handler(&self) {
+                    set_handler(Self::PIN.irq_bit(), None)
+                }
+            }
+
+            impl<MODE> OutputPin for $PXi<Output<MODE>> {
+                type Error = Infallible;
+
+                fn set_low(&mut self) -> Result<(), Self::Error> {
+                    Self::PIN.set(false);
+                    Ok(())
+                }
+
+                fn set_high(&mut self) -> Result<(), Self::Error> {
+                    Self::PIN.set(true);
+                    Ok(())
+                }
+            }
+
+            impl<MODE> StatefulOutputPin for $PXi<Output<MODE>> {
+                fn is_set_high(&self) -> Result<bool, Self::Error> {
+                    Ok(Self::PIN.is_set())
+                }
+
+                fn is_set_low(&self) -> Result<bool, Self::Error> {
+                    Ok(!Self::PIN.is_set())
+                }
+            }
+
+            impl<MODE> ToggleableOutputPin for $PXi<Output<MODE>> {
+                type Error = Infallible;
+
+                fn toggle(&mut self) -> Result<(), Self::Error> {
+                    Self::PIN.set(!Self::PIN.is_set());
+                    Ok(())
+                }
+            }
+
+            impl<MODE> InputPin for $PXi<Input<MODE>> {
+                type Error = Infallible;
+
+                fn is_high(&self) -> Result<bool, Self::Error> {
+                    Ok(Self::PIN.is_high())
+                }
+
+                fn is_low(&self) -> Result<bool, Self::Error> {
+                    Ok(!Self::PIN.is_high())
+                }
+            }
+
+            /// The level of an open drain output can be read back
+            impl InputPin for $PXi<Output<OpenDrain>> {
+                type Error = Infallible;
+
+                fn is_high(&self) -> Result<bool, Self::Error> {
+                    Ok(Self::PIN.is_high())
+                }
+
+                fn is_low(&self) -> Result<bool, Self::Error> {
+                    Ok(!Self::PIN.is_high())
+                }
+            }
+
+            impl<EDGE> InputPin for $PXi<Interrupt<EDGE>> {
+                type Error = Infallible;
+
+                fn is_high(&self) -> Result<bool, Self::Error> {
+                    Ok(Self::PIN.is_high())
+                }
+
+                fn is_low(&self) -> Result<bool, Self::Error> {
+                    Ok(!Self::PIN.is_high())
+                }
+            }
+        )+
+    };
+}
+
+gio_pins! {
+    PA0: (pa0, GioPorts::A, 0),
+    PA1: (pa1, GioPorts::A, 1),
+    PA2: (pa2, GioPorts::A, 2),
+    PA3: (pa3, GioPorts::A, 3),
+    PA4: (pa4, GioPorts::A, 4),
+    PA5: (pa5, GioPorts::A, 5),
+    PA6: (pa6, GioPorts::A, 6),
+    PA7: (pa7, GioPorts::A, 7),
+    PB0: (pb0, GioPorts::B, 0),
+    PB1: (pb1, GioPorts::B, 1),
+    PB2: (pb2, GioPorts::B, 2),
+    PB3: (pb3, GioPorts::B, 3),
+    PB4: (pb4, GioPorts::B, 4),
+    PB5: (pb5, GioPorts::B, 5),
+    PB6: (pb6, GioPorts::B, 6),
+    PB7: (pb7, GioPorts::B, 7),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    const GIOENASET: u32 = 0xFFF7_BC10;
+    const GIOENACLR: u32 = 0xFFF7_BC14;
+    const GIOFLG: u32 = 0xFFF7_BC20;
+
+    fn gio() -> Gio {
+        mock::reset();
+        let gio = Gio::new(unsafe { GIO::steal() });
+        // enables cleared by the reset
+        mock::poke(GIOENACLR, 0);
+        gio
+    }
+
+    #[test]
+    fn interrupt() {
+        let gio = gio();
+        gio.interrupt(GioPorts::B, 2, true);
+        assert_eq!(mock::peek(GIOENASET), 0x1 << 10);
+        assert_eq!(mock::peek(GIOENACLR), 0);
+        // disabled: pending flag and enable cleared
+        gio.interrupt(GioPorts::B, 2, false);
+        assert_eq!(mock::peek(GIOFLG), 0x1 << 10);
+        assert_eq!(mock::peek(GIOENACLR), 0x1 << 10);
+    }
+
+    #[test]
+    fn pull_and_open_drain() {
+        const GIODIRA: u32 = 0xFFF7_BC34;
+        const GIOPDRA: u32 = GIODIRA + 0x14;
+        const GIOPULDISA: u32 = GIODIRA + 0x18;
+        const GIOPSLA: u32 = GIODIRA + 0x1C;
+        let gio = gio();
+        mock::poke(GIOPULDISA, 0xFF);
+        mock::poke(GIOPSLA, 0x0F);
+        mock::poke(GIOPDRA, 0x81);
+
+        // other pins left unchanged
+        gio.pull(GioPorts::A, 5, Pull::Up);
+        assert_eq!(mock::peek(GIOPSLA), 0x2F);
+        assert_eq!(mock::peek(GIOPULDISA), 0xDF);
+        gio.pull(GioPorts::A, 1, Pull::Down);
+        assert_eq!(mock::peek(GIOPSLA), 0x2D);
+        gio.pull_enable(GioPorts::A, 5, false);
+        assert_eq!(mock::peek(GIOPULDISA), 0xFD);
+
+        gio.open_drain(GioPorts::A, 7, false);
+        assert_eq!(mock::peek(GIOPDRA), 0x01);
+        gio.open_drain(GioPorts::A, 3, true);
+        assert_eq!(mock::peek(GIOPDRA), 0x09);
+    }
+}
@@ -1,4 +1,7 @@
-////
+use crate::peripherals::{sealed::Sealed, HET1};
+#[cfg(not(feature = "tms570ls0432"))]
+use crate::peripherals::HET2;
+use crate::regs::map;
 use vcell::VolatileCell;
 
 #[repr(C)]
@@ -110,6 +113,20 @@ pub enum HetID {
     Two = 1,
 }
 
+/// N2HET module instance: `HET1` or `HET2`
+pub trait Instance: Sealed {
+    const ID: HetID;
+}
+
+impl Instance for HET1 {
+    const ID: HetID = HetID::One;
+}
+
+#[cfg(not(feature = "tms570ls0432"))]
+impl Instance for HET2 {
+    const ID: HetID = HetID::Two;
+}
+
 #[allow(dead_code)]
 pub struct Het {
     pub id: HetID,
@@ -118,11 +135,12 @@ pub struct Het {
 }
 
 impl Het {
-    pub fn new(id: HetID) -> Het {
+    pub fn new<I: Instance>(_het: I) -> Het {
+        let id = I::ID;
         Het {
             id,
-            regs: unsafe { &*HET_REG_ADDR[id as usize] },
-            ram: unsafe { &*HET_RAM_ADDR[id as usize] },
+            regs: unsafe { map(HET_REG_ADDR[id as usize]) },
+            ram: unsafe { map(HET_RAM_ADDR[id as usize]) },
         }
     }
 
@@ -1,17 +1,20 @@
+//! Cyclic Redundancy Check Controller Module (MCRC)
+//!
+//! MCRC Controller is a module which is used to perform CRC
+//! (CyclicRedundancy Check) to verify the integrity of memory system.
+//! A signature representing the contents of the memory is obtained when the
+//! contents of the memory are read into MCRC Controller.
+//! The responsibility of MCRC controller is tocalculate the signature for a
+//! set of data and then compare the calculated signature value against a
+//! pre-determined good signature value. MCRC controller provides up to four
+//! channels to perform CRC calculation on multiple memories in parallel and can
+//! be used on any memory system. Channel 1 can also be put into data trace
+//! mode. In data trace mode, MCRC controller compresses each data being read
+//! through CPU read data bus.
+
 use core::ptr;
-///! Cyclic Redundancy Check Controller Module (MCRC)
-///!
-///! MCRC Controller is a module which is used to perform CRC
-///! (CyclicRedundancy Check) to verify the integrity of memory system.
-///! A signature representing the contents of the memory is obtained when the
-///! contents of the memory are read into MCRC Controller.
-///! The responsibility of MCRC controller is tocalculate the signature for a
-///! set of data and then compare the calculated signature value against a
-///! pre-determined good signature value. MCRC controller provides up to four
-///! channels to perform CRC calculation on multiple memories in parallel and can
-///! be used on any memory system. Channel 1 can also be put into data trace
-///! mode. In data trace mode, MCRC controller compresses each data being read
-///! through CPU read data bus.
+use crate::peripherals::HWCRC;
+use crate::regs::map;
 use vcell::VolatileCell;
 
 #[repr(C)]
@@ -159,8 +162,8 @@ pub enum CrcInterrupt {
 }
 
 impl HwCrc {
-    pub unsafe fn new() -> &'static HwCrc {
-        &*HWCRC_BASE_ADDR
+    pub fn new(_hwcrc: HWCRC) -> &'static HwCrc {
+        unsafe { map(HWCRC_BASE_ADDR) }
     }
 
     pub fn ch_reset(&self, ch: CrcChannel, reset: bool) {
@@ -241,6 +244,11 @@ impl HwCrc {
     }
 
     /// Generate CRC signature. (FULL CPU mode of operation only)
+    ///
+    /// # Safety
+    ///
+    /// `data` must be valid for reads of `len` words, and the PSA signature
+    /// register of `ch` hold the address data is written to.
     pub unsafe fn generate(&self, ch: CrcChannel, mode: ChannelMode, data: *const u32, len: usize) {
         if mode == ChannelMode::Full {
             match ch {
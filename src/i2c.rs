@@ -1,4 +1,6 @@
-use crate::config;
+use crate::clock::{ClockError, Clocks};
+use crate::peripherals;
+use crate::regs::map;
 use vcell::VolatileCell;
 
 #[repr(C)]
@@ -184,12 +186,16 @@ pub enum I2cBitCount {
 
 pub struct I2C {
     regs: &'static i2cRegisters,
+    clocks: Clocks,
 }
 
 impl I2C {
-    pub fn new(master_address: u8, slave_address: u8, baud: u32) -> I2C {
+    /// `baud` SCL frequency [kHz]
+    pub fn new(_i2c: peripherals::I2C, clocks: &Clocks, master_address: u8, slave_address: u8,
+               baud: u32) -> Result<I2C, ClockError> {
         let i2c = I2C {
-            regs: unsafe { &*I2C_BASE_ADDR },
+            regs: unsafe { map(I2C_BASE_ADDR) },
+            clocks: *clocks,
         };
         i2c.regs.MDR.set(0x0); // reset
         let mdr = i2c.regs.MDR.get();
@@ -198,9 +204,9 @@ impl I2C {
         i2c.set_slave_address(slave_address);
         // Backward Compatibility mode always disabled
         i2c.regs.EMDR.set(0x0);
-        i2c.set_baudrate(baud);
+        i2c.set_baudrate(baud)?;
         i2c.regs.MDR.set(mdr | flags::RESET_OUT);
-        i2c
+        Ok(i2c)
     }
 
     fn set_mode(&self, mode: u32) {
@@ -258,8 +264,11 @@ impl I2C {
         self.regs.OAR.set(slave_addr as u32);
     }
 
-    fn set_baudrate(&self, baud: u32) {
-        let prescale = (config::VCLK / MHz!(8)) - 1;
+    /// SCL = VCLK / ((IPSC + 1) x 2 x (ICCL + d)), with the module clock
+    /// VCLK / (IPSC + 1) at about 8 MHz
+    fn set_baudrate(&self, baud: u32) -> Result<(), ClockError> {
+        let vclk = self.clocks.vclk1();
+        let prescale = (vclk / MHz!(8)).max(1) - 1;
 
         let d = if prescale >= 2 {
             5
@@ -269,13 +278,16 @@ impl I2C {
             7
         };
 
-        if baud > 0 {
-            let div = 2 * baud * 1_000 * (prescale + 1);
-            let ck = (config::VCLK / div) - d;
-            self.regs.PSC.set(prescale);
-            self.regs.CKH.set(ck);
-            self.regs.CKL.set(ck);
-        }
+        let div = 2 * baud * 1_000 * (prescale + 1);
+        let ck = match (vclk / div.max(1)).checked_sub(d) {
+            Some(ck) if baud > 0 && ck <= 0xFFFF => ck,
+            _ => return Err(ClockError::Rate),
+        };
+        self.clocks.check_rate(baud * 1_000, vclk / ((prescale + 1) * 2 * (ck + d)))?;
+        self.regs.PSC.set(prescale);
+        self.regs.CKH.set(ck);
+        self.regs.CKL.set(ck);
+        Ok(())
     }
 
     pub fn nack_mode(&self) {
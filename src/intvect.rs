@@ -36,7 +36,7 @@ extern "C" {
 // "LDR PC, [PC, #-0x1B0]" at address 0x18 resolves to a load from address
 // 0xFFFF_FE70. (-0x1B0 == 0xFFFF_FE50 --> 0xFFFF_FE50 + 0x18 + 0x8 = 0xFFFF_FE70)
 
-global_asm!(r#"
+core::arch::global_asm!(r#"
     .section .intvecs,"a",%progbits
     .extern reset
     .extern undef_entry
@@ -55,4 +55,32 @@ reset_entry:
     b   phantom_interrupt
     ldr pc,[pc,#-0x1b0]
     ldr pc,[pc,#-0x1b0]
+
+    .section .text.tms570_default_irq,"ax",%progbits
+    .arm
+    .weak tms570_default_irq
+    .type tms570_default_irq, %function
+
+tms570_default_irq:
+    push {{r0-r2}}
+    @ IRQINDEX or FIQINDEX: channel + 1, 0 for a phantom interrupt
+    ldr  r1, =0xFFFFFE00
+    mrs  r0, cpsr
+    and  r0, r0, #0x1F
+    cmp  r0, #0x11
+    ldreq r0, [r1, #4]
+    ldrne r0, [r1]
+    subs r0, r0, #1
+    bmi  1f
+    @ REQENACLR[channel / 32] = 1 << (channel % 32)
+    lsr  r2, r0, #5
+    and  r0, r0, #0x1F
+    mov  r1, #1
+    lsl  r0, r1, r0
+    ldr  r1, =0xFFFFFE40
+    str  r0, [r1, r2, lsl #2]
+1:
+    pop  {{r0-r2}}
+    subs pc, lr, #4
+    .ltorg
 "#);
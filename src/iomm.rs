@@ -1,7 +1,9 @@
-use crate::pinmux;
+use crate::pinmux::PinSelect;
 ///
 /// I/O Multiplexing and Control Module (IOMM)
 ///
+use crate::peripherals::IOMM;
+use crate::regs::map;
 use vcell::VolatileCell;
 
 #[repr(C)]
@@ -39,13 +41,16 @@ pub struct Iomm {
     /// Reserved
     _reserved5: [VolatileCell<u32>; 4],
     /// Pin Multiplexing Control
+    #[cfg(not(cortex_r5))]
     pinnmr: [VolatileCell<u32>; 47],
+    #[cfg(cortex_r5)]
+    pinnmr: [VolatileCell<u32>; 180],
 }
 const IOMM_BASE_ADDR: *const Iomm = 0xFFFF_EA00 as *const Iomm;
 
 impl Iomm {
-    pub unsafe fn new() -> &'static Iomm {
-        &*IOMM_BASE_ADDR
+    pub fn new(_iomm: IOMM) -> &'static Iomm {
+        unsafe { map(IOMM_BASE_ADDR) }
     }
 
     pub fn revision(&self) -> u32 {
@@ -65,18 +70,41 @@ impl Iomm {
         }
     }
 
-    fn configure(&self, pin: pinmux::PinMux) {
-        let mut muxreg = self.pinnmr[pin.group()].get();
-        let bit = 0x1 << (pin.shift() + pin.option());
-        muxreg = (muxreg & !(0xFF << pin.shift())) | bit;
-        self.pinnmr[pin.group()].set(muxreg);
+    fn configure(&self, pin: PinSelect) {
+        let mut muxreg = self.pinnmr[pin.group].get();
+        let bit = 0x1 << (pin.shift + pin.option);
+        muxreg = (muxreg & !(0xFF << pin.shift)) | bit;
+        self.pinnmr[pin.group].set(muxreg);
     }
 
-    pub fn setup_pins(&self, pins: &[pinmux::PinMux]) {
+    /// Select the pin functions: `PinSelect` fields from the datasheet,
+    /// or `pinmux::PinMux` names where the device has a table
+    pub fn setup_pins<P: Into<PinSelect> + Copy>(&self, pins: &[P]) {
         self.kicker(true);
         for p in pins.iter() {
-            self.configure(*p);
+            self.configure((*p).into());
         }
         self.kicker(false);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    const KICKER0: u32 = 0xFFFF_EA38;
+    const PINMMR7: u32 = 0xFFFF_EB2C;
+
+    #[test]
+    fn setup_pins() {
+        mock::reset();
+        let iomm = Iomm::new(unsafe { IOMM::steal() });
+        mock::poke(PINMMR7, 0x0101_0101);
+        // field at bit 16 of PINMMR7, option 1
+        iomm.setup_pins(&[PinSelect::new(7, 16, 1)]);
+        assert_eq!(mock::peek(PINMMR7), 0x0102_0101);
+        // pin muxing locked again
+        assert_eq!(mock::peek(KICKER0), 0x0);
+    }
+}
@@ -3,61 +3,84 @@
 
 #![crate_name = "tms570"]
 #![crate_type = "rlib"]
-#![feature(llvm_asm)]
-#![feature(global_asm)]
-#![feature(naked_functions)]
 #![no_std]
 
+#[cfg(any(test, feature = "host-mock"))]
+extern crate std;
+
 #[macro_use]
 mod helpers;
+#[macro_use]
+pub mod regs;
+
+pub use peripherals::Peripherals;
+pub use tms570_macros::{exception, interrupt};
 
 pub mod adc;
 pub mod can;
 pub mod ccm;
+pub mod clock;
 pub mod config;
+pub mod dcc;
+pub mod device;
+#[cfg(not(feature = "tms570ls0432"))]
 pub mod dma;
 pub mod dma_ctrl;
 pub mod dwd;
 pub mod efuse;
+#[cfg(cortex_r5)]
+pub mod epc;
 pub mod esm;
 pub mod esm_ch;
+#[cfg(target_arch = "arm")]
+pub mod fault;
 pub mod flash;
 pub mod gio;
 pub mod het;
 pub mod hwcrc;
+#[cfg(not(feature = "tms570ls0432"))]
 pub mod i2c;
+#[cfg(target_arch = "arm")]
 pub mod intvect;
 pub mod iomm;
 pub mod mibspi;
+#[cfg(any(test, feature = "host-mock"))]
+pub mod mock;
+#[cfg(target_arch = "arm")]
+pub mod mpu;
 pub mod pbist;
 pub mod pcr;
+pub mod peripherals;
 pub mod pinmux;
 pub mod rti;
 pub mod scilin;
+#[cfg(cortex_r5)]
+pub mod scm;
 pub mod serial;
+#[cfg(target_arch = "arm")]
 pub mod startup;
 pub mod stc;
+#[cfg(target_arch = "arm")]
+pub mod svc;
+#[cfg(target_arch = "arm")]
 pub mod syscore;
 pub mod sysexc;
 pub mod system;
+#[cfg(not(cortex_r5))]
 pub mod tcram;
 pub mod vim;
 
+use core::ptr::addr_of_mut;
+
 extern "C" {
     static mut _heap_start: u32;
     static mut _heapsize: u32;
 }
 
-#[no_mangle]
-#[naked]
-pub unsafe extern "C" fn reset() -> ! {
-    startup::tms570_startup()
-}
-
 pub fn heap_start() -> *mut u32 {
-    unsafe { &mut _heap_start }
+    addr_of_mut!(_heap_start)
 }
 
 pub fn heap_size() -> *mut u32 {
-    unsafe { &mut _heapsize }
+    addr_of_mut!(_heapsize)
 }
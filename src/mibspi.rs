@@ -1,59 +1,94 @@
-///
-use vcell::VolatileCell;
+use crate::clock::{ClockError, Clocks};
+use crate::peripherals::{sealed::Sealed, MIBSPI1};
+#[cfg(not(feature = "tms570ls0432"))]
+use crate::peripherals::{MIBSPI3, MIBSPI5};
+use crate::regs::{map, Reg};
+
+register_bitfields! {
+    /// Global Control 0
+    GCR0 [
+        /// Module out of reset
+        nRESET OFFSET(0) NUMBITS(1) []
+    ],
+    /// Global Control 1
+    GCR1 [
+        /// SPI enable
+        SPIEN OFFSET(24) NUMBITS(1) [],
+        /// Internal loop-back test mode
+        LOOPBACK OFFSET(16) NUMBITS(1) [],
+        /// Power down mode
+        POWERDOWN OFFSET(8) NUMBITS(1) [],
+        /// SPICLK internally generated
+        CLKMOD OFFSET(1) NUMBITS(1) [],
+        /// Master mode
+        MASTER OFFSET(0) NUMBITS(1) []
+    ],
+    /// Interrupt Register
+    INT0 [
+        /// SPIENA pin high-impedance enable
+        ENABLEHIGHZ OFFSET(24) NUMBITS(1) [],
+        /// DMA request enable
+        DMAREQEN OFFSET(16) NUMBITS(1) []
+    ],
+    /// Data Format
+    FMT [
+        /// SPICLK prescaler (VCLK / (PRESCALE + 1))
+        PRESCALE OFFSET(8) NUMBITS(8) [],
+        /// Character length
+        CHARLEN OFFSET(0) NUMBITS(5) []
+    ]
+}
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct MibSpiRegisters {
-    GCR0: VolatileCell<u32>,            // Global Control 0
-    GCR1: VolatileCell<u32>,            // Global Control 1
-    INT0: VolatileCell<u32>,            // Interrupt Register
-    LVL: VolatileCell<u32>,             // Interrupt Level
-    FLG: VolatileCell<u32>,             // Interrupt flags
-    PC0: VolatileCell<u32>,             // Function Pin Enable
-    PC1: VolatileCell<u32>,             // Pin Direction
-    PC2: VolatileCell<u32>,             // Pin Input Latch
-    PC3: VolatileCell<u32>,             // Pin Output Latch
-    PC4: VolatileCell<u32>,             // Output Pin Set
-    PC5: VolatileCell<u32>,             // Output Pin Clr
-    PC6: VolatileCell<u32>,             // Open Drain Output Enable
-    PC7: VolatileCell<u32>,             // Pullup/Pulldown Disable
-    PC8: VolatileCell<u32>,             // Pullup/Pulldown Selection
-    DAT0: VolatileCell<u32>,            // Transmit Data
-    DAT1: VolatileCell<u32>,            // Transmit Data with Format and Chip Select
-    BUF: VolatileCell<u32>,             // Receive Buffer
-    EMU: VolatileCell<u32>,             // Emulation Receive Buffer
-    DELAY: VolatileCell<u32>,           // Delays
-    DEF: VolatileCell<u32>,             // Default Chip Select
-    FMT0: VolatileCell<u32>,            // Data Format 0
-    FMT1: VolatileCell<u32>,            // Data Format 1
-    FMT2: VolatileCell<u32>,            // Data Format 2
-    FMT3: VolatileCell<u32>,            // Data Format 3
-    INTVECT0: VolatileCell<u32>,        // Interrupt Vector 0
-    INTVECT1: VolatileCell<u32>,        // Interrupt Vector 1
-    SRSEL: VolatileCell<u32>,           // Slew Rate Select
-    PMCTRL: VolatileCell<u32>,          // Parallel Mode Control
-    MIBSPIE: VolatileCell<u32>,         // Multi-buffer Mode Enable
-    TGITENST: VolatileCell<u32>,        // TG Interrupt Enable Set
-    TGITENCR: VolatileCell<u32>,        // TG Interrupt Enable Clear
-    TGITLVST: VolatileCell<u32>,        // Transfer Group Interrupt Level Set
-    TGITLVCR: VolatileCell<u32>,        // Transfer Group Interrupt Level Clear
-    TGINTFLG: VolatileCell<u32>,        // Transfer Group Interrupt Flag
-    _reserved1: [VolatileCell<u32>; 2], // Reserved
-    TICKCNT: VolatileCell<u32>,         // Tick Counter
-    LTGPEND: VolatileCell<u32>,         // Last TG End Pointer
-    TGCTRL: [VolatileCell<u32>; 16],    // Transfer Group Control
-    DMACTRL: [VolatileCell<u32>; 8],    // DMA Control
-    DMACOUNT: [VolatileCell<u32>; 8],   // DMA Count
-    DMACNTLEN: VolatileCell<u32>,       // DMA Control length
-    _reserved2: VolatileCell<u32>,      // Reserved
-    UERRCTRL: VolatileCell<u32>,        // Multi-buffer RAM Uncorrectable Parity Error Control
-    UERRSTAT: VolatileCell<u32>,        // Multi-buffer RAM Uncorrectable Parity Error Status
-    UERRADDRRX: VolatileCell<u32>,      // RXRAM Uncorrectable Parity Error Address
-    UERRADDRTX: VolatileCell<u32>,      // TXRAM Uncorrectable Parity Error Address
-    RXOVRN_BUF_ADDR: VolatileCell<u32>, // RXRAM Overrun Buffer Address
-    IOLPKTSTCR: VolatileCell<u32>,      // IO loopback
-    EXT_PRESCALE1: VolatileCell<u32>,
-    EXT_PRESCALE2: VolatileCell<u32>,
+    GCR0: Reg<u32, GCR0::Register>, // Global Control 0
+    GCR1: Reg<u32, GCR1::Register>, // Global Control 1
+    INT0: Reg<u32, INT0::Register>, // Interrupt Register
+    LVL: Reg,                       // Interrupt Level
+    FLG: Reg,                       // Interrupt flags
+    PC0: Reg,                       // Function Pin Enable
+    PC1: Reg,                       // Pin Direction
+    PC2: Reg,                       // Pin Input Latch
+    PC3: Reg,                       // Pin Output Latch
+    PC4: Reg,                       // Output Pin Set
+    PC5: Reg,                       // Output Pin Clr
+    PC6: Reg,                       // Open Drain Output Enable
+    PC7: Reg,                       // Pullup/Pulldown Disable
+    PC8: Reg,                       // Pullup/Pulldown Selection
+    DAT0: Reg,                      // Transmit Data
+    DAT1: Reg,                      // Transmit Data with Format and Chip Select
+    BUF: Reg,                       // Receive Buffer
+    EMU: Reg,                       // Emulation Receive Buffer
+    DELAY: Reg,                     // Delays
+    DEF: Reg,                       // Default Chip Select
+    FMT: [Reg<u32, FMT::Register>; 4], // Data Format 0-3
+    INTVECT0: Reg,                  // Interrupt Vector 0
+    INTVECT1: Reg,                  // Interrupt Vector 1
+    SRSEL: Reg,                     // Slew Rate Select
+    PMCTRL: Reg,                    // Parallel Mode Control
+    MIBSPIE: Reg,                   // Multi-buffer Mode Enable
+    TGITENST: Reg,                  // TG Interrupt Enable Set
+    TGITENCR: Reg,                  // TG Interrupt Enable Clear
+    TGITLVST: Reg,                  // Transfer Group Interrupt Level Set
+    TGITLVCR: Reg,                  // Transfer Group Interrupt Level Clear
+    TGINTFLG: Reg,                  // Transfer Group Interrupt Flag
+    _reserved1: [Reg; 2],           // Reserved
+    TICKCNT: Reg,                   // Tick Counter
+    LTGPEND: Reg,                   // Last TG End Pointer
+    TGCTRL: [Reg; 16],              // Transfer Group Control
+    DMACTRL: [Reg; 8],              // DMA Control
+    DMACOUNT: [Reg; 8],             // DMA Count
+    DMACNTLEN: Reg,                 // DMA Control length
+    _reserved2: Reg,                // Reserved
+    UERRCTRL: Reg,                  // Multi-buffer RAM Uncorrectable Parity Error Control
+    UERRSTAT: Reg,                  // Multi-buffer RAM Uncorrectable Parity Error Status
+    UERRADDRRX: Reg,                // RXRAM Uncorrectable Parity Error Address
+    UERRADDRTX: Reg,                // TXRAM Uncorrectable Parity Error Address
+    RXOVRN_BUF_ADDR: Reg,           // RXRAM Overrun Buffer Address
+    IOLPKTSTCR: Reg,                // IO loopback
+    EXT_PRESCALE1: Reg,
+    EXT_PRESCALE2: Reg,
 }
 
 const MIBSPI1_ADDR: *const MibSpiRegisters = 0xFFF7_F400 as *const MibSpiRegisters;
@@ -107,42 +142,76 @@ pub enum MibSpiID {
     Five = 2,
 }
 
+/// MibSPI module instance: `MIBSPI1`, `MIBSPI3` or `MIBSPI5`
+pub trait Instance: Sealed {
+    const ID: MibSpiID;
+}
+
+impl Instance for MIBSPI1 {
+    const ID: MibSpiID = MibSpiID::One;
+}
+
+#[cfg(not(feature = "tms570ls0432"))]
+impl Instance for MIBSPI3 {
+    const ID: MibSpiID = MibSpiID::Three;
+}
+
+#[cfg(not(feature = "tms570ls0432"))]
+impl Instance for MIBSPI5 {
+    const ID: MibSpiID = MibSpiID::Five;
+}
+
 #[allow(dead_code)]
 pub struct MibSpi {
     pub id: MibSpiID,
     regs: &'static MibSpiRegisters,
     ram: &'static MibspiRam,
+    clocks: Clocks,
 }
 
 impl MibSpi {
-    pub fn new(id: MibSpiID, master: bool) -> MibSpi {
+    pub fn new<I: Instance>(_spi: I, clocks: &Clocks, master: bool) -> MibSpi {
+        let id = I::ID;
         let mibspi = MibSpi {
             id,
-            regs: unsafe { &*MIBSPI_ADDR[id as usize] },
-            ram: unsafe { &*MIBSPI_RAM_ADDR[id as usize] },
+            clocks: *clocks,
+            regs: unsafe { map(MIBSPI_ADDR[id as usize]) },
+            ram: unsafe { map(MIBSPI_RAM_ADDR[id as usize]) },
         };
         mibspi.init(master);
         mibspi
     }
 
     pub fn init(&self, master: bool) {
-        self.regs.GCR0.set(0x0);
-        self.regs.GCR0.set(0x1);
-        let internal_clock = 0x1 << 1;
+        self.regs.GCR0.write(GCR0::nRESET::CLEAR);
+        self.regs.GCR0.write(GCR0::nRESET::SET);
 
-        let gcr1 = internal_clock | (master as u32);
-        self.regs.GCR1.set(self.regs.GCR1.get() | gcr1);
+        self.regs.GCR1.modify(GCR1::CLKMOD::SET + GCR1::MASTER.val(master as u32));
 
         // startup the module
-        self.regs.GCR1.set(self.regs.GCR1.get() | 0x0100_0000);
+        self.regs.GCR1.modify(GCR1::SPIEN::SET);
     }
 
     /// SPIENA pin high-impedance enable. When active, the SPIENA pin
     /// is forced to high-impedance when not driving a low signal.
     /// If inactive, then the pin will output both a high and a low signal.
     pub fn highz(&self, enable: bool) {
-        let enablehighz = (enable as u32) << 24;
-        let int0 = self.regs.INT0.get() & !enablehighz;
-        self.regs.INT0.set(int0 | enablehighz);
+        self.regs.INT0.modify(INT0::ENABLEHIGHZ.val(enable as u32));
+    }
+
+    /// SPICLK of data format `format` (0-3): VCLK / (PRESCALE + 1),
+    /// with PRESCALE from 1 to 255
+    pub fn set_baudrate(&self, format: usize, baud: u32) -> Result<(), ClockError> {
+        if format >= self.regs.FMT.len() || baud == 0 {
+            return Err(ClockError::Rate);
+        }
+        let vclk = self.clocks.vclk1();
+        let div = udiv_round_closest!(vclk, baud);
+        if !(2..=256).contains(&div) {
+            return Err(ClockError::Rate);
+        }
+        self.clocks.check_rate(baud, vclk / div)?;
+        self.regs.FMT[format].modify(FMT::PRESCALE.val(div - 1));
+        Ok(())
     }
 }
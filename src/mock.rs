@@ -0,0 +1,283 @@
+//!
+//! Host mock of the memory mapped registers
+//!
+//! Enabled by `cfg(test)` or by the `host-mock` feature. Every register
+//! block obtained through `regs::map()` is backed by an in-memory register
+//! file instead of the fixed hardware address, and `Reg` accesses go
+//! through this module, so that drivers can run on a Linux host:
+//!
+//! ```ignore
+//! mock::reset();
+//! // each test owns its (mocked) peripherals
+//! let p = unsafe { Peripherals::steal() };
+//! let clocks = Sys::new(p.SYS).clocks();
+//! let adc = Adc::new(p.ADC1, &clocks, 16, 8, 16, AdcCoreResolution::Bit12);
+//! // RSTCR: reset asserted then released
+//! assert_eq!(mock::writes_to(0xFFF7_C000), [0x1, 0x0]);
+//!
+//! // BNDEND: buffer initialization still active on the first 3 polls
+//! mock::poke(0xFFF7_C05C, 0x1 << 16);
+//! mock::clear_after_reads(0xFFF7_C05C, 0x1 << 16, 3);
+//! adc.activate();
+//! ```
+//!
+//! The SCI, DCAN, ADC, ESM, DMA and PLL drivers check their write sequences
+//! this way in their `tests` modules. Run them with
+//! `cargo test --target x86_64-unknown-linux-gnu --features <device>`
+//! (the default target is the ARM one), or build an external test crate
+//! against the `host-mock` feature.
+//!
+//! Addresses are always the hardware ones. State is per thread, so tests
+//! running in parallel don't see each other's registers.
+
+use std::boxed::Box;
+use std::cell::RefCell;
+use std::mem;
+use std::vec::Vec;
+
+use crate::regs::RegisterWidth;
+use vcell::VolatileCell;
+
+/// Write access recorded in the log
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Write {
+    /// Hardware address of the register
+    pub addr: u32,
+    pub value: u32,
+    /// Access width in bytes
+    pub width: u8,
+}
+
+/// Register file backing a block mapped at `base`
+struct Region {
+    base: u32,
+    mem: usize,
+    len: usize,
+}
+
+enum Effect {
+    /// Bits set (or cleared) in memory once `reads` reads have been done
+    AfterReads {
+        addr: u32,
+        mask: u32,
+        set: bool,
+        reads: usize,
+    },
+    /// Values returned by the next reads, memory is not touched
+    ReadSequence { addr: u32, values: Vec<u32> },
+    /// Value stored on write, from the current and the written values
+    OnWrite {
+        addr: u32,
+        f: Box<dyn FnMut(u32, u32) -> u32>,
+    },
+}
+
+#[derive(Default)]
+struct State {
+    regions: Vec<Region>,
+    effects: Vec<Effect>,
+    log: Vec<Write>,
+}
+
+std::thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+impl State {
+    fn hw_addr(&self, ptr: usize) -> Option<u32> {
+        self.regions
+            .iter()
+            .find(|r| ptr >= r.mem && ptr < r.mem + r.len)
+            .map(|r| r.base + (ptr - r.mem) as u32)
+    }
+
+    fn host_addr(&self, addr: u32) -> Option<usize> {
+        self.regions
+            .iter()
+            .find(|r| addr >= r.base && ((addr - r.base) as usize) < r.len)
+            .map(|r| r.mem + (addr - r.base) as usize)
+    }
+}
+
+/// Register file for the block at `base`. The same block is returned
+/// when a block is mapped again, e.g. by a second driver instance.
+pub(crate) fn map<T>(base: *const T) -> &'static T {
+    let base = base as usize as u32;
+    let len = mem::size_of::<T>();
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        if let Some(r) = s.regions.iter().find(|r| r.base == base && r.len >= len) {
+            return unsafe { &*(r.mem as *const T) };
+        }
+        // u64 words: alignment is enough for any register block
+        let words = len.div_ceil(8);
+        let mem: &'static mut [u64] = Box::leak(std::vec![0u64; words.max(1)].into_boxed_slice());
+        let mem = mem.as_ptr() as usize;
+        s.regions.push(Region { base, mem, len });
+        unsafe { &*(mem as *const T) }
+    })
+}
+
+pub(crate) fn read<T: RegisterWidth>(cell: &VolatileCell<T>) -> T {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        let addr = match s.hw_addr(cell as *const _ as usize) {
+            Some(addr) => addr,
+            None => return cell.get(),
+        };
+        for e in s.effects.iter_mut() {
+            if let Effect::ReadSequence { addr: a, values } = e {
+                if *a == addr && !values.is_empty() {
+                    return T::from_u32(values.remove(0));
+                }
+            }
+        }
+        let mut value = cell.get().into_u32();
+        s.effects.retain_mut(|e| match e {
+            Effect::AfterReads {
+                addr: a,
+                mask,
+                set,
+                reads,
+            } if *a == addr => {
+                if *reads > 0 {
+                    *reads -= 1;
+                    return true;
+                }
+                value = if *set { value | *mask } else { value & !*mask };
+                false
+            }
+            _ => true,
+        });
+        cell.set(T::from_u32(value));
+        T::from_u32(value)
+    })
+}
+
+pub(crate) fn write<T: RegisterWidth>(cell: &VolatileCell<T>, value: T) {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        let addr = match s.hw_addr(cell as *const _ as usize) {
+            Some(addr) => addr,
+            None => return cell.set(value),
+        };
+        let value = value.into_u32();
+        s.log.push(Write {
+            addr,
+            value,
+            width: mem::size_of::<T>() as u8,
+        });
+        let mut stored = value;
+        for e in s.effects.iter_mut() {
+            if let Effect::OnWrite { addr: a, f } = e {
+                if *a == addr {
+                    stored = f(cell.get().into_u32(), value);
+                }
+            }
+        }
+        cell.set(T::from_u32(stored));
+    })
+}
+
+fn with_cell<R>(addr: u32, f: impl FnOnce(&VolatileCell<u32>) -> R) -> R {
+    STATE.with(|s| {
+        let ptr = s
+            .borrow()
+            .host_addr(addr)
+            .unwrap_or_else(|| panic!("mock: no register block mapped at {:#010x}", addr));
+        f(unsafe { &*(ptr as *const VolatileCell<u32>) })
+    })
+}
+
+/// Clear registers, effects and write log of the current thread
+pub fn reset() {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        for r in s.regions.iter() {
+            unsafe { core::ptr::write_bytes(r.mem as *mut u8, 0, r.len) };
+        }
+        s.effects.clear();
+        s.log.clear();
+    })
+}
+
+/// Current content of the 32 bit register at `addr`, without side effects
+pub fn peek(addr: u32) -> u32 {
+    with_cell(addr, |c| c.get())
+}
+
+/// Set the 32 bit register at `addr`, without side effects and not logged
+pub fn poke(addr: u32, value: u32) {
+    with_cell(addr, |c| c.set(value))
+}
+
+/// Bits of `mask` set after `reads` reads of `addr`
+/// (the first `reads` reads see the bits unchanged)
+pub fn set_after_reads(addr: u32, mask: u32, reads: usize) {
+    push(Effect::AfterReads {
+        addr,
+        mask,
+        set: true,
+        reads,
+    })
+}
+
+/// Bits of `mask` cleared after `reads` reads of `addr`
+pub fn clear_after_reads(addr: u32, mask: u32, reads: usize) {
+    push(Effect::AfterReads {
+        addr,
+        mask,
+        set: false,
+        reads,
+    })
+}
+
+/// Next reads of `addr` return `values`, in order
+pub fn read_sequence(addr: u32, values: &[u32]) {
+    push(Effect::ReadSequence {
+        addr,
+        values: values.to_vec(),
+    })
+}
+
+/// Bits of `mask` are cleared by writing 1 (status flags)
+pub fn write_one_to_clear(addr: u32, mask: u32) {
+    on_write(addr, move |old, v| (old & mask & !v) | (v & !mask))
+}
+
+/// Value stored on each write to `addr`, computed by `f` from the
+/// current and the written value
+pub fn on_write<F>(addr: u32, f: F)
+where
+    F: FnMut(u32, u32) -> u32 + 'static,
+{
+    push(Effect::OnWrite {
+        addr,
+        f: Box::new(f),
+    })
+}
+
+fn push(e: Effect) {
+    STATE.with(|s| s.borrow_mut().effects.push(e))
+}
+
+/// All the writes done since the last `reset()` or `clear_log()`
+pub fn writes() -> Vec<Write> {
+    STATE.with(|s| s.borrow().log.clone())
+}
+
+/// Values written to `addr`, in order
+pub fn writes_to(addr: u32) -> Vec<u32> {
+    STATE.with(|s| {
+        s.borrow()
+            .log
+            .iter()
+            .filter(|w| w.addr == addr)
+            .map(|w| w.value)
+            .collect()
+    })
+}
+
+pub fn clear_log() {
+    STATE.with(|s| s.borrow_mut().log.clear())
+}
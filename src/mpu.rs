@@ -0,0 +1,101 @@
+//!
+//! Default MPU layout for TMS570 MCUs
+//!
+//! Regions are computed from the linker script symbols, so the same
+//! layout works for every device linker script (flash and RAM sizes
+//! are taken from the `MEMORY` definition).
+//!
+//! | # | Region                 | Access       | Type             | XN  |
+//! |---|------------------------|--------------|------------------|-----|
+//! | 0 | whole 4GB (background) | Priv RW      | Strongly-ordered | yes |
+//! | 1 | flash                  | Read-only    | Normal WT        | no  |
+//! | 2 | RAM (stacks and data)  | Priv RW      | Normal WT        | yes |
+//! | 3 | peripheral memories    | Priv RW      | Device           | yes |
+//! | 4 | peripheral registers   | Priv RW      | Strongly-ordered | yes |
+//! | 5 | User mode RAM          | Full access  | Normal WT        | yes |
+//!
+//! Kernel RAM (privileged stacks, data, the SVC service table and
+//! windows) is not writable from User mode: User mode code only writes
+//! its stack and the `.user_data` statics (`.user` section, see bsp.ld
+//! and svc.rs), region 5 taking precedence over region 2.
+//!
+//! Reference: TMS570LS Series Technical Reference Manual (TRM), code SPNU489B
+//!            Chapter 2.2 Memory Organization
+
+use cortexr4::mpu::{self, Access, MemoryType, MpuError, Region, RegionSize};
+
+extern "C" {
+    static _rom_start: u32;
+    static _rom_end: u32;
+    static _cpustack_start: u32;
+    static _ram_end: u32;
+    static _suser: u32;
+    static _euser: u32;
+}
+
+/// Peripheral memories (CAN, ADC, HET, MibSPI RAMs, CRC)
+const PERIPH_MEM_BASE: u32 = 0xFC00_0000;
+/// Peripheral control registers and system modules frame
+const PERIPH_REGS_BASE: u32 = 0xFFF8_0000;
+
+/// Number of regions used by the default layout
+pub const DEFAULT_REGIONS: usize = 6;
+
+fn symbol_address(sym: &'static u32) -> u32 {
+    sym as *const u32 as u32
+}
+
+/// Region large enough to cover `[start, end)`, with a base aligned to
+/// its size. Both flash and RAM start on a power-of-two boundary on
+/// TMS570, so the rounded-up region doesn't shift the base.
+fn covering(number: u32, start: u32, end: u32) -> Region {
+    let size = RegionSize::from_bytes(end - start).unwrap_or(RegionSize::Size32B);
+    Region::new(number, start & !(size.bytes().wrapping_sub(1)), size)
+}
+
+/// Build the default region layout from the linker symbols
+pub fn default_regions() -> [Region; DEFAULT_REGIONS] {
+    let (rom_start, rom_end, ram_start, ram_end, user_start, user_end) = unsafe {
+        (
+            symbol_address(&_rom_start),
+            symbol_address(&_rom_end),
+            symbol_address(&_cpustack_start),
+            symbol_address(&_ram_end),
+            symbol_address(&_suser),
+            symbol_address(&_euser),
+        )
+    };
+
+    let background = Region::new(0, 0x0, RegionSize::Size4G);
+
+    let mut flash = covering(1, rom_start, rom_end);
+    flash.access = Access::ReadOnly;
+    flash.xn = false;
+    flash.mem_type = MemoryType::NormalWriteThrough;
+
+    let mut ram = covering(2, ram_start, ram_end);
+    ram.mem_type = MemoryType::NormalWriteThrough;
+
+    let mut periph_mem = Region::new(3, PERIPH_MEM_BASE, RegionSize::Size64M);
+    periph_mem.mem_type = MemoryType::Device;
+
+    let periph_regs = Region::new(4, PERIPH_REGS_BASE, RegionSize::Size512K);
+
+    let mut user = covering(5, user_start, user_end);
+    user.access = Access::FullAccess;
+    user.mem_type = MemoryType::NormalWriteThrough;
+
+    [background, flash, ram, periph_mem, periph_regs, user]
+}
+
+/// Program the default layout, disable any other region and enable
+/// the MPU with the background region disabled.
+pub unsafe fn setup_default() -> Result<(), MpuError> {
+    mpu::disable();
+    mpu::disable_all_regions();
+    for r in default_regions().iter() {
+        mpu::set_region(r)?;
+    }
+    mpu::enable(false);
+    Ok(())
+}
@@ -1,55 +1,341 @@
-///
-/// PCR Driver
-///
+//!
+//! PCR Driver
+//!
+//! Cortex-R4F parts have one PCR, Cortex-R5F parts have three of them,
+//! each one controlling the peripherals of a peripheral segment:
+//! PCR1 (PS0-PS31), PCR2 (PS32-PS63) and PCR3 (PS64-PS95), see the
+//! device datasheet for the peripheral to PS mapping.
+//!
+//! Each peripheral select quadrant and each peripheral memory chip select
+//! can be powered down, and its writes restricted to the privileged
+//! modes, by `PeripheralSelect` or by name (`Peripheral`). On the
+//! Cortex-R5F parts a peripheral spans two PCRs, its memory on PCR1 and
+//! its register frames on PCR3: `Pcrs` controls the named ones.
 
-use vcell::VolatileCell;
+use crate::peripherals::sealed::Sealed;
+#[cfg(not(cortex_r5))]
+use crate::peripherals::PCR;
+#[cfg(cortex_r5)]
+use crate::peripherals::{PCR1, PCR2, PCR3};
+use crate::regs::{map, Reg};
 
 #[repr(C)]
 #[allow(non_snake_case)]
 pub struct Pcr {
-    PMPROTSET0: VolatileCell<u32>,
-    PMPROTSET1: VolatileCell<u32>,
-    _reserved1: [VolatileCell<u32>; 2],
-    PMPROTCLR0: VolatileCell<u32>,
-    PMPROTCLR1: VolatileCell<u32>,
-    _reserved2: [VolatileCell<u32>; 2],
-    PPROTSET0: VolatileCell<u32>,
-    PPROTSET1: VolatileCell<u32>,
-    PPROTSET2: VolatileCell<u32>,
-    PPROTSET3: VolatileCell<u32>,
-    _reserved3: [VolatileCell<u32>; 4],
-    PPROTCLR0: VolatileCell<u32>,
-    PPROTCLR1: VolatileCell<u32>,
-    PPROTCLR2: VolatileCell<u32>,
-    PPROTCLR3: VolatileCell<u32>,
-    _reserved4: [VolatileCell<u32>; 4],
-    PCSPWRDWNSET0: VolatileCell<u32>,
-    PCSPWRDWNSET1: VolatileCell<u32>,
-    _reserved5: [VolatileCell<u32>; 2],
-    PCSPWRDWNCLR0: VolatileCell<u32>,
-    PCSPWRDWNCLR1: VolatileCell<u32>,
-    _reserved6: [VolatileCell<u32>; 2],
-    PSPWRDWNSET0: VolatileCell<u32>,
-    PSPWRDWNSET1: VolatileCell<u32>,
-    PSPWRDWNSET2: VolatileCell<u32>,
-    PSPWRDWNSET3: VolatileCell<u32>,
-    _reserved7: [VolatileCell<u32>; 4],
-    PSPWRDWNCLR0: VolatileCell<u32>,
-    PSPWRDWNCLR1: VolatileCell<u32>,
-    PSPWRDWNCLR2: VolatileCell<u32>,
-    PSPWRDWNCLR3: VolatileCell<u32>,
-}
-const PCR_BASE_ADDR: *const Pcr = 0xFFFF_E000 as *const Pcr;
+    /// Peripheral memory (PCS) protection, set
+    PMPROTSET: [Reg; 2],
+    _reserved1: [Reg; 2],
+    /// Peripheral memory (PCS) protection, clear
+    PMPROTCLR: [Reg; 2],
+    _reserved2: [Reg; 2],
+    /// Peripheral (PS quadrants) protection, set
+    PPROTSET: [Reg; 4],
+    _reserved3: [Reg; 4],
+    /// Peripheral (PS quadrants) protection, clear
+    PPROTCLR: [Reg; 4],
+    _reserved4: [Reg; 4],
+    /// Peripheral memory (PCS) power-down, set
+    PCSPWRDWNSET: [Reg; 2],
+    _reserved5: [Reg; 2],
+    /// Peripheral memory (PCS) power-down, clear
+    PCSPWRDWNCLR: [Reg; 2],
+    _reserved6: [Reg; 2],
+    /// Peripheral (PS quadrants) power-down, set
+    PSPWRDWNSET: [Reg; 4],
+    _reserved7: [Reg; 4],
+    /// Peripheral (PS quadrants) power-down, clear
+    PSPWRDWNCLR: [Reg; 4],
+}
+
+/// Frames of a peripheral: register frame quadrants of a peripheral
+/// select and, for peripherals with a RAM, its peripheral memory chip
+/// select.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PeripheralSelect {
+    /// Peripheral select (0-31, relative to the PCR)
+    pub ps: u8,
+    /// Quadrants of `ps` (bit mask, quadrant 0 is bit 0)
+    pub quadrants: u8,
+    /// Peripheral memory chip select (0-63)
+    pub pcs: Option<u8>,
+}
+
+impl PeripheralSelect {
+    /// PS quadrants register index and bit mask
+    fn ps_bits(&self) -> (usize, u32) {
+        let ps = usize::from(self.ps);
+        (ps / 8, u32::from(self.quadrants & 0xF) << ((ps % 8) * 4))
+    }
+
+    /// PCS register index and bit mask
+    fn pcs_bits(&self) -> Option<(usize, u32)> {
+        self.pcs.map(|pcs| (usize::from(pcs) / 32, 0x1 << (pcs % 32)))
+    }
+}
+
+/// Peripherals controlled by the PCR (`Pcrs` on Cortex-R5F parts)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Peripheral {
+    Adc1,
+    #[cfg(not(feature = "tms570ls0432"))]
+    Adc2,
+    Dcan1,
+    Dcan2,
+    #[cfg(not(feature = "tms570ls0432"))]
+    Dcan3,
+    Gio,
+    Het1,
+    #[cfg(not(feature = "tms570ls0432"))]
+    Het2,
+    #[cfg(not(feature = "tms570ls0432"))]
+    I2c,
+    Lin,
+    MibSpi1,
+    #[cfg(not(feature = "tms570ls0432"))]
+    MibSpi3,
+    #[cfg(not(feature = "tms570ls0432"))]
+    MibSpi5,
+    #[cfg(not(feature = "tms570ls0432"))]
+    Sci,
+}
+
+impl Peripheral {
+    /// Register frame PS and quadrants, and memory PCS (TMS570LS31x/21x
+    /// and TMS570LC43x datasheets, "Device Memory Map" table)
+    fn frames(self) -> (u8, u8, Option<u8>) {
+        match self {
+            Peripheral::Adc1 => (15, 0x3, Some(31)),
+            #[cfg(not(feature = "tms570ls0432"))]
+            Peripheral::Adc2 => (15, 0xC, Some(29)),
+            Peripheral::Dcan1 => (8, 0x3, Some(15)),
+            Peripheral::Dcan2 => (8, 0xC, Some(14)),
+            #[cfg(not(feature = "tms570ls0432"))]
+            Peripheral::Dcan3 => (7, 0x3, Some(13)),
+            Peripheral::Gio => (16, 0x1, None),
+            Peripheral::Het1 => (17, 0x1, Some(35)),
+            #[cfg(not(feature = "tms570ls0432"))]
+            Peripheral::Het2 => (17, 0x2, Some(34)),
+            #[cfg(not(feature = "tms570ls0432"))]
+            Peripheral::I2c => (10, 0x1, None),
+            Peripheral::Lin => (6, 0x1, None),
+            Peripheral::MibSpi1 => (2, 0x3, Some(7)),
+            #[cfg(not(feature = "tms570ls0432"))]
+            Peripheral::MibSpi3 => (1, 0x3, Some(6)),
+            #[cfg(not(feature = "tms570ls0432"))]
+            Peripheral::MibSpi5 => (0, 0x3, Some(5)),
+            #[cfg(not(feature = "tms570ls0432"))]
+            Peripheral::Sci => (6, 0x2, None),
+        }
+    }
+
+    /// Frames of the peripheral
+    #[cfg(not(cortex_r5))]
+    pub fn select(self) -> PeripheralSelect {
+        let (ps, quadrants, pcs) = self.frames();
+        PeripheralSelect { ps, quadrants, pcs }
+    }
+
+    /// Frames of the peripheral controlled by PCR1, PCR2 and PCR3: the
+    /// memory on PCR1, the register frames on PCR3 (PS64-PS95). None of
+    /// the named peripherals is on PCR2.
+    #[cfg(cortex_r5)]
+    pub fn select(self) -> [Option<PeripheralSelect>; 3] {
+        let (ps, quadrants, pcs) = self.frames();
+        [
+            pcs.map(|pcs| PeripheralSelect { ps: 0, quadrants: 0, pcs: Some(pcs) }),
+            None,
+            Some(PeripheralSelect { ps, quadrants, pcs: None }),
+        ]
+    }
+}
+
+/// PCR module instance: `PCR`, or `PCR1`, `PCR2` and `PCR3` on
+/// Cortex-R5F parts
+pub trait Instance: Sealed {
+    const BASE_ADDR: *const Pcr;
+}
+
+#[cfg(not(cortex_r5))]
+impl Instance for PCR {
+    const BASE_ADDR: *const Pcr = 0xFFFF_E000 as *const Pcr;
+}
+
+#[cfg(cortex_r5)]
+impl Instance for PCR1 {
+    const BASE_ADDR: *const Pcr = 0xFFFF_1000 as *const Pcr;
+}
+
+#[cfg(cortex_r5)]
+impl Instance for PCR2 {
+    const BASE_ADDR: *const Pcr = 0xFCFF_1000 as *const Pcr;
+}
+
+#[cfg(cortex_r5)]
+impl Instance for PCR3 {
+    const BASE_ADDR: *const Pcr = 0xFFF7_8000 as *const Pcr;
+}
 
 impl Pcr {
-    pub unsafe fn new() -> &'static Pcr {
-        &*PCR_BASE_ADDR
+    pub fn new<I: Instance>(_pcr: I) -> &'static Pcr {
+        unsafe { map(I::BASE_ADDR) }
     }
 
     pub fn enable_all(&self) {
-        self.PSPWRDWNCLR0.set(0xFFFF_FFFF);
-        self.PSPWRDWNCLR1.set(0xFFFF_FFFF);
-        self.PSPWRDWNCLR2.set(0xFFFF_FFFF);
-        self.PSPWRDWNCLR3.set(0xFFFF_FFFF);
+        for clr in self.PSPWRDWNCLR.iter() {
+            clr.set(0xFFFF_FFFF);
+        }
+    }
+
+    /// Power down (or up) the frames of `sel`. Accesses to a peripheral
+    /// powered down generate a bus error.
+    pub fn select_power_down(&self, sel: PeripheralSelect, down: bool) {
+        let (i, bits) = sel.ps_bits();
+        if down {
+            self.PSPWRDWNSET[i].set(bits);
+        } else {
+            self.PSPWRDWNCLR[i].set(bits);
+        }
+        if let Some((i, bit)) = sel.pcs_bits() {
+            if down {
+                self.PCSPWRDWNSET[i].set(bit);
+            } else {
+                self.PCSPWRDWNCLR[i].set(bit);
+            }
+        }
+    }
+
+    /// 'true' if all the frames of `sel` are powered down
+    pub fn select_powered_down(&self, sel: PeripheralSelect) -> bool {
+        let (i, bits) = sel.ps_bits();
+        self.PSPWRDWNSET[i].get() & bits == bits
+            && sel.pcs_bits().is_none_or(|(i, bit)| self.PCSPWRDWNSET[i].get() & bit != 0)
+    }
+
+    /// Allow writes to the frames of `sel` in privileged mode only
+    /// (`true`), or in user mode too. Reads are always allowed.
+    pub fn select_protect(&self, sel: PeripheralSelect, privileged: bool) {
+        let (i, bits) = sel.ps_bits();
+        if privileged {
+            self.PPROTSET[i].set(bits);
+        } else {
+            self.PPROTCLR[i].set(bits);
+        }
+        if let Some((i, bit)) = sel.pcs_bits() {
+            if privileged {
+                self.PMPROTSET[i].set(bit);
+            } else {
+                self.PMPROTCLR[i].set(bit);
+            }
+        }
     }
-}
\ No newline at end of file
+
+    /// 'true' if all the frames of `sel` are writable in privileged
+    /// mode only
+    pub fn select_protected(&self, sel: PeripheralSelect) -> bool {
+        let (i, bits) = sel.ps_bits();
+        self.PPROTSET[i].get() & bits == bits
+            && sel.pcs_bits().is_none_or(|(i, bit)| self.PMPROTSET[i].get() & bit != 0)
+    }
+
+    /// Power down (or up) a peripheral
+    #[cfg(not(cortex_r5))]
+    pub fn power_down(&self, p: Peripheral, down: bool) {
+        self.select_power_down(p.select(), down)
+    }
+
+    #[cfg(not(cortex_r5))]
+    pub fn powered_down(&self, p: Peripheral) -> bool {
+        self.select_powered_down(p.select())
+    }
+
+    /// Peripheral writable in privileged mode only (`true`), or in user
+    /// mode too
+    #[cfg(not(cortex_r5))]
+    pub fn protect(&self, p: Peripheral, privileged: bool) {
+        self.select_protect(p.select(), privileged)
+    }
+
+    #[cfg(not(cortex_r5))]
+    pub fn protected(&self, p: Peripheral) -> bool {
+        self.select_protected(p.select())
+    }
+}
+
+/// The three PCRs of the Cortex-R5F parts, controlling the named
+/// peripherals
+#[cfg(cortex_r5)]
+pub struct Pcrs {
+    pcr: [&'static Pcr; 3],
+}
+
+#[cfg(cortex_r5)]
+impl Pcrs {
+    pub fn new(pcr1: PCR1, pcr2: PCR2, pcr3: PCR3) -> Pcrs {
+        Pcrs {
+            pcr: [Pcr::new(pcr1), Pcr::new(pcr2), Pcr::new(pcr3)],
+        }
+    }
+
+    /// PCRs and frames of a peripheral
+    fn selects(&self, p: Peripheral)
+               -> impl Iterator<Item = (&'static Pcr, PeripheralSelect)> + '_ {
+        self.pcr
+            .iter()
+            .zip(p.select())
+            .filter_map(|(&pcr, sel)| sel.map(|sel| (pcr, sel)))
+    }
+
+    /// Power down (or up) a peripheral
+    pub fn power_down(&self, p: Peripheral, down: bool) {
+        self.selects(p).for_each(|(pcr, sel)| pcr.select_power_down(sel, down));
+    }
+
+    pub fn powered_down(&self, p: Peripheral) -> bool {
+        self.selects(p).all(|(pcr, sel)| pcr.select_powered_down(sel))
+    }
+
+    /// Peripheral writable in privileged mode only (`true`), or in user
+    /// mode too
+    pub fn protect(&self, p: Peripheral, privileged: bool) {
+        self.selects(p).for_each(|(pcr, sel)| pcr.select_protect(sel, privileged));
+    }
+
+    pub fn protected(&self, p: Peripheral) -> bool {
+        self.selects(p).all(|(pcr, sel)| pcr.select_protected(sel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[cfg(not(cortex_r5))]
+    #[test]
+    fn power_down() {
+        mock::reset();
+        let pcr = Pcr::new(unsafe { PCR::steal() });
+        pcr.power_down(Peripheral::Dcan2, true);
+        // PSPWRDWNSET1 PS8 quadrants 2-3, PCSPWRDWNSET0 PCS14
+        assert_eq!(mock::writes_to(0xFFFF_E084), [0xC]);
+        assert_eq!(mock::writes_to(0xFFFF_E060), [0x1 << 14]);
+        assert!(pcr.powered_down(Peripheral::Dcan2));
+    }
+
+    #[cfg(cortex_r5)]
+    #[test]
+    fn power_down() {
+        mock::reset();
+        let pcrs = unsafe { Pcrs::new(PCR1::steal(), PCR2::steal(), PCR3::steal()) };
+        pcrs.power_down(Peripheral::Dcan2, true);
+        // memory PCS14 on PCR1, frames PS72 quadrants 2-3 on PCR3
+        assert_eq!(mock::writes_to(0xFFFF_1060), [0x1 << 14]);
+        assert_eq!(mock::writes_to(0xFFF7_8084), [0xC]);
+        assert!(mock::writes().iter().all(|w| w.addr & 0xFFFF_F000 != 0xFCFF_1000));
+        // PCR3 frames only, for a peripheral without memory
+        mock::clear_log();
+        pcrs.protect(Peripheral::Gio, true);
+        assert_eq!(mock::writes_to(0xFFF7_8028), [0x1]);
+        assert_eq!(mock::writes().len(), 1);
+    }
+}
@@ -0,0 +1,190 @@
+//!
+//! Peripheral ownership
+//!
+//! Each module instance is represented by a zero-sized token, handed out
+//! exactly once by `Peripherals::take()`. Driver constructors consume the
+//! token of the instance they drive, so two subsystems can't configure
+//! the same peripheral:
+//!
+//! ```ignore
+//! let p = Peripherals::take().unwrap();
+//! let clocks = Sys::new(p.SYS).clocks();
+//! let can = DCan::new(p.DCAN1, &clocks);
+//! let spi = MibSpi::new(p.MIBSPI3, &clocks, true);
+//! let adc = Adc::new(p.ADC1, &clocks, 16, 8, 16, AdcCoreResolution::Bit12);
+//! ```
+//!
+//! Drivers with more instances are generic over an `Instance` trait
+//! (e.g. `can::Instance`) implemented by the tokens of that module only.
+//!
+//! Only the modules available on the selected device (see `device`) have
+//! a token.
+
+use cortexr4::interrupt;
+
+pub(crate) mod sealed {
+    /// Only tokens defined in this crate can implement the `Instance`
+    /// traits of the drivers
+    pub trait Sealed {}
+}
+
+macro_rules! peripherals {
+    ($($(#[doc = $doc:expr])* $(#[cfg($cfg:meta)])* $name:ident,)*) => {
+        $(
+            $(#[doc = $doc])*
+            $(#[cfg($cfg)])*
+            pub struct $name {
+                _0: (),
+            }
+
+            $(#[cfg($cfg)])*
+            impl $name {
+                /// Token not tracked by `Peripherals::take()`.
+                /// Used by the startup code (before `main`) and by drivers
+                /// relying on other modules (e.g. ESM error reporting).
+                #[allow(dead_code)]
+                pub(crate) unsafe fn steal() -> $name {
+                    $name { _0: () }
+                }
+            }
+
+            $(#[cfg($cfg)])*
+            impl sealed::Sealed for $name {}
+        )*
+
+        /// All the peripherals of the device
+        #[allow(non_snake_case)]
+        pub struct Peripherals {
+            $(
+                $(#[doc = $doc])*
+                $(#[cfg($cfg)])*
+                pub $name: $name,
+            )*
+        }
+
+        impl Peripherals {
+            /// All the peripherals, the first time it is called.
+            /// Then it returns `None`.
+            pub fn take() -> Option<Peripherals> {
+                interrupt::free(|_| {
+                    if unsafe { TAKEN } {
+                        None
+                    } else {
+                        Some(unsafe { Peripherals::steal() })
+                    }
+                })
+            }
+
+            /// All the peripherals, without checking if they have been
+            /// already taken.
+            ///
+            /// # Safety
+            /// Any previous owner of a peripheral is aliased
+            pub unsafe fn steal() -> Peripherals {
+                TAKEN = true;
+                Peripherals {
+                    $(
+                        $(#[cfg($cfg)])*
+                        $name: $name { _0: () },
+                    )*
+                }
+            }
+        }
+    };
+}
+
+static mut TAKEN: bool = false;
+
+peripherals! {
+    /// Analog To Digital Converter 1
+    ADC1,
+    /// Analog To Digital Converter 2
+    #[cfg(not(feature = "tms570ls0432"))]
+    ADC2,
+    /// CPU Compare Module
+    CCM,
+    /// Dual-Clock Comparator 1
+    DCC1,
+    /// Dual-Clock Comparator 2
+    #[cfg(not(feature = "tms570ls0432"))]
+    DCC2,
+    /// Controller Area Network 1
+    DCAN1,
+    /// Controller Area Network 2
+    DCAN2,
+    /// Controller Area Network 3
+    #[cfg(not(feature = "tms570ls0432"))]
+    DCAN3,
+    /// Direct Memory Access Controller
+    #[cfg(not(feature = "tms570ls0432"))]
+    DMA,
+    /// Digital Windowed Watchdog (RTI module)
+    DWWD,
+    /// eFuse Controller
+    EFC,
+    /// Error Profiling Controller
+    #[cfg(cortex_r5)]
+    EPC,
+    /// Error Signaling Module
+    ESM,
+    /// Flash Wrapper
+    FLASH,
+    /// General-Purpose Input/Output
+    GIO,
+    /// High-End Timer 1
+    HET1,
+    /// High-End Timer 2
+    #[cfg(not(feature = "tms570ls0432"))]
+    HET2,
+    /// Cyclic Redundancy Check Controller
+    HWCRC,
+    /// Inter-Integrated Circuit
+    #[cfg(not(feature = "tms570ls0432"))]
+    I2C,
+    /// I/O Multiplexing and Control Module
+    IOMM,
+    /// Local Interconnect Network (LIN/SCI)
+    LIN,
+    /// Multi-Buffered Serial Peripheral Interface 1
+    MIBSPI1,
+    /// Multi-Buffered Serial Peripheral Interface 3
+    #[cfg(not(feature = "tms570ls0432"))]
+    MIBSPI3,
+    /// Multi-Buffered Serial Peripheral Interface 5
+    #[cfg(not(feature = "tms570ls0432"))]
+    MIBSPI5,
+    /// Peripheral Central Resource
+    #[cfg(not(cortex_r5))]
+    PCR,
+    /// Peripheral Central Resource 1
+    #[cfg(cortex_r5)]
+    PCR1,
+    /// Peripheral Central Resource 2
+    #[cfg(cortex_r5)]
+    PCR2,
+    /// Peripheral Central Resource 3
+    #[cfg(cortex_r5)]
+    PCR3,
+    /// Real-Time Interrupt
+    RTI,
+    /// Serial Communication Interface
+    #[cfg(not(feature = "tms570ls0432"))]
+    SCI,
+    /// SCR Control Module (interconnect safety diagnostics)
+    #[cfg(cortex_r5)]
+    SCM,
+    /// CPU Self-Test Controller
+    STC,
+    /// System Module (system frame 1 and 2, PBIST)
+    SYS,
+    /// System exception status (reset sources)
+    SYSEXC,
+    /// TCM RAM Wrapper 1 (even bank)
+    #[cfg(not(cortex_r5))]
+    TCRAM1,
+    /// TCM RAM Wrapper 2 (odd bank)
+    #[cfg(not(cortex_r5))]
+    TCRAM2,
+    /// Vectored Interrupt Manager
+    VIM,
+}
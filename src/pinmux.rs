@@ -1,8 +1,15 @@
-///! Map PINNMRx register to bit field
-///!
-///! Reference: TMS570LS31x/21x 16/32-Bit RISC Flash Microcontroller
-///!            Chapter 4.6 Signal Multiplexing and Control
+//! Map PINNMRx register to bit field
+//!
+//! Pin numbers, PINMMR fields and options are device and package
+//! specific. `PinSelect` is the generic form, available on every device:
+//! PINMMR register, field and option as listed in the "Multiplexing
+//! control" table of the device datasheet. The named `PinMux` table is
+//! only available for the TMS570LS0714 (144-pin PGE).
+//!
+//! Reference: TMS570LS0714 16/32-Bit RISC Flash Microcontroller (SPNS226C)
+//!            Signal Multiplexing and Control
 
+#[cfg(feature = "tms570ls0714")]
 macro_rules! grp_option {
     ($pin: expr, $group: expr, $shift: expr, $option: expr) => {
         (($pin as isize) << 24)
@@ -12,6 +19,7 @@ macro_rules! grp_option {
     };
 }
 
+#[cfg(feature = "tms570ls0714")]
 #[derive(Copy, Clone)]
 #[allow(non_camel_case_types)]
 #[rustfmt::skip]
@@ -83,6 +91,7 @@ pub enum PinMux {
     N2NET1_26    = grp_option!(92, 12, 0, 0),
 }
 
+#[cfg(feature = "tms570ls0714")]
 impl PinMux {
     pub fn pin(self) -> usize {
         (self as usize) >> 24
@@ -100,3 +109,30 @@ impl PinMux {
         ((self as usize) >> 16) & 0xff
     }
 }
+
+/// Setting of a PINMMR field: field at bit `shift` of PINMMR`group`,
+/// `option` selects the bit set in the field (the field is 8 bits wide,
+/// only one option is selected at a time)
+#[derive(Copy, Clone)]
+pub struct PinSelect {
+    pub group: usize,
+    pub shift: usize,
+    pub option: usize,
+}
+
+impl PinSelect {
+    pub const fn new(group: usize, shift: usize, option: usize) -> PinSelect {
+        PinSelect {
+            group,
+            shift,
+            option,
+        }
+    }
+}
+
+#[cfg(feature = "tms570ls0714")]
+impl From<PinMux> for PinSelect {
+    fn from(pin: PinMux) -> PinSelect {
+        PinSelect::new(pin.group(), pin.shift(), pin.option())
+    }
+}
@@ -0,0 +1,379 @@
+//!
+//! Typed register access
+//!
+//! A register is declared as `Reg<T, R>`, where `T` is the access width
+//! (u8, u16 or u32) and `R` the marker type generated by
+//! `register_bitfields!` for its fields. Fields can only be used with the
+//! register they belong to: writing a `CLKCNTL` field into `GHVSRC` is a
+//! compile error.
+//!
+//! ```ignore
+//! register_bitfields! {
+//!     CLKCNTL [
+//!         /// Peripheral enable
+//!         PENA OFFSET(8) NUMBITS(1) [],
+//!         /// VBUS clock ratio
+//!         VCLKR OFFSET(16) NUMBITS(4) []
+//!     ]
+//! }
+//!
+//! clkcntl.modify(CLKCNTL::PENA::SET + CLKCNTL::VCLKR.val(1));
+//! let ratio = clkcntl.read(CLKCNTL::VCLKR);
+//! clkcntl.update(|v| if v.is_set(CLKCNTL::PENA) { v } else { v.modify(CLKCNTL::VCLKR.val(0)) });
+//! ```
+//!
+//! Registers without named fields are `Reg<T>` and keep the raw
+//! `get`/`set` access.
+//!
+//! Register blocks are obtained with `map()`, so that they can be backed
+//! by the host mock (see `mock`) when testing on a PC.
+
+use core::marker::PhantomData;
+use core::ops::Add;
+use vcell::VolatileCell;
+
+/// Register access width
+pub trait RegisterWidth: Copy {
+    fn into_u32(self) -> u32;
+    fn from_u32(value: u32) -> Self;
+}
+
+impl RegisterWidth for u8 {
+    #[inline(always)]
+    fn into_u32(self) -> u32 {
+        u32::from(self)
+    }
+    #[inline(always)]
+    fn from_u32(value: u32) -> u8 {
+        value as u8
+    }
+}
+
+impl RegisterWidth for u16 {
+    #[inline(always)]
+    fn into_u32(self) -> u32 {
+        u32::from(self)
+    }
+    #[inline(always)]
+    fn from_u32(value: u32) -> u16 {
+        value as u16
+    }
+}
+
+impl RegisterWidth for u32 {
+    #[inline(always)]
+    fn into_u32(self) -> u32 {
+        self
+    }
+    #[inline(always)]
+    fn from_u32(value: u32) -> u32 {
+        value
+    }
+}
+
+/// Register block at the fixed address `base`.
+/// With the host mock the block lives in an in-memory register file.
+///
+/// # Safety
+/// `base` must be the address of a register block of type `T`
+#[inline(always)]
+pub unsafe fn map<T>(base: *const T) -> &'static T {
+    #[cfg(not(any(test, feature = "host-mock")))]
+    {
+        &*base
+    }
+    #[cfg(any(test, feature = "host-mock"))]
+    {
+        crate::mock::map(base)
+    }
+}
+
+/// Memory mapped register of width `T` with the fields of `R`
+#[repr(transparent)]
+pub struct Reg<T: RegisterWidth = u32, R = ()> {
+    cell: VolatileCell<T>,
+    _reg: PhantomData<R>,
+}
+
+impl<T: RegisterWidth, R> Reg<T, R> {
+    /// Raw read
+    #[inline(always)]
+    pub fn get(&self) -> T {
+        #[cfg(not(any(test, feature = "host-mock")))]
+        {
+            self.cell.get()
+        }
+        #[cfg(any(test, feature = "host-mock"))]
+        {
+            crate::mock::read(&self.cell)
+        }
+    }
+
+    /// Raw write
+    #[inline(always)]
+    pub fn set(&self, value: T) {
+        #[cfg(not(any(test, feature = "host-mock")))]
+        {
+            self.cell.set(value)
+        }
+        #[cfg(any(test, feature = "host-mock"))]
+        {
+            crate::mock::write(&self.cell, value)
+        }
+    }
+
+    /// Read a field, shifted down to bit 0
+    #[inline(always)]
+    pub fn read(&self, field: Field<R>) -> u32 {
+        field.read(self.get().into_u32())
+    }
+
+    /// True if any bit of the field is set
+    #[inline(always)]
+    pub fn is_set(&self, field: Field<R>) -> bool {
+        self.read(field) != 0
+    }
+
+    /// True if all the fields of `fv` have the given values
+    #[inline(always)]
+    pub fn matches_all(&self, fv: FieldValue<R>) -> bool {
+        self.get().into_u32() & fv.mask == fv.value
+    }
+
+    /// Copy of the register content, to decode several fields
+    /// with a single access
+    #[inline(always)]
+    pub fn extract(&self) -> Value<R> {
+        Value::new(self.get().into_u32())
+    }
+
+    /// Write the given fields, all other bits are cleared
+    #[inline(always)]
+    pub fn write(&self, fv: FieldValue<R>) {
+        self.set(T::from_u32(fv.value))
+    }
+
+    /// Read-modify-write of the given fields, other bits are kept
+    #[inline(always)]
+    pub fn modify(&self, fv: FieldValue<R>) {
+        let v = self.get().into_u32();
+        self.set(T::from_u32(fv.modify(v)))
+    }
+
+    /// Read-modify-write through a closure
+    #[inline(always)]
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(Value<R>) -> Value<R>,
+    {
+        let v = f(self.extract());
+        self.set(T::from_u32(v.get()))
+    }
+}
+
+/// Bit field of a register of type `R`
+pub struct Field<R> {
+    mask: u32,
+    shift: u32,
+    _reg: PhantomData<R>,
+}
+
+impl<R> Clone for Field<R> {
+    fn clone(&self) -> Field<R> {
+        *self
+    }
+}
+
+impl<R> Copy for Field<R> {}
+
+impl<R> Field<R> {
+    /// `mask` is not shifted (i.e. it starts at bit 0)
+    pub const fn new(mask: u32, shift: u32) -> Field<R> {
+        Field {
+            mask,
+            shift,
+            _reg: PhantomData,
+        }
+    }
+
+    /// Field set to `value`, out of range bits are discarded
+    #[inline(always)]
+    pub const fn val(&self, value: u32) -> FieldValue<R> {
+        FieldValue::new(self.mask, self.shift, value)
+    }
+
+    #[inline(always)]
+    pub const fn read(&self, reg: u32) -> u32 {
+        (reg >> self.shift) & self.mask
+    }
+
+    /// Mask of the field in the register
+    #[inline(always)]
+    pub const fn mask(&self) -> u32 {
+        self.mask << self.shift
+    }
+}
+
+/// One or more fields of a register of type `R` with their values.
+/// Values of different fields are combined with `+`.
+pub struct FieldValue<R> {
+    mask: u32,
+    value: u32,
+    _reg: PhantomData<R>,
+}
+
+impl<R> Clone for FieldValue<R> {
+    fn clone(&self) -> FieldValue<R> {
+        *self
+    }
+}
+
+impl<R> Copy for FieldValue<R> {}
+
+impl<R> FieldValue<R> {
+    pub const fn new(mask: u32, shift: u32, value: u32) -> FieldValue<R> {
+        FieldValue {
+            mask: mask << shift,
+            value: (value & mask) << shift,
+            _reg: PhantomData,
+        }
+    }
+
+    /// Mask of all the fields
+    #[inline(always)]
+    pub const fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// Raw value, to be written as is
+    #[inline(always)]
+    pub const fn value(&self) -> u32 {
+        self.value
+    }
+
+    /// Apply the fields to `reg`
+    #[inline(always)]
+    pub const fn modify(&self, reg: u32) -> u32 {
+        (reg & !self.mask) | self.value
+    }
+}
+
+impl<R> Add for FieldValue<R> {
+    type Output = FieldValue<R>;
+
+    #[inline(always)]
+    fn add(self, rhs: FieldValue<R>) -> FieldValue<R> {
+        FieldValue {
+            mask: self.mask | rhs.mask,
+            value: self.value | rhs.value,
+            _reg: PhantomData,
+        }
+    }
+}
+
+/// Local copy of a register of type `R`
+pub struct Value<R> {
+    value: u32,
+    _reg: PhantomData<R>,
+}
+
+impl<R> Clone for Value<R> {
+    fn clone(&self) -> Value<R> {
+        *self
+    }
+}
+
+impl<R> Copy for Value<R> {}
+
+impl<R> Value<R> {
+    pub const fn new(value: u32) -> Value<R> {
+        Value {
+            value,
+            _reg: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub const fn get(&self) -> u32 {
+        self.value
+    }
+
+    #[inline(always)]
+    pub const fn read(&self, field: Field<R>) -> u32 {
+        field.read(self.value)
+    }
+
+    #[inline(always)]
+    pub const fn is_set(&self, field: Field<R>) -> bool {
+        field.read(self.value) != 0
+    }
+
+    #[inline(always)]
+    pub const fn modify(self, fv: FieldValue<R>) -> Value<R> {
+        Value::new(fv.modify(self.value))
+    }
+}
+
+/// Declare the fields of one or more registers.
+///
+/// For each register `REG` a module is generated with:
+/// - `REG::Register`, the marker type used in `Reg<T, REG::Register>`
+/// - `REG::FIELD`, a `Field` for each field
+/// - `REG::FIELD::SET`, `REG::FIELD::CLEAR` (all bits set/cleared) and
+///   the enumerated values listed for the field.
+#[macro_export]
+macro_rules! register_bitfields {
+    (
+        $(
+            $(#[$reg_attr:meta])*
+            $reg:ident [
+                $(
+                    $(#[$field_attr:meta])*
+                    $field:ident OFFSET($offset:expr) NUMBITS($numbits:expr) [
+                        $(
+                            $(#[$value_attr:meta])*
+                            $value:ident = $value_raw:expr
+                        ),* $(,)?
+                    ]
+                ),* $(,)?
+            ]
+        ),* $(,)?
+    ) => {
+        $(
+            $(#[$reg_attr])*
+            #[allow(non_snake_case, dead_code)]
+            pub mod $reg {
+                /// Register marker type
+                pub struct Register;
+
+                $(
+                    $(#[$field_attr])*
+                    #[allow(non_upper_case_globals)]
+                    pub const $field: $crate::regs::Field<Register> = $crate::regs::Field::new(
+                        ((1u64 << $numbits) - 1) as u32,
+                        $offset,
+                    );
+
+                    $(#[$field_attr])*
+                    #[allow(non_snake_case, non_upper_case_globals, dead_code)]
+                    pub mod $field {
+                        use super::Register;
+                        use $crate::regs::FieldValue;
+
+                        const MASK: u32 = ((1u64 << $numbits) - 1) as u32;
+
+                        /// All field bits set
+                        pub const SET: FieldValue<Register> = FieldValue::new(MASK, $offset, MASK);
+                        /// All field bits cleared
+                        pub const CLEAR: FieldValue<Register> = FieldValue::new(MASK, $offset, 0);
+                        $(
+                            $(#[$value_attr])*
+                            pub const $value: FieldValue<Register> =
+                                FieldValue::new(MASK, $offset, $value_raw);
+                        )*
+                    }
+                )*
+            }
+        )*
+    };
+}
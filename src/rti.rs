@@ -1,121 +1,153 @@
-use crate::config::RTICLK1;
+use crate::clock::{ClockError, Clocks};
 use crate::dwd::{WdViolation, DWD};
+use crate::peripherals::DWWD;
 ///
 /// RTI Control Module
 ///
 use core::mem;
-use vcell::VolatileCell;
+use crate::regs::{map, Reg};
+
+register_bitfields! {
+    /// Digital Watchdog Control
+    DWDCTRL [
+        DWDCTRL OFFSET(0) NUMBITS(32) [
+            Enable = 0xA985_59DA,
+            Disable = 0x5312_ACED
+        ]
+    ],
+    /// Digital Watchdog Preload
+    DWDPRLD [
+        /// Expiration time: (DWDPRLD + 1) x 2^13 RTICLK cycles
+        DWDPRLD OFFSET(0) NUMBITS(12) []
+    ],
+    /// Watchdog Key
+    WDKEY [
+        /// Service sequence is `Key1` then `Key2`, any other value
+        /// is a key sequence violation
+        WDKEY OFFSET(0) NUMBITS(16) [
+            Key1 = 0xE51A,
+            Key2 = 0xA35C,
+            Invalid = 0x2345
+        ]
+    ]
+}
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Counter {
     /// Free Running Counter
-    FRCx: VolatileCell<u32>,
+    FRCx: Reg,
     /// Up Counter x
-    UCx: VolatileCell<u32>,
+    UCx: Reg,
     /// Compare Up Counter x
-    CPUCx: VolatileCell<u32>,
+    CPUCx: Reg,
     /// Reserved
-    _rsvd1: VolatileCell<u32>,
+    _rsvd1: Reg,
     /// Capture Free Running Counter x
-    CAFRCx: VolatileCell<u32>,
+    CAFRCx: Reg,
     /// Capture Up Counter x
-    CAUCx: VolatileCell<u32>,
+    CAUCx: Reg,
     /// Reserved
-    _rsvd2: [VolatileCell<u32>; 2],
+    _rsvd2: [Reg; 2],
 }
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Comparator {
     /// Compare x
-    COMPx: VolatileCell<u32>,
+    COMPx: Reg,
     /// Update Compare x
-    UDCPx: VolatileCell<u32>,
+    UDCPx: Reg,
 }
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct RtiRegisters {
     /// Global Control
-    GCTRL: VolatileCell<u32>,
+    GCTRL: Reg,
     /// Timebase Control
-    TBCTRL: VolatileCell<u32>,
+    TBCTRL: Reg,
     /// Capture Control
-    CAPCTRL: VolatileCell<u32>,
+    CAPCTRL: Reg,
     /// Compare Control
-    COMPCTRL: VolatileCell<u32>,
+    COMPCTRL: Reg,
     Cnt: [Counter; 2],
     Cmp: [Comparator; 4],
     /// External Clock Timebase Low Compare
-    TBLCOMP: VolatileCell<u32>,
+    TBLCOMP: Reg,
     /// External Clock Timebase High Compare
-    TBHCOMP: VolatileCell<u32>,
+    TBHCOMP: Reg,
     /// Reserved
-    _rsvd3: [VolatileCell<u32>; 2],
+    _rsvd3: [Reg; 2],
     /// Set/Status Interrupt
-    SETINTENA: VolatileCell<u32>,
+    SETINTENA: Reg,
     /// Clear/Status Interrupt
-    CLEARINTENA: VolatileCell<u32>,
+    CLEARINTENA: Reg,
     /// Interrupt Flag
-    INTFLAG: VolatileCell<u32>,
+    INTFLAG: Reg,
     /// Reserved
-    _rsvd4: VolatileCell<u32>,
+    _rsvd4: Reg,
     /// Digital Watchdog Control
-    DWDCTRL: VolatileCell<u32>,
+    DWDCTRL: Reg<u32, DWDCTRL::Register>,
     /// Digital Watchdog Preload
-    DWDPRLD: VolatileCell<u32>,
+    DWDPRLD: Reg<u32, DWDPRLD::Register>,
     /// Watchdog Status
-    WDSTATUS: VolatileCell<u32>,
+    WDSTATUS: Reg,
     /// Watchdog Key
-    WDKEY: VolatileCell<u32>,
+    WDKEY: Reg<u32, WDKEY::Register>,
     /// Digital Watchdog Down Counter
-    DWDCNTR: VolatileCell<u32>,
+    DWDCNTR: Reg,
     /// Digital Windowed Watchdog Reaction Control
-    WWDRXNCTRL: VolatileCell<u32>,
+    WWDRXNCTRL: Reg,
     /// Digital Windowed Watchdog Window Size Control
-    WWDSIZECTRL: VolatileCell<u32>,
+    WWDSIZECTRL: Reg,
     /// RTI Compare Interrupt Clear Enable
-    INTCLRENABLE: VolatileCell<u32>,
+    INTCLRENABLE: Reg,
     /// RTI Compare 0 Clear
-    COMP0CLR: VolatileCell<u32>,
+    COMP0CLR: Reg,
     /// RTI Compare 1 Clear
-    COMP1CLR: VolatileCell<u32>,
+    COMP1CLR: Reg,
     /// RTI Compare 2 Clear
-    COMP2CLR: VolatileCell<u32>,
+    COMP2CLR: Reg,
     /// RTI Compare 3 Clear
-    COMP3CLR: VolatileCell<u32>,
+    COMP3CLR: Reg,
 }
 const RTI_BASE_ADDR: *const RtiRegisters = 0xFFFF_FC00 as *const RtiRegisters;
 
 #[derive(Clone, Copy)]
 pub struct ChipWatchDog {
     regs: &'static RtiRegisters,
+    /// RTICLK1 [Hz]
+    rticlk: u32,
 }
 
 impl DWD for ChipWatchDog {
-    fn new() -> ChipWatchDog {
+    fn new(_dwwd: DWWD, clocks: &Clocks) -> ChipWatchDog {
         ChipWatchDog {
-            regs: unsafe { &*RTI_BASE_ADDR },
+            regs: unsafe { map(RTI_BASE_ADDR) },
+            rticlk: clocks.rticlk1(),
         }
     }
 
-    fn start(&self, expire: u32) {
+    /// Start the watchdog, `expire` expiration time [ms]. The watchdog
+    /// isn't started if `expire` is out of range for RTICLK1.
+    fn start(&self, expire: u32) -> Result<(), ClockError> {
         self.status_clear();
-        self.expire(expire).unwrap();
+        self.expire(expire)?;
         self.counter_enable();
+        Ok(())
     }
 
     /// Reset Digital Watchdog
     fn reset(&self) {
-        self.regs.WDKEY.set(0xE51A);
-        self.regs.WDKEY.set(0xA35C);
+        self.regs.WDKEY.write(WDKEY::WDKEY::Key1);
+        self.regs.WDKEY.write(WDKEY::WDKEY::Key2);
     }
 
     /// Generate system reset using DWD
     fn sys_reset(&self) {
-        self.regs.WDKEY.set(0xE51A);
-        self.regs.WDKEY.set(0x2345);
+        self.regs.WDKEY.write(WDKEY::WDKEY::Key1);
+        self.regs.WDKEY.write(WDKEY::WDKEY::Invalid);
     }
 
     fn status(&self) -> WdViolation {
@@ -133,23 +165,57 @@ impl DWD for ChipWatchDog {
         self.regs.WDSTATUS.set(0xFF);
     }
 
-    fn expire(&self, expire: u32) -> Result<(), u32> {
+    /// `expire` expiration time [ms]
+    fn expire(&self, expire: u32) -> Result<(), ClockError> {
         // texp = (DWDPRLD + 1) x 2^13 / RTICLK1
         // where: DWDPRLD = 0...4095
-        let dwd_preload = ((expire * RTICLK1) / (1 << 13)) - 1;
-        if dwd_preload < 4095 {
-            self.regs.DWDPRLD.set(dwd_preload);
-            return Ok(());
+        let ticks = u64::from(expire) * u64::from(self.rticlk) / 1000;
+        match (ticks / (1 << 13)).checked_sub(1) {
+            Some(dwd_preload) if dwd_preload <= 4095 => {
+                self.regs.DWDPRLD.write(DWDPRLD::DWDPRLD.val(dwd_preload as u32));
+                Ok(())
+            }
+            _ => Err(ClockError::Rate),
         }
-        Err(dwd_preload)
     }
 
     /// Enable the DWD counter
     fn counter_enable(&self) {
-        self.regs.DWDCTRL.set(0xA985_59DA);
+        self.regs.DWDCTRL.write(DWDCTRL::DWDCTRL::Enable);
     }
 
     fn count_down(&self) -> u32 {
         self.regs.DWDCNTR.get()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    const DWDCTRL: u32 = 0xFFFF_FC90;
+    const DWDPRLD: u32 = 0xFFFF_FC94;
+
+    #[test]
+    fn watchdog_start() {
+        mock::reset();
+        let clocks = Clocks {
+            gclk: 160_000_000,
+            hclk: 160_000_000,
+            vclk: [80_000_000; 4],
+            avclk1: 80_000_000,
+            rticlk1: 80_000_000,
+            modulation: 0,
+        };
+        let wdog = ChipWatchDog::new(unsafe { DWWD::steal() }, &clocks);
+        // 4096 x 2^13 RTICLK1 cycles at most (~419 ms)
+        assert_eq!(wdog.start(1000), Err(ClockError::Rate));
+        assert_eq!(wdog.start(0), Err(ClockError::Rate));
+        assert!(mock::writes_to(DWDCTRL).is_empty());
+
+        assert_eq!(wdog.start(100), Ok(()));
+        assert_eq!(mock::peek(DWDPRLD), 975);
+        assert_eq!(mock::writes_to(DWDCTRL), [0xA985_59DA]);
+    }
+}
@@ -1,46 +1,105 @@
-use crate::config;
-use crate::serial::{event, DataBits, Lines, Parity, SerialLine, StopBits};
+use crate::clock::{ClockError, Clocks};
+use crate::serial::{event, DataBits, Line, Lines, Parity, SerialLine, StopBits};
 ///
 /// SCI/LIN Device Driver
 ///
 use core::cell::Cell;
-use vcell::VolatileCell;
+use crate::regs::{map, Reg};
+
+register_bitfields! {
+    /// Global Control 0
+    GCR0 [
+        /// Module out of reset
+        RESET OFFSET(0) NUMBITS(1) []
+    ],
+    /// Global Control 1
+    GCR1 [
+        /// Transmit enable
+        TXENA OFFSET(25) NUMBITS(1) [],
+        /// Receive enable
+        RXENA OFFSET(24) NUMBITS(1) [],
+        /// Continue on suspend
+        CONT OFFSET(17) NUMBITS(1) [],
+        /// Loop back mode
+        LOOP_BACK OFFSET(16) NUMBITS(1) [],
+        /// Software reset (active low)
+        SWNRST OFFSET(7) NUMBITS(1) [],
+        /// LIN mode
+        LIN_MODE OFFSET(6) NUMBITS(1) [],
+        /// Internal clock
+        CLOCK OFFSET(5) NUMBITS(1) [],
+        /// Number of stop bits
+        STOP OFFSET(4) NUMBITS(1) [
+            One = 0,
+            Two = 1
+        ],
+        /// Parity odd (0) or even (1)
+        PARITY OFFSET(3) NUMBITS(1) [],
+        /// Parity enable
+        PARITY_ENA OFFSET(2) NUMBITS(1) [],
+        /// Asynchronous timing mode
+        TIMING_MODE OFFSET(1) NUMBITS(1) [],
+        /// Address-bit mode
+        COMM_MODE OFFSET(0) NUMBITS(1) []
+    ],
+    /// Format Control
+    FORMAT [
+        /// Frame length (LIN/buffered SCI)
+        LENGTH OFFSET(16) NUMBITS(3) [],
+        /// Character length - 1
+        CHAR OFFSET(0) NUMBITS(3) []
+    ],
+    /// Baud Rate Selection
+    BRS [
+        /// Fractional divider (LIN)
+        M OFFSET(24) NUMBITS(4) [],
+        /// Prescaler
+        PRESCALER OFFSET(0) NUMBITS(24) []
+    ],
+    /// Pin Function
+    PIO0 [
+        /// LINTX/SCITX is the transmit pin
+        TX_FUNC OFFSET(2) NUMBITS(1) [],
+        /// LINRX/SCIRX is the receive pin
+        RX_FUNC OFFSET(1) NUMBITS(1) []
+    ]
+}
 
 #[repr(C)]
 #[allow(non_snake_case)]
 struct LinRegisters {
-    GCR0: VolatileCell<u32>,        // 0x00: Global control 0
-    GCR1: VolatileCell<u32>,        // 0x04: Global control 1
-    GCR2: VolatileCell<u32>,        // 0x08: Global control 2
-    SETINT: VolatileCell<u32>,      // 0x0C: Set interrupt enable
-    CLEARINT: VolatileCell<u32>,    // 0x10: Clear interrupt enable
-    SETINTLVL: VolatileCell<u32>,   // 0x14: Set interrupt level
-    CLEARINTLVL: VolatileCell<u32>, // 0x18: Set interrupt level
-    FLR: VolatileCell<u32>,         // 0x1C: interrupt flag
-    INTVECT0: VolatileCell<u32>,    // 0x20: interrupt vector Offset 0
-    INTVECT1: VolatileCell<u32>,    // 0x24: interrupt vector Offset 1
-    FORMAT: VolatileCell<u32>,      // 0x28: Format Control
-    BRS: VolatileCell<u32>,         // 0x2C: Baud rate selection
-    ED: VolatileCell<u32>,          // 0x30: Emulation
-    RD: VolatileCell<u32>,          // 0x34: Receive data
-    TD: VolatileCell<u32>,          // 0x38: Transmit data
-    PIO0: VolatileCell<u32>,        // 0x3C: Pin function
-    PIO1: VolatileCell<u32>,        // 0x40: Pin direction
-    PIO2: VolatileCell<u32>,        // 0x44: Pin data in
-    PIO3: VolatileCell<u32>,        // 0x48: Pin data out
-    PIO4: VolatileCell<u32>,        // 0x4C: Pin data set
-    PIO5: VolatileCell<u32>,        // 0x50: Pin data clr
-    PIO6: VolatileCell<u32>,        // 0x54: Pin open drain output enable
-    PIO7: VolatileCell<u32>,        // 0x58: Pin pullup/pulldown disable
-    PIO8: VolatileCell<u32>,        // 0x5C: Pin pullup/pulldown selection
-    COMP: VolatileCell<u32>,        // 0x60: Compare
-    RDx: [VolatileCell<u32>; 8],    // 0x64-0x0068: RX buffer
-    MASK: VolatileCell<u32>,        // 0x6C: Mask
-    ID: VolatileCell<u32>,          // 0x70: Identification
-    TDx: [VolatileCell<u32>; 8],    // 0x74-0x0078: TX buffer
-    MBRSR: VolatileCell<u32>,       // 0x7C: Maximum baud rate selection
-    _rsvd1: [VolatileCell<u32>; 4], // 0x80 - 0x8C: Reserved
-    IODFTCTRL: VolatileCell<u32>,   // 0x90: IODFT loopback
+    GCR0: Reg,        // 0x00: Global control 0
+    GCR1: Reg,        // 0x04: Global control 1
+    GCR2: Reg,        // 0x08: Global control 2
+    SETINT: Reg,      // 0x0C: Set interrupt enable
+    CLEARINT: Reg,    // 0x10: Clear interrupt enable
+    SETINTLVL: Reg,   // 0x14: Set interrupt level
+    CLEARINTLVL: Reg, // 0x18: Set interrupt level
+    FLR: Reg,         // 0x1C: interrupt flag
+    INTVECT0: Reg,    // 0x20: interrupt vector Offset 0
+    INTVECT1: Reg,    // 0x24: interrupt vector Offset 1
+    FORMAT: Reg,      // 0x28: Format Control
+    BRS: Reg,         // 0x2C: Baud rate selection
+    ED: Reg,          // 0x30: Emulation
+    RD: Reg,          // 0x34: Receive data
+    TD: Reg,          // 0x38: Transmit data
+    PIO0: Reg,        // 0x3C: Pin function
+    PIO1: Reg,        // 0x40: Pin direction
+    PIO2: Reg,        // 0x44: Pin data in
+    PIO3: Reg,        // 0x48: Pin data out
+    PIO4: Reg,        // 0x4C: Pin data set
+    PIO5: Reg,        // 0x50: Pin data clr
+    PIO6: Reg,        // 0x54: Pin open drain output enable
+    PIO7: Reg,        // 0x58: Pin pullup/pulldown disable
+    PIO8: Reg,        // 0x5C: Pin pullup/pulldown selection
+    COMP: Reg,        // 0x60: Compare
+    RDx: [Reg; 8],    // 0x64-0x0068: RX buffer
+    MASK: Reg,        // 0x6C: Mask
+    ID: Reg,          // 0x70: Identification
+    TDx: [Reg; 8],    // 0x74-0x0078: TX buffer
+    MBRSR: Reg,       // 0x7C: Maximum baud rate selection
+    _rsvd1: [Reg; 4], // 0x80 - 0x8C: Reserved
+    IODFTCTRL: Reg,   // 0x90: IODFT loopback
 }
 #[allow(dead_code)]
 const LIN_BASE_ADDR: *const LinRegisters = 0xFFF7_E400 as *const LinRegisters;
@@ -48,91 +107,71 @@ const LIN_BASE_ADDR: *const LinRegisters = 0xFFF7_E400 as *const LinRegisters;
 #[repr(C)]
 #[allow(non_snake_case)]
 struct SciRegisters {
-    GCR0: VolatileCell<u32>, // 0x00 Global Control 0
-    GCR1: VolatileCell<u32>, // 0x04 Global Control 1
-    GCR2: VolatileCell<u32>, // 0x08 Global Control 2
+    GCR0: Reg<u32, GCR0::Register>,     // 0x00 Global Control 0
+    GCR1: Reg<u32, GCR1::Register>,     // 0x04 Global Control 1
+    GCR2: Reg,                          // 0x08 Global Control 2
     // Note:GCR2 is Applicable only to LIN & SCI Compatibility
     // Mode,Reserved for standalone SCI
-    SETINT: VolatileCell<u32>,       // 0x0C Set Interrupt Enable
-    CLEARINT: VolatileCell<u32>,     // 0x10 Clear Interrupt Enable
-    SETINTLVL: VolatileCell<u32>,    // 0x14 Set Interrupt Level
-    CLEARINTLVL: VolatileCell<u32>,  // 0x18 Set Interrupt Level
-    FLR: VolatileCell<u32>,          // 0x1C Interrupt Flag
-    INTVECT0: VolatileCell<u32>,     // 0x20 Interrupt Vector Offset 0
-    INTVECT1: VolatileCell<u32>,     // 0x24 Interrupt Vector Offset 1
-    FORMAT: VolatileCell<u32>,       // 0x28 Format Control
-    BRS: VolatileCell<u32>,          // 0x2C Baud Rate Selection
-    ED: VolatileCell<u32>,           // 0x30 Emulation
-    RD: VolatileCell<u32>,           // 0x34 Receive Data Buffer
-    TD: VolatileCell<u32>,           // 0x38 Transmit Data Buffer
-    PIO0: VolatileCell<u32>,         // 0x3C Pin Function
-    PIO1: VolatileCell<u32>,         // 0x40 Pin Direction
-    PIO2: VolatileCell<u32>,         // 0x44 Pin Data In
-    PIO3: VolatileCell<u32>,         // 0x48 Pin Data Out
-    PIO4: VolatileCell<u32>,         // 0x4C Pin Data Set
-    PIO5: VolatileCell<u32>,         // 0x50 Pin Data Clr
-    PIO6: VolatileCell<u32>,         // 0x54: Pin Open Drain Output Enable
-    PIO7: VolatileCell<u32>,         // 0x58: Pin Pullup/Pulldown Disable
-    PIO8: VolatileCell<u32>,         // 0x5C: Pin Pullup/Pulldown Selection
-    _rsdv2: [VolatileCell<u32>; 12], // 0x60: Reserved
-    IODFTCTRL: VolatileCell<u32>,    // 0x90: I/O Error Enable
+    SETINT: Reg,                        // 0x0C Set Interrupt Enable
+    CLEARINT: Reg,                      // 0x10 Clear Interrupt Enable
+    SETINTLVL: Reg,                     // 0x14 Set Interrupt Level
+    CLEARINTLVL: Reg,                   // 0x18 Set Interrupt Level
+    FLR: Reg,                           // 0x1C Interrupt Flag
+    INTVECT0: Reg,                      // 0x20 Interrupt Vector Offset 0
+    INTVECT1: Reg,                      // 0x24 Interrupt Vector Offset 1
+    FORMAT: Reg<u32, FORMAT::Register>, // 0x28 Format Control
+    BRS: Reg<u32, BRS::Register>,       // 0x2C Baud Rate Selection
+    ED: Reg,                            // 0x30 Emulation
+    RD: Reg,                            // 0x34 Receive Data Buffer
+    TD: Reg,                            // 0x38 Transmit Data Buffer
+    PIO0: Reg<u32, PIO0::Register>,     // 0x3C Pin Function
+    PIO1: Reg,                          // 0x40 Pin Direction
+    PIO2: Reg,                          // 0x44 Pin Data In
+    PIO3: Reg,                          // 0x48 Pin Data Out
+    PIO4: Reg,                          // 0x4C Pin Data Set
+    PIO5: Reg,                          // 0x50 Pin Data Clr
+    PIO6: Reg,                          // 0x54: Pin Open Drain Output Enable
+    PIO7: Reg,                          // 0x58: Pin Pullup/Pulldown Disable
+    PIO8: Reg,                          // 0x5C: Pin Pullup/Pulldown Selection
+    _rsdv2: [Reg; 12],                  // 0x60: Reserved
+    IODFTCTRL: Reg,                     // 0x90: I/O Error Enable
 }
 const SCI_BASE_ADDR: *const SciRegisters = 0xFFF7_E500 as *const SciRegisters;
 const SCI_LIN_BASE_ADDR: *const SciRegisters = 0xFFF7_E400 as *const SciRegisters;
 
-const TX_ENABLE: u32 = 0x1 << 25; // enable transmit
-const RX_ENABLE: u32 = 0x1 << 24; // enable receive
-const INT_CLOCK: u32 = 0x1 << 5; // internal clock
-const ASYNC_TIMING: u32 = 0x1 << 1; // asynchronous timing mode
-
-const PIO0_TX_FUNC_LIN: u32 = 0x1 << 2; // LINTX as SCI/LIN transmit pin
-const PIO0_RX_FUNC_LIN: u32 = 0x1 << 1; // LINRX as SCI/LIN receive pin
-
-const SCIGCR1_SWNRST: u32 = 0x1 << 7;
+/// GCR2: local low-power mode
+const POWERDOWN: u32 = 0x1;
 
 pub struct SciChipset {
     baudrate: Cell<u32>,
+    clocks: Clocks,
     regs: &'static SciRegisters,
 }
 
 impl SciRegisters {
     unsafe fn as_sci() -> &'static SciRegisters {
-        &*SCI_BASE_ADDR
+        map(SCI_BASE_ADDR)
     }
 
     unsafe fn as_lin() -> &'static SciRegisters {
-        &*SCI_LIN_BASE_ADDR
+        map(SCI_LIN_BASE_ADDR)
     }
 
     pub fn reset(&self) {
-        self.GCR0.set(0x0); // module is in reset
-        self.GCR0.set(0x1); // is out of reset
+        self.GCR0.write(GCR0::RESET::CLEAR); // module is in reset
+        self.GCR0.write(GCR0::RESET::SET); // is out of reset
     }
 
     pub fn sw_reset(&self, rst: bool) {
-        if rst {
-            self.GCR1.set(self.GCR1.get() | SCIGCR1_SWNRST)
-        } else {
-            self.GCR1.set(self.GCR1.get() & !SCIGCR1_SWNRST)
-        }
+        self.GCR1.modify(GCR1::SWNRST.val(rst as u32))
     }
 
     pub fn tx_enable(&self, enable: bool) {
-        let ctrl = self.GCR1.get();
-        if enable {
-            self.GCR1.set(ctrl | TX_ENABLE)
-        } else {
-            self.GCR1.set(ctrl & !TX_ENABLE)
-        }
+        self.GCR1.modify(GCR1::TXENA.val(enable as u32))
     }
 
     pub fn rx_enable(&self, enable: bool) {
-        let ctrl = self.GCR1.get();
-        if enable {
-            self.GCR1.set(ctrl | RX_ENABLE)
-        } else {
-            self.GCR1.set(ctrl & !RX_ENABLE)
-        }
+        self.GCR1.modify(GCR1::RXENA.val(enable as u32))
     }
 
     pub fn clear_interrupts(&self) {
@@ -158,7 +197,7 @@ impl SciRegisters {
     }
 
     pub fn putc_try(&self, b: u8) -> bool {
-        let can_send = self.FLR.get() & event::TX_INT == 0;
+        let can_send = self.FLR.get() & event::TX_INT != 0;
         if can_send {
             self.TD.set(u32::from(b));
         }
@@ -185,28 +224,53 @@ impl SciRegisters {
     }
 }
 
+impl SciChipset {
+    /// Local low-power mode: the line is clocked off until a falling
+    /// edge on RX, that raises the wakeup interrupt (VIM `LinLevelx` or
+    /// `SciLevelx`) and can wake the device up from a low-power mode.
+    pub fn low_power(&self, enable: bool) {
+        if enable {
+            self.regs.SETINT.set(event::WAKE_INT);
+            self.regs.GCR2.set(self.regs.GCR2.get() | POWERDOWN);
+        } else {
+            self.regs.GCR2.set(self.regs.GCR2.get() & !POWERDOWN);
+            self.regs.CLEARINT.set(event::WAKE_INT);
+        }
+    }
+}
+
 impl SerialLine for SciChipset {
     /// `databits` Number of bit per char
     /// `stop` Number of stop bits
     /// `parity` Parity Odd, Even or None (disabled)
-    fn new(id: Lines, databits: DataBits, stop: StopBits, parity: Parity) -> SciChipset {
-        let regmap = match id {
+    fn new<L: Line>(_line: L, clocks: &Clocks, databits: DataBits, stop: StopBits,
+                    parity: Parity) -> SciChipset {
+        let regmap = match L::ID {
             Lines::Sci => unsafe { SciRegisters::as_sci() },
             Lines::Lin => unsafe { SciRegisters::as_lin() },
         };
         let ser_line = SciChipset {
             baudrate: Cell::new(0),
+            clocks: *clocks,
             regs: regmap,
         };
 
         ser_line.regs.reset();
         ser_line.regs.clear_interrupts();
 
-        let ctrl = INT_CLOCK | ASYNC_TIMING | (stop as u32) | (parity as u32);
-        ser_line.regs.GCR1.set(ctrl);
+        let stop = match stop {
+            StopBits::One => GCR1::STOP::One,
+            StopBits::Two => GCR1::STOP::Two,
+        };
+        let parity = match parity {
+            Parity::None => GCR1::PARITY_ENA::CLEAR,
+            Parity::Odd => GCR1::PARITY_ENA::SET + GCR1::PARITY::CLEAR,
+            Parity::Even => GCR1::PARITY_ENA::SET + GCR1::PARITY::SET,
+        };
+        ser_line.regs.GCR1.write(GCR1::CLOCK::SET + GCR1::TIMING_MODE::SET + stop + parity);
 
-        ser_line.regs.FORMAT.set(databits as u32);
-        ser_line.regs.PIO0.set(PIO0_TX_FUNC_LIN | PIO0_RX_FUNC_LIN);
+        ser_line.regs.FORMAT.write(FORMAT::CHAR.val(databits as u32));
+        ser_line.regs.PIO0.write(PIO0::TX_FUNC::SET + PIO0::RX_FUNC::SET);
         ser_line
     }
 
@@ -223,15 +287,23 @@ impl SerialLine for SciChipset {
         self.baudrate.get()
     }
 
-    fn set_baudrate(&mut self, baudrate: u32) -> &mut SciChipset {
-        if baudrate > 0 {
-            let is_async = self.regs.GCR1.get() & ASYNC_TIMING != 0;
-            let f = if is_async { 16 } else { 1 };
-            let br = udiv_round_closest!(config::VCLK, f * baudrate) - 1;
-            self.regs.BRS.set(br);
-            self.baudrate.set(baudrate);
+    /// Baud rate = VCLK / (16 x (P + 1)) in asynchronous mode,
+    /// VCLK / (P + 1) in isosynchronous mode
+    fn set_baudrate(&mut self, baudrate: u32) -> Result<&mut SciChipset, ClockError> {
+        let is_async = self.regs.GCR1.is_set(GCR1::TIMING_MODE);
+        let f = if is_async { 16 } else { 1 };
+        if baudrate == 0 {
+            return Err(ClockError::Rate);
         }
-        self
+        let vclk = self.clocks.vclk1();
+        let div = udiv_round_closest!(vclk, f * baudrate);
+        if div == 0 || div > 0x100_0000 {
+            return Err(ClockError::Rate);
+        }
+        self.clocks.check_rate(baudrate, vclk / (f * div))?;
+        self.regs.BRS.write(BRS::PRESCALER.val(div - 1));
+        self.baudrate.set(baudrate);
+        Ok(self)
     }
 
     #[inline]
@@ -283,3 +355,82 @@ impl SerialLine for SciChipset {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use crate::peripherals::LIN;
+
+    const GCR0: u32 = 0xFFF7_E400;
+    const GCR1: u32 = 0xFFF7_E404;
+    const CLEARINT: u32 = 0xFFF7_E410;
+    const FLR: u32 = 0xFFF7_E41C;
+    const FORMAT: u32 = 0xFFF7_E428;
+    const BRS: u32 = 0xFFF7_E42C;
+    const TD: u32 = 0xFFF7_E438;
+    const PIO0: u32 = 0xFFF7_E43C;
+
+    fn clocks() -> Clocks {
+        Clocks {
+            gclk: 160_000_000,
+            hclk: 160_000_000,
+            vclk: [80_000_000; 4],
+            avclk1: 80_000_000,
+            rticlk1: 80_000_000,
+            modulation: 0,
+        }
+    }
+
+    fn line() -> SciChipset {
+        let lin = unsafe { LIN::steal() };
+        SciChipset::new(lin, &clocks(), DataBits::Eight, StopBits::Two, Parity::Even)
+    }
+
+    #[test]
+    fn setup() {
+        mock::reset();
+        let mut sci = line();
+        // reset pulse, then all interrupts off
+        assert_eq!(mock::writes_to(GCR0), [0x0, 0x1]);
+        assert_eq!(mock::writes_to(CLEARINT), [0xFFFF_FFFF]);
+        // internal clock, asynchronous timing, 2 stop bits, even parity
+        assert_eq!(mock::writes_to(GCR1), [0x3E]);
+        assert_eq!(mock::writes_to(FORMAT), [0x7]);
+        assert_eq!(mock::writes_to(PIO0), [0x6]);
+
+        mock::clear_log();
+        sci.tx_enable(true).rx_enable(true);
+        sci.open();
+        assert_eq!(mock::writes_to(GCR1), [0x0200_003E, 0x0300_003E, 0x0300_00BE]);
+    }
+
+    #[test]
+    fn baudrate() {
+        mock::reset();
+        let mut sci = line();
+        // 80 MHz / (16 x 43)
+        assert!(sci.set_baudrate(115_200).is_ok());
+        assert_eq!(mock::writes_to(BRS), [42]);
+        assert_eq!(sci.baudrate(), 115_200);
+
+        mock::clear_log();
+        assert_eq!(sci.set_baudrate(0).err(), Some(ClockError::Rate));
+        assert_eq!(sci.set_baudrate(3_000_000).err(), Some(ClockError::Rate));
+        assert!(mock::writes_to(BRS).is_empty());
+        assert_eq!(sci.baudrate(), 115_200);
+    }
+
+    #[test]
+    fn putc_waits_tx_ready() {
+        mock::reset();
+        let sci = line();
+        mock::set_after_reads(FLR, event::TX_INT, 3);
+        sci.putc(b'A');
+        assert_eq!(mock::writes_to(TD), [u32::from(b'A')]);
+        assert!(sci.putc_try(b'B'));
+        mock::poke(FLR, 0);
+        assert!(!sci.putc_try(b'C'));
+        assert_eq!(mock::writes_to(TD), [u32::from(b'A'), u32::from(b'B')]);
+    }
+}
@@ -0,0 +1,71 @@
+//!
+//! SCR Control Module (SCM), Cortex-R5F parts only
+//!
+//! The SCM monitors the transactions of the CPU interconnect subsystem
+//! (SCR): a transaction not completed within the timeout threshold is
+//! reported to the ESM. The detection logic can be self-tested.
+//!
+//! Reference:
+//! TMS570LC43x Technical Reference Manual (TRM), code SPNU563
+
+use crate::peripherals::SCM;
+use crate::regs::{map, Reg};
+
+register_bitfields! {
+    /// SCM Control
+    SCMCNTRL [
+        /// Timeout counter self-test, key
+        TO_PASS_CODE OFFSET(24) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ],
+        /// Self-test running
+        SCM_IN_TEST OFFSET(16) NUMBITS(1) [],
+        /// Timeout counters reset, key
+        DTC_SOFT_RESET OFFSET(0) NUMBITS(4) [
+            Reset = 0xA,
+            Normal = 0x5
+        ]
+    ]
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct Scm {
+    /// Revision ID
+    SCMREVID: Reg,
+    /// Control
+    SCMCNTRL: Reg<u32, SCMCNTRL::Register>,
+    /// Timeout threshold [VCLK cycles]
+    SCMTHRESHOLD: Reg,
+}
+const SCM_BASE_ADDR: *const Scm = 0xFFFF_0A00 as *const Scm;
+
+impl Scm {
+    pub fn new(_scm: SCM) -> &'static Scm {
+        unsafe { map(SCM_BASE_ADDR) }
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.SCMREVID.get()
+    }
+
+    /// Timeout threshold [VCLK cycles]
+    pub fn set_threshold(&self, cycles: u32) {
+        self.SCMTHRESHOLD.set(cycles)
+    }
+
+    /// Reset the timeout counters
+    pub fn reset_counters(&self) {
+        self.SCMCNTRL.modify(SCMCNTRL::DTC_SOFT_RESET::Reset);
+        self.SCMCNTRL.modify(SCMCNTRL::DTC_SOFT_RESET::Normal);
+    }
+
+    /// Run the timeout detection self-test.
+    /// The timeout is reported to the ESM, the caller checks and clears it.
+    pub fn self_test(&self) {
+        self.SCMCNTRL.modify(SCMCNTRL::TO_PASS_CODE::Enable);
+        wait_until_true!(self.SCMCNTRL.is_set(SCMCNTRL::SCM_IN_TEST));
+        self.SCMCNTRL.modify(SCMCNTRL::TO_PASS_CODE::Disable);
+    }
+}
@@ -1,3 +1,8 @@
+use crate::clock::{ClockError, Clocks};
+use crate::peripherals::{sealed::Sealed, LIN};
+#[cfg(not(feature = "tms570ls0432"))]
+use crate::peripherals::SCI;
+
 pub mod event {
     pub type SciEvent = u32;
     /// Framing error
@@ -56,9 +61,24 @@ pub enum Lines {
     Lin,
 }
 
+/// SCI/LIN line instance: `SCI` or `LIN`
+pub trait Line: Sealed {
+    const ID: Lines;
+}
+
+#[cfg(not(feature = "tms570ls0432"))]
+impl Line for SCI {
+    const ID: Lines = Lines::Sci;
+}
+
+impl Line for LIN {
+    const ID: Lines = Lines::Lin;
+}
+
 pub trait SerialLine {
-    fn new(id: Lines, databits: DataBits, stop: StopBits, parity: Parity) -> Self;
-    fn set_baudrate(&mut self, baudrate: u32) -> &mut Self;
+    fn new<L: Line>(line: L, clocks: &Clocks, databits: DataBits, stop: StopBits,
+                    parity: Parity) -> Self;
+    fn set_baudrate(&mut self, baudrate: u32) -> Result<&mut Self, ClockError>;
     fn rx_enable(&mut self, enable: bool) -> &mut Self;
     fn tx_enable(&mut self, enable: bool) -> &mut Self;
     fn baudrate(&self) -> u32;
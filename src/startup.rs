@@ -1,3 +1,5 @@
+use core::ptr::{addr_of, addr_of_mut};
+use crate::config;
 use crate::dwd::DWD;
 use crate::efuse;
 use crate::esm;
@@ -5,38 +7,44 @@ use crate::esm_ch::EsmError;
 use crate::flash;
 use crate::pbist;
 use crate::pcr;
+#[cfg(not(cortex_r5))]
+use crate::peripherals::PCR;
+#[cfg(cortex_r5)]
+use crate::peripherals::{PCR1, PCR2, PCR3};
+use crate::peripherals::{DWWD, EFC, ESM, FLASH, SYS, SYSEXC, VIM};
 use crate::rti;
 use crate::syscore;
 use crate::sysexc;
 use crate::system;
 use crate::vim;
-use siliconcr4;
 
 extern "C" {
     fn main(argc: isize, argv: *const *const u8) -> isize;
-    pub fn custom_dabort();
+    pub fn custom_dabort(pc: u32, spsr: u32);
     static mut _sbss: u32;
     static mut _ebss: u32;
     static mut _sdata: u32;
     static mut _edata: u32;
     static mut _sidata: u32;
+    static mut _suser: u32;
+    static mut _suser_stack: u32;
 }
 
-#[naked]
+/// Called by the reset entry (see startup.s) in System mode, once core
+/// registers and stack pointers are initialized.
+#[no_mangle]
 pub unsafe extern "C" fn tms570_startup() -> ! {
-    syscore::init_core_registers();
-    syscore::init_stack_pointers();
     syscore::event_bus_export_enable();
 
-    #[cfg(feature = "errata57")]
-    siliconcr4::errata57();
+    let sys = system::Sys::new(SYS::steal());
 
-    #[cfg(feature = "errata66")]
-    siliconcr4::errata66();
+    // Cortex-R4 errata, as needed by the silicon revision
+    let errata = sys.device_info().errata();
+    syscore::apply_errata(errata);
 
-    let wdog: rti::ChipWatchDog = DWD::new();
+    let wdog: rti::ChipWatchDog = DWD::new(DWWD::steal(), &sys.clocks());
 
-    let sysex = sysexc::SysException::new();
+    let sysex = sysexc::SysException::new(SYSEXC::steal());
     if sysex.power_on() {
         sysex.clear_all();
     } else if sysex.wd_iecpick_reset() && wdog.time_violation() {
@@ -51,19 +59,29 @@ pub unsafe extern "C" fn tms570_startup() -> ! {
 
     // Check if there were ESM group3 errors during power-up.
     // Device operation is not reliable and not recommended in this case.
-    if esm::Esm::new().error_is_set(EsmError::EfuseAutoload) {
+    if esm::Esm::new(ESM::steal()).error_is_set(EsmError::EfuseAutoload) {
         panic!("[eFuse] autoload error");
     }
 
-    let sys = system::Sys::new();
-    sys.setup_pll();
+    let setup = match config::CLOCKS.solve() {
+        Ok(setup) => setup,
+        Err(_) => panic!("[clock] invalid configuration"),
+    };
+    sys.setup_pll(&setup);
 
-    let efuse = efuse::Efc::new();
+    let efuse = efuse::Efc::new(EFC::steal());
     let efuse_stat = efuse.check();
 
     // Disable Peripherals before powerup
     sys.enable_pheripherals(false);
-    pcr::Pcr::new().enable_all();
+    #[cfg(not(cortex_r5))]
+    pcr::Pcr::new(PCR::steal()).enable_all();
+    #[cfg(cortex_r5)]
+    {
+        pcr::Pcr::new(PCR1::steal()).enable_all();
+        pcr::Pcr::new(PCR2::steal()).enable_all();
+        pcr::Pcr::new(PCR3::steal()).enable_all();
+    }
     sys.enable_pheripherals(true);
 
     if efuse_stat == efuse::EfcError::OnGoing {
@@ -77,23 +95,15 @@ pub unsafe extern "C" fn tms570_startup() -> ! {
 
     // Setup flash before speed-up PLL otherwise MCU can't
     // properly read data from Flash memory.
-    let flash = flash::Flash::new();
+    let flash = flash::Flash::new(FLASH::steal());
     flash.setup(flash::FlashWPowerModes::Active, 3, true, true);
 
     sys.trim_lpo();
     sys.clock_domain_setup(true, system::ClockDomains::AvClk3);
     sys.wait_pll_lock();
 
-    sys.setup_clock_source(
-        system::SysClockSources::Osc,
-        system::SysClockSources::Osc,
-        system::SysClockSources::Pll1,
-    );
-
-    sys.peripherals_clock_divider(1, 1, 1, 1);
-
     // Now the PLLs are locked and the PLL outputs can be speed up
-    sys.set_pll_divider(0, 0);
+    sys.setup_clocks(&setup);
     sys.eclk_functional_mode(7, false);
 
     if !sys.clock_supervisor_test() {
@@ -140,18 +150,25 @@ pub unsafe extern "C" fn tms570_startup() -> ! {
         sys.pbist_stop();
     }
     syscore::ram_ecc_enable();
-    let vim = vim::Vim::new();
+    let vim = vim::Vim::new(VIM::steal());
     vim.parity_enable(true);
 
     sys.init_memory(system::Ram::Internal);
     sys.init_memory(system::Ram::Vim);
+    vim.install_vectors();
     syscore::irq_vic_enable();
 
     #[cfg(vfp)]
     syscore::vfp_enable();
 
-    r0::zero_bss(&mut _sbss, &mut _ebss);
-    r0::init_data(&mut _sdata, &mut _edata, &_sidata);
+    #[cfg(cortex_r5)]
+    cortexr5::cache::enable();
+
+    r0::zero_bss(addr_of_mut!(_sbss), addr_of_mut!(_ebss));
+    r0::zero_bss(addr_of_mut!(_suser), addr_of_mut!(_suser_stack));
+    r0::init_data(addr_of_mut!(_sdata), addr_of_mut!(_edata), addr_of!(_sidata));
+    // .bss is zeroed: the applied errata can be recorded
+    syscore::record_errata(errata);
 
     main(0, ::core::ptr::null());
 
@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+    .syntax unified
+    .cpu cortex-r4
+    .arm
+
+@ Reset entry: initialize core registers in all CPU modes, set up the
+@ stack pointers (see cpustack.s), then jump to tms570_startup (Rust)
+@ in System mode.
+
+    .extern tms570_startup
+    .extern _cpu_stack
+
+    .section .text.reset,"ax",%progbits
+    .global reset
+    .type reset, %function
+
+reset:
+    bl      tms570_init_core_registers
+    bl      _cpu_stack
+    b       tms570_startup
+
+@ Initialize Core registers, including floating point, in all CPU
+@ working modes. This is mandatory to avoid any lock-step compare
+@ failure at startup or at first mode switch.
+@ Does not use any stack: the return address is kept in r0.
+
+    .section .text.tms570_init_core_registers,"ax",%progbits
+    .global tms570_init_core_registers
+    .type tms570_init_core_registers, %function
+
+tms570_init_core_registers:
+    @ After reset, the CPU is in the Supervisor mode (M = 10011)
+    mov     r0, lr
+    mov     r1, #0x0000
+    mov     r2, #0x0000
+    mov     r3, #0x0000
+    mov     r4, #0x0000
+    mov     r5, #0x0000
+    mov     r6, #0x0000
+    mov     r7, #0x0000
+    mov     r8, #0x0000
+    mov     r9, #0x0000
+    mov     r10, #0x0000
+    mov     r11, #0x0000
+    mov     r12, #0x0000
+    mov     r13, #0x0000
+    mrs     r1, cpsr
+    msr     spsr_cxsf, r1
+
+    @ Switch to FIQ mode (M = 10001)
+    cps     #17
+    mov     lr, r0
+    mov     r8, #0x0000
+    mov     r9, #0x0000
+    mov     r10, #0x0000
+    mov     r11, #0x0000
+    mov     r12, #0x0000
+    mrs     r1, cpsr
+    msr     spsr_cxsf, r1
+
+    @ Switch to IRQ mode (M = 10010)
+    cps     #18
+    mov     lr, r0
+    mrs     r1, cpsr
+    msr     spsr_cxsf, r1
+
+    @ Switch to Abort mode (M = 10111)
+    cps     #23
+    mov     lr, r0
+    mrs     r1, cpsr
+    msr     spsr_cxsf, r1
+
+    @ Switch to Undefined Instruction Mode (M = 11011)
+    cps     #27
+    mov     lr, r0
+    mrs     r1, cpsr
+    msr     spsr_cxsf, r1
+
+    @ Switch to System Mode (Shares User Mode registers) (M = 11111)
+    cps     #31
+    mov     lr, r0
+    mrs     r1, cpsr
+    msr     spsr_cxsf, r1
+
+.ifdef VFP
+    .fpu vfpv3-d16
+    mrc     p15, #0x00, r2, c1, c0, #0x02
+    orr     r2, r2, #0xF00000
+    mcr     p15, #0x00, r2, c1, c0, #0x02
+    mov     r2, #0x40000000
+    vmsr    fpexc, r2
+
+    vmov    d0, r1, r1
+    vmov    d1, r1, r1
+    vmov    d2, r1, r1
+    vmov    d3, r1, r1
+    vmov    d4, r1, r1
+    vmov    d5, r1, r1
+    vmov    d6, r1, r1
+    vmov    d7, r1, r1
+    vmov    d8, r1, r1
+    vmov    d9, r1, r1
+    vmov    d10, r1, r1
+    vmov    d11, r1, r1
+    vmov    d12, r1, r1
+    vmov    d13, r1, r1
+    vmov    d14, r1, r1
+    vmov    d15, r1, r1
+.endif
+
+    @ Synchronize the return stack
+    bl      1f
+1:  bl      2f
+2:  bl      3f
+3:  bl      4f
+4:  bx      r0
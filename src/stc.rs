@@ -1,5 +1,7 @@
 use cortexr4::asm::{nop, wfi};
 use crate::system;
+use crate::peripherals::{STC, SYS};
+use crate::regs::map;
 use vcell::VolatileCell;
 
 #[repr(C)]
@@ -46,14 +48,20 @@ const SCSCR_FAULT_INS: u32 = 0x1 << 4;
 const SCSCR_SELF_CHECK_KEY: u32 = 0xA;
 
 impl Stc {
-    pub unsafe fn new() -> &'static Stc {
-        &*STC_BASE_ADDR
+    pub fn new(_stc: STC) -> &'static Stc {
+        unsafe { map(STC_BASE_ADDR) }
     }
 
     /// Perform STC module self check
+    ///
+    /// # Safety
+    ///
+    /// The CPU idles and is reset at the end of the test: nothing can run
+    /// after this call, the test result is read after the reset. The system
+    /// module is accessed without its peripheral token to set the STC clock.
     pub unsafe fn self_test(&self, intervals: u16, restart: bool) {
         // STC clock = normal mode CPU clock frequency/2 = 180MHz/2
-        let sys2 = system::Sys::new();
+        let sys2 = system::Sys::new(unsafe { SYS::steal() });
         sys2.set_stc_clock_divider(0x0100_0000);
         self.setup_intervals(intervals, restart);
 
@@ -65,6 +73,12 @@ impl Stc {
     }
 
     /// Perform CPU self test using STC module
+    ///
+    /// # Safety
+    ///
+    /// The CPU idles and is reset at the end of the test, losing its state:
+    /// nothing can run after this call, the test result is read after the
+    /// reset.
     pub unsafe fn cpu_self_test(&self, intervals: u16, timeout: u32, restart: bool) {
         // Run specified no of test intervals starting from 0
         self.setup_intervals(intervals, restart);
@@ -81,7 +95,7 @@ impl Stc {
 
     unsafe fn activate(&self) {
         // wait for 16 VBUS clock cycles at least, based on HCLK to VCLK ratio
-        wait_cycle!(16);
+        system::Sys::new(unsafe { SYS::steal() }).vbus_delay(16);
         // Enable self-test
         self.stcgcr1.set(SCSCR_SELF_CHECK_KEY);
         // Idle the CPU so that the self-test can start
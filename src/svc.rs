@@ -0,0 +1,269 @@
+//!
+//! User mode execution and Supervisor Call (SVC) interface
+//!
+//! `main` runs in System mode. `enter_user` drops the CPU into
+//! unprivileged User mode, on its own stack (`.user` section, see
+//! bsp.ld). With the default MPU layout (see mpu.rs) the `.user` section
+//! is the only RAM User mode code can write: its statics are placed there
+//! with `#[link_section = ".user_data"]`, and zeroed at startup as .bss. Once in User mode the only way back to privileged code is an
+//! exception: `svc #imm` is decoded by `svc_handler` (see svc.s) and
+//! routed to the service registered for `imm`.
+//!
+//! Service numbers below `USER_SERVICES` are reserved for the BSP; the
+//! built-in services give User mode code a checked access to the
+//! peripheral registers inside the windows allowed by the kernel (see
+//! `allow_windows`). With the default MPU layout (see mpu.rs) peripheral
+//! registers are privileged only, so unprivileged code can't access them
+//! directly.
+//!
+//! Note: User mode can't mask interrupts (`cpsid` is ignored), so
+//! critical sections (`cortexr4::interrupt::free`) don't protect data
+//! accessed from unprivileged code.
+
+use core::arch::asm;
+use core::ptr::addr_of_mut;
+use vcell::VolatileCell;
+
+/// Number of registered services, indexed by SVC immediate
+pub const SERVICES: usize = 256;
+/// First service number available for applications
+pub const USER_SERVICES: u32 = 16;
+
+/// Built-in service: 32-bit read of a peripheral register
+pub const SVC_READ32: u32 = 0;
+/// Built-in service: 32-bit write of a peripheral register
+pub const SVC_WRITE32: u32 = 1;
+/// Built-in service: read-modify-write of a peripheral register
+pub const SVC_MODIFY32: u32 = 2;
+
+/// CPSR: Mode field
+const CPSR_MODE: u32 = 0x1F;
+/// CPSR: User mode
+const MODE_USR: u32 = 0x10;
+
+/// Caller registers saved by `svc_handler`.
+/// Arguments are passed in r0-r3; a service returns values by updating
+/// them, registers are restored from the frame on exception return.
+#[repr(C)]
+pub struct SvcFrame {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    /// Return address (instruction after `svc`)
+    pub lr: u32,
+}
+
+/// Kernel service, executed in Supervisor mode
+pub type Service = fn(&mut SvcFrame);
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SvcError {
+    /// Service number out of range or reserved
+    InvalidService = 1,
+    /// No service registered for the SVC immediate
+    NoService = 2,
+    /// Address outside the allowed peripheral windows
+    Denied = 3,
+    /// Address not aligned on a 32-bit boundary
+    Misaligned = 4,
+}
+
+impl SvcError {
+    fn from_code(code: u32) -> Result<(), SvcError> {
+        match code {
+            0 => Ok(()),
+            1 => Err(SvcError::InvalidService),
+            2 => Err(SvcError::NoService),
+            3 => Err(SvcError::Denied),
+            _ => Err(SvcError::Misaligned),
+        }
+    }
+}
+
+/// Peripheral address range accessible through the built-in services
+#[derive(Copy, Clone)]
+pub struct Window {
+    pub base: u32,
+    pub len: u32,
+}
+
+impl Window {
+    pub const fn new(base: u32, len: u32) -> Window {
+        Window { base, len }
+    }
+
+    /// `true` if the `size` bytes at `addr` are all inside the window
+    fn contains(&self, addr: u32, size: u32) -> bool {
+        addr >= self.base
+            && (addr - self.base).checked_add(size).is_some_and(|end| end <= self.len)
+    }
+}
+
+static mut SERVICE_TABLE: [Option<Service>; SERVICES] = [None; SERVICES];
+static mut WINDOWS: &[Window] = &[];
+
+extern "C" {
+    static mut _euser_stack: u32;
+    fn tms570_enter_user(entry: extern "C" fn() -> !, stack_top: *mut u32) -> !;
+}
+
+/// Issue a Supervisor Call. `$imm` must be a constant expression.
+/// Up to four arguments are passed in r0-r3; returns `(r0, r1)` as
+/// updated by the service.
+#[macro_export]
+macro_rules! svc {
+    ($imm:expr) => {
+        $crate::svc!($imm, 0, 0, 0, 0)
+    };
+    ($imm:expr, $a0:expr) => {
+        $crate::svc!($imm, $a0, 0, 0, 0)
+    };
+    ($imm:expr, $a0:expr, $a1:expr) => {
+        $crate::svc!($imm, $a0, $a1, 0, 0)
+    };
+    ($imm:expr, $a0:expr, $a1:expr, $a2:expr) => {
+        $crate::svc!($imm, $a0, $a1, $a2, 0)
+    };
+    ($imm:expr, $a0:expr, $a1:expr, $a2:expr, $a3:expr) => {{
+        let (r0, r1): (u32, u32);
+        ::core::arch::asm!(
+            "svc #{imm}",
+            imm = const $imm,
+            inout("r0") $a0 as u32 => r0,
+            inout("r1") $a1 as u32 => r1,
+            inout("r2") $a2 as u32 => _,
+            inout("r3") $a3 as u32 => _,
+        );
+        (r0, r1)
+    }};
+}
+
+/// True if the CPU is running in User mode
+#[inline(always)]
+pub fn is_unprivileged() -> bool {
+    let cpsr: u32;
+    unsafe { asm!("mrs {}, cpsr", out(reg) cpsr) };
+    cpsr & CPSR_MODE == MODE_USR
+}
+
+/// Register an application service for `svc #number`.
+/// Must be called from privileged code, before entering User mode.
+pub unsafe fn register(number: u32, service: Service) -> Result<(), SvcError> {
+    if number < USER_SERVICES || number as usize >= SERVICES {
+        return Err(SvcError::InvalidService);
+    }
+    SERVICE_TABLE[number as usize] = Some(service);
+    Ok(())
+}
+
+/// Remove the service registered for `svc #number`
+pub unsafe fn unregister(number: u32) {
+    if number >= USER_SERVICES && (number as usize) < SERVICES {
+        SERVICE_TABLE[number as usize] = None;
+    }
+}
+
+/// Set the peripheral windows accessible from User mode through
+/// `read32`, `write32` and `modify32`. No window is allowed by default.
+pub unsafe fn allow_windows(windows: &'static [Window]) {
+    WINDOWS = windows;
+}
+
+/// Switch to User mode on the `.user` section stack and jump to `entry`.
+/// Must be called from System mode (i.e. from `main`). There is no way
+/// back: privileged code is only reachable through exceptions.
+pub unsafe fn enter_user(entry: extern "C" fn() -> !) -> ! {
+    tms570_enter_user(entry, addr_of_mut!(_euser_stack))
+}
+
+/// Same as `enter_user`, on a stack provided by the caller.
+/// `stack_top` must be 8 bytes aligned.
+pub unsafe fn enter_user_with_stack(entry: extern "C" fn() -> !, stack_top: *mut u32) -> ! {
+    tms570_enter_user(entry, stack_top)
+}
+
+fn check_address(addr: u32) -> Result<(), SvcError> {
+    if addr & 0x3 != 0 {
+        return Err(SvcError::Misaligned);
+    }
+    let windows = unsafe { WINDOWS };
+    if windows.iter().any(|w| w.contains(addr, 4)) {
+        Ok(())
+    } else {
+        Err(SvcError::Denied)
+    }
+}
+
+fn status(r: Result<(), SvcError>) -> u32 {
+    match r {
+        Ok(()) => 0,
+        Err(e) => e as u32,
+    }
+}
+
+fn register_at(addr: u32) -> &'static VolatileCell<u32> {
+    unsafe { &*(addr as *const VolatileCell<u32>) }
+}
+
+// r0: address -> r0: value, r1: status
+fn service_read32(frame: &mut SvcFrame) {
+    let r = check_address(frame.r0);
+    frame.r0 = if r.is_ok() { register_at(frame.r0).get() } else { 0 };
+    frame.r1 = status(r);
+}
+
+// r0: address, r1: value -> r0: status
+fn service_write32(frame: &mut SvcFrame) {
+    let r = check_address(frame.r0);
+    if r.is_ok() {
+        register_at(frame.r0).set(frame.r1);
+    }
+    frame.r0 = status(r);
+}
+
+// r0: address, r1: clear mask, r2: set mask -> r0: status
+fn service_modify32(frame: &mut SvcFrame) {
+    let r = check_address(frame.r0);
+    if r.is_ok() {
+        let reg = register_at(frame.r0);
+        reg.set((reg.get() & !frame.r1) | frame.r2);
+    }
+    frame.r0 = status(r);
+}
+
+/// Called by `svc_handler` (see svc.s) with the decoded SVC immediate.
+/// Unknown services return `SvcError::NoService` in r0.
+#[no_mangle]
+pub unsafe extern "C" fn tms570_svc_dispatch(imm: u32, frame: &mut SvcFrame) {
+    match imm {
+        SVC_READ32 => service_read32(frame),
+        SVC_WRITE32 => service_write32(frame),
+        SVC_MODIFY32 => service_modify32(frame),
+        n if (n as usize) < SERVICES => match SERVICE_TABLE[n as usize] {
+            Some(service) => service(frame),
+            None => frame.r0 = SvcError::NoService as u32,
+        },
+        _ => frame.r0 = SvcError::InvalidService as u32,
+    }
+}
+
+/// Read a peripheral register from User mode
+pub fn read32(addr: u32) -> Result<u32, SvcError> {
+    let (value, status) = unsafe { svc!(SVC_READ32, addr) };
+    SvcError::from_code(status).map(|_| value)
+}
+
+/// Write a peripheral register from User mode
+pub fn write32(addr: u32, value: u32) -> Result<(), SvcError> {
+    let (status, _) = unsafe { svc!(SVC_WRITE32, addr, value) };
+    SvcError::from_code(status)
+}
+
+/// Clear then set bits of a peripheral register from User mode.
+/// The read-modify-write is atomic with respect to User mode code.
+pub fn modify32(addr: u32, clear: u32, set: u32) -> Result<(), SvcError> {
+    let (status, _) = unsafe { svc!(SVC_MODIFY32, addr, clear, set) };
+    SvcError::from_code(status)
+}
@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+    .section .text
+    .syntax unified
+    .cpu cortex-r4
+    .arm
+
+@ Supervisor Call entry.
+@ Caller registers r0-r3, r12 and the return address are saved on the
+@ SVC stack: this frame is passed to tms570_svc_dispatch(imm, frame) and
+@ restored on exit, so services return values by updating the frame.
+@ SVC immediate is decoded from the calling instruction: 24 bits in ARM
+@ state (svc #imm24), 8 bits in Thumb state (svc #imm8).
+
+    .extern tms570_svc_dispatch
+
+    .weak svc_handler
+    .type svc_handler, %function
+
+svc_handler:
+    stmfd   sp!, {r0 - r3, r12, lr}
+    mrs     r12, spsr
+    tst     r12, #0x20          @ Thumb state?
+    ldrhne  r0, [lr, #-2]
+    andne   r0, r0, #0xFF
+    ldreq   r0, [lr, #-4]
+    biceq   r0, r0, #0xFF000000
+    mov     r1, sp
+    push    {r12}               @ SPSR (keep the stack 8 bytes aligned)
+    sub     sp, sp, #4
+    bl      tms570_svc_dispatch
+    add     sp, sp, #4
+    pop     {r12}
+    msr     spsr_cxsf, r12      @ nested SVCs from a service clobber SPSR
+    ldmfd   sp!, {r0 - r3, r12, pc}^
+
+@ tms570_enter_user(entry, stack_top): switch to User mode on its own
+@ stack and jump to entry. Must be called from System mode.
+
+    .global tms570_enter_user
+    .type tms570_enter_user, %function
+
+tms570_enter_user:
+    mov     sp, r1
+    mrs     r2, cpsr
+    bic     r2, r2, #0x1F
+    orr     r2, r2, #0x10       @ M = 10000 (User)
+    msr     cpsr_c, r2
+    bx      r0
@@ -1,4 +1,6 @@
 /// System Exception Module
+use crate::peripherals::SYSEXC;
+use crate::regs::map;
 use vcell::VolatileCell;
 
 #[repr(C)]
@@ -28,8 +30,8 @@ pub enum Reset {
 const SYS_EXC_ADDR: *const SysException = 0xFFFF_FFE4 as *const SysException;
 
 impl SysException {
-    pub unsafe fn new() -> &'static SysException {
-        &*SYS_EXC_ADDR
+    pub fn new(_sysexc: SYSEXC) -> &'static SysException {
+        unsafe { map(SYS_EXC_ADDR) }
     }
 
     pub fn power_on(&self) -> bool {
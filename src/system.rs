@@ -1,111 +1,313 @@
-///!
-///! System Control Module (SYS) and
-///! Programmable Built-In Self-Test (PBIST) Module
-///!
-///! The PBIST architecture consists of a small coprocessor
-///! with a dedicated instruction set targeted specifically
-///! toward testing memories. This coprocessor executes test
-///! routines stored in the PBIST ROMand runs them on multiple
-///! on-chip memory instances. The on-chip memory configuration
-///! information is also stored in the PBIST ROM. The testing is
-///!  done in parallel for each of the CPU data RAMs, while it is
-///! done sequentially for the rest of the memories.
-///!
-///! Reference:
-///! TMS570LS Series Technical Reference Manual (TRM), code SPNU489B
+//!
+//! System Control Module (SYS) and
+//! Programmable Built-In Self-Test (PBIST) Module
+//!
+//! The PBIST architecture consists of a small coprocessor
+//! with a dedicated instruction set targeted specifically
+//! toward testing memories. This coprocessor executes test
+//! routines stored in the PBIST ROMand runs them on multiple
+//! on-chip memory instances. The on-chip memory configuration
+//! information is also stored in the PBIST ROM. The testing is
+//!  done in parallel for each of the CPU data RAMs, while it is
+//! done sequentially for the rest of the memories.
+//!
+//! Reference:
+//! TMS570LS Series Technical Reference Manual (TRM), code SPNU489B
 
+use core::cell::Cell;
+use cortexr4::interrupt;
+use crate::clock::{ClockSetup, Clocks, Modulation, Pll};
 use crate::config;
+use crate::dcc::{Clock0Source, Clock1Source, Dcc};
+use crate::device::{DeviceInfo, DieId};
 use crate::esm;
 use crate::esm_ch::EsmError;
-use vcell::VolatileCell;
-use crate::flash;
+use crate::peripherals::{ESM, SYS};
+use crate::regs::{map, Reg};
+use crate::flash::{self, Flash, FlashWPowerModes};
+use crate::vim::{Vim, VimChannel};
+
+register_bitfields! {
+    /// Global High Frequency Clock Source
+    GHVSRC [
+        /// GCLK, HCLK and VCLK source on wakeup
+        GHVWAKE OFFSET(24) NUMBITS(4) [],
+        /// HCLK and VCLK source in low power modes
+        HVLPM OFFSET(16) NUMBITS(4) [],
+        /// GCLK, HCLK and VCLK source for normal operation
+        GHVSRC OFFSET(0) NUMBITS(4) []
+    ],
+    /// Peripheral Asynchronous Clock Source
+    VCLKASRC [
+        VCLKA2S OFFSET(8) NUMBITS(4) [],
+        VCLKA1S OFFSET(0) NUMBITS(4) []
+    ],
+    /// RTI Clock Source
+    RCLKSRC [
+        RTI2DIV OFFSET(24) NUMBITS(2) [],
+        RTI2SRC OFFSET(16) NUMBITS(4) [],
+        RTI1DIV OFFSET(8) NUMBITS(2) [],
+        RTI1SRC OFFSET(0) NUMBITS(4) []
+    ],
+    /// Clock Source Valid Status
+    CSVSTAT [
+        /// Oscillator valid
+        OSC OFFSET(0) NUMBITS(1) [],
+        /// PLL1 valid
+        PLL1 OFFSET(1) NUMBITS(1) [],
+        /// One bit per clock source
+        CLKSRVALID OFFSET(0) NUMBITS(8) []
+    ],
+    /// Memory Self-Test Global Control
+    MSTGCR [
+        /// ROM clock divider (from HCLK)
+        ROM_DIV OFFSET(8) NUMBITS(2) [
+            Div1 = 0,
+            Div2 = 1,
+            Div4 = 2,
+            Div8 = 3
+        ],
+        /// Memory self-test controller enable key
+        MSTGENA OFFSET(0) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ]
+    ],
+    /// Memory Hardware Initialization Global Control
+    MINITGCR [
+        /// Memory hardware initialization enable key
+        MINITGENA OFFSET(0) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ]
+    ],
+    /// MSTC Global Status
+    MSTCGSTAT [
+        /// Memory hardware initialization complete
+        MINIDONE OFFSET(8) NUMBITS(1) [],
+        /// Memory self-test done
+        MSTDONE OFFSET(0) NUMBITS(1) []
+    ],
+    /// PLL Control 1
+    PLLCTL1 [
+        /// Reset on oscillator slip
+        ROS OFFSET(31) NUMBITS(1) [],
+        /// Slip detection mask
+        MASK_SLIP OFFSET(29) NUMBITS(2) [
+            Bypass = 1,
+            Masked = 2
+        ],
+        /// PLL output clock divider (R - 1)
+        PLLDIV OFFSET(24) NUMBITS(5) [],
+        /// Reset on oscillator fail
+        ROF OFFSET(23) NUMBITS(1) [],
+        /// Reference clock divider (NR - 1)
+        REFCLKDIV OFFSET(16) NUMBITS(6) [],
+        /// PLL multiplication factor ((NF - 1) * 256)
+        PLLMUL OFFSET(0) NUMBITS(16) []
+    ],
+    /// PLL Control 2
+    PLLCTL2 [
+        /// Frequency modulation enable
+        FMENA OFFSET(31) NUMBITS(1) [],
+        /// Spreading rate (NS - 1)
+        SPREADINGRATE OFFSET(22) NUMBITS(9) [],
+        /// Bandwidth adjustment
+        MULMOD OFFSET(12) NUMBITS(9) [],
+        /// Internal PLL output divider (OD - 1)
+        ODPLL OFFSET(9) NUMBITS(3) [],
+        /// Spreading amount (NV - 1)
+        SPR_AMOUNT OFFSET(0) NUMBITS(9) []
+    ],
+    /// LPO/Clock Monitor Control
+    LPOMONCTL [
+        /// Bias enable
+        BIAS_ENABLE OFFSET(24) NUMBITS(1) [],
+        /// High frequency oscillator trim
+        HFTRIM OFFSET(8) NUMBITS(5) [],
+        /// Low frequency oscillator trim
+        LFTRIM OFFSET(0) NUMBITS(5) []
+    ],
+    /// Clock Test
+    CLKTEST [
+        /// Range detection control
+        RANGEDETCTRL OFFSET(25) NUMBITS(1) [],
+        /// Range detection enable select
+        RANGEDETENSSEL OFFSET(24) NUMBITS(1) []
+    ],
+    /// Clock Control
+    CLKCNTL [
+        /// VBUS clock ratio (VCLK2)
+        VCLK2R OFFSET(24) NUMBITS(4) [],
+        /// Peripheral clock ratio (VCLK)
+        VCLKR OFFSET(16) NUMBITS(4) [],
+        /// Peripheral enable
+        PENA OFFSET(8) NUMBITS(1) []
+    ],
+    /// ECP Control
+    ECPCNTL [
+        /// ECP clock source: OSCIN (1) or VCLK (0)
+        ECPSSEL OFFSET(24) NUMBITS(1) [],
+        /// ECP divider (N - 1)
+        ECPDIV OFFSET(0) NUMBITS(16) []
+    ],
+    /// Global Status
+    GBLSTAT [
+        /// Feedback slip
+        FBSLIP OFFSET(9) NUMBITS(1) [],
+        /// Reference clock slip
+        RFSLIP OFFSET(8) NUMBITS(1) [],
+        /// Oscillator fail
+        OSCFAIL OFFSET(0) NUMBITS(1) []
+    ],
+    /// PLL Control 3 (PLL2)
+    PLLCTL3 [
+        /// Internal PLL2 output divider (OD - 1)
+        ODPLL2 OFFSET(29) NUMBITS(3) [],
+        /// PLL2 output clock divider (R - 1)
+        PLLDIV2 OFFSET(24) NUMBITS(5) [],
+        /// Reference clock divider (NR - 1)
+        REFCLKDIV2 OFFSET(16) NUMBITS(6) [],
+        /// PLL2 multiplication factor ((NF - 1) * 256)
+        PLLMUL2 OFFSET(0) NUMBITS(16) []
+    ],
+    /// Clock 2 Control
+    CLK2CNTRL [
+        /// VCLK4 clock ratio
+        VCLK4R OFFSET(8) NUMBITS(4) [],
+        /// VCLK3 clock ratio
+        VCLK3R OFFSET(0) NUMBITS(4) []
+    ],
+    /// Peripheral Asynchronous Clock Configuration 1
+    VCLKACON1 [
+        /// VCLKA4 source
+        VCLKA4S OFFSET(16) NUMBITS(4) [],
+        /// VCLKA3 source
+        VCLKA3S OFFSET(0) NUMBITS(4) []
+    ],
+    /// HCLK Control (Cortex-R5F parts)
+    HCLKCNTL [
+        /// HCLK divider from GCLK (HCLKR + 1)
+        HCLKR OFFSET(0) NUMBITS(2) []
+    ],
+    /// PBIST Datalogger
+    DLR [
+        /// Configuration access mode (CPU controls PBIST)
+        DLR4 OFFSET(4) NUMBITS(1) [],
+        /// ROM-based testing
+        DLR2 OFFSET(2) NUMBITS(1) []
+    ],
+    /// PBIST Activate
+    PACT [
+        /// ROM clock enable
+        PACT1 OFFSET(1) NUMBITS(1) [],
+        /// PBIST internal clocks enable
+        PACT0 OFFSET(0) NUMBITS(1) []
+    ],
+    /// PBIST ROM Mask
+    ROM [
+        /// Information loaded from the PBIST ROM
+        ROM OFFSET(0) NUMBITS(2) [
+            RamGroups = 1,
+            Algorithms = 2,
+            Both = 3
+        ]
+    ]
+}
 
 #[repr(C)]
 struct SysRegister1 {
-    syspc1: VolatileCell<u32>,
-    syspc2: VolatileCell<u32>,
-    syspc3: VolatileCell<u32>,
-    syspc4: VolatileCell<u32>,
-    syspc5: VolatileCell<u32>,
-    syspc6: VolatileCell<u32>,
-    syspc7: VolatileCell<u32>,
-    syspc8: VolatileCell<u32>,
-    syspc9: VolatileCell<u32>,
-    sswpll1: VolatileCell<u32>,
-    sswpll2: VolatileCell<u32>,
-    sswpll3: VolatileCell<u32>,
-    csdis: VolatileCell<u32>,
-    csdisset: VolatileCell<u32>,
-    csdisclr: VolatileCell<u32>,
-    cddis: VolatileCell<u32>,
-    cddisset: VolatileCell<u32>,
-    cddisclr: VolatileCell<u32>,
-    ghvsrc: VolatileCell<u32>,
-    vclkasrc: VolatileCell<u32>,
-    rclksrc: VolatileCell<u32>,
-    csvstat: VolatileCell<u32>,
-    mstgcr: VolatileCell<u32>,
-    minitgcr: VolatileCell<u32>,
-    msinena: VolatileCell<u32>,
-    mstfail: VolatileCell<u32>,
-    mstcgstat: VolatileCell<u32>,
-    ministat: VolatileCell<u32>,
-    pllctl1: VolatileCell<u32>,
-    pllctl2: VolatileCell<u32>,
-    syspc10: VolatileCell<u32>,
-    dieid_l: VolatileCell<u32>,
-    dieid_h: VolatileCell<u32>,
-    vrctl: VolatileCell<u32>,
-    lpomonctl: VolatileCell<u32>,
-    clktest: VolatileCell<u32>,
-    dftctrlreg1: VolatileCell<u32>,
-    dftctrlreg2: VolatileCell<u32>,
-    rsvd1: VolatileCell<u32>,
-    rsvd2: VolatileCell<u32>,
-    gpreg1: VolatileCell<u32>,
-    btrmsel: VolatileCell<u32>,
-    impfasts: VolatileCell<u32>,
-    impftadd: VolatileCell<u32>,
-    ssisr1: VolatileCell<u32>,
-    ssisr2: VolatileCell<u32>,
-    ssisr3: VolatileCell<u32>,
-    ssisr4: VolatileCell<u32>,
-    ramgcr: VolatileCell<u32>,
-    bmmcr1: VolatileCell<u32>,
-    bmmcr2: VolatileCell<u32>,
-    cpurstcr: VolatileCell<u32>,
-	clkcntl: VolatileCell<u32>,
-    ecpcntl: VolatileCell<u32>,
-    dspgcr: VolatileCell<u32>,
-    devcr1: VolatileCell<u32>,
-    sysecr: VolatileCell<u32>,
-    syssrc: VolatileCell<u32>,
-    systasr: VolatileCell<u32>,
-    gblstat: VolatileCell<u32>,
-    dev: VolatileCell<u32>,
-    ssivec: VolatileCell<u32>,
-    ssif: VolatileCell<u32>
+    syspc1: Reg,
+    syspc2: Reg,
+    syspc3: Reg,
+    syspc4: Reg,
+    syspc5: Reg,
+    syspc6: Reg,
+    syspc7: Reg,
+    syspc8: Reg,
+    syspc9: Reg,
+    sswpll1: Reg,
+    sswpll2: Reg,
+    sswpll3: Reg,
+    csdis: Reg,
+    csdisset: Reg,
+    csdisclr: Reg,
+    cddis: Reg,
+    cddisset: Reg,
+    cddisclr: Reg,
+    ghvsrc: Reg<u32, GHVSRC::Register>,
+    vclkasrc: Reg<u32, VCLKASRC::Register>,
+    rclksrc: Reg<u32, RCLKSRC::Register>,
+    csvstat: Reg<u32, CSVSTAT::Register>,
+    mstgcr: Reg<u32, MSTGCR::Register>,
+    minitgcr: Reg<u32, MINITGCR::Register>,
+    msinena: Reg,
+    mstfail: Reg,
+    mstcgstat: Reg<u32, MSTCGSTAT::Register>,
+    ministat: Reg,
+    pllctl1: Reg<u32, PLLCTL1::Register>,
+    pllctl2: Reg<u32, PLLCTL2::Register>,
+    syspc10: Reg,
+    dieid_l: Reg,
+    dieid_h: Reg,
+    vrctl: Reg,
+    lpomonctl: Reg<u32, LPOMONCTL::Register>,
+    clktest: Reg<u32, CLKTEST::Register>,
+    dftctrlreg1: Reg,
+    dftctrlreg2: Reg,
+    rsvd1: Reg,
+    rsvd2: Reg,
+    gpreg1: Reg,
+    btrmsel: Reg,
+    impfasts: Reg,
+    impftadd: Reg,
+    ssisr1: Reg,
+    ssisr2: Reg,
+    ssisr3: Reg,
+    ssisr4: Reg,
+    ramgcr: Reg,
+    bmmcr1: Reg,
+    bmmcr2: Reg,
+    cpurstcr: Reg,
+    clkcntl: Reg<u32, CLKCNTL::Register>,
+    ecpcntl: Reg<u32, ECPCNTL::Register>,
+    dspgcr: Reg,
+    devcr1: Reg,
+    sysecr: Reg,
+    syssrc: Reg,
+    systasr: Reg,
+    gblstat: Reg<u32, GBLSTAT::Register>,
+    dev: Reg,
+    ssivec: Reg,
+    ssif: Reg
 }
 const SYS1_BASE_ADDR: *const SysRegister1 = 0xFFFF_FF00 as *const SysRegister1;
 
 #[repr(C)]
 struct SysRegister2 {
-    pllctl3: VolatileCell<u32>,
-    rsvd1: VolatileCell<u32>,
-    stcclkdiv: VolatileCell<u32>,
-    rsvd2: [VolatileCell<u32>; 6],
-    ecpcntrl0: VolatileCell<u32>,
-    rsvd3: [VolatileCell<u32>; 5],
-    clc2cntl: VolatileCell<u32>,
-    vclkacon1: VolatileCell<u32>,
-    rsvd4: [VolatileCell<u32>; 11],
-    clkslip: VolatileCell<u32>,
-    rsvd5: [VolatileCell<u32>; 30],
-    efc_ctlen: VolatileCell<u32>,
-    dieidl_reg0: VolatileCell<u32>,
-    dieidh_reg1: VolatileCell<u32>,
-    dieidl_reg2: VolatileCell<u32>,
-    diedih_reg3: VolatileCell<u32>
+    pllctl3: Reg<u32, PLLCTL3::Register>,
+    rsvd1: Reg,
+    stcclkdiv: Reg,
+    rsvd2: [Reg; 6],
+    ecpcntrl0: Reg,
+    rsvd3: [Reg; 5],
+    clc2cntl: Reg<u32, CLK2CNTRL::Register>,
+    vclkacon1: Reg<u32, VCLKACON1::Register>,
+    #[cfg(not(cortex_r5))]
+    rsvd4: [Reg; 11],
+    #[cfg(cortex_r5)]
+    rsvd4: [Reg; 4],
+    #[cfg(cortex_r5)]
+    hclkcntl: Reg<u32, HCLKCNTL::Register>,
+    #[cfg(cortex_r5)]
+    rsvd6: [Reg; 6],
+    clkslip: Reg,
+    rsvd5: [Reg; 30],
+    efc_ctlen: Reg,
+    dieidl_reg0: Reg,
+    dieidh_reg1: Reg,
+    dieidl_reg2: Reg,
+    diedih_reg3: Reg
 }
 const SYS2_BASE_ADDR: *const SysRegister2 = 0xFFFF_E100 as *const SysRegister2;
 
@@ -113,46 +315,76 @@ const SYS2_BASE_ADDR: *const SysRegister2 = 0xFFFF_E100 as *const SysRegister2;
 #[allow(non_snake_case)]
 struct PbistRegisters {
     /// RAM Configuration
-    RAMT: VolatileCell<u32>,
+    RAMT: Reg,
     /// Datalogger
-    DLR: VolatileCell<u32>,
-    _reserved1: [VolatileCell<u32>;6],
+    DLR: Reg<u32, DLR::Register>,
+    _reserved1: [Reg; 6],
     /// PBIST Activate
-    PACT: VolatileCell<u32>,
+    PACT: Reg<u32, PACT::Register>,
     /// PBIST ID
-    PBISTID: VolatileCell<u32>,
+    PBISTID: Reg,
     /// Override
-    OVER: VolatileCell<u32>,
-    _reserved2: VolatileCell<u32>,
+    OVER: Reg,
+    _reserved2: Reg,
     /// Fail Status Fail 0
-    FSRF0: VolatileCell<u32>,
-    _reserved5: VolatileCell<u32>,
+    FSRF0: Reg,
+    _reserved5: Reg,
     /// Fail Status Count 0
-    FSRC0: VolatileCell<u32>,
+    FSRC0: Reg,
     /// Fail Status Count 1
-    FSRC1: VolatileCell<u32>,
+    FSRC1: Reg,
     /// Fail Status Address 0
-    FSRA0: VolatileCell<u32>,
+    FSRA0: Reg,
     /// Fail Status Address 1
-    FSRA1: VolatileCell<u32>,
+    FSRA1: Reg,
     /// Fail Status Data 0
-    FSRDL0: VolatileCell<u32>,
-    _reserved3: VolatileCell<u32>,
+    FSRDL0: Reg,
+    _reserved3: Reg,
     /// Fail Status Data 1
-    FSRDL1: VolatileCell<u32>,
-    _reserved4: [VolatileCell<u32>;3],
+    FSRDL1: Reg,
+    _reserved4: [Reg; 3],
     /// ROM Mask
-    ROM: VolatileCell<u32>,
+    ROM: Reg<u32, ROM::Register>,
     /// Algorithm Mask
-    ALGO: VolatileCell<u32>,
+    ALGO: Reg,
     /// RAM Info Mask Lower
-    RINFOL: VolatileCell<u32>,
+    RINFOL: Reg,
     /// RAM Info Mask Upper
-    RINFOU: VolatileCell<u32>,
+    RINFOU: Reg,
 }
 const PBIST_BASE_ADDR: *const PbistRegisters = 0xFFFF_E560 as *const PbistRegisters;
 
 
+/// LPO nominal frequencies [Hz]
+const LPO_LOW: u32 = 80_000;
+const LPO_HIGH: u32 = MHz!(10);
+
+/// LPO trim codes are 5 bits wide
+const LPO_TRIM_CODES: u8 = 32;
+/// LPO trim measurement window [OSCIN cycles]
+const LPO_TRIM_WINDOW: u32 = 0x1_0000;
+
+/// LPO trim codes found by `Sys::calibrate_lpo()`
+#[derive(Clone, Copy, Debug)]
+pub struct LpoTrim {
+    /// HF LPO trim code
+    pub hf: u8,
+    /// LF LPO trim code
+    pub lf: u8,
+    /// HF LPO frequency with `hf` trim code [Hz]
+    pub hf_frequency: u32,
+    /// LF LPO frequency with `lf` trim code [Hz]
+    pub lf_frequency: u32,
+}
+
+impl LpoTrim {
+    /// Trim value in the OTP format (HF code in the upper byte), as
+    /// accepted by `Sys::set_lpo_trim()`
+    pub fn value(&self) -> u16 {
+        (u16::from(self.hf) << 8) | u16::from(self.lf)
+    }
+}
+
 /// MCU available Spleep Modes
 #[derive(Clone, Copy)]
 pub enum SleepMode {
@@ -164,6 +396,50 @@ pub enum SleepMode {
     Sleep = 0x000F_FFFF,
 }
 
+/// Interrupt sources waking the device up from a low-power mode.
+///
+/// In snooze and sleep modes the oscillator is off: RTI can only wake
+/// the device up from doze (or from snooze if clocked by the LPO), the
+/// others are asynchronous (pin edge, bus activity).
+#[derive(Clone, Copy)]
+pub enum WakeupSource {
+    /// GIO pin interrupts (high and low level)
+    Gio,
+    Can1,
+    Can2,
+    #[cfg(not(feature = "tms570ls0432"))]
+    Can3,
+    Lin,
+    #[cfg(not(feature = "tms570ls0432"))]
+    Sci,
+    /// RTI compare and overflow interrupts
+    Rti,
+}
+
+impl WakeupSource {
+    /// VIM channels of the source
+    pub fn channels(self) -> &'static [VimChannel] {
+        match self {
+            WakeupSource::Gio => &[VimChannel::GioHigh, VimChannel::GioLow],
+            WakeupSource::Can1 => &[VimChannel::Can1Level0, VimChannel::Can1Level1],
+            WakeupSource::Can2 => &[VimChannel::Can2Level0, VimChannel::Can2Level1],
+            #[cfg(not(feature = "tms570ls0432"))]
+            WakeupSource::Can3 => &[VimChannel::Can3Level0, VimChannel::Can3Level1],
+            WakeupSource::Lin => &[VimChannel::LinLevel0, VimChannel::LinLevel1],
+            #[cfg(not(feature = "tms570ls0432"))]
+            WakeupSource::Sci => &[VimChannel::SciLevel0, VimChannel::SciLevel1],
+            WakeupSource::Rti => &[
+                VimChannel::Rti0,
+                VimChannel::Rti1,
+                VimChannel::Rti2,
+                VimChannel::Rti3,
+                VimChannel::RtiOverflow0,
+                VimChannel::RtiOverflow1,
+            ],
+        }
+    }
+}
+
 /// Enumerates MCU's available clock domains
 #[derive(Clone, Copy)]
 pub enum ClockDomains {
@@ -238,24 +514,24 @@ pub struct Sys {
     sys1: &'static SysRegister1,
     sys2: &'static SysRegister2,
     pbist: &'static PbistRegisters,
+    /// Oscillator (OSCIN) frequency [Hz]
+    osc: Cell<u32>,
 }
 
 impl Sys {
-    pub fn new() -> Sys {
+    /// The oscillator is assumed to run at `config::OSCIN`, the one of
+    /// the clock tree set up by the startup code, until `setup_pll()`
+    pub fn new(_sys: SYS) -> Sys {
         Sys {
-            sys1: unsafe { &*SYS1_BASE_ADDR },
-            sys2: unsafe { &*SYS2_BASE_ADDR },
-            pbist: unsafe { &*PBIST_BASE_ADDR },
+            sys1: unsafe { map(SYS1_BASE_ADDR) },
+            sys2: unsafe { map(SYS2_BASE_ADDR) },
+            pbist: unsafe { map(PBIST_BASE_ADDR) },
+            osc: Cell::new(config::OSCIN),
         }
     }
 
     pub fn enable_pheripherals(&self, enable:bool) {
-        let cntl = self.sys1.clkcntl.get();
-        if enable {
-            self.sys1.clkcntl.set(cntl | 0x0000_0100);
-        } else {
-            self.sys1.clkcntl.set(cntl & 0xFFFF_FEFF);
-        }
+        self.sys1.clkcntl.modify(CLKCNTL::PENA.val(enable as u32));
     }
 
     /// Disable PLL1 and PLL2
@@ -275,100 +551,227 @@ impl Sys {
 
     /// Clear Global Status Register
     pub fn clear_global_status(&self) {
-        self.sys1.gblstat.set(0x301);
+        self.sys1.gblstat.write(GBLSTAT::FBSLIP::SET
+                                + GBLSTAT::RFSLIP::SET
+                                + GBLSTAT::OSCFAIL::SET);
     }
 
-    // Setup all PLLs
-    pub fn setup_pll(&self) {
+    /// Setup PLL1 and PLL2 as computed by `ClockConfig::solve()`.
+    /// The output dividers (R) are kept at their maximum until the PLLs
+    /// are locked: `setup_clocks()` sets them.
+    /// The oscillator frequency of `setup` is kept to decode the clock
+    /// tree (`clocks()`) and to measure against OSCIN (`calibrate_lpo()`).
+    pub fn setup_pll(&self, setup: &ClockSetup) {
+        let (pll1, pll2) = (setup.pll1(), setup.pll2());
+        self.osc.set(setup.osc());
+
         self.disable_pll();
         self.clear_global_status();
 
         // Setup pll control register 1:
-        // - Setup reset on oscillator slip
+        // - No reset on oscillator slip
         // - Setup bypass on pll slip
         // - setup Pll output clock divider to max before Lock
-        // - Setup reset on oscillator fail
+        // - No reset on oscillator fail
         // - Setup reference clock divider
         // - Setup Pll multiplier
-        let pll_ctl1 = 0x0000_0000
-                    | 0x2000_0000
-                    | (0x1F << 24)
-                    | 0x0000_0000
-                    | ((6 - 1) << 16)
-                    | (0x7700);
-        self.sys1.pllctl1.set(pll_ctl1);
+        self.sys1.pllctl1.write(PLLCTL1::ROS::CLEAR
+                                + PLLCTL1::MASK_SLIP::Bypass
+                                + PLLCTL1::PLLDIV::SET
+                                + PLLCTL1::ROF::CLEAR
+                                + PLLCTL1::REFCLKDIV.val(pll1.nr - 1)
+                                + PLLCTL1::PLLMUL.val((pll1.nf - 1) << 8));
 
         // Setup pll control register 2:
         // - Setup spreading rate
         // - Setup bandwidth adjustment
         // - Setup internal Pll output divider
         // - Setup spreading amount
-        let pll_ctl2 = (255 << 22)
-                    | (7 << 12)
-                    | ((2 - 1) << 9)
-                    | 61;
-        self.sys1.pllctl2.set(pll_ctl2);
+        // Frequency modulation enabled on request only
+        let fm = setup.modulation();
+        let m = fm.unwrap_or(Modulation::new(256, 62));
+        self.sys1.pllctl2.write(PLLCTL2::FMENA.val(fm.is_some() as u32)
+                                + PLLCTL2::SPREADINGRATE.val(m.ns - 1)
+                                + PLLCTL2::MULMOD.val(m.mulmod)
+                                + PLLCTL2::ODPLL.val(pll1.od - 1)
+                                + PLLCTL2::SPR_AMOUNT.val(m.nv - 1));
 
         // Setup pll2 control register:
+        // - Setup internal Pll output divider
         // - setup Pll output clock divider to max before Lock
         // - Setup reference clock divider
-        // - Setup internal Pll output divider
         // - Setup Pll multiplier
-        let pll_ctl3 = ((2 - 1) << 29)
-                        | (0x1F << 24)
-                        | ((6 - 1)<< 16)
-                        | (0x7700);
-        self.sys2.pllctl3.set(pll_ctl3);
+        self.sys2.pllctl3.write(PLLCTL3::ODPLL2.val(pll2.od - 1)
+                                + PLLCTL3::PLLDIV2::SET
+                                + PLLCTL3::REFCLKDIV2.val(pll2.nr - 1)
+                                + PLLCTL3::PLLMUL2.val((pll2.nf - 1) << 8));
 
         self.enable_pll()
     }
 
+    /// Run GCLK from PLL1, set the HCLK and VCLKx dividers, the
+    /// asynchronous clock sources and finally the PLL output dividers.
+    /// PLLs must be locked (`wait_pll_lock()`).
+    pub fn setup_clocks(&self, setup: &ClockSetup) -> Clocks {
+        let (vclk1, vclk2, vclk3) = setup.vclk_dividers();
+        let vclk = SysClockSources::Vclk as u32;
+        let avclk = if setup.avclk_from_pll2() {
+            SysClockSources::Pll2 as u32
+        } else {
+            vclk
+        };
+
+        // HCLK divider must be set before switching GCLK to the PLL
+        #[cfg(cortex_r5)]
+        self.set_hclk_divider((setup.hclk_divider() - 1) as u8);
+
+        self.sys1.ghvsrc.write(GHVSRC::GHVWAKE.val(SysClockSources::Osc as u32)
+                               + GHVSRC::HVLPM.val(SysClockSources::Osc as u32)
+                               + GHVSRC::GHVSRC.val(SysClockSources::Pll1 as u32));
+        self.sys1.rclksrc.write(RCLKSRC::RTI2DIV.val(1)
+                                + RCLKSRC::RTI2SRC.val(vclk)
+                                + RCLKSRC::RTI1DIV.val(1)
+                                + RCLKSRC::RTI1SRC.val(vclk));
+        self.sys1.vclkasrc.write(VCLKASRC::VCLKA2S.val(avclk)
+                                 + VCLKASRC::VCLKA1S.val(avclk));
+        self.sys2.vclkacon1.write(VCLKACON1::VCLKA4S.val(avclk)
+                                  + VCLKACON1::VCLKA3S.val(avclk));
+
+        // VCLK4 runs as VCLK3
+        self.peripherals_clock_divider((vclk1 - 1) as u8, (vclk2 - 1) as u8,
+                                       (vclk3 - 1) as u8, (vclk3 - 1) as u8);
+
+        self.set_pll_divider((setup.pll1().r - 1) as u8, (setup.pll2().r - 1) as u8);
+        self.clocks()
+    }
+
+    /// Frequency of a clock source [Hz]. The LPO ones are nominal, the
+    /// external ones unknown (0).
+    fn source_frequency(&self, source: u32) -> u32 {
+        let osc = self.osc.get();
+        match source {
+            s if s == SysClockSources::Osc as u32 => osc,
+            s if s == SysClockSources::Pll1 as u32 => Pll {
+                nr: self.sys1.pllctl1.read(PLLCTL1::REFCLKDIV) + 1,
+                nf: (self.sys1.pllctl1.read(PLLCTL1::PLLMUL) >> 8) + 1,
+                od: self.sys1.pllctl2.read(PLLCTL2::ODPLL) + 1,
+                r: self.sys1.pllctl1.read(PLLCTL1::PLLDIV) + 1,
+            }.output(osc),
+            s if s == SysClockSources::Pll2 as u32 => Pll {
+                nr: self.sys2.pllctl3.read(PLLCTL3::REFCLKDIV2) + 1,
+                nf: (self.sys2.pllctl3.read(PLLCTL3::PLLMUL2) >> 8) + 1,
+                od: self.sys2.pllctl3.read(PLLCTL3::ODPLL2) + 1,
+                r: self.sys2.pllctl3.read(PLLCTL3::PLLDIV2) + 1,
+            }.output(osc),
+            s if s == SysClockSources::Lpolow as u32 => LPO_LOW,
+            s if s == SysClockSources::LpiHigh as u32 => LPO_HIGH,
+            _ => 0,
+        }
+    }
+
+    /// Clock frequencies, decoded from the current clock tree setup and
+    /// the oscillator frequency (see `setup_pll()`)
+    pub fn clocks(&self) -> Clocks {
+        let gclk = self.source_frequency(self.sys1.ghvsrc.read(GHVSRC::GHVSRC));
+        #[cfg(cortex_r5)]
+        let hclk = gclk / (self.sys2.hclkcntl.read(HCLKCNTL::HCLKR) + 1);
+        #[cfg(not(cortex_r5))]
+        let hclk = gclk;
+        let vclk = [
+            hclk / (self.sys1.clkcntl.read(CLKCNTL::VCLKR) + 1),
+            hclk / (self.sys1.clkcntl.read(CLKCNTL::VCLK2R) + 1),
+            hclk / (self.sys2.clc2cntl.read(CLK2CNTRL::VCLK3R) + 1),
+            hclk / (self.sys2.clc2cntl.read(CLK2CNTRL::VCLK4R) + 1),
+        ];
+        let async_clock = |source: u32| {
+            if source == SysClockSources::Vclk as u32 {
+                vclk[0]
+            } else {
+                self.source_frequency(source)
+            }
+        };
+        let fm = self.sys1.pllctl2.is_set(PLLCTL2::FMENA)
+                 && self.sys1.ghvsrc.read(GHVSRC::GHVSRC) == SysClockSources::Pll1 as u32;
+        let modulation = if fm {
+            Modulation::new(1, self.sys1.pllctl2.read(PLLCTL2::SPR_AMOUNT) + 1).depth()
+        } else {
+            0
+        };
+        Clocks {
+            gclk,
+            hclk,
+            vclk,
+            avclk1: async_clock(self.sys1.vclkasrc.read(VCLKASRC::VCLKA1S)),
+            rticlk1: async_clock(self.sys1.rclksrc.read(RCLKSRC::RTI1SRC))
+                     >> self.sys1.rclksrc.read(RCLKSRC::RTI1DIV),
+            modulation,
+        }
+    }
+
+    /// Enable or disable the PLL1 frequency modulation of `setup`, the
+    /// one PLL1 runs with (`setup_pll()`). `ClockConfig::solve()` has
+    /// checked its range and the worst-case GCLK: without a modulation in
+    /// `setup`, it is disabled. The modulation is reprogrammed with FM
+    /// off. `Clocks` read before are stale: the updated ones are returned.
+    pub fn frequency_modulation(&self, setup: &ClockSetup, enable: bool) -> Clocks {
+        self.sys1.pllctl2.modify(PLLCTL2::FMENA::CLEAR);
+        if let Some(m) = setup.modulation().filter(|_| enable) {
+            self.sys1.pllctl2.modify(PLLCTL2::SPREADINGRATE.val(m.ns - 1)
+                                     + PLLCTL2::MULMOD.val(m.mulmod)
+                                     + PLLCTL2::SPR_AMOUNT.val(m.nv - 1));
+            self.sys1.pllctl2.modify(PLLCTL2::FMENA::SET);
+        }
+        self.clocks()
+    }
+
     pub fn wait_pll_lock(&self) {
         loop {
-            let csvstat = self.sys1.csvstat.get();
-            let csdis = self.sys1.csdis.get();
-            let mask = (csdis ^ 0xff) & 0xff;
-            if csvstat & mask == mask {
+            let valid = self.sys1.csvstat.read(CSVSTAT::CLKSRVALID);
+            let mask = !self.sys1.csdis.get() & 0xff;
+            if valid & mask == mask {
                 break;
             }
         }
     }
 
-    pub fn set_pll_divider(&self, div1:u8, div3:u8) {
-        let pll1 = self.sys1.pllctl1.get();
-        let pll2 = self.sys2.pllctl3.get();
-        let p1 = (pll1 & 0xE0FF_FFFF) | (u32::from(div1) << 24);
-        let p2 = (pll2 & 0xE0FF_FFFF) | (u32::from(div3) << 24);
-
-        self.sys1.pllctl1.set(p1);
-        self.sys2.pllctl3.set(p2);
-        self.sys1.pllctl2.set(self.sys1.pllctl2.get() | 0x00000000); //TODO rivedere
+    /// HCLK divider from GCLK (HCLK = GCLK / (div + 1)).
+    /// On Cortex-R5F parts GCLK can run faster than the HCLK (bus) domain:
+    /// it must be set before switching GCLK to a fast clock source.
+    #[cfg(cortex_r5)]
+    pub fn set_hclk_divider(&self, div:u8) {
+        self.sys2.hclkcntl.write(HCLKCNTL::HCLKR.val(u32::from(div)));
     }
 
-    /// Setup GCLK, HCLK and VCLK clock source for normal operation,
-    /// power down mode and after wakeup
-    pub fn setup_clock_source(&self, gclk:SysClockSources, hclk:SysClockSources,
-                              vclk:SysClockSources) {
-
+    pub fn set_pll_divider(&self, div1:u8, div3:u8) {
+        self.sys1.pllctl1.modify(PLLCTL1::PLLDIV.val(u32::from(div1)));
+        self.sys2.pllctl3.modify(PLLCTL3::PLLDIV2.val(u32::from(div3)));
+    }
 
-        self.sys1.ghvsrc.set(((gclk as u32) << 24) |
-                             ((hclk as u32) << 16) |
-                              (vclk as u32));
-        self.sys1.rclksrc.set( (0x1 << 24)
-                              | ((SysClockSources::Vclk as u32) << 16)
-                              | (0x1 << 8)
-                              | (SysClockSources::Vclk as u32));
-        self.sys1.vclkasrc.set(((SysClockSources::Vclk as u32) << 8) |
-                               (SysClockSources::Vclk as u32));
+    /// Setup GCLK, HCLK and VCLK clock source after wakeup,
+    /// in low power modes and for normal operation
+    pub fn setup_clock_source(&self, wakeup:SysClockSources, low_power:SysClockSources,
+                              normal:SysClockSources) {
+        let vclk = SysClockSources::Vclk as u32;
 
-        self.sys2.vclkacon1.set(((SysClockSources::Vclk as u32) << 16) |
-                                 (SysClockSources::Vclk as u32));
+        self.sys1.ghvsrc.write(GHVSRC::GHVWAKE.val(wakeup as u32)
+                               + GHVSRC::HVLPM.val(low_power as u32)
+                               + GHVSRC::GHVSRC.val(normal as u32));
+        self.sys1.rclksrc.write(RCLKSRC::RTI2DIV.val(1)
+                                + RCLKSRC::RTI2SRC.val(vclk)
+                                + RCLKSRC::RTI1DIV.val(1)
+                                + RCLKSRC::RTI1SRC.val(vclk));
+        self.sys1.vclkasrc.write(VCLKASRC::VCLKA2S.val(vclk)
+                                 + VCLKASRC::VCLKA1S.val(vclk));
 
+        self.sys2.vclkacon1.write(VCLKACON1::VCLKA4S.val(vclk)
+                                  + VCLKACON1::VCLKA3S.val(vclk));
     }
 
-    /// Get device infos
-    pub fn device_info(&self) -> u32 {
-        self.sys1.dev.get()
+    /// Device identification
+    pub fn device_info(&self) -> DeviceInfo {
+        let mut part_number = [0; 32];
+        flash::dev_part_number_symb(&mut part_number);
+        DeviceInfo::decode(self.sys1.dev.get(), self.die_id(), part_number)
     }
 
     /// Configure the LPO such that HF LPO is as close to 10MHz as possible.
@@ -379,47 +782,160 @@ impl Sys {
         } else {
             config::LPO
         };
+        self.set_lpo_trim(lpo);
+    }
+
+    /// Set the LPO trim value, HF trim code in the upper byte and LF
+    /// trim code in the lower one (OTP format, see `LpoTrim::value()`)
+    pub fn set_lpo_trim(&self, lpo: u16) {
         // If LPO value it too big that would result in a fault due to the
         // clock test (range check). So, disable range check for a while to be sure
         // the sudden change will not cause a fault.
         let clktest_save = self.sys1.clktest.get();
-        self.sys1.clktest.set(clktest_save | (0x1 << 24) & 0x01FF_FFFF);
-        self.sys1.lpomonctl.set((0x1 << 24) | u32::from(lpo));
+        self.sys1.clktest.modify(CLKTEST::RANGEDETENSSEL::SET + CLKTEST::RANGEDETCTRL::CLEAR);
+        self.sys1.lpomonctl.write(LPOMONCTL::BIAS_ENABLE::SET
+                                  + LPOMONCTL::HFTRIM.val(u32::from(lpo) >> 8)
+                                  + LPOMONCTL::LFTRIM.val(u32::from(lpo)));
         self.sys1.clktest.set(clktest_save);
     }
 
-    pub fn power_down(&self, mode:SleepMode) {
+    /// Trim the LPO measuring it against OSCIN with `dcc`, that shall be
+    /// DCC1 (the only one clocked by the LPO).
+    /// Every HF and LF trim code is tried, keeping the ones closest to the
+    /// nominal 10 MHz and 80 kHz: the sweep takes about 64 x 4 ms with a
+    /// 16 MHz oscillator. The LPO is left trimmed with the codes found,
+    /// returned to be stored by the application and set at the next
+    /// start with `set_lpo_trim()`.
+    /// `None` if the LPO can't be measured.
+    pub fn calibrate_lpo(&self, dcc: &Dcc) -> Option<LpoTrim> {
+        let measure = |clk: Clock1Source| {
+            dcc.frequency(Clock0Source::Oscin, self.osc.get(), clk, LPO_TRIM_WINDOW)
+        };
+        let clktest_save = self.sys1.clktest.get();
+        self.sys1.clktest.modify(CLKTEST::RANGEDETENSSEL::SET + CLKTEST::RANGEDETCTRL::CLEAR);
+
+        let lf = self.sys1.lpomonctl.read(LPOMONCTL::LFTRIM);
+        let mut trim = LpoTrim { hf: 0, lf: 0, hf_frequency: 0, lf_frequency: 0 };
+        for code in 0..LPO_TRIM_CODES {
+            self.sys1.lpomonctl.write(LPOMONCTL::BIAS_ENABLE::SET
+                                      + LPOMONCTL::HFTRIM.val(u32::from(code))
+                                      + LPOMONCTL::LFTRIM.val(lf));
+            if let Some(f) = measure(Clock1Source::HfLpo) {
+                if f.abs_diff(LPO_HIGH) < trim.hf_frequency.abs_diff(LPO_HIGH) {
+                    trim.hf = code;
+                    trim.hf_frequency = f;
+                }
+            }
+        }
+        for code in 0..LPO_TRIM_CODES {
+            self.sys1.lpomonctl.write(LPOMONCTL::BIAS_ENABLE::SET
+                                      + LPOMONCTL::HFTRIM.val(u32::from(trim.hf))
+                                      + LPOMONCTL::LFTRIM.val(u32::from(code)));
+            if let Some(f) = measure(Clock1Source::LfLpo) {
+                if f.abs_diff(LPO_LOW) < trim.lf_frequency.abs_diff(LPO_LOW) {
+                    trim.lf = code;
+                    trim.lf_frequency = f;
+                }
+            }
+        }
+
+        self.sys1.lpomonctl.write(LPOMONCTL::BIAS_ENABLE::SET
+                                  + LPOMONCTL::HFTRIM.val(u32::from(trim.hf))
+                                  + LPOMONCTL::LFTRIM.val(u32::from(trim.lf)));
+        self.sys1.clktest.set(clktest_save);
+        if trim.hf_frequency == 0 || trim.lf_frequency == 0 {
+            return None;
+        }
+        Some(trim)
+    }
+
+    /// Enter a low-power mode until one of the `wakeup` sources
+    /// interrupts, with the flash banks in the `banks` power mode.
+    /// The wakeup sources must be set up to interrupt (e.g. GIO pin
+    /// interrupt enabled, `DCan::low_power()`, `SciChipset::low_power()`).
+    ///
+    /// On wakeup the clock sources are enabled again, the PLLs relocked
+    /// and the previous GHVSRC and CDDIS restored, before returning.
+    /// IRQs are masked from the switch to the oscillator to the end of the
+    /// restore (WFI still wakes up on the pending wakeup interrupt): the
+    /// wakeup ISR runs after the restore, once the IRQ mask of the caller
+    /// is back.
+    pub fn power_down(&self, mode: SleepMode, banks: FlashWPowerModes,
+                      wakeup: &[WakeupSource], vim: &Vim, flash: &Flash) {
         let v = mode as u32;
+        let irq_masked = interrupt::irq_masked();
+        let ghvsrc = self.sys1.ghvsrc.get();
+        let cddis = self.sys1.cddis.get();
+        let csdis = self.sys1.csdis.get();
+
+        vim.wakeup_enable_all(false);
+        for src in wakeup {
+            for ch in src.channels() {
+                vim.wakeup_enable(*ch as usize, true);
+            }
+        }
+        flash.set_power_mode(banks);
+
+        interrupt::disable_irq();
+        // Run from the oscillator, so that the PLLs can be disabled,
+        // and wake up on it
+        let osc = SysClockSources::Osc as u32;
+        self.sys1.ghvsrc.write(GHVSRC::GHVWAKE.val(osc)
+                               + GHVSRC::HVLPM.val(osc)
+                               + GHVSRC::GHVSRC.val(osc));
         // Disable clock sources
         self.sys1.csdisset.set(v & 0xFF);
         // Disable clock domains
         self.sys1.cddis.set((v >> 8) & 0xFFF);
-        //TODO
-        //support::wfi()
+
+        unsafe { cortexr4::asm::wfi() };
+
+        // Clock sources back, as before power down
+        self.sys1.csdisclr.set(!csdis & 0xFF);
+        self.wait_pll_lock();
+        self.sys1.ghvsrc.set(ghvsrc);
+        self.sys1.cddis.set(cddis);
+
+        flash.set_power_mode(FlashWPowerModes::Active);
+        vim.wakeup_enable_all(true);
+        if !irq_masked {
+            unsafe { interrupt::enable_irq() };
+        }
     }
 
     pub fn activate_peripherals(&self, act:bool) {
-        if act {
-            self.sys1.clkcntl.set(self.sys1.clkcntl.get() & 0xFFFF_FEFF)
-        } else {
-            self.sys1.clkcntl.set(self.sys1.clkcntl.get() | 0x0000_0100)
-        }
+        self.sys1.clkcntl.modify(CLKCNTL::PENA.val(!act as u32))
     }
 
     pub fn peripherals_clock_divider(&self, vclk1:u8, vclk2:u8, vclk3:u8, vclk4:u8) {
-        let div1 = u32::from(vclk1) << 24;
-        let div2 = u32::from(vclk2) << 16;
-        let div3 = u32::from(vclk3) << 8;
-        let div4 = u32::from(vclk4);
-
         // Note: VCLK and VCLK2 clock ratio restrictions.
         // VCLK2 must always be greater than or equal to VCLK.
         // In addition, the VCLK and VCLK2 clock ratios must not
         // be changed simultaneously.
-        self.sys1.clkcntl.set((self.sys1.clkcntl.get() & 0xF0FF_FFFF) | div1);
-        self.sys1.clkcntl.set((self.sys1.clkcntl.get() & 0xFFF0_FFFF) | div2);
+        self.sys1.clkcntl.modify(CLKCNTL::VCLK2R.val(u32::from(vclk2)));
+        self.sys1.clkcntl.modify(CLKCNTL::VCLKR.val(u32::from(vclk1)));
+
+        self.sys2.clc2cntl.modify(CLK2CNTRL::VCLK4R.val(u32::from(vclk4))
+                                  + CLK2CNTRL::VCLK3R.val(u32::from(vclk3)));
+    }
 
-        self.sys2.clc2cntl.set((self.sys2.clc2cntl.get() & 0xFFFF_F0F0) | div3 | div4);
+    /// CPU (GCLK) cycles in `n` VBUS (VCLK) cycles, from the current
+    /// HCLK and VCLK dividers
+    fn vbus_cycles(&self, n: u32) -> u32 {
+        #[cfg(cortex_r5)]
+        let hclk_div = self.sys2.hclkcntl.read(HCLKCNTL::HCLKR) + 1;
+        #[cfg(not(cortex_r5))]
+        let hclk_div = 1;
+        n * hclk_div * (self.sys1.clkcntl.read(CLKCNTL::VCLKR) + 1)
+    }
+
+    /// Busy-wait for at least `n` VBUS (VCLK) cycles
+    pub fn vbus_delay(&self, n: u32) {
+        let cycles = self.vbus_cycles(n);
+        #[cfg(target_arch = "arm")]
+        cortexr4::pmu::delay_cycles(cycles);
+        #[cfg(not(target_arch = "arm"))]
+        let _ = cycles;
     }
 
     fn memory_self_controller(&self, enable:bool) {
@@ -427,10 +943,10 @@ impl Sys {
         // ROM_DIV = ROM clock source is HCLK divided by 2.
         // PBIST will reset for 32 VBUS cycles.
         if enable {
-            self.sys1.mstgcr.set(0x0000_0100 | 0xA);
-            wait_cycle!(32);
+            self.sys1.mstgcr.write(MSTGCR::ROM_DIV::Div2 + MSTGCR::MSTGENA::Enable);
+            self.vbus_delay(32);
         } else {
-            self.sys1.mstgcr.set(0x0000_0100 | 0x5);
+            self.sys1.mstgcr.write(MSTGCR::ROM_DIV::Div2 + MSTGCR::MSTGENA::Disable);
         }
     }
 
@@ -438,9 +954,9 @@ impl Sys {
     #[inline(always)]
     fn memory_controller_enable(&self, enable: bool) {
         if enable {
-            self.sys1.minitgcr.set(0xA)
+            self.sys1.minitgcr.write(MINITGCR::MINITGENA::Enable)
         } else {
-            self.sys1.minitgcr.set(0x5)
+            self.sys1.minitgcr.write(MINITGCR::MINITGENA::Disable)
         }
     }
 
@@ -449,7 +965,7 @@ impl Sys {
         self.memory_controller_enable(true);
         self.sys1.msinena.set(ram as u32);
         // Wait until Memory Hardware Initialization complete
-        wait_until_set!(self.sys1.mstcgstat.get(), 0x0000_0100);
+        wait_until_false!(self.sys1.mstcgstat.is_set(MSTCGSTAT::MINIDONE));
         self.memory_controller_enable(false);
     }
 
@@ -478,7 +994,8 @@ impl Sys {
 
         // Note: Suspend mode (ECPCOS) is entered while performing
         // certain JTAG debugging operations, so force ECPCOS at 0 here.
-        self.sys1.ecpcntl.set(((oscin as u32) << 24) | u32::from(divider));
+        self.sys1.ecpcntl.write(ECPCNTL::ECPSSEL.val(oscin as u32)
+                                + ECPCNTL::ECPDIV.val(u32::from(divider)));
     }
 
     pub fn eclk_gpio_setup(&self) {
@@ -491,28 +1008,25 @@ impl Sys {
         self.sys2.stcclkdiv.set(divider)
     }
 
-    /// Get Wafer and lot number
-    pub fn die_id(&self) -> (u32, u32) {
-        let wafer = self.sys2.dieidl_reg0.get() & 0x003F_FFFF;
-        let lotnum = (self.sys2.dieidl_reg0.get() & 0xFFC0_0000 >> 22) |
-                      self.sys2.dieidh_reg1.get() & 0x0000_3FFF << 10;
-        (wafer, lotnum)
+    /// Lot, wafer and die position on the wafer
+    pub fn die_id(&self) -> DieId {
+        DieId::decode(self.sys2.dieidl_reg0.get(), self.sys2.dieidh_reg1.get())
     }
 
     // Based on "PBIST Sequence" from TRM manual
     #[inline(always)]
     pub fn pbist_self_test(&self) {
         // Disable PBIST and ROM clocks
-        self.pbist.PACT.set(0x0);
+        self.pbist.PACT.write(PACT::PACT1::CLEAR + PACT::PACT0::CLEAR);
         self.memory_self_controller(false);
         self.memory_controller_enable(false);
-        self.sys1.mstcgstat.set(0x1); // Clear PBIST Done
+        self.sys1.mstcgstat.write(MSTCGSTAT::MSTDONE::SET); // Clear PBIST Done
         self.sys1.msinena.set(Ram::Internal as u32);
         self.memory_self_controller(true);
         // Enable PBIST and ROM clocks
-        self.pbist.PACT.set(0x3);
+        self.pbist.PACT.write(PACT::PACT1::SET + PACT::PACT0::SET);
         // Let CPU to take control of PBIST */
-        self.pbist.DLR.set(0x10);
+        self.pbist.DLR.write(DLR::DLR4::SET);
         //FIXME(pteti): add always fail algorithm
     }
 
@@ -536,20 +1050,20 @@ impl Sys {
         self.sys1.msinena.set(Ram::Internal as u32);
         self.memory_self_controller(true);
         // Enable PBIST clocks and ROM clock
-        self.pbist.PACT.set(0x3);
+        self.pbist.PACT.write(PACT::PACT1::SET + PACT::PACT0::SET);
         // Configure selected Algorithm and RAM groups
         self.pbist.ALGO.set(algo);
         self.pbist.RINFOL.set(memories);
         self.pbist.RINFOU.set(0x0);
         self.pbist.OVER.set(0x0);
         // load algorithm from ROM
-        self.pbist.ROM.set(0x3);
+        self.pbist.ROM.write(ROM::ROM::Both);
         // run test
-        self.pbist.DLR.set(0x14);
+        self.pbist.DLR.write(DLR::DLR4::SET + DLR::DLR2::SET);
     }
 
     pub fn pbist_completed(&self) -> bool {
-        self.sys1.mstcgstat.get() & 0x1 != 0
+        self.sys1.mstcgstat.is_set(MSTCGSTAT::MSTDONE)
     }
 
     pub fn pbist_fail(&self) -> bool {
@@ -557,30 +1071,167 @@ impl Sys {
     }
 
     pub fn pbist_stop(&self) {
-        self.pbist.PACT.set(0x0);
+        self.pbist.PACT.write(PACT::PACT1::CLEAR + PACT::PACT0::CLEAR);
         self.memory_self_controller(false);
     }
 
     /// Checks clock supervisor failure detection logic
+    ///
+    /// # Safety
+    ///
+    /// The oscillator is turned off for the test: GCLK, HCLK and VCLK must
+    /// not run from it or from a PLL (the clocks run from the HF LPO
+    /// meanwhile), nor any peripheral clock, as at startup before
+    /// `setup_pll()`. The ESM is accessed without its peripheral token, and
+    /// its oscillator failure flag is cleared.
     pub unsafe fn clock_supervisor_test(&self) -> bool {
-        self.sys1.clktest.set(self.sys1.clktest.get() | 0x0300_0000);
+        self.sys1.clktest.modify(CLKTEST::RANGEDETCTRL::SET + CLKTEST::RANGEDETENSSEL::SET);
         let ghvsrc = self.sys1.ghvsrc.get();
-        self.sys1.ghvsrc.set(0x0505_0005);
+        let lpo_high = SysClockSources::LpiHigh as u32;
+        self.sys1.ghvsrc.write(GHVSRC::GHVWAKE.val(lpo_high)
+                               + GHVSRC::HVLPM.val(lpo_high)
+                               + GHVSRC::GHVSRC.val(lpo_high));
         // disable oscillator so it fail
-        self.sys1.csdisset.set(0x1);
-        wait_until_zero!(self.sys1.gblstat.get(), 0x1);
-        let esm = esm::Esm::new();
+        self.sys1.csdisset.set(0x1 << (SysClockSources::Osc as u32));
+        wait_until_false!(self.sys1.gblstat.is_set(GBLSTAT::OSCFAIL));
+        let esm = esm::Esm::new(unsafe { ESM::steal() });
         if !esm.error_is_set(EsmError::OscFail) {
             return false;
         } else {
             // Disable test mode and restore original settings
             esm.clear_error(EsmError::OscFail);
-            self.sys1.clktest.set(self.sys1.clktest.get() & !0x0300_0000);
-            self.sys1.csdisclr.set(0x1);
-            wait_until_zero!(self.sys1.csvstat.get(), 0x3);
-            self.sys1.gblstat.set(0x301);  // clear any pending flag
+            self.sys1.clktest.modify(CLKTEST::RANGEDETCTRL::CLEAR + CLKTEST::RANGEDETENSSEL::CLEAR);
+            self.sys1.csdisclr.set(0x1 << (SysClockSources::Osc as u32));
+            wait_until_false!(self.sys1.csvstat.is_set(CSVSTAT::OSC)
+                              || self.sys1.csvstat.is_set(CSVSTAT::PLL1));
+            self.clear_global_status();  // clear any pending flag
             self.sys1.ghvsrc.set(ghvsrc);
         }
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ClockConfig;
+    use crate::mock;
+    use std::vec::Vec;
+
+    const CSDIS: u32 = 0xFFFF_FF30;
+    const CSDISSET: u32 = 0xFFFF_FF34;
+    const CSDISCLR: u32 = 0xFFFF_FF38;
+    const GHVSRC: u32 = 0xFFFF_FF48;
+    const PLLCTL1: u32 = 0xFFFF_FF70;
+    const PLLCTL2: u32 = 0xFFFF_FF74;
+    const CLKCNTL: u32 = 0xFFFF_FFD0;
+    const PLLCTL3: u32 = 0xFFFF_E100;
+
+    /// PLL1 and PLL2 clock source bits
+    const PLLS: u32 = 0x42;
+
+    #[test]
+    fn pll_setup() {
+        mock::reset();
+        let sys = Sys::new(unsafe { SYS::steal() });
+        let setup = config::CLOCKS.solve().unwrap();
+        let (pll1, pll2) = (setup.pll1(), setup.pll2());
+
+        // PLLs reported off on the third poll of CSDIS
+        mock::set_after_reads(CSDIS, PLLS, 2);
+        sys.setup_pll(&setup);
+        assert_eq!(mock::writes_to(CSDISSET), [PLLS]);
+        // slip bypass, output dividers at their maximum until locked
+        assert_eq!(mock::writes_to(PLLCTL1), [0x2000_0000 | 0x1F << 24
+                                              | (pll1.nr - 1) << 16
+                                              | (pll1.nf - 1) << 8]);
+        // no frequency modulation
+        let pllctl2 = mock::writes_to(PLLCTL2);
+        assert_eq!(pllctl2.len(), 1);
+        assert_eq!(pllctl2[0] & (0x1 << 31), 0);
+        assert_eq!((pllctl2[0] >> 9) & 0x7, pll1.od - 1);
+        assert_eq!(mock::writes_to(PLLCTL3), [(pll2.od - 1) << 29 | 0x1F << 24
+                                              | (pll2.nr - 1) << 16
+                                              | (pll2.nf - 1) << 8]);
+        // PLLs enabled last
+        assert_eq!(mock::writes().last().map(|w| (w.addr, w.value)), Some((CSDISCLR, PLLS)));
+
+        // GCLK switched to PLL1 before the PLL output dividers are set
+        mock::clear_log();
+        let clocks = sys.setup_clocks(&setup);
+        let order: Vec<u32> = mock::writes()
+            .iter()
+            .map(|w| w.addr)
+            .filter(|a| [GHVSRC, PLLCTL1].contains(a))
+            .collect();
+        assert_eq!(order, [GHVSRC, PLLCTL1]);
+        assert_eq!(mock::peek(GHVSRC), SysClockSources::Pll1 as u32);
+        assert_eq!((mock::peek(PLLCTL1) >> 24) & 0x1F, pll1.r - 1);
+        assert_eq!((mock::peek(PLLCTL3) >> 24) & 0x1F, pll2.r - 1);
+
+        // clock tree decoded back from the registers
+        assert_eq!(clocks.gclk(), setup.gclk());
+        assert_eq!(clocks.hclk(), setup.hclk());
+        assert_eq!(clocks.vclk1(), setup.vclk1());
+        assert_eq!(clocks.vclk2(), setup.vclk2());
+        assert_eq!(clocks.avclk1(), setup.avclk());
+    }
+
+    #[test]
+    fn vbus_cycles() {
+        mock::reset();
+        let sys = Sys::new(unsafe { SYS::steal() });
+        // VCLK = HCLK / 2
+        mock::poke(CLKCNTL, 0x1 << 16);
+        assert_eq!(sys.vbus_cycles(16), 32);
+        mock::poke(CLKCNTL, 0x0);
+        assert_eq!(sys.vbus_cycles(16), 16);
+    }
+
+    #[test]
+    fn oscillator() {
+        mock::reset();
+        let sys = Sys::new(unsafe { SYS::steal() });
+        let setup = ClockConfig::new(MHz!(10), MHz!(80)).solve().unwrap();
+        mock::poke(CSDIS, PLLS);
+        sys.setup_pll(&setup);
+        let clocks = sys.setup_clocks(&setup);
+        // decoded with the 10 MHz oscillator, not config::OSCIN
+        assert_eq!(clocks.gclk(), MHz!(80));
+        assert_eq!(clocks.vclk1(), setup.vclk1());
+        assert_eq!(sys.clocks(), clocks);
+    }
+
+    #[test]
+    fn frequency_modulation() {
+        mock::reset();
+        let sys = Sys::new(unsafe { SYS::steal() });
+        let plain = ClockConfig::new(MHz!(16), MHz!(60)).solve().unwrap();
+        let setup = ClockConfig::new(MHz!(16), MHz!(60))
+            .spread_spectrum(Modulation::new(40, 20))
+            .solve()
+            .unwrap();
+        mock::poke(CSDIS, PLLS);
+        sys.setup_pll(&plain);
+        sys.setup_clocks(&plain);
+
+        // modulation programmed with FM off, then enabled
+        mock::clear_log();
+        let clocks = sys.frequency_modulation(&setup, true);
+        let pllctl2 = mock::writes_to(PLLCTL2);
+        assert_eq!(pllctl2.len(), 3);
+        assert_eq!(pllctl2[0] & (0x1 << 31), 0);
+        assert_eq!(pllctl2[1] & (0x1 << 31), 0);
+        assert_eq!(pllctl2[2] >> 31, 1);
+        assert_eq!((pllctl2[2] >> 22) & 0x1FF, 40 - 1);
+        assert_eq!(pllctl2[2] & 0x1FF, 20 - 1);
+        assert_eq!(clocks.modulation_depth(), setup.modulation().unwrap().depth());
+
+        // disabled on request, and without a modulation in the setup
+        assert_eq!(sys.frequency_modulation(&setup, false).modulation_depth(), 0);
+        mock::clear_log();
+        assert_eq!(sys.frequency_modulation(&plain, true).modulation_depth(), 0);
+        assert_eq!(mock::writes_to(PLLCTL2).len(), 1);
+        assert_eq!(mock::peek(PLLCTL2) >> 31, 0);
+    }
+}
@@ -1,6 +1,7 @@
-///!
-///! Tightly-Coupled RAM (TCRAM) module
-///!
+//!
+//! Tightly-Coupled RAM (TCRAM) module
+use crate::peripherals::{sealed::Sealed, TCRAM1, TCRAM2};
+use crate::regs::map;
 use vcell::VolatileCell;
 
 #[repr(C)]
@@ -28,11 +29,24 @@ pub enum TcRamID {
     Two,
 }
 
+/// TCRAM wrapper instance: `TCRAM1` or `TCRAM2`
+pub trait Instance: Sealed {
+    const ID: TcRamID;
+}
+
+impl Instance for TCRAM1 {
+    const ID: TcRamID = TcRamID::One;
+}
+
+impl Instance for TCRAM2 {
+    const ID: TcRamID = TcRamID::Two;
+}
+
 impl Tcram {
-    pub unsafe fn new(id: TcRamID) -> &'static Tcram {
-        match id {
-            TcRamID::One => &*TCRAM1_BASE_ADDR,
-            TcRamID::Two => &*TCRAM2_BASE_ADDR,
+    pub fn new<I: Instance>(_tcram: I) -> &'static Tcram {
+        match I::ID {
+            TcRamID::One => unsafe { map(TCRAM1_BASE_ADDR) },
+            TcRamID::Two => unsafe { map(TCRAM2_BASE_ADDR) },
         }
     }
 
@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+    .syntax unified
+    .cpu cortex-r4
+    .fpu vfpv3-d16
+    .arm
+
+@ VFP context preservation for interrupt handlers registered with
+@ #[interrupt(..., fpu = "eager")] or #[interrupt(..., fpu = "lazy")].
+@ Entry trampolines (generated by the macro, compatible with the VIM
+@ hardware vectored mode) save r0-r3, r12, lr and call one of the
+@ wrappers below with the handler address in r0.
+@
+@ Eager: FPSCR and d0-d15 are always saved and restored.
+@
+@ Lazy: FPEXC.EN is cleared before calling the handler, so its first
+@ VFP instruction traps into undef_entry (see fault.s). The trap saves
+@ FPSCR and d0-d15 into the current lazy frame, sets FPEXC.EN and
+@ retries the instruction. The context is restored on exit only if it
+@ has been saved. Frames are linked, so FIQ handlers can preempt IRQ
+@ handlers.
+@
+@ Lazy frame layout (on the IRQ/FIQ stack):
+@   +0  previous frame
+@   +4  FPEXC on entry
+@   +8  saved flag
+@   +12 FPSCR
+@   +16 d0-d15
+
+    .equ FPEXC_EN, 0x40000000
+    .equ LAZY_FRAME_SIZE, 144
+
+    .section .bss.tms570_vfp_lazy_frame,"aw",%nobits
+    .align 2
+    .global tms570_vfp_lazy_frame
+tms570_vfp_lazy_frame:
+    .word 0
+
+    .section .text.tms570_vfp,"ax",%progbits
+
+    .global tms570_vfp_eager
+    .type tms570_vfp_eager, %function
+
+tms570_vfp_eager:
+    push    {r4, r5, r6, lr}
+    vmrs    r4, fpexc
+    orr     r1, r4, #FPEXC_EN
+    vmsr    fpexc, r1
+    vmrs    r5, fpscr
+    vpush   {d0 - d15}
+    blx     r0
+    vpop    {d0 - d15}
+    vmsr    fpscr, r5
+    vmsr    fpexc, r4
+    pop     {r4, r5, r6, pc}
+
+    .global tms570_vfp_lazy
+    .type tms570_vfp_lazy, %function
+
+tms570_vfp_lazy:
+    push    {r4, r5, r6, lr}
+    sub     sp, sp, #LAZY_FRAME_SIZE
+    ldr     r5, =tms570_vfp_lazy_frame
+    ldr     r1, [r5]
+    vmrs    r2, fpexc
+    mov     r3, #0
+    stmia   sp, {r1 - r3}
+    bic     r2, r2, #FPEXC_EN
+    vmsr    fpexc, r2
+    str     sp, [r5]
+    blx     r0
+    ldr     r1, [sp, #8]
+    cmp     r1, #0
+    beq     1f
+    add     r1, sp, #16
+    vldmia  r1, {d0 - d15}
+    ldr     r1, [sp, #12]
+    vmsr    fpscr, r1
+1:
+    ldr     r1, [sp, #4]
+    vmsr    fpexc, r1
+    ldr     r1, [sp]
+    str     r1, [r5]
+    add     sp, sp, #LAZY_FRAME_SIZE
+    pop     {r4, r5, r6, pc}
+
+@ Called first by undef_entry with r0, r1 saved on the Undef stack.
+@ Returns with Z set if the trap was a lazy VFP save (then the faulting
+@ instruction must be retried), Z clear otherwise.
+
+    .global tms570_vfp_lazy_trap
+    .type tms570_vfp_lazy_trap, %function
+
+tms570_vfp_lazy_trap:
+    ldr     r0, =tms570_vfp_lazy_frame
+    ldr     r0, [r0]
+    cmp     r0, #0
+    beq     2f
+    ldr     r1, [r0, #8]
+    cmp     r1, #0
+    bne     2f
+    vmrs    r1, fpexc
+    tst     r1, #FPEXC_EN
+    bne     2f
+    orr     r1, r1, #FPEXC_EN
+    vmsr    fpexc, r1
+    mov     r1, #1
+    str     r1, [r0, #8]
+    vmrs    r1, fpscr
+    str     r1, [r0, #12]
+    add     r0, r0, #16
+    vstmia  r0, {d0 - d15}
+    cmp     r0, r0              @ Z = 1: context saved
+    bx      lr
+2:
+    movs    r1, #1              @ Z = 0: not a lazy trap
+    bx      lr
+
+    .ltorg
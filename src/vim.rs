@@ -1,35 +1,56 @@
 use crate::esm::Esm;
 use crate::esm_ch::EsmError;
+use crate::peripherals::{ESM, VIM};
+use crate::regs::{map, Reg};
 use vcell::VolatileCell;
 
 pub const VIM_CHANNELS: usize = 128;
 const VIM_CH_GROUPS: usize = 32;
 
+register_bitfields! {
+    /// Parity Flag
+    PARFLG [
+        /// Parity error detected (write 1 to clear)
+        PARFLG OFFSET(0) NUMBITS(1) []
+    ],
+    /// Parity Control
+    PARCTL [
+        /// Parity mapping test: parity bits accessible
+        TEST OFFSET(8) NUMBITS(1) [],
+        /// Parity enable key
+        PARENA OFFSET(0) NUMBITS(4) [
+            Enable = 0xA,
+            Disable = 0x5
+        ]
+    ]
+}
+
 #[repr(C)]
 pub struct VimRegisters {
     /// Parity Flag
-    parflg: VolatileCell<u32>,
+    parflg: Reg<u32, PARFLG::Register>,
     /// Parity Control
-    parctl: VolatileCell<u32>,
+    parctl: Reg<u32, PARCTL::Register>,
     /// Address Parity Error
-    adderr: VolatileCell<u32>,
+    adderr: Reg,
     /// Fall-Back Address Parity Error
-    fbparerr: VolatileCell<u32>,
-    irq_index: VolatileCell<u32>,
-    fiq_index: VolatileCell<u32>,
-    _reserved1: VolatileCell<u32>,
-    _reserved2: VolatileCell<u32>,
-    firgpr: [VolatileCell<u32>; 4],
-    intreq: [VolatileCell<u32>; 4],
-    req_maskset: [VolatileCell<u32>; 4],
-    req_maskclr: [VolatileCell<u32>; 4],
-    wake_maskset: [VolatileCell<u32>; 4],
-    wake_maskclr: [VolatileCell<u32>; 4],
-    irq_vecreg: VolatileCell<u32>,
-    fiq_vecreg: VolatileCell<u32>,
-    capevt: VolatileCell<u32>,
-    _reserved3: VolatileCell<u32>,
-    chan_ctrl: [VolatileCell<u32>; 32],
+    fbparerr: Reg,
+    _reserved0: Reg,
+    irq_index: Reg,
+    fiq_index: Reg,
+    _reserved1: Reg,
+    _reserved2: Reg,
+    firgpr: [Reg; 4],
+    intreq: [Reg; 4],
+    req_maskset: [Reg; 4],
+    req_maskclr: [Reg; 4],
+    wake_maskset: [Reg; 4],
+    wake_maskclr: [Reg; 4],
+    irq_vecreg: Reg,
+    fiq_vecreg: Reg,
+    capevt: Reg,
+    _reserved3: Reg,
+    chan_ctrl: [Reg; 32],
 }
 const VIM_BASE_ADDR: *const VimRegisters = 0xFFFF_FDEC as *const VimRegisters;
 
@@ -45,8 +66,6 @@ pub struct VimParityRam {
 /// VIM Parity RAM base address
 const VIM_PRAM_BASE_ADDR: *const VimParityRam = 0xFFF8_2400 as *const VimParityRam;
 
-const TEST_ENABLE: u32 = 0x10;
-
 fn vim_dummy_isr() {}
 
 // .vim.table shall be at 0xFFF8_2000
@@ -54,6 +73,102 @@ fn vim_dummy_isr() {}
 #[link_section = ".vim_table"]
 static INTERRUPTS: [fn(); VIM_CHANNELS] = [vim_dummy_isr; VIM_CHANNELS];
 
+/// VIM channels assignment (TMS570LS31x/21x datasheet, Table 4-31
+/// "Interrupt Request Assignments"). Discriminant is the channel number.
+#[derive(Copy, Clone, PartialEq)]
+#[repr(u32)]
+pub enum VimChannel {
+    EsmHigh = 0,
+    Rti0 = 2,
+    Rti1 = 3,
+    Rti2 = 4,
+    Rti3 = 5,
+    RtiOverflow0 = 6,
+    RtiOverflow1 = 7,
+    RtiTimebase = 8,
+    GioHigh = 9,
+    Het1Level0 = 10,
+    HetTu1Level0 = 11,
+    MibSpi1Level0 = 12,
+    LinLevel0 = 13,
+    Adc1Event = 14,
+    Adc1Group1 = 15,
+    Can1Level0 = 16,
+    Spi2Level0 = 17,
+    FlexRayLevel0 = 18,
+    Crc = 19,
+    EsmLow = 20,
+    SystemSoftware = 21,
+    Pmu = 22,
+    GioLow = 23,
+    Het1Level1 = 24,
+    HetTu1Level1 = 25,
+    MibSpi1Level1 = 26,
+    LinLevel1 = 27,
+    Adc1Group2 = 28,
+    Can1Level1 = 29,
+    Spi2Level1 = 30,
+    Adc1Magnitude = 31,
+    FlexRayLevel1 = 32,
+    DmaFtca = 33,
+    DmaLfsa = 34,
+    Can2Level0 = 35,
+    DmmLevel0 = 36,
+    MibSpi3Level0 = 37,
+    MibSpi3Level1 = 38,
+    DmaHbca = 39,
+    DmaBtca = 40,
+    Emif = 41,
+    Can2Level1 = 42,
+    DmmLevel1 = 43,
+    Can1If3 = 44,
+    Can3Level0 = 45,
+    Can2If3 = 46,
+    Fpu = 47,
+    FlexRayTuStatus = 48,
+    Spi4Level0 = 49,
+    Adc2Event = 50,
+    Adc2Group1 = 51,
+    FlexRayT0c = 52,
+    MibSpi5Level0 = 53,
+    Spi4Level1 = 54,
+    Can3Level1 = 55,
+    MibSpi5Level1 = 56,
+    Adc2Group2 = 57,
+    FlexRayTuError = 58,
+    Adc2Magnitude = 59,
+    Can3If3 = 60,
+    FlashFsmDone = 61,
+    FlexRayT1c = 62,
+    Het2Level0 = 63,
+    SciLevel0 = 64,
+    HetTu2Level0 = 65,
+    I2cLevel0 = 66,
+    Het2Level1 = 73,
+    SciLevel1 = 74,
+    HetTu2Level1 = 75,
+    I2cLevel1 = 76,
+}
+
+/// Interrupt handler registered with `#[interrupt]`.
+/// Entries are collected by the linker in the `.vim_isrs` section
+/// and installed in VIM RAM by `Vim::install_vectors()`.
+#[repr(C)]
+pub struct VimEntry {
+    pub channel: VimChannel,
+    /// IRQ/FIQ entry point (exception return included)
+    pub isr: unsafe extern "C" fn(),
+}
+
+extern "C" {
+    static __vim_isrs_start: VimEntry;
+    static __vim_isrs_end: VimEntry;
+    /// Default handler for unused channels and phantom interrupts:
+    /// it disables the channel (a level request would be taken again
+    /// forever)
+    fn tms570_default_irq();
+}
+
 #[derive(Copy, Clone)]
 pub enum VimType {
     SysInterrupt,
@@ -69,11 +184,13 @@ pub struct Vim {
 #[allow(dead_code)]
 impl Vim {
 
-    pub unsafe fn new() -> Vim {
-        Vim {
-            regs: &*VIM_BASE_ADDR,
-            table: &*VIM_RAM_BASE_ADDR,
-            pram: &*VIM_PRAM_BASE_ADDR,
+    pub fn new(_vim: VIM) -> Vim {
+        unsafe {
+            Vim {
+                regs: map(VIM_BASE_ADDR),
+                table: map(VIM_RAM_BASE_ADDR),
+                pram: map(VIM_PRAM_BASE_ADDR),
+            }
         }
     }
 
@@ -87,32 +204,57 @@ impl Vim {
 
     pub fn isr_set(&self, ch: usize, isr: fn()) {
         if ch > 2 && ch < VIM_CHANNELS {
-            self.table.isr[ch].set(isr as u32);
+            self.table.isr[ch].set(isr as *const () as usize as u32);
+        }
+    }
+
+    /// Fill VIM RAM with the handlers registered with `#[interrupt]`.
+    /// Phantom interrupt and unused channels point to a default handler
+    /// that disables the request of the channel and returns.
+    /// VIM RAM must be initialized first.
+    pub fn install_vectors(&self) {
+        let default = tms570_default_irq as *const () as usize as u32;
+        for isr in self.table.isr.iter() {
+            isr.set(default);
+        }
+
+        let entries = unsafe {
+            let start = &__vim_isrs_start as *const VimEntry;
+            let end = &__vim_isrs_end as *const VimEntry;
+            let len = (end as usize - start as usize) / core::mem::size_of::<VimEntry>();
+            core::slice::from_raw_parts(start, len)
+        };
+        for e in entries {
+            // VIM RAM entry 0 is the phantom vector, channel n is at n + 1
+            let idx = (e.channel as usize) + 1;
+            if idx < VIM_CHANNELS {
+                self.table.isr[idx].set(e.isr as *const () as usize as u32);
+            }
         }
     }
 
     pub fn parity_fallback_handler(&self, fb: fn()) {
-        self.regs.fbparerr.set(fb as u32)
+        self.regs.fbparerr.set(fb as *const () as usize as u32)
     }
 
     pub fn parity_enable(&self, enable: bool) {
         if enable {
-            self.regs.parflg.set(0xA)
+            self.regs.parctl.modify(PARCTL::PARENA::Enable)
         } else {
-            self.regs.parflg.set(0x5)
+            self.regs.parctl.modify(PARCTL::PARENA::Disable)
         }
     }
 
     /// clear VIM RAM parity error flag in VIM
     pub fn parity_flag_clear(&self) {
-        self.regs.parflg.set(0x1);
+        self.regs.parflg.write(PARFLG::PARFLG::SET);
     }
 
     pub fn parity_check(&self) -> bool {
         let mut error = false;
         let parctl = self.regs.parctl.get();
         // Enable parity checking and parity test mode
-        self.regs.parctl.set(TEST_ENABLE);
+        self.regs.parctl.write(PARCTL::TEST::SET + PARCTL::PARENA::Enable);
 
         // Flip a bit for fault injection
         self.pram.parity[0].set(self.pram.parity[0].get() ^ 0x1);
@@ -123,7 +265,7 @@ impl Vim {
         let _dummy = self.pram.parity[0].get();
 
         // check if ESM group1 channel 15 is flagged
-        let esm = unsafe { Esm::new() };
+        let esm = Esm::new(unsafe { ESM::steal() });
         if !esm.error_is_set(EsmError::VimParity) {
             error = false;
         } else {
@@ -160,11 +302,43 @@ impl Vim {
         }
     }
 
+    /// Channel allowed to wake the device up from a low-power mode
+    /// (all of them after reset)
+    pub fn wakeup_enable(&self, ch: usize, enable: bool) {
+        if ch < VIM_CHANNELS {
+            let grp = ch / VIM_CH_GROUPS;
+            let id = ch % VIM_CH_GROUPS;
+            if enable {
+                self.regs.wake_maskset[grp].set(0x1 << id);
+            } else {
+                self.regs.wake_maskclr[grp].set(0x1 << id);
+            }
+        }
+    }
+
+    /// Allow (or forbid) all the channels to wake the device up
+    pub fn wakeup_enable_all(&self, enable: bool) {
+        for grp in 0..self.regs.wake_maskset.len() {
+            if enable {
+                self.regs.wake_maskset[grp].set(0xFFFF_FFFF);
+            } else {
+                self.regs.wake_maskclr[grp].set(0xFFFF_FFFF);
+            }
+        }
+    }
+
     #[inline]
     pub fn clear_esm_interrupt(&self) {
         self.regs.intreq[0].set(0x1)
     }
 
+    /// Recover from a VIM RAM parity error: clear the error and re-enable
+    /// the interrupt that hit it.
+    ///
+    /// # Safety
+    ///
+    /// Must be called from the VIM parity error fallback handler only, with
+    /// the error pending. The ESM is accessed without its peripheral token.
     pub unsafe fn default_parity_fallback_handler(&self) {
         let error_address = self.regs.adderr.get();
         let _error_ch = ((error_address & 0x1FF) >> 2) as usize;
@@ -178,7 +352,7 @@ impl Vim {
             // ESM interrupt (high-priority) We can't disable.
             // So clear it and clear related error.
             self.clear_esm_interrupt();
-            let esm = Esm::new();
+            let esm = Esm::new(unsafe { ESM::steal() });
             let vec = esm.high_level_interrupt();
             let error = EsmError::from(vec as u8);
             esm.clear_error(error);